@@ -0,0 +1,159 @@
+//! Builds the same bundle with this crate and with the Go `gen-bundle`, and
+//! compares them, i.e. `--gen-bundle`.
+//!
+//! See https://github.com/WICG/webpackage/tree/main/go/bundle
+
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{ensure, Context as _, Result};
+use webbundle::{Bundle, Exchange, Request, Severity};
+
+use crate::Cli;
+
+/// The directory of the artifacts, relative to the output directory.
+const INTEROP_DIR: &str = "interop";
+
+/// `gen-bundle` requires absolute urls.
+const BASE_URL: &str = "https://example.com/";
+
+/// Writes the resources of the bundle into `interop/src`, bundles the
+/// directory with both tools, and writes the differences to
+/// `interop/report.txt`. Does nothing if `gen-bundle` is not installed.
+pub(crate) fn build(option: &Cli, gen_bundle: &Path, bundle: &Bundle) -> Result<()> {
+    let dir = PathBuf::from(&option.out).join(INTEROP_DIR);
+    let src = dir.join("src");
+    if src.exists() {
+        std::fs::remove_dir_all(&src)?;
+    }
+    for exchange in bundle.exchanges() {
+        let path = src.join(exchange.request.url());
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        std::fs::write(path, exchange.response.body())?;
+    }
+
+    let theirs_path = dir.join("gen-bundle.wbn");
+    let output = match Command::new(gen_bundle)
+        .arg("-version")
+        .arg("b2")
+        .arg("-dir")
+        .arg(&src)
+        .arg("-baseURL")
+        .arg(BASE_URL)
+        .arg("-o")
+        .arg(&theirs_path)
+        .output()
+    {
+        Ok(output) => output,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            log::warn!("{} is not installed. Skipping", gen_bundle.display());
+            return Ok(());
+        }
+        Err(err) => return Err(err).context("Failed to run gen-bundle"),
+    };
+    ensure!(
+        output.status.success(),
+        "gen-bundle failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let theirs = std::fs::read(&theirs_path)?;
+
+    let mut ours = Bundle::builder()
+        .version(webbundle::Version::VersionB2)
+        .exchanges_from_dir_sync(&src)?
+        .build()?;
+    for exchange in ours.exchanges_mut() {
+        exchange.request = Request::new(
+            format!("{BASE_URL}{}", exchange.request.url()),
+            exchange.request.headers().clone(),
+        );
+    }
+    let ours = ours.encode()?;
+    std::fs::write(dir.join("webbundle-rs.wbn"), &ours)?;
+
+    let report = if ours == theirs {
+        "The bundles are identical\n".to_string()
+    } else {
+        compare(&ours, &theirs)?
+    };
+    std::fs::write(dir.join("report.txt"), &report)?;
+    print!("gen-bundle: {report}");
+    Ok(())
+}
+
+fn find<'a>(bundle: &'a Bundle, url: &str) -> Option<&'a Exchange> {
+    bundle
+        .exchanges()
+        .iter()
+        .find(|exchange| exchange.request.url() == url)
+}
+
+/// Returns the differences between the bundles, which should be equivalent
+/// even if their bytes differ, e.g. by the order of headers.
+fn compare(ours: &[u8], theirs: &[u8]) -> Result<String> {
+    let mut report = String::new();
+    for (name, bytes) in [("webbundle-rs", ours), ("gen-bundle", theirs)] {
+        for finding in webbundle::validate(bytes) {
+            if finding.severity == Severity::Error {
+                writeln!(report, "{name}: {finding}")?;
+            }
+        }
+    }
+
+    let ours = Bundle::from_bytes(ours).context("webbundle-rs")?;
+    let theirs = Bundle::from_bytes(theirs).context("gen-bundle")?;
+    if ours.primary_url() != theirs.primary_url() {
+        writeln!(
+            report,
+            "primary url: {:?} != {:?}",
+            ours.primary_url(),
+            theirs.primary_url()
+        )?;
+    }
+    for exchange in ours.exchanges() {
+        let url = exchange.request.url();
+        let other = match find(&theirs, url) {
+            Some(other) => other,
+            None => {
+                writeln!(report, "{url}: only in webbundle-rs")?;
+                continue;
+            }
+        };
+        let (ours, theirs) = (&exchange.response, &other.response);
+        if ours.status() != theirs.status() {
+            writeln!(
+                report,
+                "{url}: status {} != {}",
+                ours.status(),
+                theirs.status()
+            )?;
+        }
+        if ours.body() != theirs.body() {
+            writeln!(report, "{url}: bodies differ")?;
+        }
+        let mut names = ours.headers().keys().collect::<Vec<_>>();
+        names.extend(theirs.headers().keys());
+        names.sort_by_key(|name| name.as_str());
+        names.dedup();
+        for name in names {
+            if ours.headers().get(name) != theirs.headers().get(name) {
+                writeln!(
+                    report,
+                    "{url}: {name}: {:?} != {:?}",
+                    ours.headers().get(name),
+                    theirs.headers().get(name)
+                )?;
+            }
+        }
+    }
+    for exchange in theirs.exchanges() {
+        if find(&ours, exchange.request.url()).is_none() {
+            writeln!(report, "{}: only in gen-bundle", exchange.request.url())?;
+        }
+    }
+    if report.is_empty() {
+        report.push_str("The bundles are equivalent, but their bytes differ\n");
+    }
+    Ok(report)
+}
@@ -2,11 +2,22 @@ use std::path::{Path, PathBuf};
 
 use anyhow::Result;
 use askama::Template;
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use payload::AssetKind;
+use webbundle::testing::Rng;
 use webbundle::Bundle;
 
+mod interop;
+mod measure;
+mod media;
+mod payload;
+mod serve;
+
 #[derive(Parser, Debug)]
+#[command(args_conflicts_with_subcommands = true)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
     /// The output directory
     #[arg(short = 'o', long, default_value = "out")]
     out: String,
@@ -19,6 +30,62 @@ struct Cli {
     /// [Experimental] Produce two WebBundle for cache-aware WebBundles static test
     #[arg(long)]
     split: bool,
+    /// The minimum size of each module in bytes, which is padded with a comment
+    #[arg(long, value_name = "BYTES", default_value = "0")]
+    module_size: usize,
+    /// Adds random padding, up to this many bytes, to each module, so that the
+    /// sizes of modules vary
+    #[arg(long, value_name = "BYTES", default_value = "0")]
+    random_padding: usize,
+    /// The assets which each module fetches, in addition to its imports, e.g.
+    /// `css,json,image`. Repeat a kind to fetch several assets of the kind
+    #[arg(long, value_enum, value_delimiter = ',')]
+    assets: Vec<AssetKind>,
+    /// The size of each asset in bytes
+    #[arg(long, value_name = "BYTES", default_value = "1024")]
+    asset_size: usize,
+    /// The number of images of the media benchmarks. Media benchmarks are
+    /// generated if any of `--images`, `--stylesheets` and `--fonts` is given
+    #[arg(long, value_name = "N", default_value = "0")]
+    images: usize,
+    /// The number of stylesheets of the media benchmarks
+    #[arg(long, value_name = "N", default_value = "0")]
+    stylesheets: usize,
+    /// The number of fonts of the media benchmarks
+    #[arg(long, value_name = "N", default_value = "0")]
+    fonts: usize,
+    /// The seed of the random padding and the bodies of assets
+    #[arg(long, default_value = "1")]
+    seed: u64,
+    /// Also bundles the modules with the Go `gen-bundle`, if it is installed,
+    /// and writes the comparison with this crate to `<OUT>/interop/`
+    #[arg(long, value_name = "PATH", num_args = 0..=1, default_missing_value = "gen-bundle")]
+    gen_bundle: Option<PathBuf>,
+    /// Loads each benchmark in headless Chrome, and writes the metrics to
+    /// `--report`
+    #[arg(long)]
+    measure: bool,
+    /// The number of times each benchmark is loaded, with `--measure`
+    #[arg(long, default_value = "5")]
+    runs: usize,
+    /// The report of `--measure`, which is CSV if the file name ends with
+    /// `.csv`, or JSON otherwise [default: <OUT>/report.json]
+    #[arg(long, value_name = "FILE")]
+    report: Option<PathBuf>,
+    /// The Chrome executable, with `--measure`. Chrome is searched in the
+    /// usual places by default
+    #[arg(long, value_name = "PATH")]
+    browser: Option<PathBuf>,
+    /// An extra argument of Chrome, e.g.
+    /// `--chrome-arg=--enable-features=SubresourceWebBundles`
+    #[arg(long, value_name = "ARG", allow_hyphen_values = true)]
+    chrome_arg: Vec<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Serves the benchmarks, with simulated latency and bandwidth
+    Serve(serve::ServeArgs),
 }
 
 struct Module {
@@ -63,10 +130,7 @@ impl Module {
     }
 
     fn full_path(&self) -> String {
-        match &self.dir {
-            Some(dir) => dir.join(self.filename()).display().to_string(),
-            None => self.filename(),
-        }
+        self.path_in_dir(&self.filename())
     }
 
     fn relative_path_from_parent(&self) -> String {
@@ -109,19 +173,47 @@ impl Module {
         )
     }
 
-    fn export(&self, mut builder: webbundle::Builder, option: &Cli) -> Result<webbundle::Builder> {
+    // e.g. "a2_a1_a3_0.css", which is in the same directory as the module.
+    fn asset_filename(&self, index: usize, kind: AssetKind) -> String {
+        format!("{}_{index}.{}", self.name, kind.extension())
+    }
+
+    fn path_in_dir(&self, filename: &str) -> String {
+        match &self.dir {
+            Some(dir) => dir.join(filename).display().to_string(),
+            None => filename.to_string(),
+        }
+    }
+
+    fn export(
+        &self,
+        mut builder: webbundle::Builder,
+        option: &Cli,
+        rng: &mut Rng,
+    ) -> Result<webbundle::Builder> {
         match &self.dir {
             Some(dir) => log::debug!("{}", dir.join(self.filename()).display()),
             None => log::debug!("{}", self.filename()),
         };
-        let t = ModuleTemplate {
+        let mut t = ModuleTemplate {
             imports: self
                 .children
                 .iter()
                 .map(|child| child.import_me())
                 .collect(),
+            assets: (0..option.assets.len())
+                .map(|index| self.asset_filename(index, option.assets[index]))
+                .collect(),
             function_definition: self.function_definition(),
+            padding: String::new(),
         };
+        let unpadded_len = t.render().unwrap().len();
+        // "/* " and " */\n"
+        let padding =
+            option.module_size.saturating_sub(unpadded_len + 7) + rng.up_to(option.random_padding);
+        if padding > 0 {
+            t.padding = format!("/* {} */\n", rng.text(padding));
+        }
 
         let output_dir = match &self.dir {
             Some(dir) => PathBuf::from(&option.out).join(dir),
@@ -130,13 +222,20 @@ impl Module {
 
         std::fs::create_dir_all(output_dir)?;
 
+        let body = t.render().unwrap().into_bytes();
         let file = PathBuf::from(&option.out).join(self.full_path());
-        std::fs::write(file, t.render().unwrap())?;
-
-        builder = builder.exchange((self.full_path(), t.render().unwrap().into_bytes()).into());
+        std::fs::write(file, &body)?;
+        builder = builder.exchange((self.full_path(), body).into());
+
+        for (index, kind) in option.assets.iter().enumerate() {
+            let path = self.path_in_dir(&self.asset_filename(index, *kind));
+            let body = kind.body(&self.name, option.asset_size, rng);
+            std::fs::write(PathBuf::from(&option.out).join(&path), &body)?;
+            builder = builder.exchange((path, body).into());
+        }
 
         for child in &self.children {
-            builder = child.export(builder, option)?;
+            builder = child.export(builder, option, rng)?;
         }
         Ok(builder)
     }
@@ -169,8 +268,12 @@ impl Benchmark {
     }
 
     fn build(&self, option: &Cli) -> Result<()> {
-        let bundle = self.build_modules(option)?;
+        let mut rng = Rng::new(option.seed);
+        let bundle = self.build_modules(option, &mut rng)?;
         self.build_html(option)?;
+        if media::enabled(option) {
+            media::build(option, &mut rng)?;
+        }
 
         // For cache-aware Web Bundle ad-hoc tests.
         if option.split {
@@ -180,20 +283,22 @@ impl Benchmark {
                 self.build_cache_aware_bundle_html(option, &bundle0, &bundle1, cache_hit)?;
             }
         }
+        if let Some(gen_bundle) = &option.gen_bundle {
+            interop::build(option, gen_bundle, &bundle)?;
+        }
         Ok(())
     }
 
-    fn build_modules(&self, option: &Cli) -> Result<Bundle> {
+    fn build_modules(&self, option: &Cli, rng: &mut Rng) -> Result<Bundle> {
         // Build modules
         let builder = Bundle::builder().version(webbundle::Version::VersionB2);
-        let builder = self.start_module.export(builder, option)?;
+        let builder = self.start_module.export(builder, option, rng)?;
 
         // Build webbundle
         let bundle = builder.build()?;
-        println!("Build {} modules", bundle.exchanges().len());
+        println!("Build {} resources", bundle.exchanges().len());
         std::fs::create_dir_all(&option.out)?;
-        let f = std::fs::File::create(PathBuf::from(&option.out).join("webbundle.wbn"))?;
-        bundle.write_to(f)?;
+        bundle.write_to_file(PathBuf::from(&option.out).join("webbundle.wbn"))?;
 
         Ok(bundle)
     }
@@ -218,15 +323,13 @@ impl Benchmark {
         let bundle0 = builder0.build()?;
         let bundle1 = builder1.build()?;
 
-        let f = std::fs::File::create(
+        bundle0.write_to_file(
             PathBuf::from(&option.out).join(format!("webbundle-cache-aware-{cache_hit}.wbn")),
         )?;
-        bundle0.write_to(f)?;
 
         let dir = PathBuf::from(&option.out).join("cache-aware-2nd");
         std::fs::create_dir_all(&dir)?;
-        let f = std::fs::File::create(dir.join(format!("webbundle-cache-aware-{cache_hit}.wbn")))?;
-        bundle1.write_to(f)?;
+        bundle1.write_to_file(dir.join(format!("webbundle-cache-aware-{cache_hit}.wbn")))?;
 
         Ok((bundle0, bundle1))
     }
@@ -326,16 +429,25 @@ impl Benchmark {
         Ok(())
     }
 
-    fn build_index_html(&self, option: &Cli) -> Result<()> {
+    /// Returns the names of the benchmark pages, without `.html`, which
+    /// `index.html` links to.
+    fn benchmarks(option: &Cli) -> Vec<String> {
         let mut benchmarks = vec!["unbundled".to_string(), "webbundle".to_string()];
         if option.split {
             for cache_hit in CACHE_HIT {
                 benchmarks.push(format!("webbundle-cache-aware-{cache_hit}-1st"));
             }
         }
+        if media::enabled(option) {
+            benchmarks.extend(media::BENCHMARKS.map(str::to_string));
+        }
+        benchmarks
+    }
+
+    fn build_index_html(&self, option: &Cli) -> Result<()> {
         let t = IndexTemplate {
             info: format!("option: {option:#?}"),
-            benchmarks,
+            benchmarks: Benchmark::benchmarks(option),
         };
 
         std::fs::create_dir_all(&option.out)?;
@@ -349,7 +461,9 @@ impl Benchmark {
 #[template(path = "module.html")]
 struct ModuleTemplate {
     imports: Vec<String>,
+    assets: Vec<String>,
     function_definition: String,
+    padding: String,
 }
 
 #[derive(Template)]
@@ -370,9 +484,17 @@ struct IndexTemplate {
     benchmarks: Vec<String>,
 }
 
-fn main() -> Result<()> {
+#[tokio::main]
+async fn main() -> Result<()> {
     env_logger::init();
     let cli = Cli::parse();
+    if let Some(Command::Serve(args)) = &cli.command {
+        return serve::run(args).await;
+    }
     let benchmark = Benchmark::new(&cli);
-    benchmark.build(&cli)
+    benchmark.build(&cli)?;
+    if cli.measure {
+        measure::run(&cli, &Benchmark::benchmarks(&cli)).await?;
+    }
+    Ok(())
 }
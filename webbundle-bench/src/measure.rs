@@ -0,0 +1,184 @@
+//! Loads the benchmarks in headless Chrome, and reports the metrics which the
+//! pages measure, i.e. `--measure`.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context as _, Result};
+use chromiumoxide::browser::{Browser, BrowserConfig};
+use chromiumoxide::cdp::browser_protocol::network::ClearBrowserCacheParams;
+use futures_util::StreamExt as _;
+use serde_json::{json, Value};
+
+use crate::Cli;
+
+/// How long to wait for a page to report its results.
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The metrics of a run of a benchmark, in milliseconds since the navigation
+/// start, e.g. `importEnd`, and `ready` when the page has finished its work,
+/// which approximates the time to interactive.
+struct Measurement {
+    benchmark: String,
+    run: usize,
+    metrics: BTreeMap<String, f64>,
+}
+
+impl Measurement {
+    /// The time from the response of the page until the page is ready.
+    fn duration(&self) -> Option<f64> {
+        Some(self.metrics.get("ready")? - self.metrics.get("navigationResponseStart")?)
+    }
+}
+
+/// Serves the output directory, and loads each benchmark `--runs` times with
+/// an empty cache.
+pub(crate) async fn run(option: &Cli, benchmarks: &[String]) -> Result<()> {
+    let addr = serve(PathBuf::from(&option.out)).await?;
+    let mut config = BrowserConfig::builder().args(&option.chrome_arg);
+    if let Some(browser) = &option.browser {
+        config = config.chrome_executable(browser);
+    }
+    let config = config
+        .build()
+        .map_err(|err| anyhow::anyhow!("{err}. Use --browser to specify it"))?;
+    let (mut browser, mut handler) = Browser::launch(config)
+        .await
+        .context("Failed to launch Chrome")?;
+    // Events, some of which chromiumoxide may fail to parse, must be polled
+    // for the browser to make progress.
+    let handler = tokio::spawn(async move { while handler.next().await.is_some() {} });
+
+    let mut measurements = Vec::new();
+    for run in 0..option.runs {
+        for benchmark in benchmarks {
+            let url = format!("http://{addr}/{benchmark}.html");
+            let metrics = tokio::time::timeout(TIMEOUT, measure(&browser, &url))
+                .await
+                .with_context(|| format!("Timed out: {url}"))??;
+            log::info!("{benchmark} #{run}: {metrics:?}");
+            measurements.push(Measurement {
+                benchmark: benchmark.clone(),
+                run,
+                metrics,
+            });
+        }
+    }
+    browser.close().await?;
+    handler.await?;
+
+    for benchmark in benchmarks {
+        let mut durations = measurements
+            .iter()
+            .filter(|measurement| &measurement.benchmark == benchmark)
+            .filter_map(Measurement::duration)
+            .collect::<Vec<_>>();
+        durations.sort_by(f64::total_cmp);
+        if let Some(median) = durations.get(durations.len() / 2) {
+            println!(
+                "{benchmark}: {median:.1} ms (median of {} runs)",
+                durations.len()
+            );
+        }
+    }
+    let report = option
+        .report
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(&option.out).join("report.json"));
+    write_report(&report, &measurements)?;
+    println!("Wrote {}", report.display());
+    Ok(())
+}
+
+/// Serves the directory on a free port of localhost, with `webbundle-server`.
+async fn serve(dir: PathBuf) -> Result<SocketAddr> {
+    let router = webbundle_server::router(&webbundle_server::Config::root(dir, None)).await?;
+    let server = axum::Server::try_bind(&SocketAddr::from(([127, 0, 0, 1], 0)))?
+        .serve(router.into_make_service());
+    let addr = server.local_addr();
+    tokio::spawn(server);
+    Ok(addr)
+}
+
+/// Loads the page with an empty cache, and returns the numbers in its
+/// results, and the navigation timings.
+async fn measure(browser: &Browser, url: &str) -> Result<BTreeMap<String, f64>> {
+    let page = browser.new_page("about:blank").await?;
+    page.execute(ClearBrowserCacheParams::default()).await?;
+    page.goto(url).await?;
+    let results = loop {
+        let results: String = page
+            .evaluate(r##"document.querySelector("#result")?.textContent ?? """##)
+            .await?
+            .into_value()?;
+        if !results.is_empty() {
+            break results;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    };
+    let navigation: Value = page
+        .evaluate(r#"performance.getEntriesByType("navigation")[0].toJSON()"#)
+        .await?
+        .into_value()?;
+    page.close().await?;
+
+    let results: Value = serde_json::from_str(&results)?;
+    let mut metrics = BTreeMap::new();
+    for (name, value) in results.as_object().into_iter().flatten() {
+        // `res` is the value which the modules return.
+        if name != "res" {
+            if let Some(value) = value.as_f64() {
+                metrics.insert(name.clone(), value);
+            }
+        }
+    }
+    for name in ["domContentLoadedEventEnd", "loadEventEnd"] {
+        if let Some(value) = navigation[name].as_f64() {
+            metrics.insert(name.to_string(), value);
+        }
+    }
+    Ok(metrics)
+}
+
+/// Writes the measurements as CSV if the file name ends with `.csv`, or as
+/// JSON otherwise.
+fn write_report(path: &Path, measurements: &[Measurement]) -> Result<()> {
+    let report = if path.extension().is_some_and(|extension| extension == "csv") {
+        let names = measurements
+            .iter()
+            .flat_map(|measurement| measurement.metrics.keys())
+            .collect::<BTreeSet<_>>();
+        let mut csv = String::from("benchmark,run");
+        for name in &names {
+            write!(csv, ",{name}")?;
+        }
+        csv.push('\n');
+        for measurement in measurements {
+            write!(csv, "{},{}", measurement.benchmark, measurement.run)?;
+            for name in &names {
+                csv.push(',');
+                if let Some(value) = measurement.metrics.get(*name) {
+                    write!(csv, "{value}")?;
+                }
+            }
+            csv.push('\n');
+        }
+        csv
+    } else {
+        let measurements = measurements
+            .iter()
+            .map(|measurement| {
+                json!({
+                    "benchmark": measurement.benchmark,
+                    "run": measurement.run,
+                    "metrics": measurement.metrics,
+                })
+            })
+            .collect::<Vec<_>>();
+        serde_json::to_string_pretty(&measurements)?
+    };
+    std::fs::write(path, report).with_context(|| format!("Failed to write {}", path.display()))
+}
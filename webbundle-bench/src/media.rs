@@ -0,0 +1,81 @@
+//! The media benchmarks, i.e. pages which reference images, stylesheets and
+//! fonts, rather than importing modules.
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+use askama::Template;
+use webbundle::{Bundle, Exchange};
+
+use crate::payload::AssetKind;
+use crate::Cli;
+use webbundle::testing::Rng;
+
+/// The directory of the assets, relative to the output directory.
+const MEDIA_DIR: &str = "media";
+
+/// The names of the generated pages, without `.html`.
+pub(crate) const BENCHMARKS: [&str; 2] = ["media-unbundled", "media-webbundle"];
+
+#[derive(Template)]
+#[template(path = "media.html")]
+struct MediaTemplate {
+    headers: String,
+    info: String,
+    images: Vec<String>,
+    stylesheets: Vec<String>,
+    fonts: Vec<String>,
+}
+
+/// Returns true if any media is requested.
+pub(crate) fn enabled(option: &Cli) -> bool {
+    option.images + option.stylesheets + option.fonts > 0
+}
+
+/// Generates the assets, `media.wbn` which bundles them, and the pages which
+/// load them with and without the bundle.
+pub(crate) fn build(option: &Cli, rng: &mut Rng) -> Result<()> {
+    let out = PathBuf::from(&option.out);
+    std::fs::create_dir_all(out.join(MEDIA_DIR))?;
+    let mut exchanges: Vec<Exchange> = Vec::new();
+    let mut assets = |prefix: &str, count: usize, kind: AssetKind| -> Result<Vec<String>> {
+        let mut paths = Vec::new();
+        for index in 0..count {
+            let name = format!("{prefix}{index}");
+            let path = format!("{MEDIA_DIR}/{name}.{}", kind.extension());
+            let body = kind.body(&name, option.asset_size, rng);
+            std::fs::write(out.join(&path), &body)?;
+            exchanges.push((path.clone(), body).into());
+            paths.push(path);
+        }
+        Ok(paths)
+    };
+    let images = assets("image", option.images, AssetKind::Image)?;
+    let stylesheets = assets("style", option.stylesheets, AssetKind::Css)?;
+    let fonts = assets("font", option.fonts, AssetKind::Font)?;
+    let mut builder = Bundle::builder().version(webbundle::Version::VersionB2);
+    for exchange in exchanges {
+        builder = builder.exchange(exchange);
+    }
+
+    let bundle = builder.build()?;
+    println!("Build {} media assets", bundle.exchanges().len());
+    bundle.write_to_file(out.join("media.wbn"))?;
+
+    for (name, headers) in BENCHMARKS.iter().zip([
+        String::new(),
+        format!(
+            r#"<script type="webbundle"> {{ "source": "media.wbn", "scopes": ["{MEDIA_DIR}/"] }} </script>"#
+        ),
+    ]) {
+        let t = MediaTemplate {
+            headers,
+            info: format!("option: {option:#?}"),
+            images: images.clone(),
+            stylesheets: stylesheets.clone(),
+            fonts: fonts.clone(),
+        };
+        std::fs::write(out.join(format!("{name}.html")), t.render().unwrap())?;
+    }
+    Ok(())
+}
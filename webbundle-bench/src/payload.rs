@@ -0,0 +1,63 @@
+//! The payloads of generated resources, i.e. the padding of modules and the
+//! bodies of non-JS assets.
+
+use clap::ValueEnum;
+use webbundle::testing::Rng;
+
+/// The kinds of the assets which each module fetches, in addition to its
+/// imports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum AssetKind {
+    Css,
+    Json,
+    Image,
+    Font,
+}
+
+impl AssetKind {
+    pub(crate) fn extension(self) -> &'static str {
+        match self {
+            AssetKind::Css => "css",
+            AssetKind::Json => "json",
+            AssetKind::Image => "png",
+            AssetKind::Font => "woff2",
+        }
+    }
+
+    /// Returns the body of an asset, which is about `size` bytes. Images and
+    /// fonts are random bytes after their signatures, which are fetched, but
+    /// fail to decode.
+    pub(crate) fn body(self, name: &str, size: usize, rng: &mut Rng) -> Vec<u8> {
+        match self {
+            AssetKind::Css => {
+                let rule = format!(".{name} {{ color: red; }}\n");
+                let padding = size.saturating_sub(rule.len() + 7);
+                format!("{rule}/* {} */\n", rng.text(padding)).into_bytes()
+            }
+            AssetKind::Json => {
+                let padding = size.saturating_sub(name.len() + 28);
+                format!(
+                    "{{\"name\": \"{name}\", \"padding\": \"{}\"}}\n",
+                    rng.text(padding).replace('\n', " ")
+                )
+                .into_bytes()
+            }
+            AssetKind::Image => {
+                const PNG_SIGNATURE: &[u8] = &[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+                [
+                    PNG_SIGNATURE,
+                    &rng.bytes(size.saturating_sub(PNG_SIGNATURE.len())),
+                ]
+                .concat()
+            }
+            AssetKind::Font => {
+                const WOFF2_SIGNATURE: &[u8] = b"wOF2";
+                [
+                    WOFF2_SIGNATURE,
+                    &rng.bytes(size.saturating_sub(WOFF2_SIGNATURE.len())),
+                ]
+                .concat()
+            }
+        }
+    }
+}
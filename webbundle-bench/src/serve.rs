@@ -0,0 +1,150 @@
+//! Serves the generated benchmarks with simulated network conditions, i.e.
+//! `webbundle-bench serve`.
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use axum::body::{self, Body, BoxBody, Bytes, HttpBody as _};
+use axum::Router;
+use axum_extra::middleware::{self, Next};
+use clap::Args;
+use http::{Request, Response};
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+#[derive(Args, Debug)]
+pub(crate) struct ServeArgs {
+    /// The directory to serve, i.e. the output directory of the benchmarks
+    #[arg(long, default_value = "out")]
+    dir: PathBuf,
+    #[arg(short, long, default_value = "8080")]
+    port: u16,
+    /// The delay of each response, e.g. `100ms` or `1s`
+    #[arg(long, value_parser = parse_latency, default_value = "0ms")]
+    latency: Duration,
+    /// The bandwidth shared by all responses, e.g. `5mbps`, `500kbps` or
+    /// `1gbps`. Unlimited by default
+    #[arg(long, value_parser = parse_bandwidth)]
+    bandwidth: Option<u64>,
+}
+
+/// Parses a duration with a unit, `ms` or `s`.
+fn parse_latency(s: &str) -> Result<Duration, String> {
+    let (number, millis) = if let Some(number) = s.strip_suffix("ms") {
+        (number, 1.0)
+    } else if let Some(number) = s.strip_suffix('s') {
+        (number, 1000.0)
+    } else {
+        return Err("The unit must be ms or s".to_string());
+    };
+    let number = number.parse::<f64>().map_err(|err| err.to_string())?;
+    Ok(Duration::from_secs_f64(number * millis / 1000.0))
+}
+
+/// Parses bits per second with a unit, `bps`, `kbps`, `mbps` or `gbps`, and
+/// returns bytes per second.
+fn parse_bandwidth(s: &str) -> Result<u64, String> {
+    let s = s.to_ascii_lowercase();
+    let (number, bits) = [("gbps", 1e9), ("mbps", 1e6), ("kbps", 1e3), ("bps", 1.0)]
+        .into_iter()
+        .find_map(|(unit, bits)| Some((s.strip_suffix(unit)?, bits)))
+        .ok_or("The unit must be bps, kbps, mbps or gbps")?;
+    let number = number.parse::<f64>().map_err(|err| err.to_string())?;
+    let bytes = (number * bits / 8.0) as u64;
+    if bytes == 0 {
+        return Err("The bandwidth must be at least 8bps".to_string());
+    }
+    Ok(bytes)
+}
+
+/// The size of the pieces which response bodies are sent in, so that
+/// concurrent responses share the bandwidth.
+const CHUNK_BYTES: usize = 16 * 1024;
+
+/// A link, which sends bytes in order, at a fixed rate.
+struct Link {
+    bytes_per_second: u64,
+    /// When the link finishes sending the bytes which are queued.
+    busy_until: Mutex<Instant>,
+}
+
+impl Link {
+    /// Waits until `len` bytes are sent.
+    async fn send(&self, len: usize) {
+        let at = {
+            let mut busy_until = self.busy_until.lock().await;
+            *busy_until = (*busy_until).max(Instant::now())
+                + Duration::from_secs_f64(len as f64 / self.bytes_per_second as f64);
+            *busy_until
+        };
+        tokio::time::sleep_until(at).await;
+    }
+}
+
+/// Delays the responses of the router by `latency`, and sends their bodies
+/// through a link of `bandwidth` bytes per second.
+fn throttle(router: Router, latency: Duration, bandwidth: Option<u64>) -> Router {
+    let link = bandwidth.map(|bytes_per_second| {
+        Arc::new(Link {
+            bytes_per_second,
+            busy_until: Mutex::new(Instant::now()),
+        })
+    });
+    router.layer(middleware::from_fn(
+        move |req: Request<Body>, next: Next<Body>| {
+            let link = link.clone();
+            async move {
+                tokio::time::sleep(latency).await;
+                let response = next.run(req).await;
+                match link {
+                    Some(link) => throttle_body(response, link),
+                    None => response,
+                }
+            }
+        },
+    ))
+}
+
+fn throttle_body(response: Response<BoxBody>, link: Arc<Link>) -> Response<BoxBody> {
+    let (parts, body) = response.into_parts();
+    let chunks =
+        futures_util::stream::unfold((body, Bytes::new()), move |(mut body, mut pending)| {
+            let link = link.clone();
+            async move {
+                if pending.is_empty() {
+                    pending = match body.data().await? {
+                        Ok(data) => data,
+                        Err(err) => return Some((Err(err), (body, Bytes::new()))),
+                    };
+                }
+                let chunk = pending.split_to(pending.len().min(CHUNK_BYTES));
+                link.send(chunk.len()).await;
+                Some((Ok(chunk), (body, pending)))
+            }
+        });
+    Response::from_parts(parts, body::boxed(Body::wrap_stream(chunks)))
+}
+
+/// Serves the directory with `webbundle-server`, with the simulated latency
+/// and bandwidth.
+pub(crate) async fn run(args: &ServeArgs) -> Result<()> {
+    let router =
+        webbundle_server::router(&webbundle_server::Config::root(args.dir.clone(), None)).await?;
+    let router = throttle(router, args.latency, args.bandwidth);
+    let addr = SocketAddr::from(([127, 0, 0, 1], args.port));
+    println!(
+        "Listening on http://{addr}/, with latency {:?} and bandwidth {}",
+        args.latency,
+        match args.bandwidth {
+            Some(bytes) => format!("{} bytes/s", bytes),
+            None => "unlimited".to_string(),
+        }
+    );
+    axum::Server::try_bind(&addr)?
+        .serve(router.into_make_service())
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,206 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::output::Format;
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use webbundle::{Bundle, Exchange, Response, Result};
+
+/// Represents the changes of an exchange which exists in both bundles.
+#[derive(Debug, PartialEq, Eq)]
+struct Modified {
+    url: String,
+    /// The old and the new status, if changed.
+    status: Option<(u16, u16)>,
+    /// The names of the changed headers, including added and removed ones.
+    headers: Vec<String>,
+    /// The old and the new body sizes, if the body is changed.
+    body: Option<(usize, usize)>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Diff {
+    added: Vec<String>,
+    removed: Vec<String>,
+    modified: Vec<Modified>,
+}
+
+impl Diff {
+    fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+fn exchanges_by_url(bundle: &Bundle) -> BTreeMap<&str, &Exchange> {
+    bundle
+        .exchanges()
+        .iter()
+        .map(|exchange| (exchange.request.url().as_str(), exchange))
+        .collect()
+}
+
+fn changed_headers(old: &Response, new: &Response) -> Vec<String> {
+    let names = old
+        .headers()
+        .keys()
+        .chain(new.headers().keys())
+        .map(|name| name.as_str())
+        .collect::<BTreeSet<_>>();
+    names
+        .into_iter()
+        .filter(|name| {
+            !old.headers()
+                .get_all(*name)
+                .iter()
+                .eq(new.headers().get_all(*name).iter())
+        })
+        .map(|name| name.to_string())
+        .collect()
+}
+
+fn diff(old: &Bundle, new: &Bundle) -> Diff {
+    let old = exchanges_by_url(old);
+    let new = exchanges_by_url(new);
+    let mut diff = Diff {
+        added: new
+            .keys()
+            .filter(|url| !old.contains_key(*url))
+            .map(|url| url.to_string())
+            .collect(),
+        removed: old
+            .keys()
+            .filter(|url| !new.contains_key(*url))
+            .map(|url| url.to_string())
+            .collect(),
+        ..Diff::default()
+    };
+    for (url, old) in &old {
+        let new = match new.get(url) {
+            Some(new) => new,
+            None => continue,
+        };
+        let (old, new) = (&old.response, &new.response);
+        let modified = Modified {
+            url: url.to_string(),
+            status: (old.status() != new.status())
+                .then(|| (old.status().as_u16(), new.status().as_u16())),
+            headers: changed_headers(old, new),
+            body: (old.body() != new.body()).then(|| (old.body().len(), new.body().len())),
+        };
+        if modified.status.is_some() || !modified.headers.is_empty() || modified.body.is_some() {
+            diff.modified.push(modified);
+        }
+    }
+    diff
+}
+
+/// Writes the differences between the exchanges of the bundles, and returns
+/// whether there are any differences.
+pub(crate) fn write_diff(
+    old: &Bundle,
+    new: &Bundle,
+    format: Format,
+    mut write: impl Write,
+) -> Result<bool> {
+    let diff = diff(old, new);
+    match format {
+        Format::Plain => {
+            for url in &diff.added {
+                writeln!(write, "+ {url}")?;
+            }
+            for url in &diff.removed {
+                writeln!(write, "- {url}")?;
+            }
+            for modified in &diff.modified {
+                let mut changes = Vec::new();
+                if let Some((old, new)) = modified.status {
+                    changes.push(format!("status {old} -> {new}"));
+                }
+                if !modified.headers.is_empty() {
+                    changes.push(format!("headers {}", modified.headers.join(", ")));
+                }
+                if let Some((old, new)) = modified.body {
+                    changes.push(format!("body {old} -> {new} bytes"));
+                }
+                writeln!(write, "M {}: {}", modified.url, changes.join("; "))?;
+            }
+        }
+        Format::Json => {
+            let modified = diff
+                .modified
+                .iter()
+                .map(|modified| {
+                    json!({
+                        "url": modified.url,
+                        "status": modified.status.map(|(old, new)| json!({"old": old, "new": new})),
+                        "headers": modified.headers,
+                        "body": modified.body.map(|(old, new)| json!({"old": old, "new": new})),
+                    })
+                })
+                .collect::<Vec<_>>();
+            writeln!(
+                write,
+                "{}",
+                json!({
+                    "added": diff.added,
+                    "removed": diff.removed,
+                    "modified": modified,
+                })
+            )?;
+        }
+    }
+    Ok(!diff.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use webbundle::Version;
+
+    #[test]
+    fn diff_bundles() -> Result<()> {
+        let old = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchange(Exchange::from(("a.js".to_string(), b"a".to_vec())))
+            .exchange(Exchange::from(("b.js".to_string(), b"b".to_vec())))
+            .exchange(Exchange::from(("c.js".to_string(), b"c".to_vec())))
+            .build()?;
+        let mut modified = Exchange::from(("b.txt".to_string(), b"bb".to_vec()));
+        modified.request = "b.js".into();
+        let new = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchange(modified)
+            .exchange(Exchange::from(("c.js".to_string(), b"c".to_vec())))
+            .exchange(Exchange::from(("d.js".to_string(), b"d".to_vec())))
+            .build()?;
+
+        let mut out = Vec::new();
+        assert!(write_diff(&old, &new, Format::Plain, &mut out)?);
+        assert_eq!(
+            String::from_utf8(out)?,
+            "+ d.js\n- a.js\nM b.js: headers content-length, content-type; body 1 -> 2 bytes\n"
+        );
+
+        let mut out = Vec::new();
+        write_diff(&old, &new, Format::Json, &mut out)?;
+        let json: serde_json::Value = serde_json::from_slice(&out)?;
+        assert_eq!(json["added"], json!(["d.js"]));
+        assert_eq!(json["modified"][0]["body"], json!({"old": 1, "new": 2}));
+        assert_eq!(json["modified"][0]["status"], json!(null));
+
+        assert!(!write_diff(&old, &old, Format::Plain, &mut Vec::new())?);
+        Ok(())
+    }
+}
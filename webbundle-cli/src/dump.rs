@@ -0,0 +1,330 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Prints the CBOR structure of a bundle with byte offsets, for debugging
+//! interoperability problems.
+
+use anyhow::{bail, ensure};
+use std::io::Write;
+use webbundle::Result;
+
+/// The number of bytes shown for byte strings, such as response bodies.
+const PREVIEW_LEN: usize = 32;
+
+/// The maximum depth of printed items, so that crafted sections can't
+/// overflow the stack. Bundles themselves nest only a few levels.
+const MAX_DEPTH: usize = 64;
+
+struct Dumper<'a, W> {
+    bytes: &'a [u8],
+    /// The offset of `bytes` in the bundle, for nested CBOR in byte strings.
+    base: usize,
+    position: usize,
+    write: W,
+}
+
+enum Item<'a> {
+    Unsigned(u64),
+    Negative(u64),
+    Bytes(&'a [u8]),
+    Text(&'a str),
+    Array(u64),
+    Map(u64),
+    Tag(u64),
+    Simple(u64),
+}
+
+impl<'a, W: Write> Dumper<'a, W> {
+    fn new(bytes: &'a [u8], base: usize, write: W) -> Self {
+        Dumper {
+            bytes,
+            base,
+            position: 0,
+            write,
+        }
+    }
+
+    fn take(&mut self, len: u64) -> Result<&'a [u8]> {
+        let rest = &self.bytes[self.position..];
+        ensure!(
+            len <= rest.len() as u64,
+            "Unexpected end at {}",
+            self.base + self.position
+        );
+        self.position += len as usize;
+        Ok(&rest[..len as usize])
+    }
+
+    fn head(&mut self) -> Result<Item<'a>> {
+        let initial = self.take(1)?[0];
+        let argument = match initial & 0x1f {
+            info @ 0..=23 => info as u64,
+            info @ 24..=27 => self
+                .take(1 << (info - 24))?
+                .iter()
+                .fold(0, |value, &b| value << 8 | b as u64),
+            _ => bail!(
+                "Unsupported initial byte {initial:#04x} at {}",
+                self.base + self.position - 1
+            ),
+        };
+        Ok(match initial >> 5 {
+            0 => Item::Unsigned(argument),
+            1 => Item::Negative(argument),
+            2 => Item::Bytes(self.take(argument)?),
+            3 => Item::Text(std::str::from_utf8(self.take(argument)?)?),
+            4 => Item::Array(argument),
+            5 => Item::Map(argument),
+            6 => Item::Tag(argument),
+            _ => Item::Simple(argument),
+        })
+    }
+
+    fn line(&mut self, offset: usize, depth: usize, text: &str) -> Result<()> {
+        writeln!(
+            self.write,
+            "{:08x}  {:indent$}{text}",
+            self.base + offset,
+            "",
+            indent = depth * 2
+        )?;
+        Ok(())
+    }
+
+    /// Prints an item and its nested items. `label` describes the item,
+    /// e.g. the name of the section.
+    fn item(&mut self, depth: usize, label: &str) -> Result<()> {
+        let offset = self.position;
+        ensure!(
+            depth <= MAX_DEPTH,
+            "Too deeply nested at {}",
+            self.base + offset
+        );
+        let text = match self.head()? {
+            Item::Unsigned(value) => format!("unsigned({value})"),
+            Item::Negative(value) => format!("negative(-1-{value})"),
+            Item::Bytes(bytes) => format!("bytes({}) {}", bytes.len(), preview(bytes)),
+            Item::Text(text) => format!("text({}) {text:?}", text.len()),
+            Item::Array(len) => {
+                self.line(offset, depth, &format!("{label}array({len})"))?;
+                for i in 0..len {
+                    self.item(depth + 1, &format!("[{i}] "))?;
+                }
+                return Ok(());
+            }
+            Item::Map(len) => {
+                self.line(offset, depth, &format!("{label}map({len})"))?;
+                for _ in 0..len {
+                    self.item(depth + 1, "key: ")?;
+                    self.item(depth + 1, "value: ")?;
+                }
+                return Ok(());
+            }
+            Item::Tag(tag) => {
+                self.line(offset, depth, &format!("{label}tag({tag})"))?;
+                return self.item(depth + 1, "");
+            }
+            Item::Simple(value) => match value {
+                20 => "false".to_string(),
+                21 => "true".to_string(),
+                22 => "null".to_string(),
+                _ => format!("simple({value})"),
+            },
+        };
+        self.line(offset, depth, &format!("{label}{text}"))
+    }
+
+    /// Prints a byte string which contains a CBOR item, and returns the
+    /// contents.
+    fn nested(&mut self, depth: usize, label: &str) -> Result<&'a [u8]> {
+        let offset = self.position;
+        let bytes = match self.head()? {
+            Item::Bytes(bytes) => bytes,
+            _ => bail!("Expected a byte string at {}", self.base + offset),
+        };
+        self.line(
+            offset,
+            depth,
+            &format!("{label}bytes({}), which contain CBOR:", bytes.len()),
+        )?;
+        let base = self.base + self.position - bytes.len();
+        let mut nested = Dumper::new(bytes, base, &mut self.write);
+        nested.item(depth + 1, "")?;
+        Ok(bytes)
+    }
+
+    /// Returns the section names in `sectionLengths`, without printing.
+    fn section_names(bytes: &[u8]) -> Result<Vec<String>> {
+        let mut reader = Dumper::new(bytes, 0, std::io::sink());
+        let len = match reader.head()? {
+            Item::Array(len) => len,
+            _ => bail!("Invalid sectionLengths"),
+        };
+        let mut names = Vec::new();
+        for _ in 0..len / 2 {
+            match (reader.head()?, reader.head()?) {
+                (Item::Text(name), Item::Unsigned(_)) => names.push(name.to_string()),
+                _ => bail!("Invalid sectionLengths"),
+            }
+        }
+        Ok(names)
+    }
+
+    fn bundle(&mut self) -> Result<()> {
+        let len = match self.head()? {
+            Item::Array(len) => len,
+            _ => bail!("A bundle must start with an array"),
+        };
+        self.line(0, 0, &format!("bundle: array({len})"))?;
+        self.item(1, "[0] magic: ")?;
+        self.item(1, "[1] version: ")?;
+
+        let section_lengths_offset = self.position;
+        self.nested(1, "[2] sectionLengths: ")?;
+        let mut section_lengths =
+            Dumper::new(&self.bytes[section_lengths_offset..], 0, std::io::sink());
+        let names = match section_lengths.head()? {
+            Item::Bytes(bytes) => Self::section_names(bytes)?,
+            _ => unreachable!(),
+        };
+
+        let offset = self.position;
+        let sections_len = match self.head()? {
+            Item::Array(len) => len,
+            _ => bail!("Expected the sections array at {}", self.base + offset),
+        };
+        self.line(offset, 1, &format!("[3] sections: array({sections_len})"))?;
+        for (i, name) in names.iter().enumerate() {
+            let start = self.position;
+            if name == "responses" {
+                let offset = self.position;
+                let len = match self.head()? {
+                    Item::Array(len) => len,
+                    _ => bail!("Expected the responses array at {}", self.base + offset),
+                };
+                self.line(
+                    offset,
+                    2,
+                    &format!("[{i}] section \"responses\": array({len})"),
+                )?;
+                for j in 0..len {
+                    let offset = self.position;
+                    ensure!(
+                        matches!(self.head()?, Item::Array(2)),
+                        "Invalid response at {}",
+                        self.base + offset
+                    );
+                    self.line(offset, 3, &format!("[{j}] response: array(2)"))?;
+                    self.nested(4, "headers: ")?;
+                    self.item(4, "body: ")?;
+                }
+            } else {
+                self.item(2, &format!("[{i}] section {name:?}: "))?;
+            }
+            writeln!(
+                self.write,
+                "          -- end of section {name:?}: {:#x}..{:#x} ({} bytes)",
+                self.base + start,
+                self.base + self.position,
+                self.position - start
+            )?;
+        }
+
+        // The trailing length is written as 8 bytes, with or without the head
+        // of a byte string.
+        let offset = self.position;
+        let rest = &self.bytes[offset..];
+        let length = match rest {
+            [0x48, length @ ..] | length if length.len() == 8 => length,
+            _ => bail!("Invalid trailing length at {}", self.base + offset),
+        };
+        self.position = self.bytes.len();
+        self.line(
+            offset,
+            1,
+            &format!(
+                "[4] length: {}",
+                u64::from_be_bytes(length.try_into().unwrap())
+            ),
+        )
+    }
+}
+
+fn preview(bytes: &[u8]) -> String {
+    let shown = &bytes[..bytes.len().min(PREVIEW_LEN)];
+    let ellipsis = if shown.len() < bytes.len() { "..." } else { "" };
+    match std::str::from_utf8(shown) {
+        Ok(text)
+            if !text
+                .chars()
+                .any(|c| c.is_control() && !c.is_ascii_whitespace()) =>
+        {
+            format!("{text:?}{ellipsis}")
+        }
+        _ => {
+            let hex = shown.iter().map(|b| format!("{b:02x}")).collect::<String>();
+            format!("h'{hex}'{ellipsis}")
+        }
+    }
+}
+
+/// Prints the CBOR structure of the bundle.
+pub(crate) fn dump(bytes: &[u8], write: impl Write) -> Result<()> {
+    Dumper::new(bytes, 0, write).bundle()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use webbundle::{Bundle, Exchange, Version};
+
+    #[test]
+    fn dump_bundle() -> Result<()> {
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .primary_url("https://example.com/".parse()?)
+            .exchange(Exchange::from((
+                "https://example.com/".to_string(),
+                b"hello".to_vec(),
+            )))
+            .build()?;
+        let bytes = bundle.encode()?;
+        let mut out = Vec::new();
+        dump(&bytes, &mut out)?;
+        let out = String::from_utf8(out)?;
+        assert!(out
+            .starts_with("00000000  bundle: array(5)\n00000001    [0] magic: bytes(8) \"🌐📦\"\n"));
+        assert!(out.contains("section \"primary\": text(20) \"https://example.com/\""));
+        assert!(out.contains("body: bytes(5) \"hello\""));
+        assert!(out.contains("key: bytes(7) \":status\""));
+        assert!(out.ends_with(&format!("[4] length: {}\n", bytes.len())));
+        Ok(())
+    }
+
+    #[test]
+    fn dump_deeply_nested() -> Result<()> {
+        // [[[...[]...]]]
+        let mut nested = vec![0x81; MAX_DEPTH];
+        nested.push(0x80);
+        Dumper::new(&nested, 0, std::io::sink()).item(0, "")?;
+        let err = Dumper::new(&vec![0x81; 1_000_000], 0, std::io::sink())
+            .item(0, "")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("Too deeply nested at {}", MAX_DEPTH + 1)
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,493 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::output;
+use anyhow::{ensure, Context as _};
+use serde_json::json;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Component, Path, PathBuf};
+use url::Url;
+use webbundle::{Bundle, Result};
+
+/// What to do when a file to be extracted already exists.
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub(crate) enum OnConflict {
+    Overwrite,
+    Skip,
+    /// Appends a number to the file name, e.g. `index.html.1`.
+    Rename,
+}
+
+/// The result of [`extract`].
+#[derive(Debug, Default)]
+pub(crate) struct Extracted {
+    /// The urls and the paths of the extracted files.
+    pub files: Vec<(String, PathBuf)>,
+    /// The urls which are not extracted, e.g. redirects.
+    pub skipped: Vec<String>,
+}
+
+/// The name of the file written by [`write_headers_manifest`] in the output
+/// directory. Extracted files don't conflict with it because they are under
+/// `<scheme>` directories, as [`url_to_path`] escapes `.` and `..` hosts.
+pub(crate) const HEADERS_MANIFEST: &str = "headers.json";
+
+fn make_url_path_relative(path: impl AsRef<Path>) -> PathBuf {
+    path.as_ref()
+        .components()
+        .fold(PathBuf::new(), |mut result, p| match p {
+            Component::Normal(x) => {
+                result.push(x);
+                result
+            }
+            Component::ParentDir => {
+                log::warn!("path contains: {}", path.as_ref().display());
+                result.pop();
+                result
+            }
+            _ => result,
+        })
+}
+
+fn url_to_path(url: &str) -> Result<PathBuf> {
+    let url = "https://example.com/".parse::<Url>().unwrap().join(url)?;
+
+    let mut path = PathBuf::new();
+    path.push(url.scheme());
+    if let Some(host) = url.host_str() {
+        // e.g. `https://../a`, which would be `a` in the output directory.
+        match host {
+            "." => path.push("%2E"),
+            ".." => path.push("%2E%2E"),
+            host => path.push(host),
+        }
+    }
+    if let Some(port) = url.port() {
+        path.push(port.to_string());
+    }
+    let relative = make_url_path_relative(url.path());
+    // We push `relative` here even if it is empty.
+    // That makes sure path ends with "/".
+    path.push(relative);
+    // TODO: Push query
+    Ok(path)
+}
+
+fn is_directory(url: &str) -> Result<bool> {
+    let url = "https://example.com/".parse::<Url>().unwrap().join(url)?;
+    Ok(url.path().ends_with('/'))
+}
+
+/// Extracts the successful responses of the bundle into `output`
+/// directory.
+///
+/// URLs are mapped to paths as `<scheme>/<host>/<path>`. A URL which ends
+/// with `/` is extracted to `index.html` in the directory. If a URL needs a
+/// file where a directory exists, or vice versa, e.g. for `/a` and `/a/b`,
+/// the file is extracted to `index.html` in the directory.
+///
+/// The paths are resolved before any file is written, so files extracted by
+/// this call are never moved. A file which was in `output` before, and is in
+/// the way of a directory, is handled by `on_conflict`, as
+/// [`create_dir_all`] describes.
+pub(crate) fn extract(
+    bundle: &Bundle,
+    output: &Path,
+    on_conflict: OnConflict,
+) -> Result<Extracted> {
+    let mut extracted = Extracted::default();
+    let mut files = Vec::new();
+    for exchange in bundle.exchanges() {
+        let url = exchange.request.url();
+        if !exchange.response.status().is_success() {
+            log::info!("Skipping: {:?}", url);
+            extracted.skipped.push(url.clone());
+            continue;
+        }
+        let relative = url_to_path(url)?;
+        ensure!(
+            relative.is_relative(),
+            format!("path shoould be relative: {}", relative.display())
+        );
+        let relative = if is_directory(url)? {
+            relative.join("index.html")
+        } else {
+            relative
+        };
+        files.push((exchange, relative));
+    }
+    let dirs = files
+        .iter()
+        .flat_map(|(_, relative)| relative.ancestors().skip(1))
+        .collect::<HashSet<_>>();
+    let files = files
+        .iter()
+        .map(
+            |(exchange, relative)| match dirs.contains(relative.as_path()) {
+                true => (exchange, relative.join("index.html")),
+                false => (exchange, relative.clone()),
+            },
+        )
+        .collect::<Vec<_>>();
+
+    for (exchange, relative) in files {
+        let url = exchange.request.url();
+        let parent = relative.parent().context("weired url")?;
+        let file_name = relative.file_name().context("weired url")?;
+        let dir = match create_dir_all(output, parent, on_conflict)? {
+            Some(dir) => dir,
+            None => {
+                log::info!("Skipping: a file is in the way of {}", url);
+                extracted.skipped.push(url.clone());
+                continue;
+            }
+        };
+
+        let mut path = dir.join(file_name);
+        if path.is_dir() {
+            path.push("index.html");
+        }
+        if path.exists() {
+            match on_conflict {
+                OnConflict::Overwrite => {}
+                OnConflict::Skip => {
+                    log::info!("Skipping: {} already exists", path.display());
+                    extracted.skipped.push(url.clone());
+                    continue;
+                }
+                OnConflict::Rename => path = renamed(&path),
+            }
+        }
+        log::info!("extract: {} => {}", url, path.display());
+        let mut write = BufWriter::new(File::create(&path)?);
+        write.write_all(exchange.response.body())?;
+        write.flush()?;
+        extracted.files.push((url.clone(), path));
+    }
+    Ok(extracted)
+}
+
+/// Writes the status and the headers of every exchange, including ones which
+/// are not extracted, to [`HEADERS_MANIFEST`] in `output` directory, e.g.
+///
+/// ```json
+/// {
+///   "version": "b2",
+///   "primary_url": "https://example.com/",
+///   "exchanges": [
+///     {
+///       "url": "https://example.com/",
+///       "path": "https/example.com/index.html",
+///       "status": 200,
+///       "headers": [["content-type", "text/html"]]
+///     }
+///   ]
+/// }
+/// ```
+///
+/// Exchanges are in the same form as `list --format json`. `path` is relative
+/// to `output`, and is `null` if the exchange is not extracted.
+pub(crate) fn write_headers_manifest(
+    bundle: &Bundle,
+    output: &Path,
+    extracted: &Extracted,
+) -> Result<()> {
+    let exchanges = bundle
+        .exchanges()
+        .iter()
+        .map(|exchange| {
+            let url = exchange.request.url();
+            let path = extracted
+                .files
+                .iter()
+                .find(|(extracted_url, _)| extracted_url == url)
+                .and_then(|(_, path)| path.strip_prefix(output).ok());
+            let mut value = output::exchange_json(exchange);
+            value["path"] = json!(path);
+            value
+        })
+        .collect::<Vec<_>>();
+    let manifest = output::bundle_json(bundle, exchanges);
+    let mut write = BufWriter::new(File::create(output.join(HEADERS_MANIFEST))?);
+    serde_json::to_writer_pretty(&mut write, &manifest)?;
+    writeln!(write)?;
+    write.flush()?;
+    Ok(())
+}
+
+/// Creates `output/relative` directory, and returns its path. A file in the
+/// way, e.g. `a` for `a/b`, can only be one which was in `output` before
+/// [`extract`]. With [`OnConflict::Overwrite`], the file is moved to
+/// `a/index.html`. With [`OnConflict::Rename`], a numbered directory, e.g.
+/// `a.1/b`, is used instead. With [`OnConflict::Skip`], returns `None`.
+fn create_dir_all(
+    output: &Path,
+    relative: &Path,
+    on_conflict: OnConflict,
+) -> Result<Option<PathBuf>> {
+    let mut dir = output.to_path_buf();
+    for component in relative.components() {
+        dir.push(component);
+        if dir.is_file() {
+            match on_conflict {
+                OnConflict::Overwrite => {
+                    let temp = dir.with_file_name(format!(
+                        "{}.webbundle-tmp",
+                        dir.file_name().context("weired path")?.to_string_lossy()
+                    ));
+                    std::fs::rename(&dir, &temp)?;
+                    std::fs::create_dir(&dir)?;
+                    std::fs::rename(&temp, dir.join("index.html"))?;
+                }
+                OnConflict::Skip => return Ok(None),
+                OnConflict::Rename => {
+                    let renamed = numbered(&dir).find(|path| !path.is_file()).unwrap();
+                    dir = renamed;
+                }
+            }
+        }
+    }
+    std::fs::create_dir_all(&dir)?;
+    Ok(Some(dir))
+}
+
+/// Returns the paths with a number appended, e.g. `a.1`, `a.2`, and so on.
+fn numbered(path: &Path) -> impl Iterator<Item = PathBuf> + '_ {
+    (1..).map(|n| {
+        let mut name = path.file_name().unwrap_or_default().to_os_string();
+        name.push(format!(".{n}"));
+        path.with_file_name(name)
+    })
+}
+
+fn renamed(path: &Path) -> PathBuf {
+    numbered(path).find(|path| !path.exists()).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use webbundle::{Exchange, Version};
+
+    #[test]
+    fn url_to_path_test() -> Result<()> {
+        assert_eq!(
+            url_to_path("https://example.com")?,
+            Path::new("https/example.com/")
+        );
+        assert_eq!(
+            url_to_path("https://example.com/index.html")?,
+            Path::new("https/example.com/index.html")
+        );
+        assert_eq!(
+            url_to_path("https://example.com/a/")?,
+            Path::new("https/example.com/a/")
+        );
+        assert_eq!(
+            url_to_path("https://example.com/a/b")?,
+            Path::new("https/example.com/a/b")
+        );
+        assert_eq!(
+            url_to_path("https://example.com/a/b/")?,
+            Path::new("https/example.com/a/b/")
+        );
+        assert_eq!(url_to_path("")?, Path::new("https/example.com/"));
+        assert_eq!(url_to_path(".")?, Path::new("https/example.com/"));
+        assert_eq!(url_to_path("/a")?, Path::new("https/example.com/a"));
+        assert_eq!(url_to_path("..")?, Path::new("https/example.com/"));
+        assert_eq!(url_to_path("a/../../b")?, Path::new("https/example.com/b"));
+        assert_eq!(
+            url_to_path("https://../headers.json")?,
+            Path::new("https/%2E%2E/headers.json")
+        );
+        assert_eq!(url_to_path("https://./a")?, Path::new("https/%2E/a"));
+        Ok(())
+    }
+
+    fn bundle(urls: &[&str]) -> Result<Bundle> {
+        let mut builder = Bundle::builder().version(Version::VersionB2);
+        for url in urls {
+            builder = builder.exchange(Exchange::from((url.to_string(), url.as_bytes().to_vec())));
+        }
+        builder.build()
+    }
+
+    fn read(dir: &Path, path: &str) -> String {
+        std::fs::read_to_string(dir.join(path)).unwrap_or_default()
+    }
+
+    #[test]
+    fn extract_file_and_dir() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let urls = [
+            "https://example.com/a",
+            "https://example.com/a/b",
+            "https://example.com/c/d",
+            "https://example.com/c",
+            "https://example.com/",
+        ];
+        let extracted = extract(&bundle(&urls)?, dir.path(), OnConflict::Overwrite)?;
+        assert_eq!(read(dir.path(), "https/example.com/a/index.html"), urls[0]);
+        assert_eq!(read(dir.path(), "https/example.com/a/b"), urls[1]);
+        assert_eq!(read(dir.path(), "https/example.com/c/d"), urls[2]);
+        assert_eq!(read(dir.path(), "https/example.com/c/index.html"), urls[3]);
+        assert_eq!(read(dir.path(), "https/example.com/index.html"), urls[4]);
+        // The paths of `a` and `c` are the files, not the directories.
+        assert_eq!(
+            extracted
+                .files
+                .iter()
+                .map(|(url, path)| (url.as_str(), path.strip_prefix(dir.path()).unwrap()))
+                .collect::<Vec<_>>(),
+            [
+                (urls[0], Path::new("https/example.com/a/index.html")),
+                (urls[1], Path::new("https/example.com/a/b")),
+                (urls[2], Path::new("https/example.com/c/d")),
+                (urls[3], Path::new("https/example.com/c/index.html")),
+                (urls[4], Path::new("https/example.com/index.html")),
+            ]
+        );
+        assert!(extracted.files.iter().all(|(_, path)| path.is_file()));
+        Ok(())
+    }
+
+    #[test]
+    fn extract_with_existing_file_in_the_way() -> Result<()> {
+        let bundle = bundle(&["https://example.com/a/b"])?;
+        let existing = |dir: &Path| -> Result<()> {
+            std::fs::create_dir_all(dir.join("https/example.com"))?;
+            std::fs::write(dir.join("https/example.com/a"), "old")?;
+            Ok(())
+        };
+
+        let dir = tempfile::tempdir()?;
+        existing(dir.path())?;
+        let extracted = extract(&bundle, dir.path(), OnConflict::Skip)?;
+        assert!(extracted.files.is_empty());
+        assert_eq!(extracted.skipped, ["https://example.com/a/b"]);
+        assert_eq!(read(dir.path(), "https/example.com/a"), "old");
+
+        let dir = tempfile::tempdir()?;
+        existing(dir.path())?;
+        let extracted = extract(&bundle, dir.path(), OnConflict::Rename)?;
+        assert_eq!(
+            extracted.files[0].1,
+            dir.path().join("https/example.com/a.1/b")
+        );
+        assert_eq!(read(dir.path(), "https/example.com/a"), "old");
+        assert_eq!(
+            read(dir.path(), "https/example.com/a.1/b"),
+            "https://example.com/a/b"
+        );
+
+        let dir = tempfile::tempdir()?;
+        existing(dir.path())?;
+        let extracted = extract(&bundle, dir.path(), OnConflict::Overwrite)?;
+        assert_eq!(
+            extracted.files[0].1,
+            dir.path().join("https/example.com/a/b")
+        );
+        assert_eq!(read(dir.path(), "https/example.com/a/index.html"), "old");
+        Ok(())
+    }
+
+    #[test]
+    fn extract_on_conflict() -> Result<()> {
+        // Both are extracted to `a/index.html`.
+        let bundle = bundle(&["https://example.com/a/", "https://example.com/a"])?;
+
+        let dir = tempfile::tempdir()?;
+        extract(&bundle, dir.path(), OnConflict::Overwrite)?;
+        assert_eq!(
+            read(dir.path(), "https/example.com/a/index.html"),
+            "https://example.com/a"
+        );
+
+        let dir = tempfile::tempdir()?;
+        let extracted = extract(&bundle, dir.path(), OnConflict::Skip)?;
+        assert_eq!(
+            read(dir.path(), "https/example.com/a/index.html"),
+            "https://example.com/a/"
+        );
+        assert_eq!(extracted.files.len(), 1);
+        assert_eq!(extracted.skipped, ["https://example.com/a"]);
+
+        let dir = tempfile::tempdir()?;
+        extract(&bundle, dir.path(), OnConflict::Rename)?;
+        assert_eq!(
+            read(dir.path(), "https/example.com/a/index.html"),
+            "https://example.com/a/"
+        );
+        assert_eq!(
+            read(dir.path(), "https/example.com/a/index.html.1"),
+            "https://example.com/a"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn extract_dot_host() -> Result<()> {
+        let url = "https://../headers.json";
+        let dir = tempfile::tempdir()?;
+        let extracted = extract(&bundle(&[url])?, dir.path(), OnConflict::Overwrite)?;
+        write_headers_manifest(&bundle(&[url])?, dir.path(), &extracted)?;
+        assert_eq!(read(dir.path(), "https/%2E%2E/headers.json"), url);
+        assert!(read(dir.path(), HEADERS_MANIFEST).starts_with('{'));
+        Ok(())
+    }
+
+    #[test]
+    fn write_headers_manifest_test() -> Result<()> {
+        let mut redirect = Exchange::from(("https://example.com/a".to_string(), Vec::new()));
+        *redirect.response.status_mut() = http::StatusCode::FOUND;
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchange(Exchange::from((
+                "https://example.com/a.txt".to_string(),
+                b"a".to_vec(),
+            )))
+            .exchange(redirect)
+            .build()?;
+        let dir = tempfile::tempdir()?;
+        let extracted = extract(&bundle, dir.path(), OnConflict::Overwrite)?;
+        write_headers_manifest(&bundle, dir.path(), &extracted)?;
+
+        let manifest: serde_json::Value =
+            serde_json::from_str(&read(dir.path(), HEADERS_MANIFEST))?;
+        assert_eq!(
+            manifest,
+            json!({
+                "version": "b2",
+                "primary_url": null,
+                "exchanges": [
+                    {
+                        "url": "https://example.com/a.txt",
+                        "path": "https/example.com/a.txt",
+                        "status": 200,
+                        "headers": [["content-length", "1"], ["content-type", "text/plain"]],
+                    },
+                    {
+                        "url": "https://example.com/a",
+                        "path": null,
+                        "status": 302,
+                        "headers": [["content-length", "0"], ["content-type", "application/octet-stream"]],
+                    },
+                ],
+            })
+        );
+        Ok(())
+    }
+}
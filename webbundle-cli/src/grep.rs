@@ -0,0 +1,99 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Searches response bodies, like `grep`.
+
+use regex::Regex;
+use std::io::Write;
+use webbundle::{decoded_body, glob_match, Bundle, Result};
+
+/// Writes the lines which match the pattern, as `<url>:<line number>:<line>`,
+/// and returns the number of the matched lines. Bodies which are not UTF-8,
+/// e.g. images, are skipped.
+pub(crate) fn grep(
+    bundle: &Bundle,
+    pattern: &Regex,
+    url_glob: Option<&str>,
+    mut write: impl Write,
+) -> Result<usize> {
+    let mut count = 0;
+    for exchange in bundle.exchanges() {
+        let url = exchange.request.url();
+        if url_glob.is_some_and(|url_glob| !glob_match(url_glob, url)) {
+            continue;
+        }
+        let body = match decoded_body(&exchange.response) {
+            Ok(body) => body,
+            Err(err) => {
+                log::warn!("Skipping {url}: {err}");
+                continue;
+            }
+        };
+        let text = match std::str::from_utf8(&body) {
+            Ok(text) => text,
+            Err(_) => {
+                log::debug!("Skipping {url}: not UTF-8");
+                continue;
+            }
+        };
+        for (i, line) in text.lines().enumerate() {
+            if pattern.is_match(line) {
+                writeln!(write, "{url}:{}:{line}", i + 1)?;
+                count += 1;
+            }
+        }
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::header::{HeaderValue, CONTENT_ENCODING};
+    use webbundle::{Exchange, Version};
+
+    #[test]
+    fn grep_bodies() -> Result<()> {
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(b"let a = 1;\nfetch('/api');\n")?;
+        let mut compressed = Exchange::from(("b.js".to_string(), gzip.finish()?));
+        compressed
+            .response
+            .headers_mut()
+            .insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchange(Exchange::from((
+                "a.txt".to_string(),
+                b"fetch me\nnothing".to_vec(),
+            )))
+            .exchange(compressed)
+            .exchange(Exchange::from(("c.png".to_string(), vec![0xff, 0xfe])))
+            .build()?;
+
+        let mut out = Vec::new();
+        assert_eq!(grep(&bundle, &Regex::new("fetch")?, None, &mut out)?, 2);
+        assert_eq!(
+            String::from_utf8(out)?,
+            "a.txt:1:fetch me\nb.js:2:fetch('/api');\n"
+        );
+
+        let mut out = Vec::new();
+        assert_eq!(
+            grep(&bundle, &Regex::new("fetch")?, Some("*.js"), &mut out)?,
+            1
+        );
+        Ok(())
+    }
+}
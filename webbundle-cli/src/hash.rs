@@ -0,0 +1,93 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Content hashes of bundles, e.g. to track release artifacts.
+
+use ring::digest::{digest, SHA256};
+use std::io::Write;
+use webbundle::{Bundle, Result};
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Returns the SHA-256 of the canonicalized bundle, which is encoded with
+/// the exchanges sorted by url. The hash doesn't depend on the order of the
+/// exchanges, or on how the original bundle was encoded.
+pub(crate) fn bundle_hash(bundle: &mut Bundle) -> Result<String> {
+    bundle
+        .exchanges_mut()
+        .sort_by(|a, b| a.request.url().cmp(b.request.url()));
+    Ok(hex(digest(&SHA256, &bundle.encode()?).as_ref()))
+}
+
+/// Writes the hash of the bundle, and the SHA-256 of each response body if
+/// `per_resource` is true, in the format of `sha256sum`.
+pub(crate) fn write_hashes(
+    bundle: &mut Bundle,
+    name: &str,
+    per_resource: bool,
+    mut write: impl Write,
+) -> Result<()> {
+    writeln!(write, "{}  {name}", bundle_hash(bundle)?)?;
+    if per_resource {
+        for exchange in bundle.exchanges() {
+            writeln!(
+                write,
+                "{}  {}",
+                hex(digest(&SHA256, exchange.response.body()).as_ref()),
+                exchange.request.url()
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use webbundle::{Exchange, Version};
+
+    fn bundle(urls: &[&str]) -> Result<Bundle> {
+        let mut builder = Bundle::builder().version(Version::VersionB2);
+        for url in urls {
+            builder = builder.exchange(Exchange::from((url.to_string(), b"hello".to_vec())));
+        }
+        builder.build()
+    }
+
+    #[test]
+    fn bundle_hash_is_stable() -> Result<()> {
+        let hash = bundle_hash(&mut bundle(&["a.txt", "b.txt"])?)?;
+        assert_eq!(hash, bundle_hash(&mut bundle(&["b.txt", "a.txt"])?)?);
+        assert_ne!(hash, bundle_hash(&mut bundle(&["a.txt", "c.txt"])?)?);
+        Ok(())
+    }
+
+    #[test]
+    fn write_hashes_per_resource() -> Result<()> {
+        let mut out = Vec::new();
+        write_hashes(&mut bundle(&["a.txt"])?, "a.wbn", true, &mut out)?;
+        let out = String::from_utf8(out)?;
+        let lines = out.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with("  a.wbn"));
+        // echo -n hello | sha256sum
+        assert_eq!(
+            lines[1],
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824  a.txt"
+        );
+        Ok(())
+    }
+}
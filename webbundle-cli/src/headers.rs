@@ -0,0 +1,155 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Response headers for exchanges, matched by url globs.
+
+use anyhow::Context as _;
+use http::header::{HeaderName, HeaderValue};
+use std::collections::BTreeMap;
+use webbundle::{glob_match, Bundle, Result};
+
+/// Parses a header, e.g. `cache-control: immutable`.
+pub(crate) fn parse_header(header: &str) -> Result<(HeaderName, HeaderValue)> {
+    let (name, value) = header
+        .split_once(':')
+        .with_context(|| format!("Invalid header: {header}"))?;
+    Ok((
+        HeaderName::from_bytes(name.trim().as_bytes())?,
+        HeaderValue::from_str(value.trim())?,
+    ))
+}
+
+/// Sets the headers of the exchanges whose urls match the glob, replacing
+/// the existing values. Returns the number of the matched exchanges.
+pub(crate) fn set_headers(
+    bundle: &mut Bundle,
+    url_glob: &str,
+    headers: &[(HeaderName, HeaderValue)],
+) -> usize {
+    let mut count = 0;
+    for exchange in bundle.exchanges_mut() {
+        if !glob_match(url_glob, exchange.request.url()) {
+            continue;
+        }
+        for (name, value) in headers {
+            exchange
+                .response
+                .headers_mut()
+                .insert(name.clone(), value.clone());
+        }
+        count += 1;
+    }
+    count
+}
+
+/// Maps url globs to response headers, e.g.
+///
+/// ```toml
+/// ["*"]
+/// cross-origin-opener-policy = "same-origin"
+///
+/// ["*.js"]
+/// cache-control = "max-age=3600"
+/// ```
+///
+/// If several globs match a url and set the same header, the longest glob
+/// wins.
+pub(crate) struct HeadersConfig {
+    rules: Vec<(String, Vec<(HeaderName, HeaderValue)>)>,
+}
+
+impl HeadersConfig {
+    /// Reads the config from a JSON file if its extension is `.json`, or
+    /// from a TOML file otherwise.
+    pub(crate) fn from_file(path: &str) -> Result<Self> {
+        let text =
+            std::fs::read_to_string(path).with_context(|| format!("Failed to read {path}"))?;
+        let map: BTreeMap<String, BTreeMap<String, String>> = if path.ends_with(".json") {
+            serde_json::from_str(&text)?
+        } else {
+            toml::from_str(&text)?
+        };
+        Self::from_map(map).with_context(|| format!("Invalid headers in {path}"))
+    }
+
+    fn from_map(map: BTreeMap<String, BTreeMap<String, String>>) -> Result<Self> {
+        let mut rules = map
+            .into_iter()
+            .map(|(url_glob, headers)| {
+                let headers = headers
+                    .iter()
+                    .map(|(name, value)| {
+                        Ok((
+                            HeaderName::from_bytes(name.as_bytes())?,
+                            HeaderValue::from_str(value)?,
+                        ))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Ok((url_glob, headers))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        // Longer globs are applied later, so that they win.
+        rules.sort_by_key(|(url_glob, _)| url_glob.len());
+        Ok(HeadersConfig { rules })
+    }
+
+    pub(crate) fn apply(&self, bundle: &mut Bundle) {
+        for (url_glob, headers) in &self.rules {
+            set_headers(bundle, url_glob, headers);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use webbundle::{Exchange, Version};
+
+    #[test]
+    fn parse_header_test() -> Result<()> {
+        let (name, value) = parse_header("Cache-Control:  max-age=3600, immutable")?;
+        assert_eq!(name, "cache-control");
+        assert_eq!(value, "max-age=3600, immutable");
+        assert!(parse_header("cache-control").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn headers_config() -> Result<()> {
+        let map = toml::from_str(
+            r#"
+["*.js"]
+cache-control = "max-age=3600"
+
+["*"]
+cache-control = "no-cache"
+cross-origin-opener-policy = "same-origin"
+"#,
+        )?;
+        let config = HeadersConfig::from_map(map)?;
+        let mut bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchange(Exchange::from(("a.js".to_string(), Vec::new())))
+            .exchange(Exchange::from(("index.html".to_string(), Vec::new())))
+            .build()?;
+        config.apply(&mut bundle);
+
+        let headers = bundle.exchanges()[0].response.headers();
+        assert_eq!(headers["cache-control"], "max-age=3600");
+        assert_eq!(headers["cross-origin-opener-policy"], "same-origin");
+        let headers = bundle.exchanges()[1].response.headers();
+        assert_eq!(headers["cache-control"], "no-cache");
+        Ok(())
+    }
+}
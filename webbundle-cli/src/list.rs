@@ -0,0 +1,230 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::output;
+use serde_json::json;
+use std::io::Write;
+use webbundle::{Bundle, Exchange, Index, Result};
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub(crate) enum Format {
+    /// An aligned table
+    Table,
+    /// One line per exchange
+    Plain,
+    /// One JSON object, as `--format json` of the other subcommands
+    Json,
+    Debug,
+    /// Only the urls, one per line, e.g. for `xargs`
+    #[value(skip)]
+    Urls,
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub(crate) enum Sort {
+    /// By the encoded size, largest first
+    Size,
+    Url,
+    /// By the content type, then by the url
+    Type,
+}
+
+struct Row<'a> {
+    exchange: &'a Exchange,
+    content_type: &'a str,
+    /// The size of the response in the bundle, including the headers.
+    encoded_size: u64,
+}
+
+fn rows<'a>(bundle: &'a Bundle, index: &Index, sort: Option<Sort>) -> Vec<Row<'a>> {
+    let mut rows = bundle
+        .exchanges()
+        .iter()
+        .map(|exchange| Row {
+            exchange,
+            content_type: exchange
+                .response
+                .headers()
+                .get("content-type")
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default(),
+            encoded_size: index
+                .get(exchange.request.url())
+                .map_or(0, |entry| entry.length),
+        })
+        .collect::<Vec<_>>();
+    match sort {
+        None => {}
+        Some(Sort::Size) => rows.sort_by_key(|row| std::cmp::Reverse(row.encoded_size)),
+        Some(Sort::Url) => rows.sort_by_key(|row| row.exchange.request.url()),
+        Some(Sort::Type) => rows.sort_by_key(|row| (row.content_type, row.exchange.request.url())),
+    }
+    rows
+}
+
+pub(crate) fn list(
+    bundle: &Bundle,
+    index: &Index,
+    format: Format,
+    sort: Option<Sort>,
+    mut write: impl Write,
+) -> Result<()> {
+    match format {
+        Format::Table => list_table(bundle, &rows(bundle, index, sort), write)?,
+        Format::Plain => list_plain(bundle, &rows(bundle, index, sort), write)?,
+        Format::Urls => {
+            for row in rows(bundle, index, sort) {
+                writeln!(write, "{}", row.exchange.request.url())?;
+            }
+        }
+        Format::Json => output::write_json(&list_json(bundle, &rows(bundle, index, sort)), write)?,
+        Format::Debug => writeln!(write, "{bundle:#?}")?,
+    }
+    Ok(())
+}
+
+fn list_plain(bundle: &Bundle, rows: &[Row], mut write: impl Write) -> Result<()> {
+    if let Some(primary_url) = bundle.primary_url() {
+        writeln!(write, "primary_url: {primary_url}")?;
+    }
+    for row in rows {
+        let request = &row.exchange.request;
+        let response = &row.exchange.response;
+        writeln!(
+            write,
+            "{} {} {} bytes",
+            request.url(),
+            response.status(),
+            response.body().len()
+        )?;
+        log::debug!("headers: {:?}", response.headers());
+    }
+    Ok(())
+}
+
+/// Returns the exchanges in the same form as the other subcommands, with the
+/// sizes of `list`.
+fn list_json(bundle: &Bundle, rows: &[Row]) -> serde_json::Value {
+    let exchanges = rows
+        .iter()
+        .map(|row| {
+            let mut value = output::exchange_json(row.exchange);
+            value["encoded_bytes"] = json!(row.encoded_size);
+            value["body_bytes"] = json!(row.exchange.response.body().len());
+            value
+        })
+        .collect();
+    output::bundle_json(bundle, exchanges)
+}
+
+fn list_table(bundle: &Bundle, rows: &[Row], mut write: impl Write) -> Result<()> {
+    if let Some(primary_url) = bundle.primary_url() {
+        writeln!(write, "primary_url: {primary_url}")?;
+    }
+    let url_width = rows
+        .iter()
+        .map(|row| row.exchange.request.url().len())
+        .chain(["URL".len()])
+        .max()
+        .unwrap_or_default();
+    let type_width = rows
+        .iter()
+        .map(|row| row.content_type.len())
+        .chain(["TYPE".len()])
+        .max()
+        .unwrap_or_default();
+    writeln!(
+        write,
+        "{:<6} {:<url_width$} {:>6} {:<type_width$} {:>10} {:>10}",
+        "METHOD", "URL", "STATUS", "TYPE", "ENCODED", "BODY"
+    )?;
+    for row in rows {
+        writeln!(
+            write,
+            "{:<6} {:<url_width$} {:>6} {:<type_width$} {:>10} {:>10}",
+            // Bundles have only GET requests.
+            "GET",
+            row.exchange.request.url(),
+            row.exchange.response.status().as_u16(),
+            row.content_type,
+            row.encoded_size,
+            row.exchange.response.body().len()
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use webbundle::Version;
+
+    #[test]
+    fn list_table_sorted() -> Result<()> {
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchange(Exchange::from((
+                "https://example.com/b.js".to_string(),
+                b"a".to_vec(),
+            )))
+            .exchange(Exchange::from((
+                "https://example.com/a.html".to_string(),
+                b"<p>hello, world</p>".to_vec(),
+            )))
+            .build()?;
+        let bytes = bundle.encode()?;
+        let index = Index::from_bytes(&bytes)?;
+
+        let mut out = Vec::new();
+        list(&bundle, &index, Format::Table, Some(Sort::Size), &mut out)?;
+        assert_eq!(
+            String::from_utf8(out)?,
+            "\
+METHOD URL                        STATUS TYPE               ENCODED       BODY
+GET    https://example.com/a.html    200 text/html               77         19
+GET    https://example.com/b.js      200 text/javascript         64          1
+"
+        );
+
+        let mut out = Vec::new();
+        list(&bundle, &index, Format::Plain, Some(Sort::Url), &mut out)?;
+        assert_eq!(
+            String::from_utf8(out)?,
+            "https://example.com/a.html 200 OK 19 bytes\nhttps://example.com/b.js 200 OK 1 bytes\n"
+        );
+
+        let mut out = Vec::new();
+        list(&bundle, &index, Format::Urls, None, &mut out)?;
+        assert_eq!(
+            String::from_utf8(out)?,
+            "https://example.com/b.js\nhttps://example.com/a.html\n"
+        );
+
+        let mut out = Vec::new();
+        list(&bundle, &index, Format::Json, Some(Sort::Url), &mut out)?;
+        let value: serde_json::Value = serde_json::from_slice(&out)?;
+        assert_eq!(value["version"], "b2");
+        assert_eq!(
+            value["exchanges"][1],
+            json!({
+                "url": "https://example.com/b.js",
+                "status": 200,
+                "headers": [["content-length", "1"], ["content-type", "text/javascript"]],
+                "encoded_bytes": 64,
+                "body_bytes": 1,
+            })
+        );
+        Ok(())
+    }
+}
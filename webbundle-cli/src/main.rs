@@ -14,47 +14,326 @@
 
 use anyhow::{ensure, Context as _};
 use chrono::Local;
-use clap::Parser;
-use serde::Serialize;
-use std::fs::File;
-use std::io::{BufWriter, Read as _, Write as _};
-use std::path::{Component, Path, PathBuf};
+use clap::{CommandFactory as _, Parser};
+use indicatif::{HumanBytes, ProgressBar, ProgressStyle};
+use std::collections::HashSet;
+use std::io::{BufWriter, Read as _, Write};
+use std::path::Path;
 use url::Url;
-use webbundle::{Bundle, Result, Version};
+use webbundle::{Bundle, Exchange, Index, Request, Result, Severity, SigningKey, Stats, Version};
+
+mod diff;
+mod dump;
+mod extract;
+mod grep;
+mod hash;
+mod headers;
+mod list;
+mod output;
+mod snippet;
+mod sw;
+mod tree;
 
 #[derive(Parser)]
+#[command(
+    after_help = "Bundle files can be `-` for stdin or stdout, e.g. `curl https://example.com/example.wbn | webbundle list -`"
+)]
 struct Cli {
     #[clap(subcommand)]
     cmd: Command,
 }
 
-#[derive(Parser, Clone, clap::ValueEnum)]
-enum Format {
-    Plain,
-    Json,
-    Debug,
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum TargetVersion {
+    #[value(name = "b2")]
+    B2,
+    #[value(name = "1")]
+    V1,
+    /// Requires a primary url
+    #[value(name = "b1")]
+    B1,
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum Compression {
+    Br,
+    Gzip,
+}
+
+impl From<Compression> for webbundle::Compression {
+    fn from(compression: Compression) -> Self {
+        match compression {
+            Compression::Br => webbundle::Compression::Brotli,
+            Compression::Gzip => webbundle::Compression::Gzip,
+        }
+    }
 }
 
 #[derive(Parser)]
 enum Command {
     /// Example: webbundle create example.wbn foo
     Create {
+        /// Primary URL, which is resolved to the base URL. `auto` uses the URL
+        /// of the directory, which serves the root `index.html`, and requires
+        /// `--base-url`
         #[arg(short = 'p', long)]
         primary_url: Option<String>,
+        /// Base URL, e.g. `https://example.com/app/`, to which the URLs of
+        /// the resources are resolved. The directory is mapped to this URL.
+        #[arg(short = 'b', long)]
+        base_url: Option<String>,
         /// File name
         file: String,
         /// Directory from where resources are read
         resources_dir: String,
+        /// TOML or JSON file which maps url globs to response headers, e.g.
+        /// `["*.js"]` table with `cache-control = "max-age=3600"`
+        #[arg(long = "headers", value_name = "FILE")]
+        headers_file: Option<String>,
+        /// Exclude files which match the glob, e.g. `*.map` or
+        /// `node_modules/**`. Can be repeated
+        #[arg(long, value_name = "GLOB")]
+        exclude: Vec<String>,
+        /// Compress the response bodies, with `Content-Encoding` header
+        #[arg(long, value_enum)]
+        compress: Option<Compression>,
+        /// Content types, e.g. `image/png` or `video/*`, which are not
+        /// compressed. Can be repeated. Defaults to already compressed types,
+        /// e.g. images, videos and fonts
+        #[arg(long, value_name = "CONTENT_TYPE", requires = "compress")]
+        compress_skip: Vec<String>,
+        /// Don't show the progress
+        #[arg(short = 'q', long)]
+        quiet: bool,
+        /// `json` prints a summary of the created bundle
+        #[arg(long, value_enum, default_value = "plain")]
+        format: output::Format,
         // TODO: Support version
     },
     /// List the contents briefly
     List {
         file: String,
+        /// Output format. Defaults to `table`
+        #[arg(long, value_enum)]
+        format: Option<list::Format>,
+        /// Sort the exchanges, e.g. by the encoded size, largest first.
+        /// Exchanges are listed in the order of the bundle by default
         #[arg(long, value_enum)]
-        format: Option<Format>,
+        sort: Option<list::Sort>,
+        /// Print only the urls, one per line
+        #[arg(long, conflicts_with = "format")]
+        urls_only: bool,
     },
     /// Extract the contents
-    Extract { file: String },
+    Extract {
+        file: String,
+        /// Directory where the contents are extracted
+        #[arg(short = 'o', long, default_value = ".")]
+        output: String,
+        /// What to do if a file already exists, or is in the way of a directory
+        #[arg(long, value_enum, default_value = "overwrite")]
+        on_conflict: extract::OnConflict,
+        /// Write the status and the headers of every exchange to
+        /// `headers.json` in the output directory
+        #[arg(long)]
+        with_headers: bool,
+        /// `json` prints the extracted files and the skipped urls
+        #[arg(long, value_enum, default_value = "plain")]
+        format: output::Format,
+    },
+    /// Show the urls as a tree, with the total body size of each directory
+    Tree { file: String },
+    /// Summarize the bundle, e.g. the sizes by content type and the largest
+    /// resources
+    Stats {
+        file: String,
+        #[arg(long, value_enum, default_value = "plain")]
+        format: output::Format,
+    },
+    /// List the added, removed and modified urls. Like `diff`, the exit code
+    /// is 1 if there are differences
+    ///
+    /// Example: webbundle diff old.wbn new.wbn
+    Diff {
+        old: String,
+        new: String,
+        #[arg(long, value_enum, default_value = "plain")]
+        format: output::Format,
+    },
+    /// Add an exchange to the bundle, or replace the exchange for the url
+    ///
+    /// Example: webbundle add example.wbn --url https://example.com/x.js --file x.js
+    Add {
+        /// The bundle, which is rewritten in place
+        file: String,
+        #[arg(long)]
+        url: String,
+        /// The file of the response body. Content-Type is guessed from its
+        /// name
+        #[arg(long = "file", value_name = "FILE")]
+        body_file: String,
+        /// Drop the signatures section, which the changes may invalidate
+        #[arg(long)]
+        strip_signatures: bool,
+    },
+    /// Remove the exchanges for the urls from the bundle
+    ///
+    /// Example: webbundle remove example.wbn --url https://example.com/x.js
+    Remove {
+        /// The bundle, which is rewritten in place
+        file: String,
+        #[arg(long = "url", value_name = "URL", required = true)]
+        urls: Vec<String>,
+        /// Drop the signatures section, which the changes may invalidate
+        #[arg(long)]
+        strip_signatures: bool,
+    },
+    /// Set response headers of the exchanges whose urls match the glob
+    ///
+    /// Example: webbundle set-header example.wbn --url-glob '*.js' --header 'cache-control: immutable'
+    SetHeader {
+        /// The bundle, which is rewritten in place
+        file: String,
+        /// `*` and `?` don't match `/`, and `**` does. A glob without `/`,
+        /// e.g. `*.js`, matches any segment of the url
+        #[arg(long)]
+        url_glob: String,
+        /// A header, e.g. `cache-control: immutable`, which replaces the
+        /// existing values
+        #[arg(long = "header", value_name = "HEADER", required = true)]
+        headers: Vec<String>,
+        /// Drop the signatures section, which the changes may invalidate
+        #[arg(long)]
+        strip_signatures: bool,
+    },
+    /// Fetch the page and its subresources, e.g. scripts and images, and
+    /// bundle them. The page becomes the primary url
+    ///
+    /// Example: webbundle fetch https://example.com/ --depth 1 -o site.wbn
+    Fetch {
+        url: String,
+        /// How many levels of links (`<a href>`) are followed. `0` fetches
+        /// only the page and its subresources
+        #[arg(long, default_value_t = 0)]
+        depth: usize,
+        /// Fetch resources from other origins too
+        #[arg(long)]
+        cross_origin: bool,
+        #[arg(short = 'o', long)]
+        output: String,
+    },
+    /// Re-encode the bundle in another version, keeping the primary url and
+    /// the exchanges. Converting to b1 requires a primary url. Only the
+    /// first variant of each url of b1 is kept, and the signatures section is
+    /// dropped
+    ///
+    /// Example: webbundle convert --to 1 example.wbn -o example-v1.wbn
+    Convert {
+        #[arg(long, value_enum)]
+        to: TargetVersion,
+        file: String,
+        #[arg(short = 'o', long)]
+        output: String,
+    },
+    /// Print the CBOR structure of the bundle with byte offsets and section
+    /// boundaries
+    DumpCbor { file: String },
+    /// Print the lines of the response bodies which match the regex, as
+    /// `<url>:<line number>:<line>`. Compressed bodies are decoded. Like
+    /// `grep`, the exit code is 1 if no lines match
+    ///
+    /// Example: webbundle grep bundle.wbn 'fetch\(' --url-glob '*.js'
+    Grep {
+        file: String,
+        pattern: String,
+        /// Search only the exchanges whose urls match the glob, with the
+        /// syntax of `set-header`
+        #[arg(long)]
+        url_glob: Option<String>,
+    },
+    /// Print a stable hash of the bundle, which doesn't depend on the order
+    /// of the exchanges, in the format of `sha256sum`
+    ///
+    /// Example: webbundle hash bundle.wbn --per-resource
+    Hash {
+        file: String,
+        /// Also print the SHA-256 of each response body
+        #[arg(long)]
+        per_resource: bool,
+    },
+    /// Write the response body for the given url to stdout
+    ///
+    /// Example: webbundle cat example.wbn https://example.com/app.js
+    Cat {
+        file: String,
+        url: String,
+        /// Write the status and the headers before the body
+        #[arg(long)]
+        headers: bool,
+    },
+    /// Check whether browsers can load the bundle, e.g. absolute urls,
+    /// the trailing length and canonical CBOR
+    Validate {
+        file: String,
+        #[arg(long, value_enum, default_value = "plain")]
+        format: output::Format,
+    },
+    /// Sign the bundle with an Ed25519 key, prepending an integrity block.
+    ///
+    /// Example: webbundle sign --key key.pem input.wbn -o output.swbn
+    Sign {
+        /// PEM file of the Ed25519 private key, e.g. generated by
+        /// `openssl genpkey -algorithm ed25519`
+        #[arg(short = 'k', long)]
+        key: String,
+        /// The bundle to be signed
+        file: String,
+        /// The signed bundle
+        #[arg(short = 'o', long)]
+        output: String,
+    },
+    /// Verify the signatures and the integrity block of the signed bundle.
+    ///
+    /// Example: webbundle verify --key pub.pem signed.swbn
+    Verify {
+        /// The signed bundle
+        file: String,
+        /// PEM file of the Ed25519 public key, which must have signed the
+        /// bundle, e.g. generated by `openssl pkey -pubout`
+        #[arg(short = 'k', long)]
+        key: Option<String>,
+    },
+    /// Print `<script type="webbundle">` tag which loads the resources from
+    /// the bundle
+    ///
+    /// Example: webbundle snippet bundle.wbn --source /static/bundle.wbn
+    Snippet {
+        file: String,
+        /// The url of the bundle, from the page
+        #[arg(long)]
+        source: String,
+        /// Use `scopes`, the directories which contain the urls, instead of
+        /// listing all the urls in `resources`
+        #[arg(long)]
+        scopes: bool,
+    },
+    /// Generate a service worker which serves the resources of the bundle,
+    /// for browsers which don't support web bundles
+    ///
+    /// Example: webbundle gen-sw bundle.wbn -o sw.js
+    GenSw {
+        file: String,
+        /// The url of the bundle, relative to the service worker. Defaults
+        /// to the file name of the bundle
+        #[arg(long)]
+        source: Option<String>,
+        #[arg(short = 'o', long)]
+        output: String,
+    },
+    /// Print the shell completion script.
+    ///
+    /// Example: webbundle completions bash > /etc/bash_completion.d/webbundle
+    Completions { shell: clap_complete::Shell },
 }
 
 fn env_logger_init() {
@@ -74,191 +353,240 @@ fn env_logger_init() {
         .init();
 }
 
-fn list(bundle: &Bundle, format: Option<Format>) {
-    match format {
-        None | Some(Format::Plain) => list_plain(bundle),
-        Some(Format::Json) => list_json(bundle),
-        Some(Format::Debug) => list_debug(bundle),
+fn parse_base_url(base_url: &str) -> Result<Url> {
+    let mut base_url = base_url.parse::<Url>()?;
+    // Make sure that the last path segment is treated as a directory.
+    if !base_url.path().ends_with('/') {
+        base_url.set_path(&format!("{}/", base_url.path()));
     }
+    Ok(base_url)
 }
 
-fn list_plain(bundle: &Bundle) {
-    if let Some(primary_url) = bundle.primary_url() {
-        println!("primary_url: {primary_url}");
-    }
-    for exchange in bundle.exchanges() {
-        let request = &exchange.request;
-        let response = &exchange.response;
-        println!(
-            "{} {} {} bytes",
-            request.url(),
-            response.status(),
-            response.body().len()
-        );
-        log::debug!("headers: {:?}", response.headers());
+/// Resolves the relative URLs of exchanges against the given base URL.
+fn apply_base_url(bundle: &mut Bundle, base_url: &Url) -> Result<()> {
+    for exchange in bundle.exchanges_mut() {
+        let url = base_url.join(exchange.request.url())?;
+        exchange.request = Request::new(url.to_string(), exchange.request.headers().clone());
     }
+    Ok(())
 }
 
-fn list_json(bundle: &Bundle) {
-    #[derive(Serialize)]
-    struct Request {
-        uri: String,
-    }
-
-    #[derive(Serialize)]
-    struct Response {
-        status: u16,
-        size: usize,
-        body: String,
-    }
-
-    #[derive(Serialize)]
-    struct Body {
-        body: String,
-    }
+#[test]
+fn apply_base_url_test() -> Result<()> {
+    let mut bundle = Bundle::builder()
+        .version(Version::VersionB2)
+        .exchange(("".to_string(), Vec::new()).into())
+        .exchange(("js/a.js".to_string(), Vec::new()).into())
+        .exchange(("https://example.org/b.js".to_string(), Vec::new()).into())
+        .build()?;
+    apply_base_url(&mut bundle, &parse_base_url("https://example.com/app")?)?;
+    let urls = bundle
+        .exchanges()
+        .iter()
+        .map(|exchange| exchange.request.url().as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        urls,
+        [
+            "https://example.com/app/",
+            "https://example.com/app/js/a.js",
+            "https://example.org/b.js"
+        ]
+    );
+    Ok(())
+}
 
-    #[derive(Serialize)]
-    struct Exchange {
-        request: Request,
-        response: Response,
+fn cat(exchange: &Exchange, headers: bool, mut write: impl Write) -> Result<()> {
+    let response = &exchange.response;
+    if headers {
+        writeln!(write, ":status: {}", response.status().as_u16())?;
+        for (name, value) in response.headers() {
+            write!(write, "{name}: ")?;
+            write.write_all(value.as_bytes())?;
+            writeln!(write)?;
+        }
+        writeln!(write)?;
     }
+    write.write_all(response.body())?;
+    Ok(())
+}
 
-    #[derive(Serialize)]
-    struct Bundle<'a> {
-        version: &'a [u8],
-        primary_url: &'a Option<String>,
-        exchanges: Vec<Exchange>,
-    }
+#[test]
+fn cat_test() -> Result<()> {
+    let exchange = Exchange::from(("https://example.com/a.txt".to_string(), b"hello".to_vec()));
+    let mut out = Vec::new();
+    cat(&exchange, false, &mut out)?;
+    assert_eq!(out, b"hello");
 
-    let bundle = Bundle {
-        version: bundle.version().bytes(),
-        primary_url: &bundle.primary_url().as_ref().map(|uri| uri.to_string()),
-        exchanges: bundle
-            .exchanges()
-            .iter()
-            .map(|exchange| Exchange {
-                request: Request {
-                    uri: exchange.request.url().to_string(),
-                },
-                response: Response {
-                    status: exchange.response.status().as_u16(),
-                    size: exchange.response.body().len(),
-                    body: String::from_utf8_lossy(exchange.response.body()).to_string(),
-                },
-            })
-            .collect(),
-    };
-    println!("{}", serde_json::to_string(&bundle).unwrap());
+    let mut out = Vec::new();
+    cat(&exchange, true, &mut out)?;
+    assert_eq!(
+        String::from_utf8(out)?,
+        ":status: 200\ncontent-length: 5\ncontent-type: text/plain\n\nhello"
+    );
+    Ok(())
 }
 
-fn list_debug(bundle: &Bundle) {
-    println!("{bundle:#?}");
+/// Returns the bundle in the `to` version.
+///
+/// Variants of b1, i.e. responses of the same url, can't be encoded, so only
+/// the first variant of each url is kept, which doesn't have to be
+/// negotiated. The signatures section, if any, is dropped.
+fn convert(bundle: &Bundle, to: TargetVersion) -> Result<Bundle> {
+    if bundle.signatures().is_some() {
+        log::warn!("Dropping the signatures section, which doesn't sign the converted bundle");
+    }
+    let mut builder = Bundle::builder().version(match to {
+        TargetVersion::B2 => Version::VersionB2,
+        TargetVersion::V1 => Version::Version1,
+        TargetVersion::B1 => Version::VersionB1,
+    });
+    let mut urls = HashSet::new();
+    for exchange in bundle.exchanges() {
+        let url = exchange.request.url();
+        if !urls.insert(url) {
+            log::warn!("{url}: Dropping a variant of b1");
+            continue;
+        }
+        let mut exchange = exchange.clone();
+        exchange.response.headers_mut().remove("variants");
+        exchange.response.headers_mut().remove("variant-key");
+        builder = builder.exchange(exchange);
+    }
+    if let Some(primary_url) = bundle.primary_url() {
+        builder = builder.primary_url(primary_url.clone());
+    }
+    builder.build()
 }
 
-fn make_url_path_relative(path: impl AsRef<Path>) -> PathBuf {
-    path.as_ref()
-        .components()
-        .fold(PathBuf::new(), |mut result, p| match p {
-            Component::Normal(x) => {
-                result.push(x);
-                result
-            }
-            Component::ParentDir => {
-                log::warn!("path contains: {}", path.as_ref().display());
-                result.pop();
-                result
-            }
-            _ => result,
+#[test]
+fn convert_test() -> Result<()> {
+    let bundle = Bundle::from_bytes(include_bytes!("../../webbundle/tests/b1/hello.wbn"))?;
+    let converted = Bundle::from_bytes(convert(&bundle, TargetVersion::B2)?.encode()?)?;
+    assert_eq!(converted.version(), &Version::VersionB2);
+    assert_eq!(converted.primary_url(), bundle.primary_url());
+    let exchanges = converted
+        .exchanges()
+        .iter()
+        .map(|exchange| {
+            (
+                exchange.request.url().as_str(),
+                exchange.response.body().as_slice(),
+            )
         })
-}
+        .collect::<Vec<_>>();
+    assert_eq!(
+        exchanges,
+        [
+            ("https://example.com/", &b"<p>Hello</p>"[..]),
+            ("https://example.com/lang", b"Hello"),
+        ]
+    );
+    assert!(!converted.exchanges()[1]
+        .response
+        .headers()
+        .contains_key("variant-key"));
+
+    // And back.
+    let converted = convert(&converted, TargetVersion::V1)?;
+    assert_eq!(converted.version(), &Version::Version1);
+    assert_eq!(converted.exchanges().len(), 2);
 
-fn url_to_path(url: &str) -> Result<PathBuf> {
-    let url = "https://example.com/".parse::<Url>().unwrap().join(url)?;
+    // And back to b1, without the variants.
+    let converted = Bundle::from_bytes(convert(&converted, TargetVersion::B1)?.encode()?)?;
+    assert_eq!(converted.version(), &Version::VersionB1);
+    assert_eq!(converted.primary_url(), bundle.primary_url());
+    assert_eq!(converted.exchanges().len(), 2);
+    Ok(())
+}
 
-    let mut path = PathBuf::new();
-    path.push(url.scheme());
-    if let Some(host) = url.host_str() {
-        path.push(host);
+fn print_stats(
+    stats: &Stats,
+    bundle_bytes: usize,
+    gzip_bytes: usize,
+    mut write: impl Write,
+) -> Result<()> {
+    let percent = |bytes: usize| bytes as f64 * 100.0 / stats.body_bytes.max(1) as f64;
+    writeln!(write, "exchanges:    {}", stats.exchanges)?;
+    writeln!(write, "body bytes:   {}", stats.body_bytes)?;
+    writeln!(write, "bundle bytes: {bundle_bytes}")?;
+    writeln!(write, "gzip bytes:   {gzip_bytes}")?;
+    writeln!(write, "content types:")?;
+    let mut content_types = stats.content_types.iter().collect::<Vec<_>>();
+    content_types.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.body_bytes));
+    for (content_type, content_type_stats) in content_types {
+        let content_type = if content_type.is_empty() {
+            "(none)"
+        } else {
+            content_type
+        };
+        writeln!(
+            write,
+            "  {content_type:<24} {:>6} {:>10} {:>5.1}%",
+            content_type_stats.count,
+            content_type_stats.body_bytes,
+            percent(content_type_stats.body_bytes)
+        )?;
     }
-    if let Some(port) = url.port() {
-        path.push(port.to_string());
+    writeln!(write, "largest:")?;
+    for (url, bytes) in &stats.largest {
+        writeln!(write, "  {bytes:>10} {:>5.1}% {url}", percent(*bytes))?;
     }
-    let relative = make_url_path_relative(url.path());
-    // We push `relative` here even if it is empty.
-    // That makes sure path ends with "/".
-    path.push(relative);
-    // TODO: Push query
-    Ok(path)
+    Ok(())
 }
 
 #[test]
-fn url_to_path_test() -> Result<()> {
-    assert_eq!(
-        url_to_path("https://example.com")?,
-        Path::new("https/example.com/")
-    );
-    assert_eq!(
-        url_to_path("https://example.com/index.html")?,
-        Path::new("https/example.com/index.html")
-    );
+fn print_stats_test() -> Result<()> {
+    let bundle = Bundle::builder()
+        .version(Version::VersionB2)
+        .exchange(Exchange::from(("index.html".to_string(), vec![0; 100])))
+        .exchange(Exchange::from(("a.js".to_string(), vec![0; 300])))
+        .build()?;
+    let mut out = Vec::new();
+    print_stats(&bundle.stats()?, 500, 100, &mut out)?;
     assert_eq!(
-        url_to_path("https://example.com/a/")?,
-        Path::new("https/example.com/a/")
+        String::from_utf8(out)?,
+        "\
+exchanges:    2
+body bytes:   400
+bundle bytes: 500
+gzip bytes:   100
+content types:
+  text/javascript               1        300  75.0%
+  text/html                     1        100  25.0%
+largest:
+         300  75.0% a.js
+         100  25.0% index.html
+"
     );
-    assert_eq!(
-        url_to_path("https://example.com/a/b")?,
-        Path::new("https/example.com/a/b")
-    );
-    assert_eq!(
-        url_to_path("https://example.com/a/b/")?,
-        Path::new("https/example.com/a/b/")
-    );
-    assert_eq!(url_to_path("")?, Path::new("https/example.com/"));
-    assert_eq!(url_to_path(".")?, Path::new("https/example.com/"));
-    assert_eq!(url_to_path("/a")?, Path::new("https/example.com/a"));
-    assert_eq!(url_to_path("..")?, Path::new("https/example.com/"));
-    assert_eq!(url_to_path("a/../../b")?, Path::new("https/example.com/b"));
     Ok(())
 }
 
-fn extract(bundle: &Bundle) -> Result<()> {
-    // TODO: Avoid the conflict of file names.
-    // The current approach is too naive.
-    for exchange in bundle.exchanges() {
-        let path = url_to_path(exchange.request.url())?;
-        ensure!(
-            path.is_relative(),
-            format!("path shoould be relative: {}", path.display())
-        );
-        if !exchange.response.status().is_success() {
-            log::info!("Skipping: {:?}", exchange.request.url());
-            continue;
-        }
-        // TODO: "/" should be path::sep in windows?
-        if path.display().to_string().ends_with('/') {
-            if !path.exists() {
-                std::fs::create_dir_all(&path)?;
-            }
-            // Use index.html
-            let index_html = path.join("index.html");
-            log::info!(
-                "extract: {} => {}",
-                exchange.request.url(),
-                index_html.display()
-            );
-            let mut write = BufWriter::new(File::create(&index_html)?);
-            write.write_all(exchange.response.body())?;
-        } else {
-            log::info!("extract: {} => {}", exchange.request.url(), path.display());
-            let parent = path.parent().context("weired url")?;
-            if !parent.exists() {
-                std::fs::create_dir_all(parent)?;
-            }
-            let mut write = BufWriter::new(File::create(&path)?);
-            write.write_all(exchange.response.body())?;
-        }
+/// Returns the size of the bytes compressed with gzip, which estimates the
+/// transfer size of the bundle.
+fn gzip_len(bytes: &[u8]) -> Result<usize> {
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(bytes)?;
+    Ok(encoder.finish()?.len())
+}
+
+/// Reads the file, or stdin if the file is `-`.
+fn read_input(file: &str) -> Result<Vec<u8>> {
+    if file == "-" {
+        let mut bytes = Vec::new();
+        std::io::stdin().lock().read_to_end(&mut bytes)?;
+        return Ok(bytes);
     }
-    Ok(())
+    std::fs::read(file).with_context(|| format!("Failed to read {file}"))
+}
+
+/// Reads the bundle from the file, or stdin if the file is `-`.
+fn read_bundle(file: &str) -> Result<Bundle> {
+    if file == "-" {
+        return Bundle::from_reader(std::io::stdin().lock()).context("Failed to parse stdin");
+    }
+    let bytes = std::fs::read(file).with_context(|| format!("Failed to read {file}"))?;
+    Bundle::from_bytes(bytes).with_context(|| format!("Failed to parse {file}"))
 }
 
 #[tokio::main]
@@ -268,32 +596,362 @@ async fn main() -> Result<()> {
     match args.cmd {
         Command::Create {
             primary_url,
+            base_url,
             file,
             resources_dir,
+            headers_file,
+            exclude,
+            compress,
+            compress_skip,
+            quiet,
+            format,
         } => {
-            let mut builder = Bundle::builder()
-                .version(Version::VersionB2)
-                .exchanges_from_dir(resources_dir)
-                .await?;
+            ensure!(
+                !(file == "-" && matches!(format, output::Format::Json)),
+                "--format json can't be used with `-`, which writes the bundle to stdout"
+            );
+            let mut builder = Bundle::builder().version(Version::VersionB2);
+            for glob in exclude {
+                builder = builder.exclude(glob);
+            }
+            if let Some(compress) = compress {
+                builder = builder.compress(compress.into());
+            }
+            if !compress_skip.is_empty() {
+                builder = builder.compress_skip(compress_skip);
+            }
+            // The progress bar is hidden if stderr is not a terminal.
+            let progress = if quiet {
+                ProgressBar::hidden()
+            } else {
+                ProgressBar::new_spinner()
+            };
+            progress.set_style(ProgressStyle::with_template(
+                "{spinner} {pos} files, {msg} {elapsed}",
+            )?);
+            let bytes = std::sync::atomic::AtomicUsize::new(0);
+            builder = builder.progress({
+                let progress = progress.clone();
+                move |_, len| {
+                    let bytes = bytes.fetch_add(len, std::sync::atomic::Ordering::Relaxed) + len;
+                    progress.inc(1);
+                    progress.set_message(HumanBytes(bytes as u64).to_string());
+                }
+            });
+            let mut builder = builder.exchanges_from_dir(&resources_dir).await?;
+            progress.finish_and_clear();
+            let base_url = base_url.as_deref().map(parse_base_url).transpose()?;
+            let auto_primary_url = primary_url.as_deref() == Some("auto");
+            ensure!(
+                !auto_primary_url || base_url.is_some(),
+                "--primary-url auto requires --base-url"
+            );
             if let Some(primary_url) = primary_url {
+                let primary_url = match &base_url {
+                    // The root directory, which serves index.html, is mapped
+                    // to the base URL.
+                    Some(base_url) if auto_primary_url => base_url.to_string(),
+                    Some(base_url) => base_url.join(&primary_url)?.to_string(),
+                    None => primary_url,
+                };
                 builder = builder.primary_url(primary_url.parse()?);
             }
-            let bundle = builder.build()?;
+            let mut bundle = builder.build()?;
+            if let Some(base_url) = &base_url {
+                apply_base_url(&mut bundle, base_url)?;
+            }
+            if auto_primary_url {
+                let primary_url = bundle.primary_url().as_ref().map(ToString::to_string);
+                ensure!(
+                    bundle
+                        .exchanges()
+                        .iter()
+                        .any(|exchange| Some(exchange.request.url()) == primary_url.as_ref()),
+                    "--primary-url auto requires index.html in {resources_dir}"
+                );
+            }
+            if let Some(headers_file) = headers_file {
+                headers::HeadersConfig::from_file(&headers_file)?.apply(&mut bundle);
+            }
             log::debug!("{:#?}", bundle);
-            let write = BufWriter::new(File::create(&file)?);
-            bundle.write_to(write)?;
+            if file == "-" {
+                let mut write = BufWriter::new(std::io::stdout().lock());
+                bundle.write_to(&mut write)?;
+                write.flush()?;
+            } else {
+                bundle.write_to_file_async(&file).await?;
+            }
+            if let output::Format::Json = format {
+                output::write_json(
+                    &serde_json::json!({
+                        "file": file,
+                        "primary_url": bundle.primary_url().as_ref().map(ToString::to_string),
+                        "exchanges": bundle.exchanges().len(),
+                        "bundle_bytes": std::fs::metadata(&file)?.len(),
+                    }),
+                    std::io::stdout().lock(),
+                )?;
+            }
+        }
+        Command::List {
+            file,
+            format,
+            sort,
+            urls_only,
+        } => {
+            let buf = read_input(&file)?;
+            let bundle = Bundle::from_bytes(&buf)?;
+            let index = Index::from_bytes(&buf)?;
+            let format = if urls_only {
+                list::Format::Urls
+            } else {
+                format.unwrap_or(list::Format::Table)
+            };
+            list::list(&bundle, &index, format, sort, std::io::stdout().lock())?;
+        }
+        Command::Extract {
+            file,
+            output,
+            on_conflict,
+            with_headers,
+            format,
+        } => {
+            let bundle = read_bundle(&file)?;
+            let extracted = extract::extract(&bundle, Path::new(&output), on_conflict)?;
+            if with_headers {
+                extract::write_headers_manifest(&bundle, Path::new(&output), &extracted)?;
+            }
+            if let output::Format::Json = format {
+                let files = extracted
+                    .files
+                    .iter()
+                    .map(|(url, path)| serde_json::json!({"url": url, "path": path}))
+                    .collect::<Vec<_>>();
+                output::write_json(
+                    &serde_json::json!({
+                        "output": output,
+                        "files": files,
+                        "skipped": extracted.skipped,
+                    }),
+                    std::io::stdout().lock(),
+                )?;
+            }
+        }
+        Command::Tree { file } => {
+            let bundle = read_bundle(&file)?;
+            tree::tree(&bundle, std::io::stdout().lock())?;
         }
-        Command::List { file, format } => {
-            let mut buf = Vec::new();
-            File::open(file)?.read_to_end(&mut buf)?;
-            let bundle = Bundle::from_bytes(buf)?;
-            list(&bundle, format);
+        Command::Stats { file, format } => {
+            let bytes = read_input(&file)?;
+            let bundle = Bundle::from_bytes(&bytes)?;
+            let (stats, gzip_bytes) = (bundle.stats()?, gzip_len(&bytes)?);
+            match format {
+                output::Format::Plain => {
+                    print_stats(&stats, bytes.len(), gzip_bytes, std::io::stdout().lock())?
+                }
+                output::Format::Json => output::write_json(
+                    &output::stats_json(&stats, bytes.len(), gzip_bytes),
+                    std::io::stdout().lock(),
+                )?,
+            }
+        }
+        Command::Diff { old, new, format } => {
+            let (old, new) = (read_bundle(&old)?, read_bundle(&new)?);
+            if diff::write_diff(&old, &new, format, std::io::stdout().lock())? {
+                std::process::exit(1);
+            }
+        }
+        Command::Add {
+            file,
+            url,
+            body_file,
+            strip_signatures,
+        } => {
+            let mut bundle = read_bundle(&file)?;
+            let body =
+                std::fs::read(&body_file).with_context(|| format!("Failed to read {body_file}"))?;
+            // Guess Content-Type from the file name, rather than the url,
+            // which may not have an extension, e.g. `https://example.com/`.
+            let mut exchange = Exchange::from((body_file, body));
+            exchange.request = url.into();
+            if let Some(replaced) = bundle.insert(exchange) {
+                log::info!("Replaced: {}", replaced.request.url());
+            }
+            if strip_signatures {
+                bundle.strip_signatures();
+            }
+            bundle.write_to_file(&file)?;
+        }
+        Command::Remove {
+            file,
+            urls,
+            strip_signatures,
+        } => {
+            let mut bundle = read_bundle(&file)?;
+            for url in urls {
+                bundle
+                    .remove(&url)
+                    .with_context(|| format!("{url} is not found in {file}"))?;
+            }
+            if strip_signatures {
+                bundle.strip_signatures();
+            }
+            bundle.write_to_file(&file)?;
+        }
+        Command::SetHeader {
+            file,
+            url_glob,
+            headers,
+            strip_signatures,
+        } => {
+            let headers = headers
+                .iter()
+                .map(|header| headers::parse_header(header))
+                .collect::<Result<Vec<_>>>()?;
+            let mut bundle = read_bundle(&file)?;
+            let count = headers::set_headers(&mut bundle, &url_glob, &headers);
+            ensure!(count > 0, "No url matches {url_glob}");
+            if strip_signatures {
+                bundle.strip_signatures();
+            }
+            bundle.write_to_file(&file)?;
+            println!("Updated {count} exchange(s)");
+        }
+        Command::Fetch {
+            url,
+            depth,
+            cross_origin,
+            output,
+        } => {
+            let bundle = Bundle::builder()
+                .version(Version::VersionB2)
+                .primary_url(url.parse()?)
+                .crawl(&url, depth, !cross_origin)
+                .await?
+                .build()?;
+            log::info!("Fetched {} exchanges", bundle.exchanges().len());
+            bundle.write_to_file_async(&output).await?;
+        }
+        Command::Convert { to, file, output } => {
+            let bundle = read_bundle(&file)?;
+            convert(&bundle, to)?.write_to_file(&output)?;
         }
-        Command::Extract { file } => {
-            let mut buf = Vec::new();
-            File::open(file)?.read_to_end(&mut buf)?;
-            let bundle = Bundle::from_bytes(buf)?;
-            extract(&bundle)?;
+        Command::DumpCbor { file } => {
+            let bytes = read_input(&file)?;
+            dump::dump(&bytes, std::io::stdout().lock())?;
+        }
+        Command::Grep {
+            file,
+            pattern,
+            url_glob,
+        } => {
+            let bundle = read_bundle(&file)?;
+            let pattern = regex::Regex::new(&pattern)?;
+            let mut write = BufWriter::new(std::io::stdout().lock());
+            let count = grep::grep(&bundle, &pattern, url_glob.as_deref(), &mut write)?;
+            write.flush()?;
+            if count == 0 {
+                std::process::exit(1);
+            }
+        }
+        Command::Hash { file, per_resource } => {
+            let mut bundle = read_bundle(&file)?;
+            hash::write_hashes(&mut bundle, &file, per_resource, std::io::stdout().lock())?;
+        }
+        Command::Cat { file, url, headers } => {
+            let bytes = read_input(&file)?;
+            let bundle = Bundle::from_bytes(bytes)?;
+            let exchange = bundle
+                .exchanges()
+                .iter()
+                .find(|exchange| *exchange.request.url() == url)
+                .with_context(|| format!("{url} is not found in {file}"))?;
+            cat(exchange, headers, std::io::stdout().lock())?;
+        }
+        Command::Validate { file, format } => {
+            let bytes = read_input(&file)?;
+            let findings = webbundle::validate(bytes);
+            let errors = findings
+                .iter()
+                .filter(|finding| finding.severity == Severity::Error)
+                .count();
+            match format {
+                output::Format::Plain => {
+                    for finding in &findings {
+                        println!("{finding}");
+                    }
+                    if findings.is_empty() {
+                        println!("OK");
+                    }
+                }
+                output::Format::Json => {
+                    output::write_json(&output::findings_json(&findings), std::io::stdout().lock())?
+                }
+            }
+            ensure!(errors == 0, "{errors} error(s) found in {file}");
+        }
+        Command::Sign { key, file, output } => {
+            let key =
+                std::fs::read_to_string(&key).with_context(|| format!("Failed to read {key}"))?;
+            let key = SigningKey::from_pem(&key)?;
+            let bundle = read_input(&file)?;
+            // Make sure that the input is a bundle.
+            Bundle::from_bytes(&bundle)?;
+            std::fs::write(&output, webbundle::sign(&bundle, &key)?)?;
+        }
+        Command::Verify { file, key } => {
+            let signed = read_input(&file)?;
+            let (integrity_block, bundle) = webbundle::verify(&signed)?;
+            if let Some(key) = key {
+                let key = std::fs::read_to_string(&key)
+                    .with_context(|| format!("Failed to read {key}"))?;
+                let public_key = webbundle::public_key_from_pem(&key)?;
+                ensure!(
+                    integrity_block
+                        .signatures
+                        .iter()
+                        .any(|signature| signature.public_key == public_key),
+                    "The bundle is not signed with the given key"
+                );
+            }
+            Bundle::from_bytes(bundle)?;
+            if let Some(web_bundle_id) = integrity_block.web_bundle_id() {
+                println!("Web Bundle ID: {web_bundle_id}");
+            }
+            println!("OK");
+        }
+        Command::Snippet {
+            file,
+            source,
+            scopes,
+        } => {
+            let bundle = read_bundle(&file)?;
+            snippet::write_snippet(&bundle, &source, scopes, std::io::stdout().lock())?;
+        }
+        Command::GenSw {
+            file,
+            source,
+            output,
+        } => {
+            let source = match source {
+                Some(source) => source,
+                None => Path::new(&file)
+                    .file_name()
+                    .filter(|_| file != "-")
+                    .context("--source is required for stdin")?
+                    .to_string_lossy()
+                    .to_string(),
+            };
+            let bytes = read_input(&file)?;
+            std::fs::write(&output, sw::service_worker(&bytes, &source)?)?;
+        }
+        Command::Completions { shell } => {
+            clap_complete::generate(
+                shell,
+                &mut Cli::command(),
+                "webbundle",
+                &mut std::io::stdout(),
+            );
         }
     }
     Ok(())
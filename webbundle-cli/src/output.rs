@@ -0,0 +1,160 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Machine-readable output of the subcommands.
+//!
+//! With `--format json`, a subcommand writes one JSON object to stdout. Keys
+//! are `snake_case`, sizes are in bytes, and urls are written as they are in
+//! the bundle.
+
+use serde_json::{json, Value};
+use std::io::Write;
+use webbundle::{Bundle, Exchange, Finding, Result, Severity, Stats};
+
+#[derive(Clone, Copy, Default, clap::ValueEnum)]
+pub(crate) enum Format {
+    /// Human-readable text
+    #[default]
+    Plain,
+    /// One JSON object
+    Json,
+}
+
+pub(crate) fn write_json(value: &Value, mut write: impl Write) -> Result<()> {
+    writeln!(write, "{value}")?;
+    Ok(())
+}
+
+/// Returns the url, the status and the headers of the exchange. Headers are a
+/// list of name-value pairs, as a name can appear more than once.
+pub(crate) fn exchange_json(exchange: &Exchange) -> Value {
+    let headers = exchange
+        .response
+        .headers()
+        .iter()
+        .map(|(name, value)| json!([name.as_str(), String::from_utf8_lossy(value.as_bytes())]))
+        .collect::<Vec<_>>();
+    json!({
+        "url": exchange.request.url(),
+        "status": exchange.response.status().as_u16(),
+        "headers": headers,
+    })
+}
+
+/// Returns the version and the primary url of the bundle, with `exchanges`.
+pub(crate) fn bundle_json(bundle: &Bundle, exchanges: Vec<Value>) -> Value {
+    json!({
+        "version": bundle.version(),
+        "primary_url": bundle.primary_url().as_ref().map(ToString::to_string),
+        "exchanges": exchanges,
+    })
+}
+
+pub(crate) fn findings_json(findings: &[Finding]) -> Value {
+    let findings = findings
+        .iter()
+        .map(|finding| {
+            json!({
+                "severity": match finding.severity {
+                    Severity::Warning => "warning",
+                    Severity::Error => "error",
+                },
+                "url": finding.url,
+                "message": finding.message,
+            })
+        })
+        .collect::<Vec<_>>();
+    let valid = findings
+        .iter()
+        .all(|finding| finding["severity"] != "error");
+    json!({
+        "valid": valid,
+        "findings": findings,
+    })
+}
+
+pub(crate) fn stats_json(stats: &Stats, bundle_bytes: usize, gzip_bytes: usize) -> Value {
+    let content_types = stats
+        .content_types
+        .iter()
+        .map(|(content_type, content_type_stats)| {
+            (
+                content_type.clone(),
+                json!({
+                    "count": content_type_stats.count,
+                    "body_bytes": content_type_stats.body_bytes,
+                }),
+            )
+        })
+        .collect::<serde_json::Map<_, _>>();
+    let largest = stats
+        .largest
+        .iter()
+        .map(|(url, bytes)| json!({"url": url, "body_bytes": bytes}))
+        .collect::<Vec<_>>();
+    json!({
+        "exchanges": stats.exchanges,
+        "body_bytes": stats.body_bytes,
+        "bundle_bytes": bundle_bytes,
+        "gzip_bytes": gzip_bytes,
+        "content_types": content_types,
+        "largest": largest,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use webbundle::{Bundle, Exchange, Version};
+
+    #[test]
+    fn findings_json_test() -> Result<()> {
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchange(Exchange::from(("a.js".to_string(), Vec::new())))
+            .build()?;
+        let value = findings_json(&webbundle::validate(bundle.encode()?));
+        assert_eq!(value["valid"], false);
+        assert_eq!(
+            value["findings"][0],
+            json!({
+                "severity": "error",
+                "url": "a.js",
+                "message": "The url is not absolute",
+            })
+        );
+        assert_eq!(findings_json(&[]), json!({"valid": true, "findings": []}));
+        Ok(())
+    }
+
+    #[test]
+    fn stats_json_test() -> Result<()> {
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchange(Exchange::from(("a.js".to_string(), vec![0; 300])))
+            .build()?;
+        assert_eq!(
+            stats_json(&bundle.stats()?, 500, 100),
+            json!({
+                "exchanges": 1,
+                "body_bytes": 300,
+                "bundle_bytes": 500,
+                "gzip_bytes": 100,
+                "content_types": {"text/javascript": {"count": 1, "body_bytes": 300}},
+                "largest": [{"url": "a.js", "body_bytes": 300}],
+            })
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `<script type="webbundle">` snippet, which tells browsers to load the
+//! resources from the bundle.
+//!
+//! See [Subresource loading with Web Bundles](https://github.com/WICG/webpackage/blob/main/explainers/subresource-loading.md).
+
+use serde_json::json;
+use std::collections::BTreeSet;
+use std::io::Write;
+use url::Url;
+use webbundle::{Bundle, Result};
+
+/// The directory of the url, which ends with `/`, or an empty string for a
+/// relative url without `/`.
+fn directory(url: &str) -> String {
+    if let Ok(url) = Url::parse(url) {
+        if let Ok(directory) = url.join("./") {
+            return directory.to_string();
+        }
+    }
+    match url.rfind('/') {
+        Some(i) => url[..=i].to_string(),
+        None => String::new(),
+    }
+}
+
+/// Returns the fewest directories which contain all the urls of the bundle.
+fn scopes(bundle: &Bundle) -> Vec<String> {
+    let directories = bundle
+        .exchanges()
+        .iter()
+        .map(|exchange| directory(exchange.request.url()))
+        .collect::<BTreeSet<_>>();
+    let mut scopes: Vec<String> = Vec::new();
+    // A directory comes right after its ancestors in the sorted set.
+    for directory in directories {
+        if scopes
+            .last()
+            .is_some_and(|scope| directory.starts_with(scope.as_str()))
+        {
+            continue;
+        }
+        scopes.push(directory);
+    }
+    scopes
+        .into_iter()
+        .map(|scope| {
+            if scope.is_empty() {
+                ".".to_string()
+            } else {
+                scope
+            }
+        })
+        .collect()
+}
+
+/// Writes the snippet for the bundle served at `source`, with `scopes` if
+/// `use_scopes` is true, or with `resources`, which lists all the urls.
+pub(crate) fn write_snippet(
+    bundle: &Bundle,
+    source: &str,
+    use_scopes: bool,
+    mut write: impl Write,
+) -> Result<()> {
+    let rule = if use_scopes {
+        json!({"source": source, "scopes": scopes(bundle)})
+    } else {
+        let resources = bundle
+            .exchanges()
+            .iter()
+            .map(|exchange| exchange.request.url())
+            .collect::<Vec<_>>();
+        json!({"source": source, "resources": resources})
+    };
+    writeln!(write, "<script type=\"webbundle\">")?;
+    writeln!(write, "{}", serde_json::to_string_pretty(&rule)?)?;
+    writeln!(write, "</script>")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use webbundle::{Exchange, Version};
+
+    fn bundle(urls: &[&str]) -> Result<Bundle> {
+        let mut builder = Bundle::builder().version(Version::VersionB2);
+        for url in urls {
+            builder = builder.exchange(Exchange::from((url.to_string(), Vec::new())));
+        }
+        builder.build()
+    }
+
+    #[test]
+    fn scopes_test() -> Result<()> {
+        assert_eq!(
+            scopes(&bundle(&[
+                "https://example.com/app/js/a.js",
+                "https://example.com/app/index.html",
+                "https://example.com/other/b.css",
+                "https://cdn.example.com/c.js",
+            ])?),
+            [
+                "https://cdn.example.com/",
+                "https://example.com/app/",
+                "https://example.com/other/"
+            ]
+        );
+        assert_eq!(scopes(&bundle(&["js/a.js", "js/lib/b.js"])?), ["js/"]);
+        assert_eq!(scopes(&bundle(&["", "js/a.js"])?), ["."]);
+        Ok(())
+    }
+
+    #[test]
+    fn write_snippet_test() -> Result<()> {
+        let bundle = bundle(&["https://example.com/a.js", "https://example.com/b.js"])?;
+        let mut out = Vec::new();
+        write_snippet(&bundle, "/static/bundle.wbn", false, &mut out)?;
+        assert_eq!(
+            String::from_utf8(out)?,
+            r#"<script type="webbundle">
+{
+  "resources": [
+    "https://example.com/a.js",
+    "https://example.com/b.js"
+  ],
+  "source": "/static/bundle.wbn"
+}
+</script>
+"#
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,74 @@
+// Generated by `webbundle gen-sw`. Serves the resources of a web bundle from
+// a service worker, for browsers which don't support web bundles.
+//
+// The responses are read from the bundle with range requests. The bundle is
+// also cached on install, so that the resources are available offline.
+
+const BUNDLE_URL = new URL(/* BUNDLE_URL */, self.location).href;
+const CACHE_NAME = 'webbundle:' + BUNDLE_URL;
+// url -> { status, headers, body: [start, end] }, where `body` is the range of
+// the response body in the bundle.
+const ENTRIES = new Map(
+  Object.entries(/* ENTRIES */).map(([url, entry]) => [
+    new URL(url, BUNDLE_URL).href,
+    entry,
+  ])
+);
+
+self.addEventListener('install', (event) => {
+  event.waitUntil(
+    caches
+      .open(CACHE_NAME)
+      .then((cache) => cache.add(BUNDLE_URL))
+      .catch((error) => console.warn('Failed to cache the bundle', error))
+      .then(() => self.skipWaiting())
+  );
+});
+
+self.addEventListener('activate', (event) => {
+  event.waitUntil(self.clients.claim());
+});
+
+self.addEventListener('fetch', (event) => {
+  if (event.request.method !== 'GET') {
+    return;
+  }
+  const url = new URL(event.request.url);
+  url.hash = '';
+  const entry = ENTRIES.get(url.href);
+  if (entry) {
+    event.respondWith(respond(url, entry));
+  }
+});
+
+async function respond(url, entry) {
+  const headers = new Headers(entry.headers);
+  if ([301, 302, 303, 307, 308].includes(entry.status) && headers.has('location')) {
+    return Response.redirect(new URL(headers.get('location'), url).href, entry.status);
+  }
+  let content = await body(entry.body);
+  // Browsers don't decode responses made by service workers.
+  const encoding = headers.get('content-encoding');
+  if (content && encoding) {
+    content = content.stream().pipeThrough(new DecompressionStream(encoding));
+    headers.delete('content-encoding');
+    headers.delete('content-length');
+  }
+  return new Response(content, { status: entry.status, headers });
+}
+
+async function body([start, end]) {
+  if (start === end) {
+    return null;
+  }
+  const cached = await caches.match(BUNDLE_URL, { cacheName: CACHE_NAME });
+  if (cached) {
+    return (await cached.blob()).slice(start, end);
+  }
+  const response = await fetch(BUNDLE_URL, {
+    headers: { Range: `bytes=${start}-${end - 1}` },
+  });
+  const blob = await response.blob();
+  // The server may ignore the range, and return the whole bundle.
+  return response.status === 206 ? blob : blob.slice(start, end);
+}
@@ -0,0 +1,124 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A service worker which serves the resources of a bundle. See `sw.js`.
+
+use anyhow::{ensure, Context as _};
+use http::header::CONTENT_ENCODING;
+use serde_json::json;
+use webbundle::{Bundle, Index, Result};
+
+const TEMPLATE: &str = include_str!("sw.js");
+
+/// The encodings which `DecompressionStream` of service workers can decode.
+const DECODABLE_ENCODINGS: [&str; 2] = ["gzip", "deflate"];
+
+/// Generates the service worker for the bundle, which is served at `source`.
+/// A relative `source` is resolved to the url of the service worker.
+///
+/// Encoded bodies, e.g. of `create --compress gzip`, are decoded by the
+/// service worker, which supports only gzip and deflate.
+pub(crate) fn service_worker(bytes: &[u8], source: &str) -> Result<String> {
+    let bundle = Bundle::from_bytes(bytes)?;
+    let index = Index::from_bytes(bytes)?;
+    let mut entries = Vec::new();
+    for exchange in bundle.exchanges() {
+        let url = exchange.request.url();
+        let entry = index
+            .get(url)
+            .with_context(|| format!("No index entry for {url}"))?;
+        if let Some(encoding) = exchange.response.headers().get(CONTENT_ENCODING) {
+            ensure!(
+                DECODABLE_ENCODINGS.contains(&encoding.to_str()?),
+                format!("{url}: Content-Encoding {encoding:?} can't be decoded by service workers")
+            );
+        }
+        // The body is the last item of the response.
+        let end = entry
+            .offset
+            .checked_add(entry.length)
+            .with_context(|| format!("Invalid index entry for {url}"))?;
+        let start = end
+            .checked_sub(exchange.response.body().len() as u64)
+            .with_context(|| format!("Invalid index entry for {url}"))?;
+        let headers = exchange
+            .response
+            .headers()
+            .iter()
+            .map(|(name, value)| json!([name.as_str(), String::from_utf8_lossy(value.as_bytes())]))
+            .collect::<Vec<_>>();
+        let entry = json!({
+            "status": exchange.response.status().as_u16(),
+            "headers": headers,
+            "body": [start, end],
+        });
+        // One entry per line.
+        entries.push(format!("  {}: {entry}", serde_json::to_string(url)?));
+    }
+    Ok(TEMPLATE
+        .replace("/* BUNDLE_URL */", &serde_json::to_string(source)?)
+        .replace("/* ENTRIES */", &format!("{{\n{}\n}}", entries.join(",\n"))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use webbundle::{Exchange, Version};
+
+    #[test]
+    fn service_worker_test() -> Result<()> {
+        let bytes = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchange(Exchange::from(("a.txt".to_string(), b"hello".to_vec())))
+            .build()?
+            .encode()?;
+        let sw = service_worker(&bytes, "bundle.wbn")?;
+        assert!(sw.contains(r#"new URL("bundle.wbn", self.location)"#));
+
+        let entries = sw
+            .split_once("Object.entries(")
+            .and_then(|(_, rest)| rest.split_once(").map"))
+            .context("No entries")?
+            .0;
+        let entries: serde_json::Value = serde_json::from_str(entries)?;
+        let range = &entries["a.txt"]["body"];
+        let (start, end) = (
+            range[0].as_u64().context("No start")? as usize,
+            range[1].as_u64().context("No end")? as usize,
+        );
+        assert_eq!(&bytes[start..end], b"hello");
+        assert_eq!(entries["a.txt"]["status"], 200);
+        Ok(())
+    }
+
+    #[test]
+    fn service_worker_encoded_bodies() -> Result<()> {
+        let encode = |encoding: &'static str| {
+            let mut exchange = Exchange::from(("a.txt".to_string(), b"hello".to_vec()));
+            exchange
+                .response
+                .headers_mut()
+                .insert(CONTENT_ENCODING, encoding.parse()?);
+            Bundle::builder()
+                .version(Version::VersionB2)
+                .exchange(exchange)
+                .build()?
+                .encode()
+        };
+        assert!(service_worker(&encode("gzip")?, "bundle.wbn")?
+            .contains(r#"["content-encoding","gzip"]"#));
+        assert!(service_worker(&encode("br")?, "bundle.wbn").is_err());
+        Ok(())
+    }
+}
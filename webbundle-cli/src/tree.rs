@@ -0,0 +1,141 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The hierarchy of the urls, like `tree` and `du` commands.
+
+use indicatif::HumanBytes;
+use std::collections::BTreeMap;
+use std::io::Write;
+use url::Url;
+use webbundle::{Bundle, Result};
+
+#[derive(Default)]
+struct Node {
+    children: BTreeMap<String, Node>,
+    /// The total size of the bodies of this node and its descendants.
+    bytes: u64,
+}
+
+impl Node {
+    fn insert<'a>(&mut self, mut segments: impl Iterator<Item = &'a str>, bytes: u64) {
+        self.bytes += bytes;
+        match segments.next() {
+            // An empty segment is the directory itself, e.g. for `a/`.
+            Some(segment) if !segment.is_empty() => self
+                .children
+                .entry(segment.to_string())
+                .or_default()
+                .insert(segments, bytes),
+            _ => {}
+        }
+    }
+
+    fn write(&self, prefix: &str, write: &mut dyn Write) -> Result<()> {
+        let len = self.children.len();
+        for (i, (name, child)) in self.children.iter().enumerate() {
+            let last = i + 1 == len;
+            let slash = if child.children.is_empty() { "" } else { "/" };
+            writeln!(
+                write,
+                "{prefix}{}{name}{slash} ({})",
+                if last { "└── " } else { "├── " },
+                HumanBytes(child.bytes)
+            )?;
+            let prefix = format!("{prefix}{}", if last { "    " } else { "│   " });
+            child.write(&prefix, write)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the root, e.g. `https://example.com`, and the path of the url.
+/// Relative urls have `.` as the root.
+fn split_url(url: &str) -> (String, String) {
+    match Url::parse(url) {
+        Ok(parsed) if parsed.has_host() => {
+            let mut path = parsed.path().trim_start_matches('/').to_string();
+            if let Some(query) = parsed.query() {
+                path = format!("{path}?{query}");
+            }
+            let root = url.strip_suffix(&path).unwrap_or(url);
+            (root.trim_end_matches('/').to_string(), path)
+        }
+        _ => (".".to_string(), url.trim_start_matches("./").to_string()),
+    }
+}
+
+/// Writes the urls of the bundle as trees, one for each origin, with the
+/// total body size of each directory.
+pub(crate) fn tree(bundle: &Bundle, mut write: impl Write) -> Result<()> {
+    let mut roots = BTreeMap::<String, Node>::new();
+    for exchange in bundle.exchanges() {
+        let (root, path) = split_url(exchange.request.url());
+        roots
+            .entry(root)
+            .or_default()
+            .insert(path.split('/'), exchange.response.body().len() as u64);
+    }
+    for (root, node) in &roots {
+        writeln!(write, "{root}/ ({})", HumanBytes(node.bytes))?;
+        node.write("", &mut write)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use webbundle::{Exchange, Version};
+
+    #[test]
+    fn tree_test() -> Result<()> {
+        let mut builder = Bundle::builder().version(Version::VersionB2);
+        for (url, len) in [
+            ("https://example.com/", 100),
+            ("https://example.com/js/a.js", 1000),
+            ("https://example.com/js/b.js?v=1", 2000),
+            ("https://example.com/style.css", 10),
+            ("https://cdn.example.com/lib.js", 5),
+        ] {
+            builder = builder.exchange(Exchange::from((url.to_string(), vec![0; len])));
+        }
+        let mut out = Vec::new();
+        tree(&builder.build()?, &mut out)?;
+        assert_eq!(
+            String::from_utf8(out)?,
+            "\
+https://cdn.example.com/ (5 B)
+└── lib.js (5 B)
+https://example.com/ (3.04 KiB)
+├── js/ (2.93 KiB)
+│   ├── a.js (1000 B)
+│   └── b.js?v=1 (1.95 KiB)
+└── style.css (10 B)
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn split_url_test() {
+        assert_eq!(
+            split_url("https://example.com:8080/a/b"),
+            ("https://example.com:8080".to_string(), "a/b".to_string())
+        );
+        assert_eq!(
+            split_url("./js/a.js"),
+            (".".to_string(), "js/a.js".to_string())
+        );
+    }
+}
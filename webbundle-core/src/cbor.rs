@@ -0,0 +1,146 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A minimal CBOR (RFC 8949) reader for the items used in bundles.
+//!
+//! Indefinite-length items are not supported, as bundles use only
+//! deterministic encoding.
+
+use crate::{Error, Result};
+
+const UNSIGNED_INTEGER: u8 = 0;
+const BYTE_STRING: u8 = 2;
+const TEXT_STRING: u8 = 3;
+const ARRAY: u8 = 4;
+const MAP: u8 = 5;
+
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes, position: 0 }
+    }
+
+    /// Returns the number of bytes read so far.
+    pub(crate) fn position(&self) -> usize {
+        self.position
+    }
+
+    pub(crate) fn unsigned_integer(&mut self) -> Result<u64> {
+        self.head(UNSIGNED_INTEGER)
+    }
+
+    pub(crate) fn bytes(&mut self) -> Result<&'a [u8]> {
+        let len = self.head(BYTE_STRING)?;
+        self.take(len)
+    }
+
+    pub(crate) fn text(&mut self) -> Result<&'a str> {
+        let len = self.head(TEXT_STRING)?;
+        core::str::from_utf8(self.take(len)?).map_err(|_| Error::Invalid("Invalid UTF-8 text"))
+    }
+
+    pub(crate) fn array(&mut self) -> Result<u64> {
+        self.head(ARRAY)
+    }
+
+    pub(crate) fn map(&mut self) -> Result<u64> {
+        self.head(MAP)
+    }
+
+    /// Reads the head of an item of the given major type, and returns its
+    /// argument, i.e. the value, the length or the number of items.
+    fn head(&mut self, major_type: u8) -> Result<u64> {
+        let initial = self.take(1)?[0];
+        if initial >> 5 != major_type {
+            return Err(Error::Invalid(match major_type {
+                UNSIGNED_INTEGER => "Expected an unsigned integer",
+                BYTE_STRING => "Expected a byte string",
+                TEXT_STRING => "Expected a text string",
+                ARRAY => "Expected an array",
+                _ => "Expected a map",
+            }));
+        }
+        let len = match initial & 0x1f {
+            info @ 0..=23 => return Ok(info as u64),
+            24 => 1,
+            25 => 2,
+            26 => 4,
+            27 => 8,
+            _ => return Err(Error::Invalid("Unsupported CBOR item")),
+        };
+        Ok(self
+            .take(len)?
+            .iter()
+            .fold(0, |value, &b| value << 8 | b as u64))
+    }
+
+    fn take(&mut self, len: u64) -> Result<&'a [u8]> {
+        let rest = &self.bytes[self.position..];
+        if len > rest.len() as u64 {
+            return Err(Error::UnexpectedEnd);
+        }
+        self.position += len as usize;
+        Ok(&rest[..len as usize])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_items() -> Result<()> {
+        let mut reader = Reader::new(&[
+            0x82, // array(2)
+            0x17, // 23
+            0x19, 0x01, 0x00, // 256
+            0xa1, // map(1)
+            0x61, b'a', // "a"
+            0x42, 0x01, 0x02, // h'0102'
+        ]);
+        assert_eq!(reader.array()?, 2);
+        assert_eq!(reader.unsigned_integer()?, 23);
+        assert_eq!(reader.unsigned_integer()?, 256);
+        assert_eq!(reader.map()?, 1);
+        assert_eq!(reader.text()?, "a");
+        assert_eq!(reader.bytes()?, &[1, 2]);
+        assert_eq!(reader.position(), 11);
+        assert_eq!(reader.unsigned_integer(), Err(Error::UnexpectedEnd));
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_items() {
+        assert_eq!(
+            Reader::new(&[0x61]).bytes(),
+            Err(Error::Invalid("Expected a byte string"))
+        );
+        assert_eq!(
+            Reader::new(&[0x42, 0x01]).bytes(),
+            Err(Error::UnexpectedEnd)
+        );
+        assert_eq!(
+            Reader::new(&[0x9f]).array(),
+            Err(Error::Invalid("Unsupported CBOR item"))
+        );
+        assert_eq!(
+            Reader::new(&[0x61, 0xff]).text(),
+            Err(Error::Invalid("Invalid UTF-8 text"))
+        );
+    }
+}
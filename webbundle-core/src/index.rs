@@ -0,0 +1,344 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::cbor::Reader;
+use crate::{Error, Result};
+use alloc::vec::Vec;
+
+const HEADER_MAGIC_BYTES: [u8; 8] = [0xf0, 0x9f, 0x8c, 0x90, 0xf0, 0x9f, 0x93, 0xa6];
+const TOP_ARRAY_LEN: u64 = 5;
+/// Version b1 has the primary url between the version and `sectionLengths`.
+const TOP_ARRAY_LEN_B1: u64 = 6;
+const VERSION_B1: [u8; 4] = *b"b1\0\0";
+const MAX_SECTION_LENGTHS_LEN: usize = 8_192;
+
+/// Represents the metadata and the index of a bundle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Index<'a> {
+    /// The version bytes, e.g. `b"b2\0\0"`.
+    pub version: [u8; 4],
+    /// The sections, including the responses section and unknown sections.
+    pub sections: Vec<Section<'a>>,
+    /// The primary url, if the bundle has the primary section, or the
+    /// primary url field of version b1.
+    pub primary_url: Option<&'a str>,
+    /// The entries of the index section, in the order of the section.
+    ///
+    /// A url of version b1 which has variants has an entry for each variant,
+    /// in the order of the index. The responses have `Variant-Key` headers.
+    pub entries: Vec<IndexEntry<'a>>,
+}
+
+/// Represents the location of a section in a bundle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section<'a> {
+    /// The name of the section, e.g. `"index"`.
+    pub name: &'a str,
+    /// The offset of the section from the start of the bundle.
+    pub offset: u64,
+    /// The length of the section.
+    pub length: u64,
+}
+
+/// Represents the location of a response in a bundle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry<'a> {
+    /// The url of the request.
+    pub url: &'a str,
+    /// The offset of the response from the start of the bundle.
+    pub offset: u64,
+    /// The length of the response.
+    pub length: u64,
+}
+
+/// Parses the metadata and the index from the first bytes of a bundle.
+///
+/// The bytes must contain all sections before the responses section, but
+/// don't have to contain the responses section. Returns
+/// [`Error::UnexpectedEnd`] if they don't, so that the caller can retry with
+/// more bytes.
+pub fn parse_index(bytes: &[u8]) -> Result<Index<'_>> {
+    let mut reader = Reader::new(bytes);
+    let top_array_len = reader.array()?;
+    if top_array_len != TOP_ARRAY_LEN && top_array_len != TOP_ARRAY_LEN_B1 {
+        return Err(Error::Invalid("Invalid header"));
+    }
+    if reader.bytes()? != HEADER_MAGIC_BYTES {
+        return Err(Error::Invalid("Header magic mismatch"));
+    }
+    let version: [u8; 4] = reader
+        .bytes()?
+        .try_into()
+        .map_err(|_| Error::Invalid("Invalid version format"))?;
+    let is_b1 = version == VERSION_B1;
+    if is_b1 != (top_array_len == TOP_ARRAY_LEN_B1) {
+        return Err(Error::Invalid("Invalid header"));
+    }
+    let mut primary_url = if is_b1 { Some(reader.text()?) } else { None };
+    let section_lengths = reader.bytes()?;
+    if section_lengths.len() >= MAX_SECTION_LENGTHS_LEN {
+        return Err(Error::Invalid("sectionLengths is too long"));
+    }
+    let sections_len = reader.array()?;
+    let sections = parse_section_lengths(section_lengths, reader.position() as u64)?;
+    if sections_len != sections.len() as u64 {
+        return Err(Error::Invalid(
+            "The number of sections doesn't match sectionLengths",
+        ));
+    }
+
+    let responses_offset = sections.last().map_or(0, |section| section.offset);
+    let mut entries = Vec::new();
+    for section in &sections {
+        match section.name {
+            "index" => {
+                entries =
+                    parse_index_section(section_bytes(bytes, section)?, responses_offset, is_b1)?
+            }
+            "primary" => primary_url = Some(Reader::new(section_bytes(bytes, section)?).text()?),
+            _ => {}
+        }
+    }
+    Ok(Index {
+        version,
+        sections,
+        primary_url,
+        entries,
+    })
+}
+
+fn parse_section_lengths(bytes: &[u8], mut offset: u64) -> Result<Vec<Section<'_>>> {
+    let mut reader = Reader::new(bytes);
+    let len = reader.array()?;
+    if len % 2 != 0 {
+        return Err(Error::Invalid("Invalid sectionLengths"));
+    }
+    let mut sections: Vec<Section> = Vec::new();
+    for _ in 0..len / 2 {
+        let name = reader.text()?;
+        if sections.iter().any(|section| section.name == name) {
+            return Err(Error::Invalid("Duplicate section name"));
+        }
+        let length = reader.unsigned_integer()?;
+        sections.push(Section {
+            name,
+            offset,
+            length,
+        });
+        offset = offset
+            .checked_add(length)
+            .ok_or(Error::Invalid("Section length overflows"))?;
+    }
+    match sections.last() {
+        None => Err(Error::Invalid("section is empty")),
+        Some(section) if section.name != "responses" => {
+            Err(Error::Invalid("Last section is not \"responses\""))
+        }
+        Some(_) => Ok(sections),
+    }
+}
+
+fn section_bytes<'a>(bytes: &'a [u8], section: &Section) -> Result<&'a [u8]> {
+    // `offset + length` doesn't overflow, as checked in parse_section_lengths.
+    if section.offset + section.length > bytes.len() as u64 {
+        return Err(Error::UnexpectedEnd);
+    }
+    Ok(&bytes[section.offset as usize..(section.offset + section.length) as usize])
+}
+
+/// Parses the index section, whose values are `[offset, length]`, or
+/// `[variants-value, +[offset, length]]` in version b1.
+fn parse_index_section(
+    bytes: &[u8],
+    responses_offset: u64,
+    is_b1: bool,
+) -> Result<Vec<IndexEntry<'_>>> {
+    let mut reader = Reader::new(bytes);
+    let len = reader.map()?;
+    // Each entry takes at least 4 bytes.
+    let mut entries = Vec::with_capacity(len.min(bytes.len() as u64 / 4) as usize);
+    for _ in 0..len {
+        let url = reader.text()?;
+        let locations = if is_b1 {
+            let array_len = reader.array()?;
+            let has_variants = !reader.bytes()?.is_empty();
+            match array_len {
+                3 => 1,
+                len if has_variants && len > 3 && len % 2 == 1 => len / 2,
+                _ => return Err(Error::Invalid("Failed to decode index item")),
+            }
+        } else {
+            if reader.array()? != 2 {
+                return Err(Error::Invalid("Failed to decode index item"));
+            }
+            1
+        };
+        for _ in 0..locations {
+            let offset = reader.unsigned_integer()?;
+            let length = reader.unsigned_integer()?;
+            let offset = responses_offset
+                .checked_add(offset)
+                .ok_or(Error::Invalid("Response offset overflows"))?;
+            // So that `offset + length` of an entry never overflows.
+            offset
+                .checked_add(length)
+                .ok_or(Error::Invalid("Response length overflows"))?;
+            entries.push(IndexEntry {
+                url,
+                offset,
+                length,
+            });
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    /// A bundle with "https://example.com/" as both of the primary url and the
+    /// only exchange, whose response is [{":status": "200"}, "Hi"].
+    fn bundle() -> Vec<u8> {
+        let url = b"https://example.com/";
+        let section_lengths = [
+            &[0x86, 0x65][..],
+            b"index",
+            &[0x18, 0x19, 0x67],
+            b"primary",
+            &[0x15, 0x69],
+            b"responses",
+            &[0x13],
+        ]
+        .concat();
+        [
+            &[0x85, 0x48][..],
+            &HEADER_MAGIC_BYTES,
+            &[0x44, b'b', b'2', 0, 0],
+            &[0x58, section_lengths.len() as u8],
+            &section_lengths,
+            &[0x83],
+            // index: {url: [1, 18]}
+            &[0xa1, 0x74],
+            url,
+            &[0x82, 0x01, 0x12],
+            // primary: url
+            &[0x74],
+            url,
+            // responses: [[{":status": "200"}, "Hi"]]
+            &[0x81, 0x82, 0x4d, 0xa1, 0x47],
+            b":status",
+            &[0x43],
+            b"200",
+            &[0x42],
+            b"Hi",
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn parse() -> Result<()> {
+        let bytes = bundle();
+        let index = parse_index(&bytes)?;
+        assert_eq!(&index.version, b"b2\0\0");
+        assert_eq!(index.primary_url, Some("https://example.com/"));
+        assert_eq!(
+            index.sections.iter().map(|s| s.name).collect::<Vec<_>>(),
+            vec!["index", "primary", "responses"]
+        );
+        let responses = &index.sections[2];
+        assert_eq!(responses.offset + responses.length, bytes.len() as u64);
+        assert_eq!(
+            index.entries,
+            vec![IndexEntry {
+                url: "https://example.com/",
+                offset: responses.offset + 1,
+                length: 18,
+            }]
+        );
+        let entry = &index.entries[0];
+        assert!(bytes[entry.offset as usize..].ends_with(b"Hi"));
+        Ok(())
+    }
+
+    #[test]
+    fn parse_without_responses() -> Result<()> {
+        let bytes = bundle();
+        let responses_offset = parse_index(&bytes)?.sections[2].offset as usize;
+        assert_eq!(parse_index(&bytes[..responses_offset])?.entries.len(), 1);
+        assert_eq!(
+            parse_index(&bytes[..responses_offset - 1]),
+            Err(Error::UnexpectedEnd)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn parse_overflowing_index_entry() {
+        // {"a": [1, u64::MAX]}
+        let bytes = [
+            &[0xa1, 0x61, b'a', 0x82, 0x01, 0x1b][..],
+            &u64::MAX.to_be_bytes(),
+        ]
+        .concat();
+        assert_eq!(
+            parse_index_section(&bytes, 0, false),
+            Err(Error::Invalid("Response length overflows"))
+        );
+        assert_eq!(
+            parse_index_section(&bytes, u64::MAX, false),
+            Err(Error::Invalid("Response offset overflows"))
+        );
+    }
+
+    #[test]
+    fn parse_b1() -> Result<()> {
+        let bytes = include_bytes!("../../webbundle/tests/b1/hello.wbn");
+        let index = parse_index(bytes)?;
+        assert_eq!(&index.version, b"b1\0\0");
+        assert_eq!(index.primary_url, Some("https://example.com/"));
+        assert_eq!(
+            index.sections.iter().map(|s| s.name).collect::<Vec<_>>(),
+            vec!["index", "manifest", "responses"]
+        );
+        // The two variants of "/lang".
+        assert_eq!(
+            index.entries.iter().map(|e| e.url).collect::<Vec<_>>(),
+            vec![
+                "https://example.com/",
+                "https://example.com/lang",
+                "https://example.com/lang"
+            ]
+        );
+        assert!(index.entries[1].offset < index.entries[2].offset);
+
+        // A b2 bundle with the top array of b1.
+        let mut bytes = bundle();
+        bytes[0] = 0x86;
+        assert_eq!(parse_index(&bytes), Err(Error::Invalid("Invalid header")));
+        Ok(())
+    }
+
+    #[test]
+    fn parse_invalid() {
+        let mut bytes = bundle();
+        bytes[2] = 0;
+        assert_eq!(
+            parse_index(&bytes),
+            Err(Error::Invalid("Header magic mismatch"))
+        );
+        assert_eq!(parse_index(&[0x84]), Err(Error::Invalid("Invalid header")));
+    }
+}
@@ -0,0 +1,77 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # WebBundle core
+//!
+//! `webbundle-core` decodes the metadata and the index of a WebBundle
+//! without `std`, only with `alloc`, so that environments without `std::io`,
+//! e.g. WASI plugins or kernels, can enumerate the contents of a bundle.
+//!
+//! Responses are not decoded. Use `webbundle` crate to decode them.
+//!
+//! # Example
+//!
+//! ```
+//! # let bytes: &[u8] = &[];
+//! # let _ = || -> Result<(), webbundle_core::Error> {
+//! let index = webbundle_core::parse_index(bytes)?;
+//! for entry in &index.entries {
+//!     // `entry.offset..entry.offset + entry.length` is the range of the
+//!     // response in `bytes`.
+//!     let _ = (entry.url, entry.offset, entry.length);
+//! }
+//! # Ok(())
+//! # };
+//! ```
+//!
+//! # Features
+//!
+//! - `std`: Implements `std::error::Error` for [`Error`].
+
+#![no_std]
+
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+mod cbor;
+mod index;
+
+pub use index::{parse_index, Index, IndexEntry, Section};
+
+use core::fmt;
+
+/// A specialized `Result` type for this crate.
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// An error which occurs while decoding a bundle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The bytes end before all sections before the responses section.
+    UnexpectedEnd,
+    /// The bytes are not a valid bundle.
+    Invalid(&'static str),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnexpectedEnd => f.write_str("bundle: Unexpected end of bytes"),
+            Error::Invalid(reason) => write!(f, "bundle: {reason}"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
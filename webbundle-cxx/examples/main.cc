@@ -0,0 +1,36 @@
+#include "webbundle-cxx/src/lib.rs.h"
+
+#include <fstream>
+#include <iostream>
+#include <iterator>
+#include <string_view>
+#include <vector>
+
+// Print the exchanges of the given bundle.
+int main(int argc, char *argv[]) {
+  if (argc != 2) {
+    std::cerr << "usage: " << argv[0] << " filename" << std::endl;
+    return 1;
+  }
+  std::ifstream file(argv[1], std::ios::binary);
+  std::vector<uint8_t> bytes((std::istreambuf_iterator<char>(file)),
+                             std::istreambuf_iterator<char>());
+
+  try {
+    rust::Box<webbundle::Bundle> bundle = webbundle::parse(
+        rust::Slice<const uint8_t>(bytes.data(), bytes.size()));
+    std::cout << "primary_url: " << std::string(bundle->primary_url())
+              << std::endl;
+    for (size_t i = 0; i < bundle->exchanges_len(); i++) {
+      rust::Str url = bundle->url(i);
+      std::string_view url_view(url.data(), url.size());
+      rust::Slice<const uint8_t> body = bundle->body(i);
+      std::cout << url_view << " " << bundle->status(i) << " " << body.size()
+                << " bytes" << std::endl;
+    }
+  } catch (const rust::Error &e) {
+    std::cerr << "Failed to parse: " << e.what() << std::endl;
+    return 1;
+  }
+  return 0;
+}
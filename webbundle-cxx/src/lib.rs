@@ -0,0 +1,186 @@
+//! C++ bindings for `webbundle` crate, built with [cxx](https://cxx.rs/).
+//!
+//! Unlike `webbundle-ffi`, which provides a C interface, this provides C++
+//! types: `rust::Box` owns a bundle, `rust::Str` and `rust::Slice` refer to
+//! urls and bodies in the bundle without copying them, and errors are thrown
+//! as `rust::Error`.
+
+use cxx::CxxString;
+
+#[cxx::bridge(namespace = "webbundle")]
+mod ffi {
+    /// A header of a response.
+    struct Header {
+        name: String,
+        value: String,
+    }
+
+    extern "Rust" {
+        /// A parsed or built bundle, which is immutable.
+        type Bundle;
+        /// A builder to create a bundle.
+        type Builder;
+
+        /// Parses the given bytes as a bundle.
+        fn parse(bytes: &[u8]) -> Result<Box<Bundle>>;
+        /// Parses the file at the given path as a bundle.
+        fn parse_file(path: &CxxString) -> Result<Box<Bundle>>;
+
+        /// Returns `"b2"`, `"1"`, or 8 hex digits for an unknown version.
+        fn version(self: &Bundle) -> String;
+        /// Returns the primary url, or an empty string if there is none.
+        fn primary_url(self: &Bundle) -> String;
+        /// Returns the number of exchanges.
+        fn exchanges_len(self: &Bundle) -> usize;
+        /// Returns the url of the `index`-th exchange.
+        fn url(self: &Bundle, index: usize) -> Result<&str>;
+        /// Returns the status of the `index`-th exchange.
+        fn status(self: &Bundle, index: usize) -> Result<u16>;
+        /// Returns the headers of the `index`-th exchange.
+        fn headers(self: &Bundle, index: usize) -> Result<Vec<Header>>;
+        /// Returns the body of the `index`-th exchange, which is valid while
+        /// the bundle is alive.
+        fn body(self: &Bundle, index: usize) -> Result<&[u8]>;
+        /// Encodes the bundle.
+        fn encode(self: &Bundle) -> Result<Vec<u8>>;
+        /// Writes the bundle to the file at the given path.
+        fn write_file(self: &Bundle, path: &CxxString) -> Result<()>;
+
+        /// Creates a builder for the given version, `"b2"` or `"1"`.
+        fn new_builder(version: &str) -> Result<Box<Builder>>;
+        /// Sets the primary url.
+        fn set_primary_url(self: &mut Builder, url: &str) -> Result<()>;
+        /// Appends an exchange. The headers and the body are copied.
+        fn add_exchange(
+            self: &mut Builder,
+            url: &str,
+            status: u16,
+            headers: &[Header],
+            body: &[u8],
+        ) -> Result<()>;
+        /// Builds a bundle, consuming the builder.
+        fn build(builder: Box<Builder>) -> Result<Box<Bundle>>;
+    }
+}
+
+use anyhow::anyhow;
+use ffi::Header;
+use http::header::HeaderName;
+use webbundle::{Exchange, Response, Result, Version};
+
+pub struct Bundle(webbundle::Bundle);
+
+pub struct Builder(webbundle::Builder);
+
+fn parse(bytes: &[u8]) -> Result<Box<Bundle>> {
+    Ok(Box::new(Bundle(webbundle::Bundle::from_bytes(bytes)?)))
+}
+
+fn parse_file(path: &CxxString) -> Result<Box<Bundle>> {
+    let path = path.to_str()?;
+    let bytes = std::fs::read(path).map_err(|err| anyhow!("{path}: {err}"))?;
+    parse(&bytes)
+}
+
+impl Bundle {
+    fn version(&self) -> String {
+        self.0.version().to_string()
+    }
+
+    fn primary_url(&self) -> String {
+        self.0
+            .primary_url()
+            .as_ref()
+            .map(|url| url.to_string())
+            .unwrap_or_default()
+    }
+
+    fn exchanges_len(&self) -> usize {
+        self.0.exchanges().len()
+    }
+
+    fn exchange(&self, index: usize) -> Result<&Exchange> {
+        self.0
+            .exchanges()
+            .get(index)
+            .ok_or_else(|| anyhow!("No exchange at index {index}"))
+    }
+
+    fn url(&self, index: usize) -> Result<&str> {
+        Ok(self.exchange(index)?.request.url())
+    }
+
+    fn status(&self, index: usize) -> Result<u16> {
+        Ok(self.exchange(index)?.response.status().as_u16())
+    }
+
+    fn headers(&self, index: usize) -> Result<Vec<Header>> {
+        Ok(self
+            .exchange(index)?
+            .response
+            .headers()
+            .iter()
+            .map(|(name, value)| Header {
+                name: name.to_string(),
+                value: String::from_utf8_lossy(value.as_bytes()).into_owned(),
+            })
+            .collect())
+    }
+
+    fn body(&self, index: usize) -> Result<&[u8]> {
+        Ok(self.exchange(index)?.response.body())
+    }
+
+    fn encode(&self) -> Result<Vec<u8>> {
+        self.0.encode()
+    }
+
+    fn write_file(&self, path: &CxxString) -> Result<()> {
+        self.0.write_to_file(path.to_str()?)
+    }
+}
+
+fn new_builder(version: &str) -> Result<Box<Builder>> {
+    let version = match version {
+        "b2" => Version::VersionB2,
+        "1" => Version::Version1,
+        _ => return Err(anyhow!("Unknown version: {version}")),
+    };
+    Ok(Box::new(Builder(
+        webbundle::Bundle::builder().version(version),
+    )))
+}
+
+impl Builder {
+    fn set_primary_url(&mut self, url: &str) -> Result<()> {
+        let url = url.parse()?;
+        self.0 = std::mem::take(&mut self.0).primary_url(url);
+        Ok(())
+    }
+
+    fn add_exchange(
+        &mut self,
+        url: &str,
+        status: u16,
+        headers: &[Header],
+        body: &[u8],
+    ) -> Result<()> {
+        let mut response = Response::new(body.to_vec());
+        *response.status_mut() = status.try_into()?;
+        for header in headers {
+            response.headers_mut().append(
+                HeaderName::from_bytes(header.name.as_bytes())?,
+                header.value.parse()?,
+            );
+        }
+        self.0 = std::mem::take(&mut self.0).exchange(Exchange {
+            request: url.into(),
+            response,
+        });
+        Ok(())
+    }
+}
+
+fn build(builder: Box<Builder>) -> Result<Box<Bundle>> {
+    Ok(Box::new(Bundle(builder.0.build()?)))
+}
@@ -14,7 +14,8 @@ fn main() {
         .to_string();
 
     cbindgen::Builder::new()
-        .with_crate(crate_dir)
+        .with_crate(&crate_dir)
+        .with_config(cbindgen::Config::from_root_or_default(&crate_dir))
         .with_language(cbindgen::Language::C)
         .generate()
         .expect("Unable to generate bindings")
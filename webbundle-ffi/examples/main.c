@@ -3,12 +3,14 @@
 #include <stdio.h>
 #include <stdlib.h>
 
-// Print primary url of the given bundle.
+// Print primary url of the given bundle, and write it to `output` if given.
 int main(int argc, char *argv[]) {
-  if (argc != 2) {
-    printf( "usage: %s filename", argv[0]);
+  if (argc != 2 && argc != 3) {
+    printf( "usage: %s filename [output]", argv[0]);
     return 1;
   }
+  assert(webbundle_abi_version() == WEBBUNDLE_ABI_VERSION);
+
   FILE *f = fopen(argv[1], "rb");
   fseek(f, 0, SEEK_END);
   long fsize = ftell(f);
@@ -18,6 +20,10 @@ int main(int argc, char *argv[]) {
   assert(read_size == fsize);
 
   const WebBundle* bundle = webbundle_parse(bytes, fsize);
+  if (bundle == NULL) {
+    printf("Failed to parse: %s\n", webbundle_last_error_message());
+    return 1;
+  }
 
   char primary_url[300];
   int primary_url_size = webbundle_primary_url(bundle, primary_url, 300 - 1);
@@ -27,6 +33,11 @@ int main(int argc, char *argv[]) {
 
   printf("primary_url: %s\n", primary_url);
 
+  if (argc == 3 && webbundle_write_file(bundle, argv[2]) != 0) {
+    printf("Failed to write: %s\n", webbundle_last_error_message());
+    return 1;
+  }
+
   // Closing
   fclose(f);
   free(bytes);
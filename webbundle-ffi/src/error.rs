@@ -0,0 +1,87 @@
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::fmt::Display;
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+
+/// No error.
+pub const WEBBUNDLE_OK: c_int = 0;
+/// An argument is null or invalid, e.g. a string which is not UTF-8.
+pub const WEBBUNDLE_ERROR_INVALID_ARGUMENT: c_int = 1;
+/// The bytes are not a valid WebBundle representation.
+pub const WEBBUNDLE_ERROR_PARSE: c_int = 2;
+/// The bundle can not be built or encoded.
+pub const WEBBUNDLE_ERROR_ENCODE: c_int = 3;
+/// An I/O error, e.g. failing to write a file.
+pub const WEBBUNDLE_ERROR_IO: c_int = 4;
+/// The user-provided buffer's length is not enough.
+pub const WEBBUNDLE_ERROR_BUFFER_TOO_SMALL: c_int = 5;
+/// The requested item doesn't exist, e.g. the primary url.
+pub const WEBBUNDLE_ERROR_NOT_FOUND: c_int = 6;
+
+pub(crate) struct Error {
+    code: c_int,
+    message: String,
+}
+
+impl Error {
+    pub(crate) fn new(code: c_int, message: impl Display) -> Self {
+        Error {
+            code,
+            message: message.to_string(),
+        }
+    }
+
+    pub(crate) fn invalid_argument(message: impl Display) -> Self {
+        Error::new(WEBBUNDLE_ERROR_INVALID_ARGUMENT, message)
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<(c_int, CString)>> = const { RefCell::new(None) };
+}
+
+/// Records the error as the last error of this thread, and returns `value`.
+pub(crate) fn fail<T>(err: Error, value: T) -> T {
+    // Messages come from Rust errors, which may contain NUL in theory.
+    let message = CString::new(err.message.replace('\0', "\\0")).unwrap_or_default();
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some((err.code, message)));
+    value
+}
+
+/// Get the error code of the last failed call on the current thread, or
+/// `WEBBUNDLE_OK` if no call has failed since [`webbundle_clear_last_error()`].
+///
+/// [`webbundle_clear_last_error()`]: fn.webbundle_clear_last_error.html
+#[no_mangle]
+pub extern "C" fn webbundle_last_error_code() -> c_int {
+    LAST_ERROR.with(|last_error| {
+        last_error
+            .borrow()
+            .as_ref()
+            .map_or(WEBBUNDLE_OK, |(code, _)| *code)
+    })
+}
+
+/// Get the NUL-terminated message which describes the last failed call on
+/// the current thread, or a null pointer if there is no error.
+///
+/// The returned pointer is valid until the next failed call or
+/// [`webbundle_clear_last_error()`] on the same thread. Don't free it.
+///
+/// [`webbundle_clear_last_error()`]: fn.webbundle_clear_last_error.html
+#[no_mangle]
+pub extern "C" fn webbundle_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|last_error| {
+        last_error
+            .borrow()
+            .as_ref()
+            .map_or(ptr::null(), |(_, message)| message.as_ptr())
+    })
+}
+
+/// Clear the last error of the current thread.
+#[no_mangle]
+pub extern "C" fn webbundle_clear_last_error() {
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = None);
+}
@@ -1,16 +1,90 @@
-use libc::size_t;
-// use std::ffi::CStr;
+use crate::error::{fail, Error};
+use http::header::{HeaderName, HeaderValue};
+use http::StatusCode;
+use libc::{size_t, ssize_t};
+use std::ffi::CStr;
 use std::os::raw::{c_char, c_int};
+use std::path::Path;
 use std::ptr;
 use std::slice;
-use webbundle::Bundle;
+use webbundle::{Builder, Bundle, DecodeOptions, Exchange, Response, Version};
 
+mod error;
+mod mmap;
+
+pub use error::*;
+
+/// The version of the ABI of this library, which is incremented when the ABI
+/// changes incompatibly. Compare it with [`webbundle_abi_version()`] at
+/// runtime to check the compatibility of the header and the library.
+///
+/// [`webbundle_abi_version()`]: fn.webbundle_abi_version.html
+pub const WEBBUNDLE_ABI_VERSION: u32 = 1;
+
+/// A parsed or built bundle.
+///
+/// A `WebBundle` is never modified after it is constructed, so it can be
+/// shared across threads, and functions which take `const WebBundle*` can be
+/// called concurrently. [`webbundle_destroy()`] must not be called while
+/// other threads are using it.
+///
+/// [`webbundle_destroy()`]: fn.webbundle_destroy.html
 pub struct WebBundle(Bundle);
 
+/// A builder to create a `WebBundle`.
+///
+/// A `WebBundleBuilder` can be moved to another thread, but must not be used
+/// from multiple threads at the same time.
+pub struct WebBundleBuilder(Builder);
+
+// Enforces the thread-safety contract documented above.
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    const fn assert_send<T: Send>() {}
+    assert_send_sync::<WebBundle>();
+    assert_send::<WebBundleBuilder>();
+};
+
+/// A header of a response, as a pair of NUL-terminated strings.
+#[repr(C)]
+pub struct WebBundleHeader {
+    pub name: *const c_char,
+    pub value: *const c_char,
+}
+
+/// Get the version of the ABI of this library, which should be equal to
+/// `WEBBUNDLE_ABI_VERSION` in the header used by the caller.
+#[no_mangle]
+pub extern "C" fn webbundle_abi_version() -> u32 {
+    WEBBUNDLE_ABI_VERSION
+}
+
+/// Options to parse untrusted input.
+#[repr(C)]
+pub struct WebBundleParseOptions {
+    /// The maximum number of exchanges, or `0` for unlimited.
+    pub max_exchanges: size_t,
+    /// The maximum number of bytes of each response body, or `0` for unlimited.
+    pub max_body_bytes: u64,
+    /// Rejects unknown sections and duplicate urls in the index if true.
+    pub strict: bool,
+}
+
+impl WebBundleParseOptions {
+    fn to_decode_options(&self) -> DecodeOptions {
+        let mut options = DecodeOptions::default();
+        options.max_exchanges = (self.max_exchanges != 0).then_some(self.max_exchanges);
+        options.max_body_bytes = (self.max_body_bytes != 0).then_some(self.max_body_bytes);
+        options.strict = self.strict;
+        options
+    }
+}
+
 /// Construct a new `WebBundle` from the provided `bytes`.
 ///
 /// If the bytes passed in isn't a valid WebBundle representation,
-/// this will return a null pointer.
+/// this will return a null pointer. See [`webbundle_last_error_message()`]
+/// for the reason.
 ///
 /// # Safety
 ///
@@ -18,12 +92,90 @@ pub struct WebBundle(Bundle);
 /// done with it.
 ///
 /// [`webbundle_destroy()`]: fn.webbundle_destroy.html
+/// [`webbundle_last_error_message()`]: fn.webbundle_last_error_message.html
 #[no_mangle]
 pub unsafe extern "C" fn webbundle_parse(bytes: *const c_char, length: size_t) -> *const WebBundle {
-    let slice = slice::from_raw_parts(bytes as *mut u8, length as usize);
-    match Bundle::from_bytes(slice) {
-        Ok(bundle) => Box::into_raw(Box::new(WebBundle(bundle))),
-        Err(_) => ptr::null(),
+    webbundle_parse_with_options(bytes, length, ptr::null())
+}
+
+/// Same as [`webbundle_parse()`], but with the given `options`, which should
+/// be used for untrusted input. If `options` is null, this is same as
+/// [`webbundle_parse()`].
+///
+/// # Safety
+///
+/// - The passed `options` must be null or a valid pointer.
+/// - Make sure you destroy the WebBundle with [`webbundle_destroy()`] once you are
+///   done with it.
+///
+/// [`webbundle_parse()`]: fn.webbundle_parse.html
+/// [`webbundle_destroy()`]: fn.webbundle_destroy.html
+#[no_mangle]
+pub unsafe extern "C" fn webbundle_parse_with_options(
+    bytes: *const c_char,
+    length: size_t,
+    options: *const WebBundleParseOptions,
+) -> *const WebBundle {
+    let slice = slice_or_empty(bytes as *const u8, length);
+    new_bundle(Bundle::from_bytes_with_options(
+        slice,
+        &decode_options(options),
+    ))
+}
+
+/// Construct a new `WebBundle` from the file at `path`.
+///
+/// The file is memory-mapped while it is parsed, instead of being read into
+/// a heap buffer. The file must not be modified during the call.
+///
+/// If the file can't be read or isn't a valid WebBundle representation,
+/// this will return a null pointer. See [`webbundle_last_error_message()`]
+/// for the reason.
+///
+/// # Safety
+///
+/// - The passed `path` must be a NUL-terminated string.
+/// - Make sure you destroy the WebBundle with [`webbundle_destroy()`] once you are
+///   done with it.
+///
+/// [`webbundle_destroy()`]: fn.webbundle_destroy.html
+/// [`webbundle_last_error_message()`]: fn.webbundle_last_error_message.html
+#[no_mangle]
+pub unsafe extern "C" fn webbundle_parse_file(path: *const c_char) -> *const WebBundle {
+    webbundle_parse_file_with_options(path, ptr::null())
+}
+
+/// Same as [`webbundle_parse_file()`], but with the given `options`, which
+/// should be used for untrusted input. If `options` is null, this is same as
+/// [`webbundle_parse_file()`].
+///
+/// # Safety
+///
+/// - The passed `path` must be a NUL-terminated string.
+/// - The passed `options` must be null or a valid pointer.
+/// - Make sure you destroy the WebBundle with [`webbundle_destroy()`] once you are
+///   done with it.
+///
+/// [`webbundle_parse_file()`]: fn.webbundle_parse_file.html
+/// [`webbundle_destroy()`]: fn.webbundle_destroy.html
+#[no_mangle]
+pub unsafe extern "C" fn webbundle_parse_file_with_options(
+    path: *const c_char,
+    options: *const WebBundleParseOptions,
+) -> *const WebBundle {
+    let path = match to_str(path, "path") {
+        Ok(path) => path,
+        Err(err) => return fail(err, ptr::null()),
+    };
+    let options = decode_options(options);
+    match mmap::with_file_bytes(Path::new(path), |bytes| {
+        Bundle::from_bytes_with_options(bytes, &options)
+    }) {
+        Ok(result) => new_bundle(result),
+        Err(err) => fail(
+            Error::new(WEBBUNDLE_ERROR_IO, format!("Failed to read {path}: {err}")),
+            ptr::null(),
+        ),
     }
 }
 
@@ -44,13 +196,17 @@ pub unsafe extern "C" fn webbundle_destroy(bundle: *mut WebBundle) {
 /// Copy the `bundle`'s primary_url into a user-provided `buffer`,
 /// returning the number of bytes copied.
 ///
-/// If there is no primary-url in the bundle, this returns `-1`.
-/// If user-provided buffer's length is not enough, this returns `-2`.
+/// If there is no primary-url in the bundle, or if user-provided buffer's
+/// length is not enough, this returns `-1`. [`webbundle_last_error_code()`]
+/// tells them apart, i.e. `WEBBUNDLE_ERROR_NOT_FOUND` or
+/// `WEBBUNDLE_ERROR_BUFFER_TOO_SMALL`.
 ///
 /// # Safety
 ///
 /// - The passed `bundle` must be a valid WebBundle created by [`webbundle_parse()`] function.
 /// - The user-provided `buffer` should have `length` length.
+///
+/// [`webbundle_last_error_code()`]: fn.webbundle_last_error_code.html
 #[no_mangle]
 pub unsafe extern "C" fn webbundle_primary_url(
     bundle: *const WebBundle,
@@ -58,21 +214,379 @@ pub unsafe extern "C" fn webbundle_primary_url(
     length: size_t,
 ) -> c_int {
     if bundle.is_null() {
-        return -1;
+        return fail(Error::invalid_argument("bundle is null"), -1);
     }
     let bundle: &Bundle = &((*bundle).0);
     if let Some(uri) = bundle.primary_url() {
         let uri = uri.to_string();
 
-        let buffer: &mut [u8] = slice::from_raw_parts_mut(buffer as *mut u8, length as usize);
-
-        if buffer.len() < uri.len() {
-            return -1;
+        if length < uri.len() {
+            return fail(
+                Error::new(
+                    WEBBUNDLE_ERROR_BUFFER_TOO_SMALL,
+                    format!("The primary url needs {} bytes", uri.len()),
+                ),
+                -1,
+            );
         }
 
-        ptr::copy_nonoverlapping(uri.as_ptr(), buffer.as_mut_ptr(), uri.len());
+        ptr::copy_nonoverlapping(uri.as_ptr(), buffer as *mut u8, uri.len());
         uri.len() as c_int
     } else {
-        -1
+        fail(
+            Error::new(WEBBUNDLE_ERROR_NOT_FOUND, "The bundle has no primary url"),
+            -1,
+        )
+    }
+}
+
+/// Get the number of exchanges in the `bundle`.
+///
+/// # Safety
+///
+/// The passed `bundle` must be a valid WebBundle created by [`webbundle_parse()`] function.
+///
+/// [`webbundle_parse()`]: fn.webbundle_parse.html
+#[no_mangle]
+pub unsafe extern "C" fn webbundle_exchanges_length(bundle: *const WebBundle) -> size_t {
+    if bundle.is_null() {
+        return fail(Error::invalid_argument("bundle is null"), 0);
+    }
+    (*bundle).0.exchanges().len()
+}
+
+/// Get the body of the `index`-th exchange in the `bundle` without copying it.
+///
+/// On success, this sets `out_ptr` to the pointer to the body and `out_length` to its
+/// length, and returns `0`. The body is owned by the bundle. The pointer is valid until
+/// the bundle is destroyed, and must not be freed nor written.
+/// If `index` is out of range, this returns `-1`.
+///
+/// # Safety
+///
+/// - The passed `bundle` must be a valid WebBundle created by [`webbundle_parse()`] function.
+/// - The passed `out_ptr` and `out_length` must be valid pointers.
+///
+/// [`webbundle_parse()`]: fn.webbundle_parse.html
+#[no_mangle]
+pub unsafe extern "C" fn webbundle_exchange_body(
+    bundle: *const WebBundle,
+    index: size_t,
+    out_ptr: *mut *const u8,
+    out_length: *mut size_t,
+) -> c_int {
+    if bundle.is_null() || out_ptr.is_null() || out_length.is_null() {
+        return fail(
+            Error::invalid_argument("bundle, out_ptr or out_length is null"),
+            -1,
+        );
+    }
+    match (*bundle).0.exchanges().get(index) {
+        Some(exchange) => {
+            let body = exchange.response.body();
+            *out_ptr = body.as_ptr();
+            *out_length = body.len();
+            0
+        }
+        None => fail(
+            Error::new(
+                WEBBUNDLE_ERROR_NOT_FOUND,
+                format!("No exchange at index {index}"),
+            ),
+            -1,
+        ),
+    }
+}
+
+/// Construct a new `WebBundleBuilder`.
+///
+/// # Safety
+///
+/// Make sure you pass the builder to [`webbundle_builder_build()`] or
+/// [`webbundle_builder_destroy()`] once you are done with it.
+///
+/// [`webbundle_builder_build()`]: fn.webbundle_builder_build.html
+/// [`webbundle_builder_destroy()`]: fn.webbundle_builder_destroy.html
+#[no_mangle]
+pub unsafe extern "C" fn webbundle_builder_new() -> *mut WebBundleBuilder {
+    Box::into_raw(Box::new(WebBundleBuilder(Bundle::builder())))
+}
+
+/// Destroy a `WebBundleBuilder` without building a bundle.
+///
+/// # Safety
+///
+/// The passed `builder` must be a valid builder created by [`webbundle_builder_new()`] function.
+///
+/// [`webbundle_builder_new()`]: fn.webbundle_builder_new.html
+#[no_mangle]
+pub unsafe extern "C" fn webbundle_builder_destroy(builder: *mut WebBundleBuilder) {
+    if !builder.is_null() {
+        drop(Box::from_raw(builder));
+    }
+}
+
+/// Set the version of the bundle to be built, `"b2"` or `"1"`.
+///
+/// Returns `0` on success, or `-1` if the version is unknown.
+///
+/// # Safety
+///
+/// - The passed `builder` must be a valid builder created by [`webbundle_builder_new()`] function.
+/// - The passed `version` must be a NUL-terminated string.
+///
+/// [`webbundle_builder_new()`]: fn.webbundle_builder_new.html
+#[no_mangle]
+pub unsafe extern "C" fn webbundle_builder_set_version(
+    builder: *mut WebBundleBuilder,
+    version: *const c_char,
+) -> c_int {
+    let version = match to_str(version, "version") {
+        Ok("b2") => Version::VersionB2,
+        Ok("1") => Version::Version1,
+        Ok(version) => {
+            return fail(
+                Error::invalid_argument(format!("Unknown version: {version}")),
+                -1,
+            )
+        }
+        Err(err) => return fail(err, -1),
+    };
+    update_builder(builder, |builder| builder.version(version))
+}
+
+/// Set the primary url of the bundle to be built.
+///
+/// Returns `0` on success, or `-1` if the url is invalid.
+///
+/// # Safety
+///
+/// - The passed `builder` must be a valid builder created by [`webbundle_builder_new()`] function.
+/// - The passed `url` must be a NUL-terminated string.
+///
+/// [`webbundle_builder_new()`]: fn.webbundle_builder_new.html
+#[no_mangle]
+pub unsafe extern "C" fn webbundle_builder_set_primary_url(
+    builder: *mut WebBundleBuilder,
+    url: *const c_char,
+) -> c_int {
+    let url = match to_str(url, "url").and_then(|url| {
+        url.parse()
+            .map_err(|err| Error::invalid_argument(format!("Invalid url {url}: {err}")))
+    }) {
+        Ok(url) => url,
+        Err(err) => return fail(err, -1),
+    };
+    update_builder(builder, |builder| builder.primary_url(url))
+}
+
+/// Append an exchange to the bundle to be built. The `headers` and the
+/// `body` are copied.
+///
+/// Returns `0` on success, or `-1` if any argument is invalid.
+///
+/// # Safety
+///
+/// - The passed `builder` must be a valid builder created by [`webbundle_builder_new()`] function.
+/// - The passed `url` and the names and the values of `headers` must be NUL-terminated strings.
+/// - The passed `headers` should have `headers_length` length, and `body` should have
+///   `body_length` length. They can be null if their length is `0`.
+///
+/// [`webbundle_builder_new()`]: fn.webbundle_builder_new.html
+#[no_mangle]
+pub unsafe extern "C" fn webbundle_builder_append_exchange(
+    builder: *mut WebBundleBuilder,
+    url: *const c_char,
+    status: u16,
+    headers: *const WebBundleHeader,
+    headers_length: size_t,
+    body: *const u8,
+    body_length: size_t,
+) -> c_int {
+    let url = match to_str(url, "url") {
+        Ok(url) => url,
+        Err(err) => return fail(err, -1),
+    };
+    let headers = slice_or_empty(headers, headers_length);
+    let body = slice_or_empty(body, body_length);
+    let exchange = match new_exchange(url, status, headers, body) {
+        Ok(exchange) => exchange,
+        Err(err) => return fail(err, -1),
+    };
+    update_builder(builder, |builder| builder.exchange(exchange))
+}
+
+/// Build a `WebBundle`, consuming the `builder`.
+///
+/// The `builder` is destroyed even if it fails. If the version isn't set,
+/// this will return a null pointer. See [`webbundle_last_error_message()`]
+/// for the reason.
+///
+/// # Safety
+///
+/// - The passed `builder` must be a valid builder created by [`webbundle_builder_new()`] function.
+/// - Make sure you destroy the WebBundle with [`webbundle_destroy()`] once you are done with it.
+///
+/// [`webbundle_builder_new()`]: fn.webbundle_builder_new.html
+/// [`webbundle_destroy()`]: fn.webbundle_destroy.html
+/// [`webbundle_last_error_message()`]: fn.webbundle_last_error_message.html
+#[no_mangle]
+pub unsafe extern "C" fn webbundle_builder_build(
+    builder: *mut WebBundleBuilder,
+) -> *const WebBundle {
+    if builder.is_null() {
+        return fail(Error::invalid_argument("builder is null"), ptr::null());
+    }
+    match Box::from_raw(builder).0.build() {
+        Ok(bundle) => Box::into_raw(Box::new(WebBundle(bundle))),
+        Err(err) => fail(
+            Error::new(WEBBUNDLE_ERROR_ENCODE, format!("{err:#}")),
+            ptr::null(),
+        ),
+    }
+}
+
+/// Encode the `bundle` into a user-provided `buffer`, returning the number
+/// of bytes written. A parsed bundle is re-encoded, not copied from the
+/// parsed bytes.
+///
+/// If `buffer` is null, this returns the number of bytes required, without
+/// writing anything.
+/// If user-provided buffer's length is not enough, this returns `-2`.
+/// If the bundle can not be encoded, this returns `-1`.
+///
+/// # Safety
+///
+/// - The passed `bundle` must be a valid WebBundle created by [`webbundle_parse()`] or
+///   [`webbundle_builder_build()`] function.
+/// - The user-provided `buffer` should have `length` length.
+///
+/// [`webbundle_parse()`]: fn.webbundle_parse.html
+/// [`webbundle_builder_build()`]: fn.webbundle_builder_build.html
+#[no_mangle]
+pub unsafe extern "C" fn webbundle_encode(
+    bundle: *const WebBundle,
+    buffer: *mut u8,
+    length: size_t,
+) -> ssize_t {
+    if bundle.is_null() {
+        return fail(Error::invalid_argument("bundle is null"), -1);
+    }
+    let bytes = match (*bundle).0.encode() {
+        Ok(bytes) => bytes,
+        Err(err) => return fail(Error::new(WEBBUNDLE_ERROR_ENCODE, format!("{err:#}")), -1),
+    };
+    if buffer.is_null() {
+        return bytes.len() as ssize_t;
+    }
+    if length < bytes.len() {
+        return fail(
+            Error::new(
+                WEBBUNDLE_ERROR_BUFFER_TOO_SMALL,
+                format!("The bundle needs {} bytes", bytes.len()),
+            ),
+            -2,
+        );
+    }
+    ptr::copy_nonoverlapping(bytes.as_ptr(), buffer, bytes.len());
+    bytes.len() as ssize_t
+}
+
+/// Write the `bundle` to the file at `path`.
+///
+/// The file is replaced atomically, so `path` can be the file from which the
+/// bundle is parsed. Returns `0` on success, or `-1` on failure.
+///
+/// # Safety
+///
+/// - The passed `bundle` must be a valid WebBundle created by [`webbundle_parse()`] or
+///   [`webbundle_builder_build()`] function.
+/// - The passed `path` must be a NUL-terminated string.
+///
+/// [`webbundle_parse()`]: fn.webbundle_parse.html
+/// [`webbundle_builder_build()`]: fn.webbundle_builder_build.html
+#[no_mangle]
+pub unsafe extern "C" fn webbundle_write_file(
+    bundle: *const WebBundle,
+    path: *const c_char,
+) -> c_int {
+    let path = match to_str(path, "path") {
+        Ok(path) => path,
+        Err(err) => return fail(err, -1),
+    };
+    if bundle.is_null() {
+        return fail(Error::invalid_argument("bundle is null"), -1);
+    }
+    match (*bundle).0.write_to_file(path) {
+        Ok(()) => 0,
+        Err(err) => fail(Error::new(WEBBUNDLE_ERROR_IO, format!("{err:#}")), -1),
+    }
+}
+
+fn new_bundle(result: webbundle::Result<Bundle>) -> *const WebBundle {
+    match result {
+        Ok(bundle) => Box::into_raw(Box::new(WebBundle(bundle))),
+        Err(err) => fail(
+            Error::new(WEBBUNDLE_ERROR_PARSE, format!("{err:#}")),
+            ptr::null(),
+        ),
+    }
+}
+
+unsafe fn decode_options(options: *const WebBundleParseOptions) -> DecodeOptions {
+    options
+        .as_ref()
+        .map(WebBundleParseOptions::to_decode_options)
+        .unwrap_or_default()
+}
+
+unsafe fn update_builder(
+    builder: *mut WebBundleBuilder,
+    f: impl FnOnce(Builder) -> Builder,
+) -> c_int {
+    if builder.is_null() {
+        return fail(Error::invalid_argument("builder is null"), -1);
+    }
+    let builder = &mut (*builder).0;
+    *builder = f(std::mem::take(builder));
+    0
+}
+
+unsafe fn new_exchange(
+    url: &str,
+    status: u16,
+    headers: &[WebBundleHeader],
+    body: &[u8],
+) -> Result<Exchange, Error> {
+    let mut response = Response::new(body.to_vec());
+    *response.status_mut() = StatusCode::from_u16(status).map_err(Error::invalid_argument)?;
+    for header in headers {
+        let name = to_str(header.name, "header name")?;
+        let value = to_str(header.value, "header value")?;
+        response.headers_mut().append(
+            HeaderName::from_bytes(name.as_bytes()).map_err(Error::invalid_argument)?,
+            HeaderValue::from_str(value).map_err(Error::invalid_argument)?,
+        );
+    }
+    Ok(Exchange {
+        request: url.into(),
+        response,
+    })
+}
+
+unsafe fn to_str<'a>(s: *const c_char, name: &str) -> Result<&'a str, Error> {
+    if s.is_null() {
+        return Err(Error::invalid_argument(format!("{name} is null")));
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|_| Error::invalid_argument(format!("{name} is not UTF-8")))
+}
+
+unsafe fn slice_or_empty<'a, T>(data: *const T, length: size_t) -> &'a [T] {
+    if data.is_null() || length == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(data, length)
     }
 }
@@ -0,0 +1,56 @@
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+/// Calls `f` with the contents of the file at `path`, which are
+/// memory-mapped on Unix, or read into memory on other platforms.
+#[cfg(unix)]
+pub(crate) fn with_file_bytes<T>(path: &Path, f: impl FnOnce(&[u8]) -> T) -> io::Result<T> {
+    use std::os::unix::io::AsRawFd;
+    use std::{ptr, slice};
+
+    struct Mmap {
+        ptr: *mut libc::c_void,
+        len: usize,
+    }
+
+    impl Drop for Mmap {
+        fn drop(&mut self) {
+            unsafe { libc::munmap(self.ptr, self.len) };
+        }
+    }
+
+    let file = File::open(path)?;
+    let len = usize::try_from(file.metadata()?.len())
+        .map_err(|_| io::Error::other("The file is too large"))?;
+    // mmap(2) fails with a zero length.
+    if len == 0 {
+        return Ok(f(&[]));
+    }
+    let ptr = unsafe {
+        libc::mmap(
+            ptr::null_mut(),
+            len,
+            libc::PROT_READ,
+            libc::MAP_PRIVATE,
+            file.as_raw_fd(),
+            0,
+        )
+    };
+    if ptr == libc::MAP_FAILED {
+        return Err(io::Error::last_os_error());
+    }
+    let mmap = Mmap { ptr, len };
+    // The mapping stays valid after the file is closed, until munmap.
+    let bytes = unsafe { slice::from_raw_parts(mmap.ptr as *const u8, mmap.len) };
+    Ok(f(bytes))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn with_file_bytes<T>(path: &Path, f: impl FnOnce(&[u8]) -> T) -> io::Result<T> {
+    use std::io::Read as _;
+
+    let mut bytes = Vec::new();
+    File::open(path)?.read_to_end(&mut bytes)?;
+    Ok(f(&bytes))
+}
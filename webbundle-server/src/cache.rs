@@ -0,0 +1,415 @@
+//! Caches the bundles built from directories, so that a directory is encoded
+//! again only if its files have changed.
+
+use crate::metrics::Metrics;
+use crate::range;
+use axum::body::Bytes;
+use headers::ETag;
+use http::HeaderValue;
+use ring::digest::{digest, SHA256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime};
+use tokio::sync::Mutex;
+use webbundle::{Bundle, Request, SigningKey, Version};
+
+/// The paths, the modification times and the sizes of the files under a
+/// directory. A bundle is rebuilt if this changes.
+///
+/// Symlinks are not followed, as `DirEntry::file_type` and
+/// `DirEntry::metadata` describe the links themselves. This matches the
+/// bundle, which skips symlinks, so a change of their targets doesn't
+/// change the bundle either.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DirState(Vec<(PathBuf, SystemTime, u64)>);
+
+impl DirState {
+    pub(crate) async fn read(dir: &Path) -> std::io::Result<DirState> {
+        let mut files = Vec::new();
+        let mut dirs = vec![dir.to_path_buf()];
+        while let Some(dir) = dirs.pop() {
+            let mut read_dir = tokio::fs::read_dir(&dir).await?;
+            while let Some(entry) = read_dir.next_entry().await? {
+                let file_type = entry.file_type().await?;
+                if file_type.is_dir() {
+                    dirs.push(entry.path());
+                } else if file_type.is_file() {
+                    let metadata = entry.metadata().await?;
+                    files.push((entry.path(), metadata.modified()?, metadata.len()));
+                }
+            }
+        }
+        files.sort();
+        Ok(DirState(files))
+    }
+}
+
+/// Signs bundles as an Isolated Web App, whose urls are in the
+/// `isolated-app://<Web Bundle ID>/` origin.
+#[derive(Debug)]
+pub(crate) struct Signer {
+    key: SigningKey,
+    origin: String,
+}
+
+impl Signer {
+    pub(crate) fn new(key: SigningKey) -> Signer {
+        let origin = format!("isolated-app://{}/", key.web_bundle_id());
+        Signer { key, origin }
+    }
+
+    /// The origin of the signed bundles, e.g.
+    /// `isolated-app://<Web Bundle ID>/`.
+    pub(crate) fn origin(&self) -> &str {
+        &self.origin
+    }
+
+    /// Moves the exchanges, whose urls are relative to the directory, into
+    /// the origin.
+    fn rebase(&self, bundle: &mut Bundle) {
+        for exchange in bundle.exchanges_mut() {
+            exchange.request = Request::new(
+                format!("{}{}", self.origin, exchange.request.url()),
+                exchange.request.headers().clone(),
+            );
+        }
+    }
+}
+
+/// A bundle and its encoded bytes.
+#[derive(Debug, Clone)]
+pub(crate) struct CachedBundle {
+    pub(crate) bundle: Arc<Bundle>,
+    /// The encoded bundle, which is signed if `signer` is given.
+    pub(crate) bytes: Bytes,
+    /// The SHA-256 of the bytes, which doesn't change unless the bundle
+    /// changes.
+    pub(crate) etag: ETag,
+    /// `Content-Digest` of the bytes.
+    pub(crate) content_digest: HeaderValue,
+    signer: Option<Arc<Signer>>,
+}
+
+impl CachedBundle {
+    fn new(bundle: Bundle, signer: Option<Arc<Signer>>) -> anyhow::Result<CachedBundle> {
+        let mut bytes = bundle.encode()?;
+        if let Some(signer) = &signer {
+            bytes = webbundle::sign(&bytes, &signer.key)?;
+        }
+        let sha256 = digest(&SHA256, &bytes);
+        Ok(CachedBundle {
+            bundle: Arc::new(bundle),
+            bytes: Bytes::from(bytes),
+            etag: format!("\"{}\"", hex(sha256.as_ref()))
+                .parse()
+                .expect("a valid ETag"),
+            content_digest: range::content_digest(sha256.as_ref()),
+            signer,
+        })
+    }
+
+    /// Returns the bundle which has only the exchanges for which the given
+    /// predicate returns true. The predicate takes the url relative to the
+    /// directory, e.g. `a.js`, even if the bundle is signed.
+    pub(crate) fn filter(&self, f: impl Fn(&str) -> bool) -> anyhow::Result<CachedBundle> {
+        let mut bundle = Bundle::clone(&self.bundle);
+        bundle.retain(|exchange| {
+            let url = exchange.request.url().as_str();
+            match &self.signer {
+                Some(signer) => f(url.strip_prefix(signer.origin()).unwrap_or(url)),
+                None => f(url),
+            }
+        });
+        CachedBundle::new(bundle, self.signer.clone())
+    }
+}
+
+/// Returns the SHA-256 of the bytes, as a lowercase hex string.
+pub(crate) fn sha256_hex(bytes: &[u8]) -> String {
+    hex(digest(&SHA256, bytes).as_ref())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// The default of the byte budget of [`BundleCache`].
+pub(crate) const DEFAULT_CACHE_BYTES: u64 = 256 * 1024 * 1024;
+
+struct Entry {
+    state: DirState,
+    bundle: CachedBundle,
+    /// The tick when this entry was used last.
+    last_used: u64,
+}
+
+impl Entry {
+    /// The bytes which this entry holds, i.e. the encoded bundle and the
+    /// bodies of the decoded one.
+    fn bytes(&self) -> u64 {
+        self.bundle.bytes.len() as u64
+            + self
+                .bundle
+                .bundle
+                .exchanges()
+                .iter()
+                .map(|exchange| exchange.response.body().len() as u64)
+                .sum::<u64>()
+    }
+}
+
+/// The directory and the version of a bundle.
+type Key = (PathBuf, [u8; 4]);
+
+#[derive(Default)]
+struct Entries {
+    map: HashMap<Key, Entry>,
+    bytes: u64,
+    tick: u64,
+}
+
+/// The encoded bundles, keyed by the directory and the version. The least
+/// recently used bundles are evicted when the bundles exceed the byte budget.
+pub(crate) struct BundleCache {
+    max_bytes: u64,
+    metrics: Arc<Metrics>,
+    signer: Option<Arc<Signer>>,
+    entries: Mutex<Entries>,
+    /// The locks for the keys which are being built, so that concurrent
+    /// requests for the same bundle wait for one build.
+    building: std::sync::Mutex<HashMap<Key, Arc<Mutex<()>>>>,
+}
+
+impl BundleCache {
+    /// Creates a cache which holds bundles up to `max_bytes`. The bundles are
+    /// signed if `signer` is given.
+    pub(crate) fn new(
+        max_bytes: u64,
+        metrics: Arc<Metrics>,
+        signer: Option<Arc<Signer>>,
+    ) -> BundleCache {
+        BundleCache {
+            max_bytes,
+            metrics,
+            signer,
+            entries: Mutex::default(),
+            building: std::sync::Mutex::default(),
+        }
+    }
+
+    /// Returns the encoded bundle of the directory, which is built only if
+    /// the directory has changed since the last build.
+    ///
+    /// Concurrent calls for the same bundle are coalesced: one of them builds
+    /// the bundle, and the others wait for it, and get it from the cache.
+    pub(crate) async fn get_or_build(
+        &self,
+        dir: &Path,
+        version: &Version,
+    ) -> anyhow::Result<CachedBundle> {
+        let key = (dir.to_path_buf(), *version.bytes());
+        let lock = self
+            .building
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_default()
+            .clone();
+        let result = {
+            let _guard = lock.lock().await;
+            self.get_or_build_locked(key.clone(), dir, version).await
+        };
+        let mut building = self.building.lock().unwrap();
+        // No one else waits for the lock, other than `building`.
+        if Arc::strong_count(&lock) == 2 {
+            building.remove(&key);
+        }
+        result
+    }
+
+    async fn get_or_build_locked(
+        &self,
+        key: Key,
+        dir: &Path,
+        version: &Version,
+    ) -> anyhow::Result<CachedBundle> {
+        let state = DirState::read(dir).await?;
+        {
+            let mut entries = self.entries.lock().await;
+            entries.tick += 1;
+            let tick = entries.tick;
+            if let Some(entry) = entries.map.get_mut(&key) {
+                if entry.state == state {
+                    tracing::debug!("cache hit: {}", dir.display());
+                    self.metrics.record_cache_hit();
+                    entry.last_used = tick;
+                    return Ok(entry.bundle.clone());
+                }
+            }
+        }
+        let start = Instant::now();
+        let mut bundle = Bundle::builder()
+            .version(version.clone())
+            .exchanges_from_dir(dir)
+            .await?
+            .build()?;
+        if let Some(signer) = &self.signer {
+            signer.rebase(&mut bundle);
+        }
+        let encode_start = Instant::now();
+        let bundle = CachedBundle::new(bundle, self.signer.clone())?;
+        let (build, encode) = (start.elapsed(), encode_start.elapsed());
+        self.metrics.record_build(build, encode);
+        tracing::info!(
+            dir = %dir.display(),
+            bytes = bundle.bytes.len(),
+            build_ms = build.as_secs_f64() * 1e3,
+            encode_ms = encode.as_secs_f64() * 1e3,
+            "built bundle"
+        );
+        self.insert(key, state, bundle.clone()).await;
+        Ok(bundle)
+    }
+
+    async fn insert(&self, key: Key, state: DirState, bundle: CachedBundle) {
+        let mut entries = self.entries.lock().await;
+        entries.tick += 1;
+        let entry = Entry {
+            state,
+            bundle,
+            last_used: entries.tick,
+        };
+        if let Some(old) = entries.map.remove(&key) {
+            entries.bytes -= old.bytes();
+        }
+        if entry.bytes() > self.max_bytes {
+            tracing::debug!("too large to cache: {}", key.0.display());
+            return;
+        }
+        entries.bytes += entry.bytes();
+        entries.map.insert(key, entry);
+        while entries.bytes > self.max_bytes {
+            let oldest = entries
+                .map
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+                .expect("the cache is not empty");
+            let evicted = entries.map.remove(&oldest).expect("the oldest entry");
+            entries.bytes -= evicted.bytes();
+            self.metrics.record_cache_eviction();
+            tracing::debug!("evicted: {}", oldest.0.display());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn rebuild_if_changed() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::write(dir.path().join("a.txt"), "a")?;
+        let cache = BundleCache::new(DEFAULT_CACHE_BYTES, Arc::default(), None);
+        let bundle = cache.get_or_build(dir.path(), &Version::VersionB2).await?;
+        let cached = cache.get_or_build(dir.path(), &Version::VersionB2).await?;
+        assert_eq!(cached.bytes, bundle.bytes);
+        assert_eq!(cached.etag, bundle.etag);
+
+        std::fs::create_dir(dir.path().join("b"))?;
+        std::fs::write(dir.path().join("b/c.txt"), "c")?;
+        let rebuilt = cache.get_or_build(dir.path(), &Version::VersionB2).await?;
+        assert_ne!(rebuilt.bytes, bundle.bytes);
+        assert_ne!(rebuilt.etag, bundle.etag);
+        let bundle = Bundle::from_bytes(&rebuilt.bytes)?;
+        assert!(bundle
+            .exchanges()
+            .iter()
+            .any(|exchange| exchange.request.url() == "b/c.txt"));
+
+        let subset = rebuilt.filter(|url| url == "b/c.txt")?;
+        let bundle = Bundle::from_bytes(&subset.bytes)?;
+        assert_eq!(bundle.exchanges().len(), 1);
+        assert_eq!(bundle.exchanges()[0].request.url(), "b/c.txt");
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn dir_state_skips_symlinks() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let outside = tempfile::tempdir()?;
+        std::fs::write(dir.path().join("a.txt"), "a")?;
+        std::fs::write(outside.path().join("b.txt"), "b")?;
+        std::os::unix::fs::symlink(outside.path().join("b.txt"), dir.path().join("b.txt"))?;
+        std::os::unix::fs::symlink(outside.path(), dir.path().join("c"))?;
+        let state = DirState::read(dir.path()).await?;
+        assert_eq!(state.0.len(), 1);
+        assert_eq!(state.0[0].0, dir.path().join("a.txt"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn evict_least_recently_used() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        for name in ["a", "b", "c"] {
+            std::fs::create_dir(dir.path().join(name))?;
+            std::fs::write(dir.path().join(name).join("index.txt"), [b'x'; 1000])?;
+        }
+        let metrics = Arc::new(Metrics::default());
+        // Enough for two bundles, but not for three.
+        let cache = BundleCache::new(5000, metrics.clone(), None);
+        let get = |name: &str| {
+            let dir = dir.path().join(name);
+            let cache = &cache;
+            async move { cache.get_or_build(&dir, &Version::VersionB2).await }
+        };
+        get("a").await?;
+        get("b").await?;
+        get("a").await?;
+        // Evicts "b", which is used less recently than "a".
+        get("c").await?;
+        assert_eq!(metrics.cache_evictions(), 1);
+        let hits = metrics.cache_hits();
+        get("a").await?;
+        assert_eq!(metrics.cache_hits(), hits + 1);
+        get("b").await?;
+        assert_eq!(metrics.cache_hits(), hits + 1);
+
+        // Too large to cache.
+        let cache = BundleCache::new(100, metrics.clone(), None);
+        cache
+            .get_or_build(&dir.path().join("a"), &Version::VersionB2)
+            .await?;
+        assert!(cache.entries.lock().await.map.is_empty());
+        Ok(())
+    }
+
+    // Building a bundle doesn't yield, so builds run concurrently only on
+    // different threads.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn coalesce_concurrent_builds() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        for i in 0..100 {
+            std::fs::write(dir.path().join(format!("{i}.txt")), [b'x'; 10000])?;
+        }
+        let metrics = Arc::new(Metrics::default());
+        let cache = Arc::new(BundleCache::new(DEFAULT_CACHE_BYTES, metrics.clone(), None));
+        let tasks = (0..4)
+            .map(|_| {
+                let cache = cache.clone();
+                let dir = dir.path().to_path_buf();
+                tokio::spawn(async move { cache.get_or_build(&dir, &Version::VersionB2).await })
+            })
+            .collect::<Vec<_>>();
+        for task in tasks {
+            task.await??;
+        }
+        assert_eq!(metrics.builds(), 1);
+        assert_eq!(metrics.cache_hits(), 3);
+        assert!(cache.building.lock().unwrap().is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,108 @@
+//! Compresses responses with gzip, deflate or brotli, as `Accept-Encoding`
+//! of the request allows.
+
+use crate::range::CONTENT_DIGEST;
+use axum::{body::Body, Router};
+use axum_extra::middleware::{self, Next};
+use headers::{ETag, HeaderMapExt as _};
+use http::{header, Extensions, HeaderMap, HeaderValue, Request, StatusCode, Version};
+use tower_http::compression::{
+    predicate::{DefaultPredicate, Predicate as _},
+    CompressionLayer,
+};
+
+/// Range requests are served from the uncompressed bytes, so partial
+/// responses must not be compressed.
+fn is_not_partial(status: StatusCode, _: Version, headers: &HeaderMap, _: &Extensions) -> bool {
+    status != StatusCode::PARTIAL_CONTENT && !headers.contains_key(header::CONTENT_RANGE)
+}
+
+/// Adds `Vary: Accept-Encoding`, and makes `ETag` of a compressed response
+/// weak, because the compressed bytes differ from the bytes which the strong
+/// `ETag` identifies. `If-None-Match` still matches it, with the weak
+/// comparison. `Content-Digest` of the uncompressed bytes is removed for the
+/// same reason, and so is `Accept-Ranges`, as ranges of the compressed bytes
+/// are not served.
+async fn fix_headers(req: Request<Body>, next: Next<Body>) -> http::Response<axum::body::BoxBody> {
+    let mut response = next.run(req).await;
+    let headers = response.headers_mut();
+    headers.append(header::VARY, HeaderValue::from_static("accept-encoding"));
+    if headers.contains_key(header::CONTENT_ENCODING) {
+        headers.remove(CONTENT_DIGEST);
+        headers.remove(header::ACCEPT_RANGES);
+        if let Some(etag) = headers.get(header::ETAG) {
+            if !etag.as_bytes().starts_with(b"W/") {
+                let weak = format!("W/{}", etag.to_str().unwrap_or_default());
+                if let Ok(weak) = weak.parse::<ETag>() {
+                    headers.typed_insert(weak);
+                }
+            }
+        }
+    }
+    response
+}
+
+/// Compresses the responses of the router. The `Content-Type`, e.g.
+/// `application/webbundle`, and `X-Content-Type-Options: nosniff` are kept as
+/// they are.
+pub(crate) fn compress(router: Router) -> Router {
+    router
+        .layer(CompressionLayer::new().compress_when(DefaultPredicate::new().and(is_not_partial)))
+        .layer(middleware::from_fn(fix_headers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::range::range_response;
+    use axum::{body::Bytes, routing::get};
+    use tower::ServiceExt as _;
+
+    async fn get_bundle(headers: &[(&str, &str)]) -> http::Response<axum::body::BoxBody> {
+        let router = compress(Router::new().route(
+            "/",
+            get(|req: Request<Body>| async move {
+                let mut response = range_response(
+                    req.headers(),
+                    Bytes::from(vec![b'a'; 1000]),
+                    &"\"abc\"".parse().unwrap(),
+                    &HeaderValue::from_static("sha-256=:abc:"),
+                );
+                response.headers_mut().insert(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static("application/webbundle"),
+                );
+                response
+            }),
+        ));
+        let mut req = Request::builder().uri("/");
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        router
+            .oneshot(req.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn compress_responses() {
+        let response = get_bundle(&[("accept-encoding", "gzip")]).await;
+        assert_eq!(response.headers()["content-encoding"], "gzip");
+        assert_eq!(response.headers()["content-type"], "application/webbundle");
+        assert_eq!(response.headers()["vary"], "accept-encoding");
+        assert_eq!(response.headers()["etag"], "W/\"abc\"");
+        assert!(!response.headers().contains_key("content-digest"));
+        assert!(!response.headers().contains_key("accept-ranges"));
+
+        let response = get_bundle(&[]).await;
+        assert!(!response.headers().contains_key("content-encoding"));
+        assert_eq!(response.headers()["etag"], "\"abc\"");
+        assert_eq!(response.headers()["content-digest"], "sha-256=:abc:");
+        assert_eq!(response.headers()["accept-ranges"], "bytes");
+
+        let response = get_bundle(&[("accept-encoding", "br"), ("range", "bytes=0-9")]).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert!(!response.headers().contains_key("content-encoding"));
+    }
+}
@@ -0,0 +1,244 @@
+//! The configuration file, which defines what the server serves.
+
+use crate::cache::DEFAULT_CACHE_BYTES;
+use anyhow::Context as _;
+use http::header::{HeaderName, HeaderValue};
+use http::HeaderMap;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use webbundle::Version;
+
+/// The configuration of the server, e.g.:
+///
+/// ```toml
+/// # Compresses responses. Defaults to false.
+/// compress = true
+/// # Serves the metrics at `/metrics`. Defaults to true.
+/// metrics = true
+/// # The byte budget of the cache of generated bundles. Defaults to 256 MiB.
+/// cache_bytes = 268435456
+/// # Signs the generated bundles with the Ed25519 private key, as an
+/// # Isolated Web App. Not signed by default.
+/// sign_key = "key.pem"
+///
+/// # Added to all responses.
+/// [headers]
+/// cross-origin-opener-policy = "same-origin"
+///
+/// # Serves the files in the directory, and the bundles of its
+/// # subdirectories at `/wbn/<subdirectory>/`.
+/// [[mount]]
+/// path = "/"
+/// source = "public"
+/// # The version of the bundles. Defaults to "b2".
+/// version = "b2"
+///
+/// # Serves the resources in the bundle file, at `/site/`.
+/// [[mount]]
+/// path = "/site/"
+/// source = "site.wbn"
+/// base_url = "https://example.com/"
+/// headers = { cache-control = "no-cache" }
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[non_exhaustive]
+pub struct Config {
+    /// Whether to compress responses with gzip, deflate or brotli.
+    #[serde(default)]
+    pub compress: bool,
+    /// Whether to serve the metrics at `/metrics`, in the Prometheus text
+    /// format.
+    #[serde(default = "default_true")]
+    pub metrics: bool,
+    /// The byte budget of the cache of generated bundles. The least recently
+    /// used bundles are evicted when the cache exceeds this.
+    #[serde(default = "default_cache_bytes")]
+    pub cache_bytes: u64,
+    /// The PEM file of an Ed25519 private key, which signs the bundles
+    /// generated from directories. The urls in the signed bundles are in the
+    /// `isolated-app://<Web Bundle ID>/` origin, so that they can be installed
+    /// as Isolated Web Apps.
+    pub sign_key: Option<PathBuf>,
+    /// The headers added to all responses.
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default, rename = "mount")]
+    pub mounts: Vec<Mount>,
+}
+
+/// A directory, or a bundle file, served at a path.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[non_exhaustive]
+pub struct Mount {
+    /// The path which the source is served at, e.g. `/` or `/site/`.
+    pub path: String,
+    /// A directory, or a bundle file.
+    pub source: PathBuf,
+    /// The version of the bundles generated from a directory.
+    #[serde(default = "default_version")]
+    pub version: Version,
+    /// The url which `path` is mapped to, for a bundle file. A request for
+    /// `{path}a/b.html` is served by the exchange for `{base_url}a/b.html`.
+    /// Defaults to the origin of the primary url of the bundle.
+    pub base_url: Option<String>,
+    /// The headers added to the responses for this mount.
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_cache_bytes() -> u64 {
+    DEFAULT_CACHE_BYTES
+}
+
+fn default_version() -> Version {
+    Version::VersionB2
+}
+
+impl Config {
+    /// Reads the configuration file.
+    pub fn from_file(path: &Path) -> anyhow::Result<Config> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let config: Config =
+            toml::from_str(&text).with_context(|| format!("Invalid {}", path.display()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Serves the given directory, or the bundle file, at the root.
+    pub fn root(source: PathBuf, base_url: Option<String>) -> Config {
+        Config {
+            compress: false,
+            metrics: true,
+            cache_bytes: DEFAULT_CACHE_BYTES,
+            sign_key: None,
+            headers: BTreeMap::new(),
+            mounts: vec![Mount {
+                base_url,
+                ..Mount::new("/", source)
+            }],
+        }
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(!self.mounts.is_empty(), "No mount");
+        header_map(&self.headers)?;
+        for (i, mount) in self.mounts.iter().enumerate() {
+            anyhow::ensure!(
+                mount.path.starts_with('/'),
+                "The path of a mount must start with '/': {}",
+                mount.path
+            );
+            anyhow::ensure!(
+                !self.mounts[..i]
+                    .iter()
+                    .any(|other| other.prefix() == mount.prefix()),
+                "Duplicated mount path: {}",
+                mount.path
+            );
+            header_map(&mount.headers)?;
+        }
+        Ok(())
+    }
+}
+
+impl Mount {
+    /// Serves the given directory, or the bundle file, at the path, with the
+    /// defaults of the configuration file.
+    pub fn new(path: impl Into<String>, source: PathBuf) -> Mount {
+        Mount {
+            path: path.into(),
+            source,
+            version: default_version(),
+            base_url: None,
+            headers: BTreeMap::new(),
+        }
+    }
+
+    /// The path without the trailing slash, e.g. `/site`, or an empty string
+    /// for the root.
+    pub fn prefix(&self) -> &str {
+        self.path.trim_end_matches('/')
+    }
+
+    /// Returns true if the source is a bundle file, rather than a directory.
+    pub fn is_bundle(&self) -> bool {
+        self.source.is_file()
+    }
+}
+
+pub(crate) fn header_map(headers: &BTreeMap<String, String>) -> anyhow::Result<HeaderMap> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            Ok((
+                HeaderName::from_bytes(name.as_bytes())
+                    .with_context(|| format!("Invalid header name: {name}"))?,
+                HeaderValue::from_str(value)
+                    .with_context(|| format!("Invalid header value: {value}"))?,
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_config() -> anyhow::Result<()> {
+        let config: Config = toml::from_str(
+            r#"
+[headers]
+cross-origin-opener-policy = "same-origin"
+
+[[mount]]
+path = "/"
+source = "public"
+
+[[mount]]
+path = "/site/"
+source = "site.wbn"
+version = "1"
+base_url = "https://example.com/"
+headers = { cache-control = "no-cache" }
+"#,
+        )?;
+        config.validate()?;
+        assert!(!config.compress);
+        assert!(config.metrics);
+        assert_eq!(config.mounts.len(), 2);
+        assert_eq!(config.mounts[0].prefix(), "");
+        assert_eq!(config.mounts[0].version, Version::VersionB2);
+        assert_eq!(config.mounts[1].prefix(), "/site");
+        assert_eq!(config.mounts[1].version, Version::Version1);
+        assert_eq!(
+            header_map(&config.mounts[1].headers)?["cache-control"],
+            "no-cache"
+        );
+
+        let config: Config = toml::from_str(
+            r#"
+[[mount]]
+path = "/a"
+source = "a"
+
+[[mount]]
+path = "/a/"
+source = "b"
+"#,
+        )?;
+        assert!(config.validate().is_err());
+        assert!(
+            toml::from_str::<Config>("[[mount]]\npath = \"/\"\nsource = \".\"\nfoo = 1").is_err()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,103 @@
+//! Parses the digest of the resources which a client has already cached, so
+//! that they can be omitted from a bundle.
+//!
+//! A digest is a comma-separated list of the prefixes of SHA-256 hex strings,
+//! at least 8 characters each. The SHA-256 is computed from the path of a
+//! resource relative to the directory, e.g. `/a.js` for `a.js`, as with
+//! `?include=`. A shorter prefix makes the digest smaller, at the cost of
+//! false positives.
+
+use crate::cache::sha256_hex;
+use http::HeaderMap;
+use std::collections::HashSet;
+
+/// The header for the digest.
+pub(crate) const CACHE_DIGEST_HEADER: &str = "cache-digest";
+/// The query parameter for the digest, e.g. `?cached=3a2f1c9b,0e44d1a2`.
+pub(crate) const CACHE_DIGEST_QUERY: &str = "cached";
+const MIN_PREFIX_LEN: usize = 8;
+
+/// The cached resources, as the prefixes of the hashes of their paths.
+#[derive(Debug, Default)]
+pub(crate) struct CacheDigest {
+    prefixes: HashSet<String>,
+    prefix_lens: HashSet<usize>,
+}
+
+impl CacheDigest {
+    /// Parses a digest, e.g. `3a2f1c9b,0e44d1a2`.
+    pub(crate) fn parse(digest: &str) -> anyhow::Result<CacheDigest> {
+        let mut cache_digest = CacheDigest::default();
+        for prefix in digest.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            anyhow::ensure!(
+                prefix.len() >= MIN_PREFIX_LEN
+                    && prefix.len() <= 64
+                    && prefix.bytes().all(|b| b.is_ascii_hexdigit()),
+                "Invalid cache digest: {prefix}"
+            );
+            cache_digest.prefix_lens.insert(prefix.len());
+            cache_digest.prefixes.insert(prefix.to_ascii_lowercase());
+        }
+        Ok(cache_digest)
+    }
+
+    /// Gets the digest from the header, or the query parameter, of a request.
+    /// Returns `None` if neither is given.
+    pub(crate) fn from_request(
+        headers: &HeaderMap,
+        query: Option<&str>,
+    ) -> anyhow::Result<Option<CacheDigest>> {
+        let mut digests = headers
+            .get_all(CACHE_DIGEST_HEADER)
+            .iter()
+            .map(|value| Ok(value.to_str()?.to_string()))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        digests.extend(
+            url::form_urlencoded::parse(query.unwrap_or_default().as_bytes())
+                .filter(|(name, _)| name == CACHE_DIGEST_QUERY)
+                .map(|(_, value)| value.into_owned()),
+        );
+        if digests.is_empty() {
+            return Ok(None);
+        }
+        CacheDigest::parse(&digests.join(",")).map(Some)
+    }
+
+    /// Returns true if the resource for the url, which is relative to the
+    /// directory, is in the digest.
+    pub(crate) fn contains(&self, url: &str) -> bool {
+        let hash = sha256_hex(format!("/{url}").as_bytes());
+        self.prefix_lens
+            .iter()
+            .any(|&len| self.prefixes.contains(&hash[..len]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_digest() -> anyhow::Result<()> {
+        let a = sha256_hex(b"/a.js");
+        let digest = CacheDigest::parse(&format!("{}, {}", &a[..8], &sha256_hex(b"/b.css")))?;
+        assert!(digest.contains("a.js"));
+        assert!(digest.contains("b.css"));
+        assert!(!digest.contains("c.png"));
+
+        assert!(CacheDigest::parse("abc").is_err());
+        assert!(CacheDigest::parse("not-a-hex-string").is_err());
+
+        let mut headers = HeaderMap::new();
+        assert!(CacheDigest::from_request(&headers, Some("include=/a.js"))?.is_none());
+        headers.insert(CACHE_DIGEST_HEADER, a[..10].parse()?);
+        let digest = CacheDigest::from_request(
+            &headers,
+            Some(&format!("cached={}", &sha256_hex(b"/b.css")[..8])),
+        )?
+        .unwrap();
+        assert!(digest.contains("a.js"));
+        assert!(digest.contains("b.css"));
+        Ok(())
+    }
+}
@@ -0,0 +1,196 @@
+//! Access logs, and the metrics in the Prometheus text format at `/metrics`.
+
+use axum::{
+    body::{Body, BoxBody, HttpBody as _},
+    routing::get,
+    Router,
+};
+use axum_extra::middleware::{self, Next};
+use http::{header, HeaderValue, Request, Response};
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// The path of the metrics endpoint.
+pub(crate) const METRICS_PATH: &str = "/metrics";
+
+#[derive(Debug, Default)]
+pub(crate) struct Metrics {
+    requests: AtomicU64,
+    response_bytes: AtomicU64,
+    bundle_builds: AtomicU64,
+    bundle_cache_hits: AtomicU64,
+    bundle_cache_evictions: AtomicU64,
+    bundle_build_micros: AtomicU64,
+    bundle_encode_micros: AtomicU64,
+}
+
+impl Metrics {
+    fn record_request(&self, bytes: Option<u64>) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.response_bytes
+            .fetch_add(bytes.unwrap_or_default(), Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_cache_hit(&self) {
+        self.bundle_cache_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_cache_eviction(&self) {
+        self.bundle_cache_evictions.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[cfg(test)]
+    pub(crate) fn builds(&self) -> u64 {
+        self.bundle_builds.load(Ordering::Relaxed)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn cache_hits(&self) -> u64 {
+        self.bundle_cache_hits.load(Ordering::Relaxed)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn cache_evictions(&self) -> u64 {
+        self.bundle_cache_evictions.load(Ordering::Relaxed)
+    }
+
+    /// Records a build of a bundle. `build` includes `encode`.
+    pub(crate) fn record_build(&self, build: Duration, encode: Duration) {
+        self.bundle_builds.fetch_add(1, Ordering::Relaxed);
+        self.bundle_build_micros
+            .fetch_add(build.as_micros() as u64, Ordering::Relaxed);
+        self.bundle_encode_micros
+            .fetch_add(encode.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Renders the metrics in the Prometheus text format.
+    pub(crate) fn render(&self) -> String {
+        let seconds = |micros: &AtomicU64| micros.load(Ordering::Relaxed) as f64 / 1e6;
+        let mut text = String::new();
+        for (name, help, value) in [
+            (
+                "webbundle_server_requests_total",
+                "The number of requests.",
+                self.requests.load(Ordering::Relaxed) as f64,
+            ),
+            (
+                "webbundle_server_response_bytes_total",
+                "The bytes of the response bodies whose sizes are known.",
+                self.response_bytes.load(Ordering::Relaxed) as f64,
+            ),
+            (
+                "webbundle_server_bundle_builds_total",
+                "The number of bundles built from directories.",
+                self.bundle_builds.load(Ordering::Relaxed) as f64,
+            ),
+            (
+                "webbundle_server_bundle_cache_hits_total",
+                "The number of bundles served from the cache.",
+                self.bundle_cache_hits.load(Ordering::Relaxed) as f64,
+            ),
+            (
+                "webbundle_server_bundle_cache_evictions_total",
+                "The number of bundles evicted from the cache.",
+                self.bundle_cache_evictions.load(Ordering::Relaxed) as f64,
+            ),
+            (
+                "webbundle_server_bundle_build_seconds_total",
+                "The time spent building bundles, including encoding.",
+                seconds(&self.bundle_build_micros),
+            ),
+            (
+                "webbundle_server_bundle_encode_seconds_total",
+                "The time spent encoding bundles.",
+                seconds(&self.bundle_encode_micros),
+            ),
+        ] {
+            writeln!(text, "# HELP {name} {help}").unwrap();
+            writeln!(text, "# TYPE {name} counter").unwrap();
+            writeln!(text, "{name} {value}").unwrap();
+        }
+        text
+    }
+}
+
+/// Logs each request, counts it, and adds the metrics endpoint.
+pub(crate) fn instrument(router: Router, metrics: Arc<Metrics>) -> Router {
+    let metrics_for_endpoint = metrics.clone();
+    router
+        .layer(middleware::from_fn(
+            move |req: Request<Body>, next: Next<Body>| {
+                let metrics = metrics.clone();
+                async move { access_log(req, next, &metrics).await }
+            },
+        ))
+        .route(
+            METRICS_PATH,
+            get(move || {
+                let metrics = metrics_for_endpoint.clone();
+                async move {
+                    let mut response = Response::new(metrics.render());
+                    response.headers_mut().insert(
+                        header::CONTENT_TYPE,
+                        HeaderValue::from_static("text/plain; version=0.0.4"),
+                    );
+                    response
+                }
+            }),
+        )
+}
+
+async fn access_log(req: Request<Body>, next: Next<Body>, metrics: &Metrics) -> Response<BoxBody> {
+    let start = Instant::now();
+    let method = req.method().clone();
+    let uri = req.uri().clone();
+    let response = next.run(req).await;
+    let bytes = response.body().size_hint().exact();
+    metrics.record_request(bytes);
+    tracing::info!(
+        %method,
+        %uri,
+        status = response.status().as_u16(),
+        bytes,
+        elapsed_ms = start.elapsed().as_secs_f64() * 1e3,
+        "access"
+    );
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower::ServiceExt as _;
+
+    #[tokio::test]
+    async fn count_requests() -> anyhow::Result<()> {
+        let metrics = Arc::new(Metrics::default());
+        metrics.record_cache_hit();
+        metrics.record_build(Duration::from_millis(1500), Duration::from_millis(500));
+        let router = instrument(
+            Router::new().route("/", get(|| async { "hello" })),
+            metrics.clone(),
+        );
+        router
+            .clone()
+            .oneshot(Request::builder().uri("/").body(Body::empty())?)
+            .await?;
+
+        let response = router
+            .oneshot(Request::builder().uri(METRICS_PATH).body(Body::empty())?)
+            .await?;
+        let text = String::from_utf8(hyper::body::to_bytes(response.into_body()).await?.to_vec())?;
+        for line in [
+            "webbundle_server_requests_total 1",
+            "webbundle_server_response_bytes_total 5",
+            "webbundle_server_bundle_builds_total 1",
+            "webbundle_server_bundle_cache_hits_total 1",
+            "webbundle_server_bundle_build_seconds_total 1.5",
+            "webbundle_server_bundle_encode_seconds_total 0.5",
+        ] {
+            assert!(text.lines().any(|l| l == line), "{line} in {text}");
+        }
+        Ok(())
+    }
+}
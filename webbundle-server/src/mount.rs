@@ -0,0 +1,127 @@
+//! Serves the resources in a bundle file, as a static site.
+
+use axum::body::{self, BoxBody, Bytes, Full};
+use http::{Method, Request, Response, StatusCode};
+use std::path::Path;
+use webbundle::Index;
+
+/// A bundle file, whose responses are decoded lazily for each request.
+pub(crate) struct MountedBundle {
+    bytes: Bytes,
+    index: Index,
+    base_url: String,
+}
+
+impl MountedBundle {
+    /// Reads the bundle file. A request for `/a/b.html` is served by the
+    /// exchange for `{base_url}a/b.html`. If `base_url` is not given, the
+    /// origin of the primary url is used, or the urls are considered as
+    /// relative.
+    pub(crate) async fn open(path: &Path, base_url: Option<String>) -> anyhow::Result<Self> {
+        let bytes = Bytes::from(tokio::fs::read(path).await?);
+        let index = Index::from_bytes(&bytes)?;
+        let mut base_url = base_url
+            .or_else(|| {
+                let primary_url = index.primary_url().as_ref()?;
+                Some(format!(
+                    "{}://{}/",
+                    primary_url.scheme_str()?,
+                    primary_url.authority()?
+                ))
+            })
+            .unwrap_or_default();
+        if !base_url.is_empty() && !base_url.ends_with('/') {
+            base_url.push('/');
+        }
+        Ok(MountedBundle {
+            bytes,
+            index,
+            base_url,
+        })
+    }
+
+    pub(crate) fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Returns the response of the exchange for the path of the request.
+    pub(crate) fn response<B>(&self, req: &Request<B>) -> anyhow::Result<Response<BoxBody>> {
+        if req.method() != Method::GET && req.method() != Method::HEAD {
+            return Ok(status_response(StatusCode::METHOD_NOT_ALLOWED));
+        }
+        let path = req.uri().path().trim_start_matches('/');
+        let url = format!("{}{path}", self.base_url);
+        let entry = match self.index.get(&url).or_else(|| {
+            url.ends_with('/')
+                .then(|| self.index.get(&format!("{url}index.html")))
+                .flatten()
+        }) {
+            Some(entry) => entry,
+            None => return Ok(status_response(StatusCode::NOT_FOUND)),
+        };
+        let range = entry.range();
+        let exchange_bytes = self
+            .bytes
+            .get(range.start as usize..range.end as usize)
+            .ok_or_else(|| anyhow::anyhow!("The response of {url} is out of the bundle"))?;
+        let (parts, body) = entry.response_from(exchange_bytes)?.into_parts();
+        let body = if req.method() == Method::HEAD {
+            Vec::new()
+        } else {
+            body
+        };
+        Ok(Response::from_parts(parts, body::boxed(Full::from(body))))
+    }
+}
+
+fn status_response(status: StatusCode) -> Response<BoxBody> {
+    let mut response = Response::new(body::boxed(body::Empty::new()));
+    *response.status_mut() = status;
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use webbundle::{Bundle, Exchange, Version};
+
+    #[tokio::test]
+    async fn serve_mounted_bundle() -> anyhow::Result<()> {
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .primary_url("https://example.com/".parse()?)
+            .exchange(Exchange::from((
+                "https://example.com/index.html".to_string(),
+                b"top".to_vec(),
+            )))
+            .exchange(Exchange::from((
+                "https://example.com/a.js".to_string(),
+                b"a".to_vec(),
+            )))
+            .build()?;
+        let file = tempfile::NamedTempFile::new()?;
+        std::fs::write(file.path(), bundle.encode()?)?;
+        let mounted = MountedBundle::open(file.path(), None).await?;
+        assert_eq!(mounted.base_url(), "https://example.com/");
+
+        let get = |method: Method, path: &str| {
+            let req = Request::builder()
+                .method(method)
+                .uri(path)
+                .body(())
+                .unwrap();
+            mounted.response(&req).unwrap()
+        };
+        for (path, expected) in [("/", "top"), ("/a.js", "a")] {
+            let response = get(Method::GET, path);
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(hyper::body::to_bytes(response.into_body()).await?, expected);
+        }
+        assert_eq!(get(Method::GET, "/b.js").status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            get(Method::POST, "/a.js").status(),
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,209 @@
+//! Serves the bytes of a bundle with `Range` requests, so that clients can
+//! fetch individual responses.
+//!
+//! The responses have `Content-Digest` (RFC 9530) of their content, i.e. the
+//! whole bytes or the requested range of them, so that clients can verify it.
+
+use axum::body::{self, BoxBody, Bytes, Full};
+use base64::Engine as _;
+use headers::{AcceptRanges, ContentLength, ContentRange, ETag, HeaderMapExt as _, IfRange};
+use http::{header::HeaderName, HeaderMap, HeaderValue, Response, StatusCode};
+use ring::digest::{digest, SHA256};
+use std::ops::{Bound, Range};
+
+/// Returns the requested range of `len` bytes. `Ok(None)` means the whole
+/// bytes, e.g. if there is no `Range` header, or `If-Range` doesn't match.
+/// Multiple ranges are not supported, and the whole bytes are returned for
+/// them.
+fn requested_range(
+    headers: &HeaderMap,
+    len: u64,
+    etag: &ETag,
+) -> Result<Option<Range<u64>>, ContentRange> {
+    let range = match headers.typed_get::<headers::Range>() {
+        Some(range) => range,
+        None => return Ok(None),
+    };
+    if let Some(if_range) = headers.typed_get::<IfRange>() {
+        if if_range.is_modified(Some(etag), None) {
+            return Ok(None);
+        }
+    }
+    let mut ranges = range.iter();
+    let (start, end) = match (ranges.next(), ranges.next()) {
+        (Some(bounds), None) => bounds,
+        _ => return Ok(None),
+    };
+    let range = match (start, end) {
+        (Bound::Included(start), Bound::Included(end)) => start..end.saturating_add(1).min(len),
+        (Bound::Included(start), Bound::Unbounded) => start..len,
+        // The last `n` bytes.
+        (Bound::Unbounded, Bound::Included(n)) => len.saturating_sub(n)..len,
+        _ => return Ok(None),
+    };
+    if range.start >= range.end {
+        return Err(ContentRange::unsatisfied_bytes(len));
+    }
+    Ok(Some(range))
+}
+
+pub(crate) const CONTENT_DIGEST: HeaderName = HeaderName::from_static("content-digest");
+
+/// Returns the value of `Content-Digest` header for the given SHA-256, e.g.
+/// `sha-256=:<base64>:`.
+pub(crate) fn content_digest(sha256: &[u8]) -> HeaderValue {
+    let base64 = base64::engine::general_purpose::STANDARD.encode(sha256);
+    HeaderValue::from_str(&format!("sha-256=:{base64}:")).expect("a valid header value")
+}
+
+/// Returns `200 OK` with the bytes, or `206 Partial Content` with the
+/// requested range of them. `bytes_digest` is `Content-Digest` of the whole
+/// bytes.
+pub(crate) fn range_response(
+    headers: &HeaderMap,
+    bytes: Bytes,
+    etag: &ETag,
+    bytes_digest: &HeaderValue,
+) -> Response<BoxBody> {
+    let len = bytes.len() as u64;
+    let mut response = match requested_range(headers, len, etag) {
+        Ok(None) => {
+            let mut response = Response::new(body::boxed(Full::from(bytes)));
+            response.headers_mut().typed_insert(ContentLength(len));
+            response
+                .headers_mut()
+                .insert(CONTENT_DIGEST, bytes_digest.clone());
+            response
+        }
+        Ok(Some(range)) => {
+            let content = bytes.slice(range.start as usize..range.end as usize);
+            let digest = content_digest(digest(&SHA256, &content).as_ref());
+            let mut response = Response::new(body::boxed(Full::from(content)));
+            *response.status_mut() = StatusCode::PARTIAL_CONTENT;
+            response.headers_mut().insert(CONTENT_DIGEST, digest);
+            response
+                .headers_mut()
+                .typed_insert(ContentRange::bytes(range.clone(), len).expect("a valid range"));
+            response
+                .headers_mut()
+                .typed_insert(ContentLength(range.end - range.start));
+            response
+        }
+        Err(content_range) => {
+            let mut response = Response::new(body::boxed(body::Empty::new()));
+            *response.status_mut() = StatusCode::RANGE_NOT_SATISFIABLE;
+            response.headers_mut().typed_insert(content_range);
+            response
+        }
+    };
+    response.headers_mut().typed_insert(AcceptRanges::bytes());
+    response.headers_mut().typed_insert(etag.clone());
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get(range: Option<&str>, if_range: Option<&str>) -> (StatusCode, Option<String>) {
+        let etag = "\"abc\"".parse::<ETag>().unwrap();
+        let mut headers = HeaderMap::new();
+        if let Some(range) = range {
+            headers.insert(http::header::RANGE, range.parse().unwrap());
+        }
+        if let Some(if_range) = if_range {
+            headers.insert(http::header::IF_RANGE, if_range.parse().unwrap());
+        }
+        let response = range_response(
+            &headers,
+            Bytes::from_static(b"0123456789"),
+            &etag,
+            &HeaderValue::from_static("sha-256=:all:"),
+        );
+        assert_eq!(response.headers()["accept-ranges"], "bytes");
+        let content_range = response
+            .headers()
+            .get("content-range")
+            .map(|value| value.to_str().unwrap().to_string());
+        (response.status(), content_range)
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(get(None, None), (StatusCode::OK, None));
+        assert_eq!(
+            get(Some("bytes=2-4"), None),
+            (
+                StatusCode::PARTIAL_CONTENT,
+                Some("bytes 2-4/10".to_string())
+            )
+        );
+        assert_eq!(
+            get(Some("bytes=8-"), None),
+            (
+                StatusCode::PARTIAL_CONTENT,
+                Some("bytes 8-9/10".to_string())
+            )
+        );
+        assert_eq!(
+            get(Some("bytes=-3"), None),
+            (
+                StatusCode::PARTIAL_CONTENT,
+                Some("bytes 7-9/10".to_string())
+            )
+        );
+        assert_eq!(
+            get(Some("bytes=5-100"), None),
+            (
+                StatusCode::PARTIAL_CONTENT,
+                Some("bytes 5-9/10".to_string())
+            )
+        );
+        assert_eq!(
+            get(Some("bytes=0-18446744073709551615"), None),
+            (
+                StatusCode::PARTIAL_CONTENT,
+                Some("bytes 0-9/10".to_string())
+            )
+        );
+        assert_eq!(
+            get(Some("bytes=10-"), None),
+            (
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                Some("bytes */10".to_string())
+            )
+        );
+        assert_eq!(get(Some("bytes=0-1, 3-4"), None), (StatusCode::OK, None));
+        assert_eq!(
+            get(Some("bytes=2-4"), Some("\"abc\"")),
+            (
+                StatusCode::PARTIAL_CONTENT,
+                Some("bytes 2-4/10".to_string())
+            )
+        );
+        assert_eq!(
+            get(Some("bytes=2-4"), Some("\"old\"")),
+            (StatusCode::OK, None)
+        );
+    }
+    #[test]
+    fn content_digests() {
+        let bytes = Bytes::from_static(b"0123456789");
+        let bytes_digest = content_digest(digest(&SHA256, &bytes).as_ref());
+        assert_eq!(
+            bytes_digest,
+            "sha-256=:hNiYd/DUBB77a/kaFvAkjy/Vc+avBcGflr7bn4gveII=:"
+        );
+        let etag = "\"abc\"".parse::<ETag>().unwrap();
+        let response = range_response(&HeaderMap::new(), bytes.clone(), &etag, &bytes_digest);
+        assert_eq!(response.headers()["content-digest"], bytes_digest);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(http::header::RANGE, "bytes=2-4".parse().unwrap());
+        let response = range_response(&headers, bytes, &etag, &bytes_digest);
+        assert_eq!(
+            response.headers()["content-digest"],
+            "sha-256=:EUvRUfj7DFhkLSFw2krn18V5dyYKwsyJBTBsq2sqyrw=:"
+        );
+    }
+}
@@ -0,0 +1,158 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! JavaScript bindings for `webbundle` crate.
+//!
+//! A bundle is represented as a plain object,
+//! `{ version, primaryUrl, exchanges }`, and each exchange as
+//! `{ url, status, headers, body }`. See README.md for details.
+
+use http::header::{HeaderName, HeaderValue};
+use http::StatusCode;
+use js_sys::{Array, Object, Reflect, Uint8Array};
+use wasm_bindgen::prelude::*;
+use webbundle::{Bundle, Exchange, Request, Response};
+
+/// Parses the given bytes as a bundle, and returns it as an object.
+#[wasm_bindgen]
+pub fn parse(bytes: &[u8]) -> Result<Object, JsValue> {
+    let bundle = Bundle::from_bytes(bytes).map_err(to_js_error)?;
+    let exchanges = bundle
+        .exchanges()
+        .iter()
+        .map(exchange_to_object)
+        .collect::<Result<Array, _>>()?;
+    let object = Object::new();
+    set(&object, "version", bundle.version().to_string())?;
+    set(
+        &object,
+        "primaryUrl",
+        bundle.primary_url().as_ref().map(|url| url.to_string()),
+    )?;
+    set(&object, "exchanges", exchanges)?;
+    Ok(object)
+}
+
+/// Builds a bundle from the given object, and returns its bytes.
+#[wasm_bindgen]
+pub fn build(object: &Object) -> Result<Vec<u8>, JsValue> {
+    let version = get(object, "version")?
+        .as_string()
+        .ok_or_else(|| js_error("version must be a string"))?;
+    let mut builder = Bundle::builder().version(version.parse().map_err(to_js_error)?);
+    let primary_url = get(object, "primaryUrl")?;
+    if !primary_url.is_undefined() && !primary_url.is_null() {
+        let primary_url = primary_url
+            .as_string()
+            .ok_or_else(|| js_error("primaryUrl must be a string"))?;
+        builder = builder.primary_url(primary_url.parse().map_err(to_js_error)?);
+    }
+    let exchanges = get(object, "exchanges")?;
+    if !Array::is_array(&exchanges) {
+        return Err(js_error("exchanges must be an array"));
+    }
+    for exchange in Array::from(&exchanges).iter() {
+        builder = builder.exchange(exchange_from_object(&exchange)?);
+    }
+    let bundle = builder.build().map_err(to_js_error)?;
+    bundle.encode().map_err(to_js_error)
+}
+
+fn exchange_to_object(exchange: &Exchange) -> Result<JsValue, JsValue> {
+    let headers = exchange
+        .response
+        .headers()
+        .iter()
+        .map(|(name, value)| {
+            Array::of2(
+                &name.as_str().into(),
+                &String::from_utf8_lossy(value.as_bytes()).as_ref().into(),
+            )
+        })
+        .collect::<Array>();
+    let object = Object::new();
+    set(&object, "url", exchange.request.url().as_str())?;
+    set(&object, "status", exchange.response.status().as_u16())?;
+    set(&object, "headers", headers)?;
+    set(
+        &object,
+        "body",
+        Uint8Array::from(exchange.response.body().as_slice()),
+    )?;
+    Ok(object.into())
+}
+
+fn exchange_from_object(object: &JsValue) -> Result<Exchange, JsValue> {
+    let url = get(object, "url")?
+        .as_string()
+        .ok_or_else(|| js_error("url must be a string"))?;
+
+    let body = get(object, "body")?;
+    let body = if body.is_undefined() {
+        Vec::new()
+    } else if let Some(body) = body.as_string() {
+        body.into_bytes()
+    } else {
+        Uint8Array::new(&body).to_vec()
+    };
+    let mut response = Response::new(body);
+
+    let status = get(object, "status")?;
+    if !status.is_undefined() {
+        let status = status
+            .as_f64()
+            .ok_or_else(|| js_error("status must be a number"))?;
+        *response.status_mut() = StatusCode::from_u16(status as u16).map_err(to_js_error)?;
+    }
+
+    let headers = get(object, "headers")?;
+    if !headers.is_undefined() {
+        if !Array::is_array(&headers) {
+            return Err(js_error("headers must be an array of [name, value]"));
+        }
+        for header in Array::from(&headers).iter() {
+            let header = Array::from(&header);
+            let (name, value) = match (header.get(0).as_string(), header.get(1).as_string()) {
+                (Some(name), Some(value)) if header.length() == 2 => (name, value),
+                _ => return Err(js_error("headers must be an array of [name, value]")),
+            };
+            response.headers_mut().append(
+                HeaderName::from_bytes(name.as_bytes()).map_err(to_js_error)?,
+                HeaderValue::from_str(&value).map_err(to_js_error)?,
+            );
+        }
+    }
+
+    Ok(Exchange {
+        request: Request::from(url),
+        response,
+    })
+}
+
+fn get(object: &JsValue, key: &str) -> Result<JsValue, JsValue> {
+    Reflect::get(object, &key.into())
+}
+
+fn set(object: &Object, key: &str, value: impl Into<JsValue>) -> Result<(), JsValue> {
+    Reflect::set(object, &key.into(), &value.into())?;
+    Ok(())
+}
+
+fn js_error(message: &str) -> JsValue {
+    js_sys::Error::new(message).into()
+}
+
+fn to_js_error(err: impl std::fmt::Display) -> JsValue {
+    js_error(&format!("{err:#}"))
+}
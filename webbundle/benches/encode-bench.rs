@@ -0,0 +1,20 @@
+use criterion::Criterion;
+use criterion::*;
+
+use webbundle::testing::synthetic_bundle;
+use webbundle::Bundle;
+
+/// Benchmarks for encoder.rs and decoder.rs, with a synthetic bundle of 781
+/// modules of 4KB.
+fn encode_benchmark(c: &mut Criterion) {
+    let bundle = synthetic_bundle(4, 5, 4096);
+    c.bench_function("encode", |b| b.iter(|| bundle.encode().unwrap()));
+}
+
+fn decode_benchmark(c: &mut Criterion) {
+    let bytes = synthetic_bundle(4, 5, 4096).encode().unwrap();
+    c.bench_function("decode", |b| b.iter(|| Bundle::from_bytes(&bytes).unwrap()));
+}
+
+criterion_group!(benches, encode_benchmark, decode_benchmark);
+criterion_main!(benches);
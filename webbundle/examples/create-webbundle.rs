@@ -1,6 +1,5 @@
 use anyhow::Result;
 use headers::ContentType;
-use std::io::BufWriter;
 use webbundle::{Bundle, Exchange, Version};
 
 // This creates a webbundle which can be used in
@@ -24,6 +23,6 @@ fn main() -> Result<()> {
 
     let out_path =
         std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("examples/create-webbundle.wbn");
-    bundle.write_to(BufWriter::new(std::fs::File::create(out_path)?))?;
+    bundle.write_to_file(out_path)?;
     Ok(())
 }
@@ -0,0 +1,266 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! axum integration.
+
+use crate::bundle::Bundle;
+use crate::encoded_body::EncodedBundleBody;
+use axum_core::body::{boxed, BoxBody};
+use axum_core::extract::{FromRequest, RequestParts};
+use axum_core::response::IntoResponse;
+use axum_core::BoxError;
+use bytes::{Buf as _, BufMut as _};
+use headers::{ContentLength, HeaderMapExt as _};
+use http::header::{HeaderValue, CONTENT_TYPE, X_CONTENT_TYPE_OPTIONS};
+use http::StatusCode;
+use http_body::Body as _;
+
+/// The media type of a bundle.
+const WEBBUNDLE_CONTENT_TYPE: &str = "application/webbundle";
+
+/// Responds with the encoded bundle, with `Content-Type: application/webbundle`
+/// and `X-Content-Type-Options: nosniff`, which browsers require to load a
+/// bundle.
+impl IntoResponse for Bundle {
+    fn into_response(self) -> http::Response<BoxBody> {
+        let body = match EncodedBundleBody::new(self) {
+            Ok(body) => body,
+            Err(err) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to encode the bundle: {err}"),
+                )
+                    .into_response()
+            }
+        };
+        let content_length = ContentLength(body.len());
+        let mut response = http::Response::new(boxed(body));
+        let headers = response.headers_mut();
+        headers.typed_insert(content_length);
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static(WEBBUNDLE_CONTENT_TYPE),
+        );
+        headers.insert(X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+        response
+    }
+}
+
+/// An extractor which parses a bundle in the request body.
+///
+/// The body can be a bundle itself, or `multipart/form-data` whose first file
+/// is a bundle. A request with an invalid bundle is rejected with
+/// `400 Bad Request`, and a body larger than [`WbnFile::MAX_BYTES`] is
+/// rejected with `413 Payload Too Large` without being read to the end.
+///
+/// # Examples
+///
+/// ```no_run
+/// use webbundle::WbnFile;
+///
+/// // e.g. `axum::Router::new().route("/upload", post(upload))`
+/// async fn upload(WbnFile(bundle): WbnFile) -> String {
+///     format!("{} exchanges", bundle.exchanges().len())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct WbnFile(pub Bundle);
+
+impl WbnFile {
+    /// The limit of the request body, including the multipart framing.
+    pub const MAX_BYTES: usize = 64 * 1024 * 1024;
+}
+
+#[async_trait::async_trait]
+impl<B> FromRequest<B> for WbnFile
+where
+    B: http_body::Body + Send,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    type Rejection = (StatusCode, String);
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let boundary = req
+            .headers()
+            .and_then(|headers| headers.get(CONTENT_TYPE))
+            .and_then(|value| value.to_str().ok())
+            .and_then(multipart_boundary);
+        let too_large = || {
+            (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!("The request body exceeds {} bytes", WbnFile::MAX_BYTES),
+            )
+        };
+        let content_length = req
+            .headers()
+            .and_then(|headers| headers.typed_get::<ContentLength>());
+        if let Some(ContentLength(len)) = content_length {
+            if len > WbnFile::MAX_BYTES as u64 {
+                return Err(too_large());
+            }
+        }
+        let mut body = match req.take_body() {
+            Some(body) => Box::pin(body),
+            None => {
+                return Err((
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "The body was taken by another extractor".to_string(),
+                ))
+            }
+        };
+        let mut bytes = Vec::new();
+        while let Some(chunk) = body.data().await {
+            let chunk = chunk.map_err(|err| (StatusCode::BAD_REQUEST, err.into().to_string()))?;
+            if bytes.len() + chunk.remaining() > WbnFile::MAX_BYTES {
+                return Err(too_large());
+            }
+            bytes.put(chunk);
+        }
+        let bytes = match &boundary {
+            Some(boundary) => first_file(&bytes, boundary).ok_or((
+                StatusCode::BAD_REQUEST,
+                "No file in multipart/form-data".to_string(),
+            ))?,
+            None => &bytes[..],
+        };
+        let bundle = Bundle::from_bytes(bytes)
+            .map_err(|err| (StatusCode::BAD_REQUEST, format!("Invalid bundle: {err}")))?;
+        Ok(WbnFile(bundle))
+    }
+}
+
+fn multipart_boundary(content_type: &str) -> Option<String> {
+    let mut params = content_type.split(';');
+    if !params
+        .next()?
+        .trim()
+        .eq_ignore_ascii_case("multipart/form-data")
+    {
+        return None;
+    }
+    params.find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case("boundary")
+            .then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Returns the body of the first part which has a filename, or of the first
+/// part if no part has a filename.
+fn first_file<'a>(body: &'a [u8], boundary: &str) -> Option<&'a [u8]> {
+    let delimiter = format!("--{boundary}");
+    let mut parts = Vec::new();
+    let mut rest = &body[find(body, delimiter.as_bytes())? + delimiter.len()..];
+    while let Some(part_start) = rest.strip_prefix(b"\r\n") {
+        let end = find(part_start, format!("\r\n{delimiter}").as_bytes())?;
+        let part = &part_start[..end];
+        let head_end = find(part, b"\r\n\r\n")?;
+        let head = String::from_utf8_lossy(&part[..head_end]).to_ascii_lowercase();
+        parts.push((head.contains("filename="), &part[head_end + 4..]));
+        rest = &part_start[end + 2 + delimiter.len()..];
+    }
+    parts
+        .iter()
+        .find(|(is_file, _)| *is_file)
+        .or_else(|| parts.first())
+        .map(|(_, body)| *body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundle::{Exchange, Version};
+    use crate::prelude::*;
+    use bytes::Bytes;
+    use http_body::Full;
+    use std::task::{Context, Poll};
+
+    fn bundle() -> Result<Bundle> {
+        Bundle::builder()
+            .version(Version::VersionB2)
+            .exchange(Exchange::from(("a.html", b"a".to_vec())))
+            .build()
+    }
+
+    fn extract(content_type: &str, body: Vec<u8>) -> Result<WbnFile, (StatusCode, String)> {
+        let request = http::Request::builder()
+            .header(CONTENT_TYPE, content_type)
+            .body(Full::new(Bytes::from(body)))
+            .unwrap();
+        let mut parts = RequestParts::new(request);
+        let mut future = WbnFile::from_request(&mut parts);
+        let mut cx = Context::from_waker(std::task::Waker::noop());
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => panic!("The body should be ready"),
+        }
+    }
+
+    #[test]
+    fn into_response() -> Result<()> {
+        let bytes = bundle()?.encode()?;
+        let mut response = bundle()?.into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "application/webbundle");
+        assert_eq!(response.headers()["x-content-type-options"], "nosniff");
+        assert_eq!(
+            response.headers()["content-length"],
+            bytes.len().to_string()
+        );
+        let mut cx = Context::from_waker(std::task::Waker::noop());
+        let mut body = Vec::new();
+        while let Poll::Ready(Some(data)) =
+            std::pin::Pin::new(response.body_mut()).poll_data(&mut cx)
+        {
+            body.extend_from_slice(&data.unwrap());
+        }
+        assert_eq!(body, bytes);
+        Ok(())
+    }
+
+    #[test]
+    fn wbn_file() -> Result<()> {
+        let bytes = bundle()?.encode()?;
+
+        let WbnFile(bundle) = extract("application/webbundle", bytes.clone()).unwrap();
+        assert_eq!(bundle.exchanges().len(), 1);
+
+        let multipart = [
+            &b"--XyZ\r\nContent-Disposition: form-data; name=\"note\"\r\n\r\nhello\r\n"[..],
+            b"--XyZ\r\nContent-Disposition: form-data; name=\"file\"; filename=\"a.wbn\"\r\n",
+            b"Content-Type: application/webbundle\r\n\r\n",
+            &bytes,
+            b"\r\n--XyZ--\r\n",
+        ]
+        .concat();
+        let WbnFile(bundle) = extract("multipart/form-data; boundary=\"XyZ\"", multipart).unwrap();
+        assert_eq!(bundle.exchanges()[0].request.url(), "a.html");
+
+        let (status, _) = extract("application/webbundle", b"invalid".to_vec()).unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, _) =
+            extract("application/webbundle", vec![0; WbnFile::MAX_BYTES + 1]).unwrap_err();
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        Ok(())
+    }
+}
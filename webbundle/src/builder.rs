@@ -12,7 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::bundle::{Bundle, Exchange, Uri, Version};
+use crate::bundle::{Bundle, Exchange, IndexOrder, Uri, Version};
+use crate::canonical_url::canonicalize_url;
 use crate::prelude::*;
 
 /// A Bundle builder.
@@ -21,7 +22,11 @@ pub struct Builder {
     version: Option<Version>,
     primary_url: Option<Uri>,
     manifest: Option<Uri>,
+    index_order: IndexOrder,
+    strict: bool,
     pub(crate) exchanges: Vec<Exchange>,
+    #[cfg(feature = "fs")]
+    pub(crate) fs_options: crate::fs::builder::Options,
 }
 
 impl Builder {
@@ -47,6 +52,21 @@ impl Builder {
         self
     }
 
+    /// Sets the order of the index section. Defaults to
+    /// [`IndexOrder::Canonical`].
+    pub fn index_order(mut self, index_order: IndexOrder) -> Self {
+        self.index_order = index_order;
+        self
+    }
+
+    /// Canonicalizes urls as Chromium does, and fails to build if any url is
+    /// rejected, e.g. because of a fragment, or if any request is not `GET`.
+    /// See [`canonicalize_url`].
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
     /// Adds the exchange.
     pub fn exchange(mut self, exchange: Exchange) -> Self {
         self.exchanges.push(exchange);
@@ -54,11 +74,35 @@ impl Builder {
     }
 
     /// Builds the bundle.
-    pub fn build(self) -> Result<Bundle> {
+    pub fn build(mut self) -> Result<Bundle> {
+        if self.strict {
+            if let Some(primary_url) = &self.primary_url {
+                self.primary_url = Some(
+                    canonicalize_url(&primary_url.to_string())?
+                        .parse()
+                        .context("Failed to parse primary_url")?,
+                );
+            }
+            for exchange in &mut self.exchanges {
+                ensure!(
+                    exchange.request.method() == http::Method::GET,
+                    format!(
+                        "{} {}: Only GET requests can be bundled",
+                        exchange.request.method(),
+                        exchange.request.url()
+                    )
+                );
+                let url = canonicalize_url(exchange.request.url())?;
+                exchange.request.set_url(url);
+            }
+        }
         Ok(Bundle {
             version: self.version.context("no version")?,
             primary_url: self.primary_url,
             exchanges: self.exchanges,
+            index_order: self.index_order,
+            ignored_sections: Vec::new(),
+            signatures: None,
         })
     }
 }
@@ -108,4 +152,37 @@ mod tests {
         );
         Ok(())
     }
+
+    #[test]
+    fn build_strict() -> Result<()> {
+        let bundle = Builder::new()
+            .version(Version::VersionB2)
+            .primary_url("HTTPS://EXAMPLE.COM:443/a/../index.html".parse()?)
+            .exchange(Exchange::from((
+                "https://Example.com:443/%7euser/a b".to_string(),
+                vec![],
+            )))
+            .strict(true)
+            .build()?;
+        assert_eq!(
+            bundle.primary_url,
+            Some("https://example.com/index.html".parse::<Uri>()?)
+        );
+        assert_eq!(
+            bundle.exchanges[0].request.url(),
+            "https://example.com/~user/a%20b"
+        );
+
+        let builder = || {
+            Builder::new()
+                .version(Version::VersionB2)
+                .exchange(Exchange::from((
+                    "https://example.com/#top".to_string(),
+                    vec![],
+                )))
+        };
+        assert!(builder().build().is_ok());
+        assert!(builder().strict(true).build().is_err());
+        Ok(())
+    }
 }
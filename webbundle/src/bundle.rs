@@ -13,16 +13,18 @@
 // limitations under the License.
 
 use crate::builder::Builder;
+use crate::canonical_url::resolve_url;
 use crate::decoder;
 use crate::encoder;
 use crate::prelude::*;
 use http::StatusCode;
-pub use http::Uri;
+pub use http::{Method, Uri};
 
 use headers::{ContentLength, ContentType, HeaderMapExt as _};
 
+use std::collections::HashSet;
 use std::convert::TryFrom;
-use std::io::Write;
+use std::io::{Seek, Write};
 use std::path::Path;
 
 pub type Body = Vec<u8>;
@@ -37,13 +39,29 @@ pub type HeaderMap = http::header::HeaderMap;
 #[derive(Debug, Clone)]
 pub struct Request {
     url: String,
+    method: Method,
     headers: HeaderMap,
 }
 
 impl Request {
-    /// Creates a new `Request` with the given url and headers.
+    /// Creates a new `GET` request with the given url and headers.
     pub fn new(url: String, headers: HeaderMap) -> Request {
-        Request { url, headers }
+        Request {
+            url,
+            method: Method::GET,
+            headers,
+        }
+    }
+
+    /// Returns this request with the given method.
+    ///
+    /// Bundles contain only responses to `GET` requests, so a request with
+    /// another method is encoded as `GET` with a warning, and
+    /// [`Builder::strict`](crate::Builder::strict) rejects it. Decoded
+    /// requests are always `GET`.
+    pub fn with_method(mut self, method: Method) -> Request {
+        self.method = method;
+        self
     }
 
     /// Returns a reference to the associated url.
@@ -51,7 +69,25 @@ impl Request {
         &self.url
     }
 
+    pub(crate) fn set_url(&mut self, url: String) {
+        self.url = url;
+    }
+
+    /// Returns a reference to the associated method.
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
     /// Returns a reference to the associated header field map.
+    ///
+    /// Request headers are neither encoded nor decoded. b2 and version 1
+    /// have no place for them, and responses are keyed only by urls, so
+    /// exchanges negotiated by request headers, e.g. `Accept`, can't be
+    /// represented. Encoding drops them with a warning, and decoded requests
+    /// have none. Variants of b1 are decoded as exchanges of the same url,
+    /// whose responses have `Variant-Key` headers.
+    ///
+    /// They are kept only in the JSON form of the `serde` feature.
     pub fn headers(&self) -> &HeaderMap {
         &self.headers
     }
@@ -69,6 +105,12 @@ impl From<String> for Request {
     }
 }
 
+impl From<&str> for Request {
+    fn from(url: &str) -> Self {
+        Self::new(url.to_string(), HeaderMap::new())
+    }
+}
+
 // TODO: Use TryFrom?
 impl From<&Path> for Request {
     fn from(path: &Path) -> Self {
@@ -86,15 +128,30 @@ impl From<&Path> for Request {
 pub const HEADER_MAGIC_BYTES: [u8; 8] = [0xf0, 0x9f, 0x8c, 0x90, 0xf0, 0x9f, 0x93, 0xa6];
 pub(crate) const VERSION_BYTES_LEN: usize = 4;
 pub(crate) const TOP_ARRAY_LEN: usize = 5;
-pub(crate) const KNOWN_SECTION_NAMES: [&str; 4] = ["index", "critical", "responses", "primary"];
+/// Version b1 has the primary url between the version and `sectionLengths`.
+pub(crate) const TOP_ARRAY_LEN_B1: usize = 6;
+pub(crate) const KNOWN_SECTION_NAMES: [&str; 6] = [
+    "index",
+    "critical",
+    "responses",
+    "primary",
+    "signatures",
+    "manifest",
+];
+/// The sections which the decoder reads. The others are skipped.
+pub(crate) const PARSED_SECTION_NAMES: [&str; 4] = ["index", "responses", "primary", "signatures"];
 
 /// Represents the version of WebBundle.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Version {
     /// Version b2, which is used in Google Chrome
     VersionB2,
     /// Version 1
     Version1,
+    /// Version b1, which older versions of Google Chrome used. Bundles of
+    /// this version require a primary url, and variants, i.e. responses of
+    /// the same url, can be decoded, but not encoded.
+    VersionB1,
     /// Unknown version
     Unknown([u8; 4]),
 }
@@ -105,11 +162,66 @@ impl Version {
         match self {
             Version::VersionB2 => &[0x62, 0x32, 0, 0],
             Version::Version1 => &[0x31, 0, 0, 0],
+            Version::VersionB1 => &[0x62, 0x31, 0, 0],
             Version::Unknown(a) => a,
         }
     }
 }
 
+/// Formats the version as `"b2"`, `"1"`, `"b1"`, or the hex of the bytes of
+/// an unknown version, e.g. `"31300000"`.
+impl std::fmt::Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Version::VersionB2 => f.write_str("b2"),
+            Version::Version1 => f.write_str("1"),
+            Version::VersionB1 => f.write_str("b1"),
+            Version::Unknown(bytes) => bytes.iter().try_for_each(|b| write!(f, "{b:02x}")),
+        }
+    }
+}
+
+/// Parses the format of [`Display`](std::fmt::Display).
+impl std::str::FromStr for Version {
+    type Err = anyhow::Error;
+
+    fn from_str(version: &str) -> Result<Self> {
+        match version {
+            "b2" => Ok(Version::VersionB2),
+            "1" => Ok(Version::Version1),
+            "b1" => Ok(Version::VersionB1),
+            // Works on bytes, as slicing a non-ASCII string may split a char.
+            hex if hex.len() == 8 => {
+                let mut bytes = [0; 4];
+                for (byte, hex) in bytes.iter_mut().zip(hex.as_bytes().chunks(2)) {
+                    *byte = std::str::from_utf8(hex)
+                        .ok()
+                        .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                        .with_context(|| format!("Invalid version: {version}"))?;
+                }
+                Ok(Version::Unknown(bytes))
+            }
+            _ => bail!("Invalid version: {version}"),
+        }
+    }
+}
+
+/// The order of the urls in the index section of an encoded bundle.
+///
+/// The responses section is always in the order of the exchanges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IndexOrder {
+    /// Sorts the urls as canonical CBOR requires, i.e. shorter urls first,
+    /// then bytewise.
+    #[default]
+    Canonical,
+    /// Keeps the order of the exchanges, for readers which depend on the
+    /// order of the index. The index is not canonical CBOR unless the
+    /// exchanges happen to be sorted, so [`validate`](crate::validate)
+    /// reports it.
+    Insertion,
+}
+
 /// Represents an HTTP exchange, a pair of a request and a response.
 #[derive(Debug)]
 pub struct Exchange {
@@ -161,12 +273,49 @@ where
     }
 }
 
+/// The raw signatures section of a decoded bundle, which is written again when
+/// the bundle is encoded.
+#[derive(Debug, Clone)]
+pub(crate) struct SignaturesSection {
+    pub(crate) bytes: Vec<u8>,
+    /// The fingerprint of the exchanges when the bundle was decoded, to tell
+    /// whether changes may have invalidated the signatures.
+    pub(crate) fingerprint: u64,
+}
+
+/// Returns a hash of the urls and the responses, which doesn't depend on the
+/// order of the exchanges or of the headers.
+pub(crate) fn exchanges_fingerprint(exchanges: &[Exchange]) -> u64 {
+    use std::hash::{Hash, Hasher};
+
+    let mut exchanges = exchanges.iter().collect::<Vec<_>>();
+    exchanges.sort_by_key(|exchange| exchange.request.url());
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    for exchange in exchanges {
+        exchange.request.url().hash(&mut hasher);
+        exchange.response.status().hash(&mut hasher);
+        let mut headers = exchange
+            .response
+            .headers()
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_bytes()))
+            .collect::<Vec<_>>();
+        headers.sort();
+        headers.hash(&mut hasher);
+        exchange.response.body().hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
 /// Represents a WebBundle.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Bundle {
     pub(crate) version: Version,
     pub(crate) primary_url: Option<Uri>,
     pub(crate) exchanges: Vec<Exchange>,
+    pub(crate) index_order: IndexOrder,
+    pub(crate) ignored_sections: Vec<String>,
+    pub(crate) signatures: Option<SignaturesSection>,
 }
 
 impl Bundle {
@@ -180,14 +329,179 @@ impl Bundle {
         &self.primary_url
     }
 
+    /// Gets the order of the index section. For a decoded bundle, this is
+    /// `Canonical` if its index is sorted, and `Insertion` otherwise, so that
+    /// encoding it again keeps the order.
+    pub fn index_order(&self) -> IndexOrder {
+        self.index_order
+    }
+
+    /// Sets the order of the index section.
+    pub fn set_index_order(&mut self, index_order: IndexOrder) {
+        self.index_order = index_order;
+    }
+
+    /// Gets the names of the sections which were skipped when decoding this
+    /// bundle, e.g. `"critical"` or unknown sections, in the order of the
+    /// bundle. Encoding doesn't write them, so this is empty
+    /// unless the bundle is decoded.
+    pub fn ignored_sections(&self) -> &[String] {
+        &self.ignored_sections
+    }
+
+    /// Gets the raw CBOR of the signatures section, if the bundle was decoded
+    /// with it, e.g. a signed `b1` bundle.
+    ///
+    /// The section is written again when this bundle is encoded. If the
+    /// exchanges have changed since, the signatures may be invalid, and
+    /// encoding logs a warning. Use [`strip_signatures`](Bundle::strip_signatures)
+    /// to drop them.
+    ///
+    /// The signatures of `b1` sign the exchanges as encoded in `b1`, so
+    /// writing the section into a bundle of another version, e.g. `b2` or
+    /// `1`, doesn't produce a verifiable signature.
+    pub fn signatures(&self) -> Option<&[u8]> {
+        self.signatures
+            .as_ref()
+            .map(|signatures| &signatures.bytes[..])
+    }
+
+    /// Removes the signatures section, so that encoding doesn't write it.
+    pub fn strip_signatures(&mut self) {
+        self.signatures = None;
+    }
+
     /// Gets the exchanges.
     pub fn exchanges(&self) -> &[Exchange] {
         &self.exchanges
     }
 
+    /// Gets the exchanges as mutable.
+    pub fn exchanges_mut(&mut self) -> &mut [Exchange] {
+        &mut self.exchanges
+    }
+
+    /// Retains only the exchanges for which the given predicate returns true.
+    pub fn retain(&mut self, f: impl FnMut(&Exchange) -> bool) {
+        self.exchanges.retain(f);
+    }
+
+    /// Removes the exchange for the given url and returns it.
+    pub fn remove(&mut self, url: &str) -> Option<Exchange> {
+        let index = self
+            .exchanges
+            .iter()
+            .position(|exchange| exchange.request.url() == url)?;
+        Some(self.exchanges.remove(index))
+    }
+
+    /// Inserts the given exchange.
+    ///
+    /// If there is already an exchange for the same url, it is replaced in
+    /// place and returned. Otherwise, the exchange is appended.
+    pub fn insert(&mut self, exchange: Exchange) -> Option<Exchange> {
+        match self
+            .exchanges
+            .iter_mut()
+            .find(|e| e.request.url() == exchange.request.url())
+        {
+            Some(existing) => Some(std::mem::replace(existing, exchange)),
+            None => {
+                self.exchanges.push(exchange);
+                None
+            }
+        }
+    }
+
+    /// Gets the error page for the given url.
+    ///
+    /// An error page is an exchange whose response status is `404 Not Found`.
+    /// It covers the URLs in the directory of its URL, e.g. `docs/404.html`
+    /// covers `docs/foo.html`. If several error pages cover the given url,
+    /// the most specific one is returned.
+    pub fn not_found_exchange(&self, url: &str) -> Option<&Exchange> {
+        self.exchanges
+            .iter()
+            .filter(|exchange| exchange.response.status() == StatusCode::NOT_FOUND)
+            .filter_map(|exchange| {
+                let request_url = exchange.request.url();
+                let scope = match request_url.rfind('/') {
+                    Some(pos) => &request_url[..=pos],
+                    None => "",
+                };
+                url.starts_with(scope).then_some((scope.len(), exchange))
+            })
+            .max_by_key(|(len, _)| *len)
+            .map(|(_, exchange)| exchange)
+    }
+
     /// Parses the given bytes and returns the parsed Bundle.
     pub fn from_bytes(bytes: impl AsRef<[u8]>) -> Result<Bundle> {
-        decoder::parse(bytes)
+        decoder::parse(bytes, &Default::default())
+    }
+
+    /// Parses the given bytes with the given options, e.g. to limit the
+    /// resources used for untrusted input.
+    pub fn from_bytes_with_options(
+        bytes: impl AsRef<[u8]>,
+        options: &decoder::DecodeOptions,
+    ) -> Result<Bundle> {
+        decoder::parse(bytes, options)
+    }
+
+    /// Parses the given bytes and resolves relative urls against `base_url`,
+    /// as browsers resolve them against the url of the bundle, so that all
+    /// urls of the returned Bundle are absolute.
+    ///
+    /// Returns an error if two urls resolve to the same url, e.g. `a.js` and
+    /// `./a.js`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use webbundle::Bundle;
+    /// let bundle = Bundle::from_bytes_with_base(
+    ///     std::fs::read("example.wbn")?,
+    ///     &"https://example.com/dir/example.wbn".parse()?,
+    /// )?;
+    /// # std::result::Result::Ok::<_, anyhow::Error>(())
+    /// ```
+    pub fn from_bytes_with_base(bytes: impl AsRef<[u8]>, base_url: &Uri) -> Result<Bundle> {
+        let mut bundle = Bundle::from_bytes(bytes)?;
+        if let Some(primary_url) = &bundle.primary_url {
+            if primary_url.scheme().is_none() {
+                bundle.primary_url = Some(
+                    resolve_url(base_url, &primary_url.to_string())?
+                        .parse()
+                        .context("Failed to parse primary_url")?,
+                );
+            }
+        }
+        let mut urls = HashSet::new();
+        for exchange in &mut bundle.exchanges {
+            let url = resolve_url(base_url, exchange.request.url())?;
+            ensure!(
+                urls.insert(url.clone()),
+                format!(
+                    "{} resolves to {}, which is a duplicate url",
+                    exchange.request.url(),
+                    url
+                )
+            );
+            exchange.request.set_url(url);
+        }
+        Ok(bundle)
+    }
+
+    /// Reads a bundle from the given `read`, e.g. from stdin, and returns the
+    /// parsed Bundle.
+    ///
+    /// The bytes are streamed. Only the sections before the responses are
+    /// read up front, then the responses are read and parsed one by one, so
+    /// the whole bundle is never buffered. The bytes after the last response,
+    /// i.e. the trailing length, are not read.
+    pub fn from_reader(read: impl std::io::Read) -> Result<Bundle> {
+        decoder::parse_reader(read, &Default::default())
     }
 
     /// Encodes this bundle and write the result to the given `write`.
@@ -195,6 +509,71 @@ impl Bundle {
         encoder::encode(self, write)
     }
 
+    /// Encodes this bundle and writes the result to the given seekable
+    /// `write`, e.g. a file.
+    ///
+    /// Unlike `write_to`, response bodies are written directly without being
+    /// copied into one buffer, and the trailing length is patched after the
+    /// responses are written.
+    pub fn write_to_seekable<W: Write + Seek>(&self, write: W) -> Result<()> {
+        encoder::encode_seekable(self, write)
+    }
+
+    /// Encodes this bundle and writes the result to the given async `write`.
+    ///
+    /// Unlike `encode`, response bodies are written directly without being
+    /// copied into one buffer.
+    #[cfg(feature = "tokio")]
+    pub async fn write_to_async<W>(&self, write: W) -> Result<()>
+    where
+        W: tokio::io::AsyncWrite + Unpin,
+    {
+        encoder::encode_async(self, write).await
+    }
+
+    /// Encodes this bundle and writes the result to the file at the given path.
+    ///
+    /// The result is written to a temporary file in the same directory first,
+    /// which is then renamed to the path, so that the file is never left
+    /// partially written.
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    pub fn write_to_file(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let temp_path = temp_path(path)?;
+        let result = (|| -> Result<()> {
+            let file = std::fs::File::create(&temp_path)?;
+            let mut write = std::io::BufWriter::new(file);
+            self.write_to_seekable(&mut write)?;
+            write.into_inner()?.sync_all()?;
+            std::fs::rename(&temp_path, path)?;
+            Ok(())
+        })();
+        if result.is_err() {
+            let _ = std::fs::remove_file(&temp_path);
+        }
+        result.with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Same as `write_to_file`, but writes the file asynchronously.
+    #[cfg(feature = "tokio")]
+    pub async fn write_to_file_async(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let temp_path = temp_path(path)?;
+        let result: Result<()> = async {
+            let file = tokio::fs::File::create(&temp_path).await?;
+            let mut write = tokio::io::BufWriter::new(file);
+            self.write_to_async(&mut write).await?;
+            write.into_inner().sync_all().await?;
+            tokio::fs::rename(&temp_path, path).await?;
+            Ok(())
+        }
+        .await;
+        if result.is_err() {
+            let _ = tokio::fs::remove_file(&temp_path).await;
+        }
+        result.with_context(|| format!("Failed to write {}", path.display()))
+    }
+
     /// Encodes this bundle.
     pub fn encode(&self) -> Result<Vec<u8>> {
         encoder::encode_to_vec(self)
@@ -214,6 +593,23 @@ impl<'a> TryFrom<&'a [u8]> for Bundle {
     }
 }
 
+/// Returns a temporary path in the same directory as the given path.
+///
+/// The name has the process id and a counter, so that concurrent writes to
+/// the same path, from threads or processes, never share a temporary file.
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+fn temp_path(path: &Path) -> Result<std::path::PathBuf> {
+    static COUNTER: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+    let file_name = path
+        .file_name()
+        .with_context(|| format!("Invalid file path: {}", path.display()))?;
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(file_name);
+    let count = COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    temp_name.push(format!(".{}.{count}.tmp", std::process::id()));
+    Ok(path.with_file_name(temp_name))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -231,6 +627,23 @@ mod tests {
         assert_eq!(request.url(), "foo/bar");
     }
 
+    #[test]
+    fn version_from_str() -> Result<()> {
+        for version in [
+            Version::VersionB2,
+            Version::Version1,
+            Version::VersionB1,
+            Version::Unknown([0x31, 0x30, 0, 0]),
+        ] {
+            assert_eq!(version.to_string().parse::<Version>()?, version);
+        }
+        assert_eq!("31300000".parse::<Version>()?.bytes(), b"10\0\0");
+        for invalid in ["b3", "3130000x", "31300é0"] {
+            assert!(invalid.parse::<Version>().is_err(), "{invalid}");
+        }
+        Ok(())
+    }
+
     #[test]
     fn exchange_from() {
         let exchange = Exchange::from(("index.html".to_string(), "hello".to_string().into_bytes()));
@@ -242,14 +655,202 @@ mod tests {
         );
     }
 
+    #[test]
+    fn write_to_and_from_reader() -> Result<()> {
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchange(Exchange::from((
+                "index.html".to_string(),
+                b"hello".to_vec(),
+            )))
+            .build()?;
+        let mut bytes = Vec::new();
+        bundle.write_to(&mut bytes)?;
+        let bundle = Bundle::from_reader(&bytes[..])?;
+        assert_eq!(bundle.exchanges()[0].response.body(), b"hello");
+
+        // Larger than the first read.
+        let bundle = crate::testing::synthetic_bundle(3, 4, 1000);
+        let bytes = bundle.encode()?;
+        let from_reader = Bundle::from_reader(&bytes[..])?;
+        assert_eq!(from_reader.encode()?, bytes);
+        assert!(Bundle::from_reader(&bytes[..bytes.len() - 100]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn from_bytes_with_base() -> Result<()> {
+        let bytes = Bundle::builder()
+            .version(Version::VersionB2)
+            .primary_url("/index.html".parse()?)
+            .exchange(Exchange::from(("index.html".to_string(), vec![])))
+            .exchange(Exchange::from(("../lib/a.js".to_string(), vec![])))
+            .exchange(Exchange::from((
+                "https://cdn.example/b.js".to_string(),
+                vec![],
+            )))
+            .build()?
+            .encode()?;
+        let bundle =
+            Bundle::from_bytes_with_base(&bytes, &"https://example.com/app/x.wbn".parse()?)?;
+        assert_eq!(
+            bundle.primary_url(),
+            &Some("https://example.com/index.html".parse()?)
+        );
+        let urls = bundle
+            .exchanges()
+            .iter()
+            .map(|exchange| exchange.request.url().as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                "https://example.com/app/index.html",
+                "https://example.com/lib/a.js",
+                "https://cdn.example/b.js",
+            ]
+        );
+        assert!(Bundle::from_bytes_with_base(&bytes, &"/app/x.wbn".parse()?).is_err());
+
+        let bytes = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchange(Exchange::from(("a.js".to_string(), vec![])))
+            .exchange(Exchange::from(("./a.js".to_string(), vec![])))
+            .build()?
+            .encode()?;
+        assert!(Bundle::from_bytes(&bytes).is_ok());
+        assert!(
+            Bundle::from_bytes_with_base(&bytes, &"https://example.com/x.wbn".parse()?).is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn not_found_exchange() -> Result<()> {
+        let not_found = |url: &str| {
+            let mut exchange = Exchange::from((url.to_string(), vec![]));
+            *exchange.response.status_mut() = StatusCode::NOT_FOUND;
+            exchange
+        };
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchange(Exchange::from(("index.html".to_string(), vec![])))
+            .exchange(not_found("404.html"))
+            .exchange(not_found("docs/404.html"))
+            .build()?;
+
+        assert_eq!(
+            bundle.not_found_exchange("foo.html").unwrap().request.url(),
+            "404.html"
+        );
+        assert_eq!(
+            bundle
+                .not_found_exchange("docs/foo.html")
+                .unwrap()
+                .request
+                .url(),
+            "docs/404.html"
+        );
+        assert!(Bundle::builder()
+            .version(Version::VersionB2)
+            .build()?
+            .not_found_exchange("foo.html")
+            .is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn mutation() -> Result<()> {
+        let mut bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchange(Exchange::from(("a.html", b"a".to_vec())))
+            .exchange(Exchange::from(("b.html", b"b".to_vec())))
+            .exchange(Exchange::from(("c.js", b"c".to_vec())))
+            .build()?;
+
+        let old = bundle.insert(Exchange::from(("a.html", b"new".to_vec())));
+        assert_eq!(old.unwrap().response.body(), b"a");
+        assert!(bundle.insert(Exchange::from(("d.html", vec![]))).is_none());
+        assert_eq!(bundle.exchanges()[0].response.body(), b"new");
+        assert_eq!(bundle.exchanges().len(), 4);
+
+        assert_eq!(bundle.remove("b.html").unwrap().request.url(), "b.html");
+        assert!(bundle.remove("b.html").is_none());
+
+        bundle.retain(|e| e.request.url().ends_with(".html"));
+        for exchange in bundle.exchanges_mut() {
+            *exchange.response.status_mut() = StatusCode::NOT_FOUND;
+        }
+        let urls = bundle
+            .exchanges()
+            .iter()
+            .map(|e| e.request.url().as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(urls, ["a.html", "d.html"]);
+        assert_eq!(
+            bundle.exchanges()[1].response.status(),
+            StatusCode::NOT_FOUND
+        );
+
+        Bundle::from_bytes(bundle.encode()?)?;
+        Ok(())
+    }
+
     #[test]
     fn exchange_from_with_content_type() {
         let exchange = Exchange::from(("./foo/".to_string(), vec![], ContentType::html()));
         assert_eq!(exchange.request.url(), "./foo/");
-        assert_eq!(exchange.response.body(), &[]);
+        assert_eq!(exchange.response.body(), b"");
         assert_eq!(
             exchange.response.headers().typed_get::<ContentType>(),
             Some(ContentType::html())
         );
     }
+
+    #[test]
+    fn write_to_file() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("example.wbn");
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchange(Exchange::from(("index.html", b"hello".to_vec())))
+            .build()?;
+        bundle.write_to_file(&path)?;
+        assert_eq!(std::fs::read(&path)?, bundle.encode()?);
+        // No temporary file is left.
+        assert_eq!(std::fs::read_dir(dir.path())?.count(), 1);
+
+        assert!(bundle
+            .write_to_file(dir.path().join("missing/example.wbn"))
+            .is_err());
+
+        // Concurrent writes to the same path use their own temporary files.
+        std::thread::scope(|scope| {
+            let writes = (0..4)
+                .map(|_| scope.spawn(|| bundle.write_to_file(&path)))
+                .collect::<Vec<_>>();
+            writes
+                .into_iter()
+                .try_for_each(|write| write.join().unwrap())
+        })?;
+        assert_eq!(std::fs::read(&path)?, bundle.encode()?);
+        assert_eq!(std::fs::read_dir(dir.path())?.count(), 1);
+        assert_ne!(temp_path(&path)?, temp_path(&path)?);
+        Ok(())
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn write_to_file_async() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("example.wbn");
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchange(Exchange::from(("index.html", b"hello".to_vec())))
+            .build()?;
+        bundle.write_to_file_async(&path).await?;
+        assert_eq!(std::fs::read(&path)?, bundle.encode()?);
+        assert_eq!(std::fs::read_dir(dir.path())?.count(), 1);
+        Ok(())
+    }
 }
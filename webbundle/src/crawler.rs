@@ -0,0 +1,309 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::bundle::Exchange;
+use crate::http_client::fetch;
+use crate::prelude::*;
+use headers::{ContentType, HeaderMapExt as _};
+use std::collections::{HashSet, VecDeque};
+use url::Url;
+
+impl crate::builder::Builder {
+    /// Crawls pages from the given url, and appends the fetched responses as
+    /// exchanges.
+    ///
+    /// Subresources of a page, e.g. scripts, stylesheets and images, are
+    /// always fetched. Links (`<a href>`) are followed until `depth`; `0`
+    /// fetches only the start page and its subresources. If `same_origin_only`
+    /// is true, URLs whose origin is different from the start url's are not
+    /// fetched.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # async {
+    /// use webbundle::{Bundle, Version};
+    /// let bundle = Bundle::builder()
+    ///     .version(Version::VersionB2)
+    ///     .primary_url("https://example.com/".parse()?)
+    ///     .crawl("https://example.com/", 1, true).await?
+    ///     .build()?;
+    /// # std::result::Result::Ok::<_, anyhow::Error>(bundle)
+    /// # };
+    /// ```
+    pub async fn crawl(
+        mut self,
+        start_url: &str,
+        depth: usize,
+        same_origin_only: bool,
+    ) -> Result<Self> {
+        let start_url = Url::parse(start_url)?;
+        let client = reqwest::Client::new();
+
+        let mut visited = HashSet::new();
+        let mut queue = VecDeque::new();
+        visited.insert(start_url.clone());
+        queue.push_back((start_url.clone(), 0));
+
+        while let Some((url, current_depth)) = queue.pop_front() {
+            let exchange = match fetch(&client, url.as_str()).await {
+                Ok(exchange) => exchange,
+                Err(err) => {
+                    log::warn!("Skipping {}: {:?}", url, err);
+                    continue;
+                }
+            };
+            for (kind, reference) in references(&exchange) {
+                let next_depth = match kind {
+                    Reference::Subresource => current_depth,
+                    Reference::Link => current_depth + 1,
+                };
+                if next_depth > depth {
+                    continue;
+                }
+                let mut next = match url.join(&reference) {
+                    Ok(next) => next,
+                    Err(_) => continue,
+                };
+                next.set_fragment(None);
+                if !matches!(next.scheme(), "http" | "https") {
+                    continue;
+                }
+                if same_origin_only && next.origin() != start_url.origin() {
+                    continue;
+                }
+                if visited.insert(next.clone()) {
+                    queue.push_back((next, next_depth));
+                }
+            }
+            self.exchanges.push(exchange);
+        }
+        Ok(self)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Reference {
+    Subresource,
+    Link,
+}
+
+fn references(exchange: &Exchange) -> Vec<(Reference, String)> {
+    if !exchange.response.status().is_success() {
+        return Vec::new();
+    }
+    let mime = match exchange.response.headers().typed_get::<ContentType>() {
+        Some(content_type) => mime_guess::Mime::from(content_type),
+        None => return Vec::new(),
+    };
+    let body = String::from_utf8_lossy(exchange.response.body());
+    match mime.essence_str() {
+        "text/html" => html_references(&body),
+        "text/css" => css_references(&body)
+            .into_iter()
+            .map(|url| (Reference::Subresource, url))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Extracts `src` and `href` attributes from HTML.
+///
+/// This is not a conforming HTML parser, but is good enough to discover
+/// resources in typical pages.
+fn html_references(html: &str) -> Vec<(Reference, String)> {
+    let mut references = Vec::new();
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        let end = tag_end(rest);
+        let tag = &rest[..end];
+        rest = &rest[end..];
+
+        let mut parts = tag.splitn(2, |c: char| c.is_ascii_whitespace());
+        let name = parts.next().unwrap_or_default().to_ascii_lowercase();
+        if name.starts_with('!') || name.starts_with('/') {
+            continue;
+        }
+        for (attr, value) in attributes(parts.next().unwrap_or_default()) {
+            match (name.as_str(), attr.as_str()) {
+                ("a" | "area", "href") => references.push((Reference::Link, value)),
+                (_, "src" | "href" | "poster") => references.push((Reference::Subresource, value)),
+                _ => {}
+            }
+        }
+        if name == "style" {
+            let end = rest.find("</style").unwrap_or(rest.len());
+            references.extend(
+                css_references(&rest[..end])
+                    .into_iter()
+                    .map(|url| (Reference::Subresource, url)),
+            );
+        }
+    }
+    references
+}
+
+/// Returns the position of `>` which closes the tag, skipping quoted values.
+fn tag_end(s: &str) -> usize {
+    let mut quote = None;
+    for (i, c) in s.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '>') => return i,
+            _ => {}
+        }
+    }
+    s.len()
+}
+
+fn attributes(s: &str) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    let mut rest = s.trim_start();
+    while !rest.is_empty() {
+        let name_end = rest
+            .find(|c: char| c.is_ascii_whitespace() || c == '=' || c == '/')
+            .unwrap_or(rest.len());
+        let name = rest[..name_end].to_ascii_lowercase();
+        rest = rest[name_end..].trim_start();
+        if let Some(after_eq) = rest.strip_prefix('=') {
+            let after_eq = after_eq.trim_start();
+            let (value, after_value) = match after_eq.chars().next() {
+                Some(q @ ('"' | '\'')) => {
+                    let value_end = after_eq[1..]
+                        .find(q)
+                        .map(|i| i + 1)
+                        .unwrap_or(after_eq.len());
+                    (
+                        &after_eq[1..value_end],
+                        after_eq.get(value_end + 1..).unwrap_or_default(),
+                    )
+                }
+                _ => {
+                    let value_end = after_eq
+                        .find(|c: char| c.is_ascii_whitespace())
+                        .unwrap_or(after_eq.len());
+                    (&after_eq[..value_end], &after_eq[value_end..])
+                }
+            };
+            attributes.push((name, value.trim().to_string()));
+            rest = after_value.trim_start();
+        } else if name_end == 0 {
+            // Skip a stray character, e.g. '/' in `<br />`.
+            rest = rest[1..].trim_start();
+        }
+    }
+    attributes
+}
+
+/// Extracts `url(...)` and `@import "..."` from CSS.
+fn css_references(css: &str) -> Vec<String> {
+    let mut references = Vec::new();
+    let mut rest = css;
+    while let Some(start) = rest.find("url(") {
+        rest = &rest[start + 4..];
+        let end = rest.find(')').unwrap_or(rest.len());
+        let url = rest[..end].trim().trim_matches(|c| c == '"' || c == '\'');
+        if !url.is_empty() && !url.starts_with("data:") {
+            references.push(url.to_string());
+        }
+        rest = &rest[end..];
+    }
+    let mut rest = css;
+    while let Some(start) = rest.find("@import") {
+        rest = rest[start + 7..].trim_start();
+        if let Some(q @ ('"' | '\'')) = rest.chars().next() {
+            if let Some(end) = rest[1..].find(q) {
+                references.push(rest[1..end + 1].to_string());
+            }
+        }
+    }
+    references
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundle::{Bundle, Version};
+    use crate::http_client::tests::serve;
+
+    #[test]
+    fn html_references_test() {
+        let html = r#"<!DOCTYPE html>
+<link rel=stylesheet href="style.css">
+<script src='app.js' defer></script>
+<img alt="a > b" src=a.png />
+<a href="/next.html">next</a>
+<style>body { background: url("bg.png"); }</style>"#;
+        assert_eq!(
+            html_references(html),
+            vec![
+                (Reference::Subresource, "style.css".to_string()),
+                (Reference::Subresource, "app.js".to_string()),
+                (Reference::Subresource, "a.png".to_string()),
+                (Reference::Link, "/next.html".to_string()),
+                (Reference::Subresource, "bg.png".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn css_references_test() {
+        let css = r#"@import "base.css";
+body { background: url(bg.png); }
+div { background: url('data:image/png;base64,AAAA'); }"#;
+        assert_eq!(css_references(css), vec!["bg.png", "base.css"]);
+    }
+
+    #[tokio::test]
+    async fn crawl() -> Result<()> {
+        let origin = serve(vec![
+            (
+                "/",
+                "text/html",
+                r#"<link rel=stylesheet href=style.css><a href="next.html">next</a><a href="https://example.com/">external</a>"#,
+            ),
+            (
+                "/style.css",
+                "text/css",
+                "body { background: url(bg.png); }",
+            ),
+            ("/bg.png", "image/png", "png"),
+            ("/next.html", "text/html", r#"<a href="last.html">last</a>"#),
+            ("/last.html", "text/html", "last"),
+        ]);
+
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .crawl(&format!("{origin}/"), 1, true)
+            .await?
+            .build()?;
+        let urls = bundle
+            .exchanges()
+            .iter()
+            .map(|exchange| exchange.request.url().as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            vec![
+                format!("{origin}/"),
+                format!("{origin}/style.css"),
+                format!("{origin}/next.html"),
+                format!("{origin}/bg.png"),
+            ]
+        );
+        Ok(())
+    }
+}
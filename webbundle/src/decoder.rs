@@ -12,7 +12,9 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::bundle::{self, Bundle, Exchange, Request, Response, Uri, Version};
+use crate::bundle::{self, Bundle, Exchange, IndexOrder, Response, Version};
+use crate::canonical_url::canonicalize_url;
+use crate::fetch_plan::{Index, IndexEntry};
 use crate::prelude::*;
 use cbor_event::Len;
 use http::{
@@ -20,45 +22,305 @@ use http::{
     StatusCode,
 };
 use std::collections::HashSet;
-use std::convert::TryInto;
-use std::io::Cursor;
+use std::io::{Cursor, Read};
+
+/// Options to parse a bundle, e.g. to limit resources for untrusted input.
+///
+/// # Examples
+///
+/// ```no_run
+/// use webbundle::{Bundle, DecodeOptions};
+/// let mut options = DecodeOptions::default();
+/// options.max_exchanges = Some(1_000);
+/// options.max_body_bytes = Some(10 * 1024 * 1024);
+/// options.strict = true;
+/// let bundle = Bundle::from_bytes_with_options(std::fs::read("example.wbn")?, &options)?;
+/// # std::result::Result::Ok::<_, anyhow::Error>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct DecodeOptions {
+    /// The maximum number of exchanges. Unlimited if `None`.
+    pub max_exchanges: Option<usize>,
+    /// The maximum number of bytes of each response body. Unlimited if `None`.
+    ///
+    /// The length of each response in the index is checked before the
+    /// response is read, and the lengths of the byte strings in a response
+    /// are checked against the response before they are allocated, so that
+    /// the limit bounds the memory to parse it. The headers of each response
+    /// may take 64 KiB more.
+    pub max_body_bytes: Option<u64>,
+    /// Rejects unknown sections and duplicate urls in the index, which are
+    /// skipped by default, and canonicalizes urls as Chromium does. See
+    /// [`canonicalize_url`](crate::canonicalize_url).
+    pub strict: bool,
+}
+
+/// The bytes which the headers of a response may take beyond
+/// [`DecodeOptions::max_body_bytes`], when the length of the response is
+/// checked before the response is read.
+const MAX_HEADERS_BYTES: u64 = 64 * 1024;
 
-pub(crate) fn parse(bytes: impl AsRef<[u8]>) -> Result<Bundle> {
-    Decoder::new(bytes).decode()
+pub(crate) fn parse(bytes: impl AsRef<[u8]>, options: &DecodeOptions) -> Result<Bundle> {
+    let bytes = bytes.as_ref();
+    let metadata = read_index(bytes, options)?;
+    parse_responses(metadata, options, |entry| {
+        let range = entry.range();
+        ensure!(
+            range.end <= bytes.len() as u64,
+            format!(
+                "bundle: Range {}..{} is out of {} bytes",
+                range.start,
+                range.end,
+                bytes.len()
+            )
+        );
+        parse_response(&bytes[range.start as usize..range.end as usize])
+    })
 }
 
-#[derive(Debug)]
-struct SectionOffset {
-    name: String,
-    offset: u64,
-    length: u64,
+/// Parses a bundle from `read`, reading only the bytes before the responses
+/// section up front. The responses are read and parsed one by one, so the
+/// whole bytes are never in memory.
+pub(crate) fn parse_reader(mut read: impl Read, options: &DecodeOptions) -> Result<Bundle> {
+    // Read more bytes, twice as many as before, until the sections before
+    // the responses section are read.
+    const MIN_READ_LEN: u64 = 16 * 1024;
+    let mut buf = Vec::new();
+    let metadata = loop {
+        let read_len = (&mut read)
+            .take((buf.len() as u64).max(MIN_READ_LEN))
+            .read_to_end(&mut buf)?;
+        match read_index(&buf, options) {
+            Ok(metadata) => break metadata,
+            Err(err)
+                if read_len > 0
+                    && err.downcast_ref::<webbundle_core::Error>()
+                        == Some(&webbundle_core::Error::UnexpectedEnd) => {}
+            Err(err) => return Err(err),
+        }
+    };
+
+    // `buf` has the bytes from `buf_offset` of the bundle.
+    let mut buf_offset = 0;
+    parse_responses(metadata, options, |entry| {
+        // The entries are sorted by offsets, so the bytes before the offset
+        // are no longer needed.
+        let buf_end = buf_offset + buf.len() as u64;
+        if entry.offset <= buf_end {
+            buf.drain(..(entry.offset - buf_offset) as usize);
+        } else {
+            buf.clear();
+            std::io::copy(
+                &mut (&mut read).take(entry.offset - buf_end),
+                &mut std::io::sink(),
+            )?;
+        }
+        buf_offset = entry.offset;
+        if entry.length > buf.len() as u64 {
+            // Grows as the bytes are read, as the length is untrusted.
+            (&mut read)
+                .take(entry.length - buf.len() as u64)
+                .read_to_end(&mut buf)?;
+        }
+        ensure!(
+            entry.length <= buf.len() as u64,
+            format!("bundle: Unexpected end of the response of {}", entry.url)
+        );
+        parse_response(&buf[..entry.length as usize])
+    })
 }
 
-#[derive(Debug)]
-struct ResponseLocation {
-    offset: u64,
-    length: u64,
+/// Builds a bundle, reading the response of each index entry, in the order
+/// of the offsets, by `read_response`.
+fn parse_responses(
+    metadata: Metadata,
+    options: &DecodeOptions,
+    mut read_response: impl FnMut(&IndexEntry) -> Result<Response>,
+) -> Result<Bundle> {
+    let Metadata {
+        mut index,
+        ignored_sections,
+        signatures,
+    } = metadata;
+    if let Some(max_exchanges) = options.max_exchanges {
+        ensure!(
+            index.entries.len() <= max_exchanges,
+            format!(
+                "bundle: {} exchanges exceed the limit of {}",
+                index.entries.len(),
+                max_exchanges
+            )
+        );
+    }
+    let index_order = index_order(&index.entries);
+    // The responses are in the original order of the exchanges, whichever
+    // order the index is in.
+    index.entries.sort_by_key(|entry| entry.offset);
+    let exchanges = index
+        .entries
+        .into_iter()
+        .map(|entry| {
+            if let Some(max_body_bytes) = options.max_body_bytes {
+                // The response has the headers too, which are not limited.
+                ensure!(
+                    entry.length <= max_body_bytes.saturating_add(MAX_HEADERS_BYTES),
+                    format!(
+                        "bundle: The response of {} exceeds the limit of {} bytes",
+                        entry.url,
+                        max_body_bytes.saturating_add(MAX_HEADERS_BYTES)
+                    )
+                );
+            }
+            let response = read_response(&entry)?;
+            if let Some(max_body_bytes) = options.max_body_bytes {
+                ensure!(
+                    response.body().len() as u64 <= max_body_bytes,
+                    format!(
+                        "bundle: The body of {} exceeds the limit of {} bytes",
+                        entry.url, max_body_bytes
+                    )
+                );
+            }
+            Ok(Exchange {
+                request: entry.url.into(),
+                response,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Bundle {
+        version: index.version,
+        primary_url: index.primary_url,
+        signatures: signatures.map(|bytes| bundle::SignaturesSection {
+            bytes,
+            fingerprint: bundle::exchanges_fingerprint(&exchanges),
+        }),
+        exchanges,
+        index_order,
+        ignored_sections,
+    })
 }
 
-impl ResponseLocation {
-    pub fn new(responses_section_offset: u64, offset: u64, length: u64) -> ResponseLocation {
-        ResponseLocation {
-            offset: responses_section_offset + offset,
-            length,
-        }
+/// Returns `Canonical` if the urls are sorted as canonical CBOR, i.e. shorter
+/// urls first, then bytewise.
+fn index_order(entries: &[IndexEntry]) -> IndexOrder {
+    if entries
+        .windows(2)
+        .all(|pair| (pair[0].url.len(), &pair[0].url) < (pair[1].url.len(), &pair[1].url))
+    {
+        IndexOrder::Canonical
+    } else {
+        IndexOrder::Insertion
     }
 }
 
-#[derive(Debug)]
-struct RequestEntry {
-    request: Request,
-    response_location: ResponseLocation,
+/// Parses the sections before the responses section. `bytes` doesn't have to
+/// contain the responses section.
+pub(crate) fn parse_index(bytes: impl AsRef<[u8]>) -> Result<Index> {
+    Ok(read_index(bytes.as_ref(), &DecodeOptions::default())?.index)
 }
 
-#[derive(Debug)]
+/// The sections before the responses section.
 struct Metadata {
-    version: Version,
-    section_offsets: Vec<SectionOffset>,
+    index: Index,
+    /// The names of the sections which are skipped.
+    ignored_sections: Vec<String>,
+    /// The raw bytes of the signatures section.
+    signatures: Option<Vec<u8>>,
+}
+
+fn read_index(bytes: &[u8], options: &DecodeOptions) -> Result<Metadata> {
+    let index = webbundle_core::parse_index(bytes)?;
+    let signatures = match index
+        .sections
+        .iter()
+        .find(|section| section.name == "signatures")
+    {
+        Some(section) => Some(
+            bytes
+                .get(section.offset as usize..(section.offset + section.length) as usize)
+                .context("bundle: The signatures section is out of the bundle")?
+                .to_vec(),
+        ),
+        None => None,
+    };
+    let ignored_sections = index
+        .sections
+        .iter()
+        .filter(|section| !bundle::PARSED_SECTION_NAMES.contains(&section.name))
+        .map(|section| section.name.to_string())
+        .collect();
+    for section in &index.sections {
+        if !bundle::KNOWN_SECTION_NAMES.contains(&section.name) {
+            ensure!(
+                !options.strict,
+                format!("bundle: Unknown section name: {}", section.name)
+            );
+            log::warn!("Unknows section name: {}. Skipping", section.name);
+        }
+    }
+    // Chromium canonicalizes urls, so urls which differ only in their
+    // spelling, e.g. "HTTPS://example.com/", are duplicates in strict mode.
+    let canonicalize = |url: &str| {
+        if options.strict {
+            canonicalize_url(url)
+        } else {
+            Ok(url.to_string())
+        }
+    };
+    let index = Index {
+        version: version_from_bytes(index.version),
+        primary_url: index
+            .primary_url
+            .map(|url| {
+                canonicalize(url)?
+                    .parse()
+                    .context("Failed to parse primary_url")
+            })
+            .transpose()?,
+        entries: index
+            .entries
+            .into_iter()
+            .map(|entry| {
+                Ok(IndexEntry {
+                    url: canonicalize(entry.url)?,
+                    offset: entry.offset,
+                    length: entry.length,
+                })
+            })
+            .collect::<Result<_>>()?,
+    };
+    if options.strict {
+        let mut urls = HashSet::new();
+        for entry in &index.entries {
+            ensure!(
+                urls.insert(&entry.url),
+                format!("bundle: Duplicate url in the index: {}", entry.url)
+            );
+        }
+    }
+    Ok(Metadata {
+        index,
+        ignored_sections,
+        signatures,
+    })
+}
+
+/// Parses a response, given the bytes of an entry in the responses section.
+pub(crate) fn parse_response(bytes: impl AsRef<[u8]>) -> Result<Response> {
+    Decoder::new(bytes).read_response()
+}
+
+fn version_from_bytes(bytes: [u8; bundle::VERSION_BYTES_LEN]) -> Version {
+    if &bytes == Version::Version1.bytes() {
+        Version::Version1
+    } else if &bytes == Version::VersionB2.bytes() {
+        Version::VersionB2
+    } else if &bytes == Version::VersionB1.bytes() {
+        Version::VersionB1
+    } else {
+        Version::Unknown(bytes)
+    }
 }
 
 type Deserializer<R> = cbor_event::de::Deserializer<R>;
@@ -75,74 +337,7 @@ impl<T> Decoder<T> {
     }
 }
 
-type PrimaryUrl = Uri;
-
 impl<T: AsRef<[u8]>> Decoder<T> {
-    fn decode(&mut self) -> Result<Bundle> {
-        let metadata = self.read_metadata()?;
-        log::debug!("metadata {:?}", metadata);
-
-        let (requests, primary_url) = self.read_sections(&metadata.section_offsets)?;
-        let exchanges = self.read_responses(requests)?;
-
-        Ok(Bundle {
-            version: metadata.version,
-            primary_url,
-            exchanges,
-        })
-    }
-
-    fn read_metadata(&mut self) -> Result<Metadata> {
-        ensure!(
-            self.read_array_len()? as usize == bundle::TOP_ARRAY_LEN,
-            "Invalid header"
-        );
-        self.read_magic_bytes()?;
-        let version = self.read_version()?;
-        let section_offsets = self.read_section_offsets()?;
-        Ok(Metadata {
-            version,
-            section_offsets,
-        })
-    }
-
-    fn read_magic_bytes(&mut self) -> Result<()> {
-        log::debug!("read_magic_bytes");
-        let magic: Vec<u8> = self.de.bytes().context("Invalid magic bytes")?;
-        ensure!(magic == bundle::HEADER_MAGIC_BYTES, "Header magic mismatch");
-        Ok(())
-    }
-
-    fn read_version(&mut self) -> Result<Version> {
-        log::debug!("read_version");
-        let bytes: Vec<u8> = self.de.bytes().context("Invalid version format")?;
-        ensure!(
-            bytes.len() == bundle::VERSION_BYTES_LEN,
-            "Invalid version format"
-        );
-        let version: [u8; bundle::VERSION_BYTES_LEN] =
-            AsRef::<[u8]>::as_ref(&bytes).try_into().unwrap();
-        Ok(if &version == bundle::Version::Version1.bytes() {
-            Version::Version1
-        } else if &version == bundle::Version::VersionB2.bytes() {
-            Version::VersionB2
-        } else {
-            Version::Unknown(version)
-        })
-    }
-
-    fn read_section_offsets(&mut self) -> Result<Vec<SectionOffset>> {
-        let bytes = self
-            .de
-            .bytes()
-            .context("Failed to read sectionLength byte string")?;
-        ensure!(
-            bytes.len() < 8_192,
-            format!("sectionLengthsLength is too long ({} bytes)", bytes.len())
-        );
-        Decoder::new(bytes).read_section_offsets_cbor(self.position())
-    }
-
     fn read_array_len(&mut self) -> Result<u64> {
         match self.de.array()? {
             Len::Len(n) => Ok(n),
@@ -150,152 +345,19 @@ impl<T: AsRef<[u8]>> Decoder<T> {
         }
     }
 
-    fn position(&self) -> u64 {
-        self.de.as_ref().position()
-    }
-
-    fn read_section_offsets_cbor(&mut self, mut offset: u64) -> Result<Vec<SectionOffset>> {
-        let n = self
-            .read_array_len()
-            .context("bundle: bundle: Failed to decode sectionOffset array header")?;
-        let section_num = n / 2;
-        offset += self.position();
-        let mut seen_names = HashSet::new();
-        let mut section_offsets = Vec::with_capacity(section_num as usize);
-        for _ in 0..section_num {
-            let name = self.de.text()?;
-            ensure!(!seen_names.contains(&name), "Duplicate section name");
-            seen_names.insert(name.clone());
-            let length = self.de.unsigned_integer()?;
-            section_offsets.push(SectionOffset {
-                name,
-                offset,
-                length,
-            });
-            offset += length;
-        }
-        ensure!(!section_offsets.is_empty(), "bundle: section is empty");
-        ensure!(
-            section_offsets.last().unwrap().name == "responses",
-            "bundle: Last section is not \"responses\""
-        );
-        Ok(section_offsets)
-    }
-
-    fn inner_buf(&self) -> &[u8] {
-        self.de.as_ref().get_ref().as_ref()
-    }
-
-    fn new_decoder_from_range(&self, start: u64, end: u64) -> Decoder<&[u8]> {
-        // TODO: Check range, instead of panic
-        Decoder::new(&self.inner_buf()[start as usize..end as usize])
-    }
-
-    fn read_sections(
-        &mut self,
-        section_offsets: &[SectionOffset],
-    ) -> Result<(Vec<RequestEntry>, Option<PrimaryUrl>)> {
-        log::debug!("read_sections");
-        let n = self
-            .read_array_len()
-            .context("Failed to read section header")?;
-        log::debug!("n: {:?}", n);
-        ensure!(
-            n as usize == section_offsets.len(),
-            format!(
-                "bundle: Expected {} sections, got {} sections",
-                section_offsets.len(),
-                n
-            )
-        );
-
-        let responses_section_offset = section_offsets.last().unwrap().offset;
-
-        let mut requests = vec![];
-        let mut primary_url: Option<PrimaryUrl> = None;
-
-        for SectionOffset {
-            name,
-            offset,
-            length,
-        } in section_offsets
-        {
-            if !bundle::KNOWN_SECTION_NAMES.iter().any(|&n| n == name) {
-                log::warn!("Unknows section name: {}. Skipping", name);
-                continue;
-            }
-            let mut section_decoder = self.new_decoder_from_range(*offset, offset + length);
-
-            // TODO: Support ignoredSections
-            match name.as_ref() {
-                "index" => {
-                    requests = section_decoder.read_index(responses_section_offset)?;
-                }
-                "responses" => {
-                    // Skip responses section becuase we read responses later.
-                }
-                "primary" => {
-                    primary_url = Some(section_decoder.read_primary_url()?);
-                }
-                _ => {
-                    log::warn!("Unknown section found: {}", name);
-                }
-            }
-        }
-        Ok((requests, primary_url))
-    }
-
-    fn read_primary_url(&mut self) -> Result<PrimaryUrl> {
-        log::debug!("read_primary_url");
-        self.de
-            .text()
-            .context("bundle: Failed to read primary_url string")?
-            .parse()
-            .context("Failed to parse primary_url")
-    }
-
-    fn read_index(&mut self, responses_section_offset: u64) -> Result<Vec<RequestEntry>> {
-        let index_map_len = match self.de.map()? {
-            Len::Len(n) => n,
-            Len::Indefinite => {
-                bail!("bundle: Failed to decode index section map header");
-            }
-        };
-        // dbg!(index_map_len);
-
-        let mut requests = vec![];
-        for _ in 0..index_map_len {
-            // TODO: support relative URL, which can not be Uri.
-            let url = self.de.text()?;
+    /// Reads a byte string. `Deserializer::bytes` allocates the length in the
+    /// head before reading the bytes, so the length is checked against the
+    /// remaining bytes first, as a crafted length can be huge.
+    fn read_bytes(&mut self) -> Result<Vec<u8>> {
+        let cursor = self.de.as_ref();
+        let remaining = (cursor.get_ref().as_ref().len() as u64).saturating_sub(cursor.position());
+        if let (Len::Len(len), _) = self.de.cbor_len()? {
             ensure!(
-                self.read_array_len()? == 2,
-                "bundle: Failed to decode index item"
+                len <= remaining,
+                format!("bundle: A byte string of {len} bytes exceeds the response")
             );
-            let offset = self.de.unsigned_integer()?;
-            let length = self.de.unsigned_integer()?;
-            requests.push(RequestEntry {
-                request: url.into(),
-                response_location: ResponseLocation::new(responses_section_offset, offset, length),
-            });
         }
-        Ok(requests)
-    }
-
-    fn read_responses(&mut self, requests: Vec<RequestEntry>) -> Result<Vec<Exchange>> {
-        requests
-            .into_iter()
-            .map(
-                |RequestEntry {
-                     request,
-                     response_location: ResponseLocation { offset, length },
-                 }| {
-                    let response = self
-                        .new_decoder_from_range(offset, offset + length)
-                        .read_response()?;
-                    Ok(Exchange { request, response })
-                },
-            )
-            .collect()
+        Ok(self.de.bytes()?)
     }
 
     fn read_response(&mut self) -> Result<Response> {
@@ -307,11 +369,11 @@ impl<T: AsRef<[u8]>> Decoder<T> {
             "bundle: Failed to decode response entry"
         );
         log::debug!("read_response: headers byte 1");
-        let headers = self.de.bytes()?;
+        let headers = self.read_bytes()?;
         log::debug!("read_response: headers byte 2");
         let mut nested = Decoder::new(headers);
         let (status, headers) = nested.read_headers_cbor()?;
-        let body = self.de.bytes()?;
+        let body = self.read_bytes()?;
         let mut response = Response::new(body);
         *response.status_mut() = status;
         *response.headers_mut() = headers;
@@ -328,8 +390,8 @@ impl<T: AsRef<[u8]>> Decoder<T> {
         let mut headers = HeaderMap::new();
         let mut status = None;
         for _ in 0..headers_map_len {
-            let name = String::from_utf8(self.de.bytes()?)?;
-            let value = String::from_utf8(self.de.bytes()?)?;
+            let name = String::from_utf8(self.read_bytes()?)?;
+            let value = String::from_utf8(self.read_bytes()?)?;
             if name.starts_with(':') {
                 ensure!(name == ":status", "Unknown pseudo headers");
                 ensure!(status.is_none(), ":status is duplicated");
@@ -350,6 +412,7 @@ impl<T: AsRef<[u8]>> Decoder<T> {
 mod tests {
     use super::*;
     use crate::bundle::{Bundle, Version};
+    use crate::encoder;
 
     #[test]
     fn encode_and_decode() -> Result<()> {
@@ -376,7 +439,321 @@ mod tests {
             bundle.exchanges()[0].request.url(),
             "https://example.com/index.html"
         );
-        assert_eq!(bundle.exchanges()[0].response.body(), &[]);
+        assert_eq!(bundle.exchanges()[0].response.body(), b"");
+        Ok(())
+    }
+
+    #[test]
+    fn index_order() -> Result<()> {
+        let urls = [
+            "https://example.com/b.js",
+            "https://example.com/a.js",
+            "https://example.com/",
+        ];
+        for index_order in [IndexOrder::Canonical, IndexOrder::Insertion] {
+            let mut builder = Bundle::builder()
+                .version(Version::VersionB2)
+                .index_order(index_order);
+            for url in urls {
+                builder = builder.exchange(Exchange::from((url.to_string(), vec![])));
+            }
+            let encoded = builder.build()?.encode()?;
+
+            let index = crate::Index::from_bytes(&encoded)?;
+            let index_urls = index
+                .entries
+                .iter()
+                .map(|entry| entry.url.as_str())
+                .collect::<Vec<_>>();
+            let bundle = Bundle::from_bytes(&encoded)?;
+            match index_order {
+                IndexOrder::Canonical => {
+                    assert_eq!(index_urls, [urls[2], urls[1], urls[0]]);
+                    assert!(crate::validate(&encoded).is_empty());
+                }
+                IndexOrder::Insertion => assert_eq!(index_urls, urls),
+            }
+            // Decoding reports the original order, and keeps it.
+            assert_eq!(bundle.index_order(), index_order);
+            assert_eq!(
+                bundle
+                    .exchanges()
+                    .iter()
+                    .map(|exchange| exchange.request.url().as_str())
+                    .collect::<Vec<_>>(),
+                urls
+            );
+            assert_eq!(bundle.encode()?, encoded);
+        }
+        Ok(())
+    }
+
+    /// Returns a bundle with an exchange for `https://example.com/`, and the
+    /// given sections before the index.
+    fn bundle_with_sections(extra_sections: Vec<(&str, Vec<u8>)>) -> Result<Vec<u8>> {
+        use cbor_event::se::Serializer;
+
+        let mut response = Serializer::new_vec();
+        response.write_array(Len::Len(2))?;
+        response.write_bytes(encoder::encode_headers(&Response::new(Vec::new()))?)?;
+        response.write_bytes(b"")?;
+        let response = response.finalize();
+
+        let mut index = Serializer::new_vec();
+        index.write_map(Len::Len(1))?;
+        index.write_text("https://example.com/")?;
+        index.write_array(Len::Len(2))?;
+        // After the header of the responses array.
+        index.write_unsigned_integer(1)?;
+        index.write_unsigned_integer(response.len() as u64)?;
+        let mut sections = extra_sections;
+        sections.push(("index", index.finalize()));
+        sections.push(("responses", [vec![0x81], response].concat()));
+
+        let mut section_lengths = Serializer::new_vec();
+        section_lengths.write_array(Len::Len(sections.len() as u64 * 2))?;
+        for (name, bytes) in &sections {
+            section_lengths.write_text(name)?;
+            section_lengths.write_unsigned_integer(bytes.len() as u64)?;
+        }
+        let mut se = Serializer::new_vec();
+        se.write_array(Len::Len(bundle::TOP_ARRAY_LEN as u64))?;
+        se.write_bytes(bundle::HEADER_MAGIC_BYTES)?;
+        se.write_bytes(Version::VersionB2.bytes())?;
+        se.write_bytes(section_lengths.finalize())?;
+        se.write_array(Len::Len(sections.len() as u64))?;
+        for (_, bytes) in &sections {
+            se.write_raw_bytes(bytes)?;
+        }
+        let mut bytes = se.finalize();
+        bytes.extend_from_slice(&(bytes.len() as u64 + 8).to_be_bytes());
+        Ok(bytes)
+    }
+
+    #[test]
+    fn ignored_sections() -> Result<()> {
+        // [] and 0.
+        let bytes = bundle_with_sections(vec![("critical", vec![0x80]), ("custom", vec![0x00])])?;
+        let bundle = Bundle::from_bytes(&bytes)?;
+        assert_eq!(bundle.ignored_sections(), ["critical", "custom"]);
+        assert_eq!(bundle.exchanges()[0].request.url(), "https://example.com/");
+        // Re-encoding drops them.
+        assert!(Bundle::from_bytes(bundle.encode()?)?
+            .ignored_sections()
+            .is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn preserve_signatures() -> Result<()> {
+        // [[], []], i.e. no authorities and no vouched subsets.
+        let signatures = vec![0x82, 0x80, 0x80];
+        let bytes = bundle_with_sections(vec![("signatures", signatures.clone())])?;
+        let mut bundle = Bundle::from_bytes(&bytes)?;
+        assert_eq!(bundle.signatures(), Some(&signatures[..]));
+        assert!(bundle.ignored_sections().is_empty());
+        assert!(Bundle::from_bytes_with_options(
+            &bytes,
+            &DecodeOptions {
+                strict: true,
+                ..Default::default()
+            }
+        )
+        .is_ok());
+
+        // Re-encoding keeps them, even if the exchanges change.
+        let reencoded = Bundle::from_bytes(bundle.encode()?)?;
+        assert_eq!(reencoded.signatures(), Some(&signatures[..]));
+        let fingerprint = reencoded.signatures.as_ref().unwrap().fingerprint;
+        assert_eq!(
+            fingerprint,
+            bundle::exchanges_fingerprint(reencoded.exchanges())
+        );
+        *bundle.exchanges_mut()[0].response.body_mut() = b"changed".to_vec();
+        assert_ne!(
+            fingerprint,
+            bundle::exchanges_fingerprint(bundle.exchanges())
+        );
+        assert_eq!(
+            Bundle::from_bytes(bundle.encode()?)?.signatures(),
+            Some(&signatures[..])
+        );
+
+        bundle.strip_signatures();
+        assert!(Bundle::from_bytes(bundle.encode()?)?.signatures().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn decode_with_options() -> Result<()> {
+        let encoded = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchange(Exchange::from((
+                "https://example.com/a".to_string(),
+                b"aaaa".to_vec(),
+            )))
+            .exchange(Exchange::from((
+                "https://example.com/b".to_string(),
+                b"bb".to_vec(),
+            )))
+            .build()?
+            .encode()?;
+
+        let options = DecodeOptions {
+            max_exchanges: Some(2),
+            max_body_bytes: Some(4),
+            strict: true,
+        };
+        assert_eq!(
+            Bundle::from_bytes_with_options(&encoded, &options)?
+                .exchanges()
+                .len(),
+            2
+        );
+        assert!(Bundle::from_bytes_with_options(
+            &encoded,
+            &DecodeOptions {
+                max_exchanges: Some(1),
+                ..options.clone()
+            }
+        )
+        .is_err());
+        assert!(Bundle::from_bytes_with_options(
+            &encoded,
+            &DecodeOptions {
+                max_body_bytes: Some(3),
+                ..options
+            }
+        )
+        .is_err());
+
+        // Rejected by the length in the index, before the body is read.
+        let encoded = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchange(Exchange::from((
+                "https://example.com/large".to_string(),
+                vec![0; 2 * MAX_HEADERS_BYTES as usize],
+            )))
+            .build()?
+            .encode()?;
+        let err = Bundle::from_bytes_with_options(
+            &encoded,
+            &DecodeOptions {
+                max_body_bytes: Some(4),
+                ..Default::default()
+            },
+        )
+        .unwrap_err();
+        assert!(format!("{err}").contains("The response of https://example.com/large"));
+        Ok(())
+    }
+
+    #[test]
+    fn decode_huge_declared_length() -> Result<()> {
+        let mut encoded = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchange(Exchange::from((
+                "https://example.com/a".to_string(),
+                b"0123456789".to_vec(),
+            )))
+            .build()?
+            .encode()?;
+        // Replaces the head of the body with a length of u64::MAX, keeping
+        // the length of the response.
+        let body = encoded
+            .windows(11)
+            .position(|window| window == b"\x4a0123456789")
+            .unwrap();
+        encoded[body] = 0x5b;
+        encoded[body + 1..body + 9].fill(0xff);
+        let options = DecodeOptions {
+            max_body_bytes: Some(10),
+            strict: true,
+            ..Default::default()
+        };
+        let err = Bundle::from_bytes_with_options(&encoded, &options).unwrap_err();
+        assert!(format!("{err}").contains("exceeds the response"));
+        assert!(Bundle::from_bytes(&encoded).is_err());
+        assert!(Bundle::from_reader(&encoded[..]).is_err());
+
+        // The same for the headers.
+        assert!(
+            parse_response([0x82, 0x5b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]).is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn decode_strict_canonicalizes_urls() -> Result<()> {
+        let encode = |urls: &[&str]| {
+            let mut builder = Bundle::builder().version(Version::VersionB2);
+            for url in urls {
+                builder = builder.exchange(Exchange::from((url.to_string(), vec![])));
+            }
+            builder.build()?.encode()
+        };
+        let strict = DecodeOptions {
+            strict: true,
+            ..Default::default()
+        };
+
+        let encoded = encode(&["HTTPS://Example.com:443/%7ea"])?;
+        assert_eq!(
+            Bundle::from_bytes(&encoded)?.exchanges()[0].request.url(),
+            "HTTPS://Example.com:443/%7ea"
+        );
+        assert_eq!(
+            Bundle::from_bytes_with_options(&encoded, &strict)?.exchanges()[0]
+                .request
+                .url(),
+            "https://example.com/~a"
+        );
+
+        let encoded = encode(&["https://example.com/a", "https://EXAMPLE.com/a"])?;
+        assert!(Bundle::from_bytes(&encoded).is_ok());
+        assert!(Bundle::from_bytes_with_options(&encoded, &strict).is_err());
+
+        let encoded = encode(&["https://example.com/#a"])?;
+        assert!(Bundle::from_bytes(&encoded).is_ok());
+        assert!(Bundle::from_bytes_with_options(&encoded, &strict).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn decode_b1() -> Result<()> {
+        let bundle = Bundle::from_bytes(include_bytes!("../tests/b1/hello.wbn"))?;
+        assert_eq!(bundle.version(), &Version::VersionB1);
+        assert_eq!(bundle.primary_url(), &Some("https://example.com/".parse()?));
+        assert_eq!(bundle.ignored_sections(), ["manifest"]);
+        let exchanges = bundle
+            .exchanges()
+            .iter()
+            .map(|exchange| {
+                (
+                    exchange.request.url().as_str(),
+                    exchange.response.headers().get("variant-key"),
+                    exchange.response.body().as_slice(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            exchanges,
+            [
+                ("https://example.com/", None, &b"<p>Hello</p>"[..]),
+                (
+                    "https://example.com/lang",
+                    Some(&HeaderValue::from_static("en")),
+                    b"Hello"
+                ),
+                (
+                    "https://example.com/lang",
+                    Some(&HeaderValue::from_static("ja")),
+                    "こんにちは".as_bytes()
+                ),
+            ]
+        );
+        // Variants can't be encoded.
+        assert!(bundle.encode().is_err());
         Ok(())
     }
 
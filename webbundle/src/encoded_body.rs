@@ -0,0 +1,156 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::bundle::Bundle;
+use crate::encoder;
+use crate::prelude::*;
+use bytes::{Bytes, BytesMut};
+use http_body::SizeHint;
+use std::convert::Infallible;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// An `http_body::Body` which yields an encoded bundle lazily.
+///
+/// The first chunk has the metadata and the index. Each of the following
+/// chunks has one response, and the last chunk has the length of the
+/// bundle. A response is copied only when its chunk is polled, so a server
+/// can stream a bundle with backpressure.
+///
+/// # Examples
+///
+/// ```no_run
+/// use webbundle::{Bundle, EncodedBundleBody};
+/// let bundle = Bundle::from_bytes(std::fs::read("example.wbn")?)?;
+/// let response = http::Response::new(EncodedBundleBody::new(bundle)?);
+/// # std::result::Result::Ok::<_, anyhow::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct EncodedBundleBody {
+    bundle: Bundle,
+    head: Option<Bytes>,
+    prefixes: std::vec::IntoIter<Vec<u8>>,
+    next_exchange: usize,
+    trailer: Option<Bytes>,
+    remaining: u64,
+}
+
+impl EncodedBundleBody {
+    /// Creates a body for the given bundle. This fails if the bundle can't be
+    /// encoded.
+    pub fn new(bundle: Bundle) -> Result<Self> {
+        let layout = encoder::layout(&bundle)?;
+        let trailer = Bytes::copy_from_slice(&layout.trailer());
+        Ok(EncodedBundleBody {
+            bundle,
+            head: Some(Bytes::from(layout.head)),
+            prefixes: layout.prefixes.into_iter(),
+            next_exchange: 0,
+            trailer: Some(trailer),
+            remaining: layout.len,
+        })
+    }
+
+    /// Returns the length of the encoded bundle.
+    pub fn len(&self) -> u64 {
+        self.remaining
+    }
+
+    /// Returns true if all chunks have been yielded.
+    pub fn is_empty(&self) -> bool {
+        self.remaining == 0
+    }
+
+    fn next_chunk(&mut self) -> Option<Bytes> {
+        if let Some(head) = self.head.take() {
+            return Some(head);
+        }
+        if let Some(prefix) = self.prefixes.next() {
+            let body = self.bundle.exchanges[self.next_exchange].response.body();
+            self.next_exchange += 1;
+            let mut chunk = BytesMut::with_capacity(prefix.len() + body.len());
+            chunk.extend_from_slice(&prefix);
+            chunk.extend_from_slice(body);
+            return Some(chunk.freeze());
+        }
+        self.trailer.take()
+    }
+}
+
+impl http_body::Body for EncodedBundleBody {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let this = self.get_mut();
+        let chunk = this.next_chunk();
+        if let Some(chunk) = &chunk {
+            this.remaining -= chunk.len() as u64;
+        }
+        Poll::Ready(chunk.map(Ok))
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<std::result::Result<Option<http::HeaderMap>, Self::Error>> {
+        Poll::Ready(Ok(None))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.remaining == 0
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::with_exact(self.remaining)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundle::{Exchange, Version};
+    use http_body::Body as _;
+
+    #[test]
+    fn encoded_bundle_body() -> Result<()> {
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .primary_url("https://example.com/".parse()?)
+            .exchange(Exchange::from(("https://example.com/", b"hello".to_vec())))
+            .exchange(Exchange::from((
+                "https://example.com/a.js",
+                vec![b'a'; 1000],
+            )))
+            .build()?;
+        let expected = bundle.encode()?;
+
+        let mut body = EncodedBundleBody::new(bundle)?;
+        assert_eq!(body.size_hint().exact(), Some(expected.len() as u64));
+
+        let mut cx = Context::from_waker(std::task::Waker::noop());
+        let mut chunks = Vec::new();
+        while let Poll::Ready(Some(chunk)) = Pin::new(&mut body).poll_data(&mut cx) {
+            chunks.push(chunk.unwrap());
+        }
+        // The head, two responses and the trailer.
+        assert_eq!(chunks.len(), 4);
+        assert_eq!(chunks.concat(), expected);
+        assert!(body.is_end_stream());
+        Ok(())
+    }
+}
@@ -12,10 +12,10 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::bundle::{self, Bundle, Exchange, Response, Uri};
+use crate::bundle::{self, Bundle, Exchange, IndexOrder, Response, Uri, Version};
 use crate::prelude::*;
 use cbor_event::Len;
-use std::io::Write;
+use std::io::{Seek, SeekFrom, Write};
 
 use cbor_event::se::Serializer;
 
@@ -46,8 +46,7 @@ impl<W: Write> Write for CountWrite<W> {
 }
 
 pub(crate) fn encode<W: Write + Sized>(bundle: &Bundle, write: W) -> Result<()> {
-    Encoder::new(CountWrite::new(write)).encode(bundle)?;
-    Ok(())
+    Encoder::new(write).encode(bundle)
 }
 
 pub(crate) fn encode_to_vec(bundle: &Bundle) -> Result<Vec<u8>> {
@@ -56,77 +55,191 @@ pub(crate) fn encode_to_vec(bundle: &Bundle) -> Result<Vec<u8>> {
     Ok(write)
 }
 
-struct Encoder<W: Write> {
-    se: Serializer<W>,
+/// Encodes the bundle into a seekable `write`, e.g. a file, writing response
+/// bodies directly without copying them into the responses section first.
+///
+/// The section lengths precede the sections and must be canonical CBOR, so
+/// they are computed from the lengths of the responses rather than patched.
+/// The trailing length is written as a placeholder, and patched with the
+/// number of bytes actually written, measured by seeking.
+pub(crate) fn encode_seekable<W: Write + Seek>(bundle: &Bundle, mut write: W) -> Result<()> {
+    let start = write.stream_position()?;
+    let layout = layout(bundle)?;
+    write.write_all(&layout.head)?;
+    for (prefix, exchange) in layout.prefixes.iter().zip(&bundle.exchanges) {
+        write.write_all(prefix)?;
+        write.write_all(exchange.response.body())?;
+    }
+    write.write_all(&[0; 8])?;
+
+    let end = write.stream_position()?;
+    let len = end - start;
+    ensure!(
+        len == layout.len,
+        "bundle: Wrote {} bytes, but the section lengths expect {}",
+        len,
+        layout.len
+    );
+    write.seek(SeekFrom::Start(end - 8))?;
+    write.write_all(&len.to_be_bytes())?;
+    write.seek(SeekFrom::Start(end))?;
+    Ok(())
 }
 
-trait Count {
-    fn count(&self) -> usize;
+/// Represents an encoded bundle whose response bodies are not copied.
+pub(crate) struct Layout {
+    /// The bytes before the first response, which end with the header of the
+    /// responses array.
+    pub(crate) head: Vec<u8>,
+    /// The bytes before each response body.
+    pub(crate) prefixes: Vec<Vec<u8>>,
+    /// The length of the whole bundle.
+    pub(crate) len: u64,
 }
 
-impl<W: Write> Count for Serializer<CountWrite<W>> {
-    fn count(&self) -> usize {
-        // Use unsafe because Serializer.0 is private.
-        // TODO: Avoid to use unsafe.
-        let se_ptr: *const Serializer<CountWrite<W>> = self;
-        let count_write_ptr = se_ptr.cast::<CountWrite<W>>();
-        unsafe { (*count_write_ptr).count }
+#[cfg(any(feature = "http-body", feature = "tokio"))]
+impl Layout {
+    /// Returns the last bytes of the bundle, which is the length of the bundle.
+    pub(crate) fn trailer(&self) -> [u8; 8] {
+        self.len.to_be_bytes()
     }
 }
 
-impl<W: Write> Encoder<W> {
-    fn new(write: W) -> Self {
-        Encoder {
-            se: Serializer::new(write),
-        }
-    }
+pub(crate) fn layout(bundle: &Bundle) -> Result<Layout> {
+    let responses = encode_responses(&bundle.exchanges)?;
+    let sections = encode_metadata_sections(bundle, &responses)?;
+    let section_lengths = sections
+        .iter()
+        .map(|section| (section.name, section.bytes.len()))
+        .chain(std::iter::once(("responses", responses.len)))
+        .collect::<Vec<_>>();
 
-    fn write_magic(&mut self) -> Result<()> {
-        self.se.write_bytes(bundle::HEADER_MAGIC_BYTES)?;
-        Ok(())
+    let mut se = Serializer::new_vec();
+    encode_head(
+        &mut se,
+        bundle,
+        encode_section_lengths(section_lengths.into_iter())?,
+        sections.len() + 1,
+    )?;
+    for section in &sections {
+        se.write_raw_bytes(&section.bytes)?;
     }
+    se.write_raw_bytes(&responses.header)?;
+    let head = se.finalize();
+
+    let len = (head.len() + responses.len - responses.header.len()) as u64 + 8;
+    Ok(Layout {
+        head,
+        prefixes: responses.prefixes,
+        len,
+    })
+}
 
-    fn write_version(&mut self, version: &bundle::Version) -> Result<()> {
-        self.se.write_bytes(version.bytes())?;
-        Ok(())
+/// Encodes the bundle, writing response bodies directly to `write` without
+/// copying them into an intermediate buffer.
+#[cfg(feature = "tokio")]
+pub(crate) async fn encode_async<W>(bundle: &Bundle, mut write: W) -> Result<()>
+where
+    W: tokio::io::AsyncWrite + Unpin,
+{
+    use tokio::io::AsyncWriteExt as _;
+
+    let layout = layout(bundle)?;
+    write.write_all(&layout.head).await?;
+    for (prefix, exchange) in layout.prefixes.iter().zip(&bundle.exchanges) {
+        write.write_all(prefix).await?;
+        write.write_all(exchange.response.body()).await?;
     }
+    write.write_all(&layout.trailer()).await?;
+    write.flush().await?;
+    Ok(())
 }
 
-impl<W: Write + Sized> Encoder<CountWrite<W>> {
-    fn encode(&mut self, bundle: &Bundle) -> Result<()> {
-        self.se
-            .write_array(Len::Len(bundle::TOP_ARRAY_LEN as u64))?;
-        self.write_magic()?;
-        self.write_version(&bundle.version)?;
-
-        let sections = encode_sections(bundle)?;
+/// Encodes a bundle into `write`, counting the written bytes for the trailing
+/// length.
+struct Encoder<W: Write> {
+    write: CountWrite<W>,
+}
 
-        let section_length_cbor = encode_section_lengths(&sections)?;
-        self.se.write_bytes(section_length_cbor)?;
+impl<W: Write> Encoder<W> {
+    fn new(write: W) -> Self {
+        Encoder {
+            write: CountWrite::new(write),
+        }
+    }
 
-        self.se.write_array(Len::Len(sections.len() as u64))?;
+    fn encode(mut self, bundle: &Bundle) -> Result<()> {
+        let sections = encode_sections(bundle)?;
+        let section_length_cbor = encode_section_lengths(
+            sections
+                .iter()
+                .map(|section| (section.name, section.bytes.len())),
+        )?;
+
+        let mut se = Serializer::new_vec();
+        encode_head(&mut se, bundle, section_length_cbor, sections.len())?;
+        self.write.write_all(&se.finalize())?;
         for section in sections {
-            self.se.write_raw_bytes(&section.bytes)?;
+            self.write.write_all(&section.bytes)?;
         }
 
         // Write the length of bytes
         // Spec: https://wpack-wg.github.io/bundled-responses/draft-ietf-wpack-bundled-responses.html#name-trailing-length
-        let bundle_len = self.se.count() as u64 + 8;
-        self.se.write_raw_bytes(&bundle_len.to_be_bytes())?;
+        let bundle_len = self.write.count as u64 + 8;
+        self.write.write_all(&bundle_len.to_be_bytes())?;
         Ok(())
     }
 }
 
+/// Writes the top array up to the header of the sections array.
+fn encode_head(
+    se: &mut Serializer<Vec<u8>>,
+    bundle: &Bundle,
+    section_lengths: Vec<u8>,
+    sections_len: usize,
+) -> Result<()> {
+    if bundle.version == Version::VersionB1 {
+        // b1 has the primary url field instead of the primary section.
+        let primary_url = bundle
+            .primary_url
+            .as_ref()
+            .context("bundle: Version b1 requires a primary url")?;
+        se.write_array(Len::Len(bundle::TOP_ARRAY_LEN_B1 as u64))?;
+        se.write_bytes(bundle::HEADER_MAGIC_BYTES)?;
+        se.write_bytes(bundle.version.bytes())?;
+        se.write_text(primary_url.to_string())?;
+    } else {
+        se.write_array(Len::Len(bundle::TOP_ARRAY_LEN as u64))?;
+        se.write_bytes(bundle::HEADER_MAGIC_BYTES)?;
+        se.write_bytes(bundle.version.bytes())?;
+    }
+    se.write_bytes(section_lengths)?;
+    se.write_array(Len::Len(sections_len as u64))?;
+    Ok(())
+}
+
 struct Section {
     name: &'static str,
     bytes: Vec<u8>,
 }
 
 fn encode_sections(bundle: &Bundle) -> Result<Vec<Section>> {
+    let responses = encode_responses(&bundle.exchanges)?;
+    let mut sections = encode_metadata_sections(bundle, &responses)?;
+    sections.push(Section {
+        name: "responses",
+        bytes: responses.to_vec(&bundle.exchanges),
+    });
+    Ok(sections)
+}
+
+/// Encodes the sections other than the responses section.
+fn encode_metadata_sections(bundle: &Bundle, responses: &EncodedResponses) -> Result<Vec<Section>> {
+    let is_b1 = bundle.version == Version::VersionB1;
     let mut sections = Vec::new();
 
-    // primary url
-    if let Some(uri) = &bundle.primary_url {
+    // primary url, which b1 has in the top array instead
+    if let Some(uri) = bundle.primary_url.as_ref().filter(|_| !is_b1) {
         let bytes = encode_primary_url_section(uri)?;
         sections.push(Section {
             name: "primary",
@@ -134,22 +247,22 @@ fn encode_sections(bundle: &Bundle) -> Result<Vec<Section>> {
         });
     };
 
-    // responses
-    let (response_section_bytes, response_locations) = encode_response_section(&bundle.exchanges)?;
-
-    let response_section = Section {
-        name: "responses",
-        bytes: response_section_bytes,
-    };
-
     // index from responses
-    let index_section = Section {
+    sections.push(Section {
         name: "index",
-        bytes: encode_index_section(&response_locations)?,
-    };
+        bytes: encode_index_section(&responses.locations, bundle.index_order, is_b1)?,
+    });
 
-    sections.push(index_section);
-    sections.push(response_section);
+    // signatures of a decoded bundle
+    if let Some(signatures) = &bundle.signatures {
+        if signatures.fingerprint != bundle::exchanges_fingerprint(&bundle.exchanges) {
+            log::warn!("The exchanges have changed since decoding. The signatures may be invalid");
+        }
+        sections.push(Section {
+            name: "signatures",
+            bytes: signatures.bytes.clone(),
+        });
+    }
     Ok(sections)
 }
 
@@ -165,68 +278,166 @@ struct ResponseLocation {
     length: usize,
 }
 
-fn encode_response_section(exchanges: &[Exchange]) -> Result<(Vec<u8>, Vec<ResponseLocation>)> {
-    let mut se = Serializer::new(CountWrite::new(Vec::new()));
+/// Represents the responses section whose bodies are not copied yet.
+struct EncodedResponses {
+    /// The header of the responses array.
+    header: Vec<u8>,
+    /// The bytes before each response body.
+    prefixes: Vec<Vec<u8>>,
+    locations: Vec<ResponseLocation>,
+    len: usize,
+}
 
-    se.write_array(Len::Len(exchanges.len() as u64))?;
+impl EncodedResponses {
+    fn to_vec(&self, exchanges: &[Exchange]) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.len);
+        bytes.extend_from_slice(&self.header);
+        for (prefix, exchange) in self.prefixes.iter().zip(exchanges) {
+            bytes.extend_from_slice(prefix);
+            bytes.extend_from_slice(exchange.response.body());
+        }
+        bytes
+    }
+}
 
-    let mut response_locations = Vec::new();
+/// Returns the CBOR header of the byte string with the given length.
+fn bytes_header(len: usize) -> Vec<u8> {
+    const MAJOR_TYPE_BYTES: u8 = 2 << 5;
+    let len = len as u64;
+    if len < 24 {
+        vec![MAJOR_TYPE_BYTES | len as u8]
+    } else if len <= u8::MAX as u64 {
+        vec![MAJOR_TYPE_BYTES | 24, len as u8]
+    } else if len <= u16::MAX as u64 {
+        [&[MAJOR_TYPE_BYTES | 25][..], &(len as u16).to_be_bytes()].concat()
+    } else if len <= u32::MAX as u64 {
+        [&[MAJOR_TYPE_BYTES | 26][..], &(len as u32).to_be_bytes()].concat()
+    } else {
+        [&[MAJOR_TYPE_BYTES | 27][..], &len.to_be_bytes()].concat()
+    }
+}
 
-    for exchange in exchanges {
-        let offset = se.count();
+fn encode_responses(exchanges: &[Exchange]) -> Result<EncodedResponses> {
+    let mut se = Serializer::new_vec();
+    se.write_array(Len::Len(exchanges.len() as u64))?;
+    let header = se.finalize();
+    let mut len = header.len();
 
+    let mut prefixes = Vec::new();
+    let mut locations = Vec::new();
+    for exchange in exchanges {
+        // Responses are implicitly for GET requests, which the format can't
+        // override. `Builder::strict` rejects other methods.
+        if exchange.request.method() != http::Method::GET {
+            log::warn!(
+                "{}: Only GET requests can be bundled. Encoding {} as GET",
+                exchange.request.url(),
+                exchange.request.method()
+            );
+        }
+        // Neither b2 nor version 1 has request headers, e.g. `Accept` of
+        // variants, whose responses are keyed only by urls. See
+        // `Request::headers`.
+        if !exchange.request.headers().is_empty() {
+            log::warn!(
+                "{}: Request headers can't be bundled. Dropping {:?}",
+                exchange.request.url(),
+                exchange.request.headers().keys().collect::<Vec<_>>()
+            );
+        }
+        let body = exchange.response.body();
+        let mut se = Serializer::new_vec();
         se.write_array(Len::Len(2))?;
         se.write_bytes(&encode_headers(&exchange.response)?)?;
-        se.write_bytes(exchange.response.body())?;
+        se.write_raw_bytes(&bytes_header(body.len()))?;
+        let prefix = se.finalize();
 
-        response_locations.push(ResponseLocation {
+        let length = prefix.len() + body.len();
+        locations.push(ResponseLocation {
             url: exchange.request.url().clone(),
-            offset,
-            length: se.count() - offset,
+            offset: len,
+            length,
         });
+        len += length;
+        prefixes.push(prefix);
     }
 
-    Ok((se.finalize().inner, response_locations))
+    Ok(EncodedResponses {
+        header,
+        prefixes,
+        locations,
+        len,
+    })
 }
 
-fn encode_index_section(response_locations: &[ResponseLocation]) -> Result<Vec<u8>> {
-    // Map keys must be sorted.
-    // See [3.9. Canonical CBOR](https://tools.ietf.org/html/rfc7049#section-3.9)
-    let mut map = std::collections::BTreeMap::<Vec<u8>, Vec<u8>>::new();
-
+/// Encodes the index section, whose values are `[offset, length]`, or
+/// `[variants-value, offset, length]` in version b1, whose `variants-value`
+/// is empty without variants.
+fn encode_index_section(
+    response_locations: &[ResponseLocation],
+    index_order: IndexOrder,
+    is_b1: bool,
+) -> Result<Vec<u8>> {
+    let mut entries = Vec::new();
+
+    let mut urls = std::collections::HashSet::new();
     for response_location in response_locations {
+        // Only b1 can have responses of the same url, i.e. variants.
+        ensure!(
+            urls.insert(&response_location.url),
+            format!(
+                "bundle: Duplicate url: {}. Variants can't be encoded",
+                response_location.url
+            )
+        );
         let mut key = Serializer::new_vec();
         key.write_text(&response_location.url)?;
 
         let mut value = Serializer::new_vec();
-        value.write_array(Len::Len(2))?;
+        if is_b1 {
+            value.write_array(Len::Len(3))?;
+            value.write_bytes([])?;
+        } else {
+            value.write_array(Len::Len(2))?;
+        }
         value.write_unsigned_integer(response_location.offset as u64)?;
         value.write_unsigned_integer(response_location.length as u64)?;
 
-        map.insert(key.finalize(), value.finalize());
+        entries.push((key.finalize(), value.finalize()));
+    }
+    if index_order == IndexOrder::Canonical {
+        // Map keys must be sorted.
+        // See [3.9. Canonical CBOR](https://tools.ietf.org/html/rfc7049#section-3.9)
+        entries = entries
+            .into_iter()
+            .collect::<std::collections::BTreeMap<_, _>>()
+            .into_iter()
+            .collect();
     }
 
     let mut se = Serializer::new_vec();
     se.write_map(Len::Len(response_locations.len() as u64))?;
-    for (key, value) in map {
+    for (key, value) in entries {
         se.write_raw_bytes(&key)?;
         se.write_raw_bytes(&value)?;
     }
     Ok(se.finalize())
 }
 
-fn encode_section_lengths(sections: &[Section]) -> Result<Vec<u8>> {
+fn encode_section_lengths(
+    sections: impl ExactSizeIterator<Item = (&'static str, usize)>,
+) -> Result<Vec<u8>> {
     let mut se = Serializer::new_vec();
 
     se.write_array(Len::Len((sections.len() * 2) as u64))?;
-    for section in sections {
-        se.write_text(section.name)?;
-        se.write_unsigned_integer(section.bytes.len() as u64)?;
+    for (name, len) in sections {
+        se.write_text(name)?;
+        se.write_unsigned_integer(len as u64)?;
     }
     Ok(se.finalize())
 }
 
-fn encode_headers(response: &Response) -> Result<Vec<u8>> {
+pub(crate) fn encode_headers(response: &Response) -> Result<Vec<u8>> {
     // Map keys must be sorted.
     // See [3.9. Canonical CBOR](https://tools.ietf.org/html/rfc7049#section-3.9)
     let mut map = std::collections::BTreeMap::<Vec<u8>, Vec<u8>>::new();
@@ -287,4 +498,153 @@ mod tests {
         assert!(res.status.success(), "dump-bundle should read the bundle");
         Ok(())
     }
+
+    #[test]
+    fn trailing_length() -> Result<()> {
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchange(Exchange::from((
+                "https://example.com/index.html".to_string(),
+                vec![0; 1000],
+            )))
+            .build()?;
+
+        // Write through a writer which accepts only a few bytes at once.
+        let mut write = std::io::BufWriter::with_capacity(3, Vec::new());
+        encode(&bundle, &mut write)?;
+        let bytes = write.into_inner()?;
+        assert_eq!(bytes, encode_to_vec(&bundle)?);
+        assert_eq!(bytes[bytes.len() - 8..], (bytes.len() as u64).to_be_bytes());
+        Ok(())
+    }
+
+    #[test]
+    fn encode_seekable_test() -> Result<()> {
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .primary_url("https://example.com/index.html".parse()?)
+            .exchange(Exchange::from((
+                "https://example.com/index.html".to_string(),
+                b"hello".to_vec(),
+            )))
+            .exchange(Exchange::from((
+                "https://example.com/large.js".to_string(),
+                vec![0; 100_000],
+            )))
+            .build()?;
+
+        // The trailing length doesn't include the bytes before the bundle.
+        let mut write = std::io::Cursor::new(b"prefix".to_vec());
+        write.seek(SeekFrom::End(0))?;
+        encode_seekable(&bundle, &mut write)?;
+        let bytes = write.into_inner();
+        assert_eq!(bytes[..6], *b"prefix");
+        assert_eq!(bytes[6..], encode_to_vec(&bundle)?);
+        Ok(())
+    }
+
+    #[test]
+    fn encode_b1() -> Result<()> {
+        let exchange = Exchange::from(("https://example.com/".to_string(), b"hello".to_vec()));
+        let bundle = Bundle::builder()
+            .version(Version::VersionB1)
+            .exchange(exchange.clone())
+            .build()?;
+        assert!(bundle.encode().is_err());
+
+        let bundle = Bundle::builder()
+            .version(Version::VersionB1)
+            .primary_url("https://example.com/".parse()?)
+            .exchange(exchange)
+            .build()?;
+        let bytes = encode_to_vec(&bundle)?;
+        let index = webbundle_core::parse_index(&bytes)?;
+        assert_eq!(&index.version, b"b1\0\0");
+        assert_eq!(index.primary_url, Some("https://example.com/"));
+        assert!(index
+            .sections
+            .iter()
+            .all(|section| section.name != "primary"));
+
+        let decoded = Bundle::from_bytes(&bytes)?;
+        assert_eq!(decoded.version(), &Version::VersionB1);
+        assert_eq!(decoded.primary_url(), bundle.primary_url());
+        assert_eq!(decoded.exchanges()[0].response.body(), b"hello");
+
+        let mut write = std::io::Cursor::new(Vec::new());
+        encode_seekable(&bundle, &mut write)?;
+        assert_eq!(write.into_inner(), bytes);
+        Ok(())
+    }
+
+    #[test]
+    fn encode_non_get_requests() -> Result<()> {
+        let mut exchange = Exchange::from(("https://example.com/".to_string(), vec![]));
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchange(exchange.clone())
+            .build()?;
+        assert_eq!(
+            Bundle::from_bytes(bundle.encode()?)?.exchanges()[0]
+                .request
+                .method(),
+            http::Method::GET
+        );
+
+        // Encoded as GET, unless strict.
+        exchange.request = exchange.request.with_method(http::Method::POST);
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchange(exchange.clone())
+            .build()?;
+        assert_eq!(
+            Bundle::from_bytes(bundle.encode()?)?.exchanges()[0]
+                .request
+                .method(),
+            http::Method::GET
+        );
+        assert!(Bundle::builder()
+            .version(Version::VersionB2)
+            .strict(true)
+            .exchange(exchange)
+            .build()
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn bytes_header_test() -> Result<()> {
+        for len in [0, 23, 24, 255, 256, 65535, 65536] {
+            let mut se = Serializer::new_vec();
+            se.write_bytes(vec![0; len])?;
+            assert_eq!(
+                se.finalize()[..],
+                [bytes_header(len), vec![0; len]].concat()
+            );
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn encode_async() -> Result<()> {
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .primary_url("https://example.com/index.html".parse()?)
+            .exchange(Exchange::from((
+                "https://example.com/index.html".to_string(),
+                b"hello".to_vec(),
+            )))
+            .exchange(Exchange::from((
+                "https://example.com/large.js".to_string(),
+                vec![b'a'; 70000],
+            )))
+            .build()?;
+
+        let mut bytes = Vec::new();
+        bundle.write_to_async(&mut bytes).await?;
+        assert_eq!(bytes, bundle.encode()?);
+        assert_eq!(Bundle::from_bytes(bytes)?.exchanges().len(), 2);
+        Ok(())
+    }
 }
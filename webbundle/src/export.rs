@@ -0,0 +1,278 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::bundle::{Bundle, Exchange};
+use crate::prelude::*;
+use std::collections::HashSet;
+use std::io::{Seek, Write};
+
+/// The path of the manifest in an exported archive.
+pub const MANIFEST_PATH: &str = ".webbundle.json";
+
+impl Bundle {
+    /// Writes the contents of this bundle as a zip archive.
+    ///
+    /// The body of each successful response is stored at a path derived from
+    /// its URL, e.g. `https://example.com/a/b.js` is stored at
+    /// `https/example.com/a/b.js`, and `./a/` is stored at `a/index.html`.
+    /// A query is percent-encoded into the path, e.g. `a.js?v=1` is stored
+    /// at `a.js%3Fv=1`. Returns an error if two URLs are stored at the same
+    /// path. The manifest at [`MANIFEST_PATH`] lists every exchange with its path,
+    /// status and headers.
+    ///
+    /// [`MANIFEST_PATH`]: crate::MANIFEST_PATH
+    pub fn write_zip<W: Write + Seek>(&self, write: W) -> Result<()> {
+        let mut zip = zip::ZipWriter::new(write);
+        let options = zip::write::FileOptions::default();
+        for (path, body) in self.archive_entries()? {
+            zip.start_file(path, options)?;
+            zip.write_all(&body)?;
+        }
+        zip.finish()?;
+        Ok(())
+    }
+
+    /// Writes the contents of this bundle as a tar archive.
+    ///
+    /// The layout is the same as [`write_zip`](Bundle::write_zip).
+    pub fn write_tar<W: Write>(&self, write: W) -> Result<()> {
+        let mut tar = tar::Builder::new(write);
+        for (path, body) in self.archive_entries()? {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(body.len() as u64);
+            header.set_mode(0o644);
+            tar.append_data(&mut header, path, body.as_slice())?;
+        }
+        tar.into_inner()?.flush()?;
+        Ok(())
+    }
+
+    /// Returns the pairs of a path and its contents, including the manifest.
+    fn archive_entries(&self) -> Result<Vec<(String, Vec<u8>)>> {
+        let dirs = self
+            .exchanges
+            .iter()
+            .flat_map(|exchange| {
+                let path = url_to_path(exchange.request.url());
+                path.match_indices('/')
+                    .map(|(i, _)| path[..i].to_string())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<HashSet<_>>();
+
+        let mut entries = Vec::new();
+        let mut paths = HashSet::new();
+        let mut manifest = Vec::new();
+        for exchange in &self.exchanges {
+            let path = if exchange.response.status().is_success() {
+                let mut path = url_to_path(exchange.request.url());
+                if path.is_empty() || path.ends_with('/') {
+                    path.push_str("index.html");
+                } else if dirs.contains(&path) {
+                    // The URL of a directory, e.g. "a" for "a/index.html".
+                    path.push_str("/index.html");
+                }
+                ensure!(
+                    paths.insert(path.clone()),
+                    format!(
+                        "{}: The path {} is used by another url",
+                        exchange.request.url(),
+                        path
+                    )
+                );
+                entries.push((path.clone(), exchange.response.body().clone()));
+                Some(path)
+            } else {
+                None
+            };
+            manifest.push(manifest_entry(exchange, path)?);
+        }
+        entries.push((
+            MANIFEST_PATH.to_string(),
+            serde_json::to_vec_pretty(&manifest)?,
+        ));
+        Ok(entries)
+    }
+}
+
+fn manifest_entry(exchange: &Exchange, path: Option<String>) -> Result<serde_json::Value> {
+    let headers = exchange
+        .response
+        .headers()
+        .iter()
+        .map(|(name, value)| Ok(serde_json::json!([name.as_str(), value.to_str()?])))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(serde_json::json!({
+        "url": exchange.request.url(),
+        "path": path,
+        "status": exchange.response.status().as_u16(),
+        "headers": headers,
+    }))
+}
+
+/// Converts a URL to a relative path, e.g. `https://example.com:8080/a` to
+/// `https/example.com/8080/a`. A query is percent-encoded and appended, e.g.
+/// `a.js?v=1` to `a.js%3Fv=1`, a fragment is dropped, and `..` segments are
+/// removed.
+fn url_to_path(url: &str) -> String {
+    let url = url.split('#').next().unwrap_or_default();
+    let (url, query) = match url.split_once('?') {
+        Some((url, query)) => (url, Some(query)),
+        None => (url, None),
+    };
+    let (prefix, path) = match url.split_once("://") {
+        Some((scheme, rest)) => {
+            let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+            let authority = authority.rsplit('@').next().unwrap_or_default();
+            let authority = match authority.rsplit_once(':') {
+                Some((host, port)) => format!("{host}/{port}"),
+                None => authority.to_string(),
+            };
+            (format!("{scheme}/{authority}/"), path)
+        }
+        None => (String::new(), url),
+    };
+    let mut segments = Vec::new();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    let mut path = prefix + &segments.join("/");
+    if url.ends_with('/') && !segments.is_empty() {
+        path.push('/');
+    }
+    if let Some(query) = query {
+        path.push_str("%3F");
+        path.extend(query.bytes().map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'=' | b'&' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        }));
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundle::{Exchange, Version};
+    use http::StatusCode;
+    use std::io::{Cursor, Read as _};
+
+    #[test]
+    fn url_to_path_test() {
+        assert_eq!(url_to_path("https://example.com"), "https/example.com/");
+        assert_eq!(
+            url_to_path("https://example.com:8080/a/b.js#f"),
+            "https/example.com/8080/a/b.js"
+        );
+        assert_eq!(
+            url_to_path("https://example.com/a/b.js?v=1&q=a/b#f"),
+            "https/example.com/a/b.js%3Fv=1&q=a%2Fb"
+        );
+        assert_eq!(
+            url_to_path("https://example.com/a/"),
+            "https/example.com/a/"
+        );
+        assert_eq!(url_to_path("./a/../b/"), "b/");
+        assert_eq!(url_to_path(""), "");
+    }
+
+    fn bundle() -> Result<Bundle> {
+        let mut redirect = Exchange::from(("index.html".to_string(), vec![]));
+        *redirect.response.status_mut() = StatusCode::MOVED_PERMANENTLY;
+        Bundle::builder()
+            .version(Version::VersionB2)
+            .exchange(Exchange::from(("".to_string(), b"top".to_vec())))
+            .exchange(redirect)
+            .exchange(Exchange::from(("js".to_string(), b"js".to_vec())))
+            .exchange(Exchange::from(("js/a.js".to_string(), b"a".to_vec())))
+            .build()
+    }
+
+    #[test]
+    fn write_zip() -> Result<()> {
+        let mut cursor = Cursor::new(Vec::new());
+        bundle()?.write_zip(&mut cursor)?;
+
+        let mut zip = zip::ZipArchive::new(cursor)?;
+        let mut names = zip.file_names().collect::<Vec<_>>();
+        names.sort_unstable();
+        assert_eq!(
+            names,
+            vec![MANIFEST_PATH, "index.html", "js/a.js", "js/index.html"]
+        );
+
+        let mut manifest = String::new();
+        zip.by_name(MANIFEST_PATH)?.read_to_string(&mut manifest)?;
+        let manifest: serde_json::Value = serde_json::from_str(&manifest)?;
+        assert_eq!(manifest[1]["url"], "index.html");
+        assert_eq!(manifest[1]["path"], serde_json::Value::Null);
+        assert_eq!(manifest[1]["status"], 301);
+        assert_eq!(manifest[3]["path"], "js/a.js");
+        assert_eq!(
+            manifest[3]["headers"][0],
+            serde_json::json!(["content-length", "1"])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn write_tar() -> Result<()> {
+        let mut bytes = Vec::new();
+        bundle()?.write_tar(&mut bytes)?;
+
+        let mut tar = tar::Archive::new(bytes.as_slice());
+        let mut entries = Vec::new();
+        for entry in tar.entries()? {
+            let mut entry = entry?;
+            let mut body = String::new();
+            entry.read_to_string(&mut body)?;
+            entries.push((entry.path()?.display().to_string(), body));
+        }
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[0], ("index.html".to_string(), "top".to_string()));
+        assert_eq!(entries[1], ("js/index.html".to_string(), "js".to_string()));
+        Ok(())
+    }
+
+    #[test]
+    fn archive_queries() -> Result<()> {
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchange(Exchange::from(("a.js?v=1".to_string(), b"1".to_vec())))
+            .exchange(Exchange::from(("a.js?v=2".to_string(), b"2".to_vec())))
+            .build()?;
+        let paths = bundle
+            .archive_entries()?
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect::<Vec<_>>();
+        assert_eq!(paths, vec!["a.js%3Fv=1", "a.js%3Fv=2", MANIFEST_PATH]);
+
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchange(Exchange::from(("a.js".to_string(), b"1".to_vec())))
+            .exchange(Exchange::from(("./a.js".to_string(), b"2".to_vec())))
+            .build()?;
+        assert!(bundle.archive_entries().is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,236 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::bundle::{Response, Uri, Version};
+use crate::decoder;
+use crate::prelude::*;
+use std::ops::Range;
+
+/// Represents the index of a bundle, which is parsed without the responses.
+///
+/// # Examples
+///
+/// ```no_run
+/// use webbundle::{FetchPlan, Index};
+/// // The first bytes of a bundle, e.g. fetched with `Range: bytes=0-4095`.
+/// let head = std::fs::read("example.wbn")?;
+/// let index = Index::from_bytes(&head)?;
+/// let plan = FetchPlan::new(&index, ["https://example.com/a.js"]);
+/// println!("Range: {}", plan.range_header().unwrap());
+/// # std::result::Result::Ok::<_, anyhow::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Index {
+    pub(crate) version: Version,
+    pub(crate) primary_url: Option<Uri>,
+    pub(crate) entries: Vec<IndexEntry>,
+}
+
+/// Represents the location of a response in a bundle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+    /// The url of the request.
+    pub url: String,
+    /// The offset of the response from the start of the bundle.
+    pub offset: u64,
+    /// The length of the response.
+    pub length: u64,
+}
+
+impl IndexEntry {
+    /// Returns the range of the response in the bundle.
+    pub fn range(&self) -> Range<u64> {
+        self.offset..self.offset + self.length
+    }
+
+    /// Parses the response from the bytes in the `range` of the bundle.
+    pub fn response_from(&self, bytes: impl AsRef<[u8]>) -> Result<Response> {
+        let bytes = bytes.as_ref();
+        ensure!(
+            bytes.len() as u64 == self.length,
+            format!(
+                "Expected {} bytes for {}, got {} bytes",
+                self.length,
+                self.url,
+                bytes.len()
+            )
+        );
+        decoder::parse_response(bytes)
+    }
+}
+
+impl Index {
+    /// Parses the index from the first bytes of a bundle.
+    ///
+    /// The bytes must contain all sections before the responses section, but
+    /// don't have to contain the responses section.
+    pub fn from_bytes(bytes: impl AsRef<[u8]>) -> Result<Index> {
+        decoder::parse_index(bytes)
+    }
+
+    /// Gets the version.
+    pub fn version(&self) -> &Version {
+        &self.version
+    }
+
+    /// Gets the primary url.
+    pub fn primary_url(&self) -> &Option<Uri> {
+        &self.primary_url
+    }
+
+    /// Gets the entries.
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.entries
+    }
+
+    /// Gets the entry for the given url.
+    pub fn get(&self, url: &str) -> Option<&IndexEntry> {
+        self.entries.iter().find(|entry| entry.url == url)
+    }
+}
+
+/// Represents the byte ranges to fetch for the responses of the given urls.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FetchPlan {
+    ranges: Vec<Range<u64>>,
+    missing_urls: Vec<String>,
+}
+
+impl FetchPlan {
+    /// Creates the minimal list of byte ranges which cover the responses of
+    /// the given urls. Adjacent or overlapping ranges are merged.
+    pub fn new<'a>(index: &Index, urls: impl IntoIterator<Item = &'a str>) -> FetchPlan {
+        let mut ranges = Vec::new();
+        let mut missing_urls = Vec::new();
+        for url in urls {
+            match index.get(url) {
+                Some(entry) => ranges.push(entry.range()),
+                None => missing_urls.push(url.to_string()),
+            }
+        }
+        ranges.sort_by_key(|range| range.start);
+
+        let mut merged: Vec<Range<u64>> = Vec::new();
+        for range in ranges {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+        FetchPlan {
+            ranges: merged,
+            missing_urls,
+        }
+    }
+
+    /// Gets the byte ranges, sorted by their offsets.
+    pub fn ranges(&self) -> &[Range<u64>] {
+        &self.ranges
+    }
+
+    /// Gets the urls which are not in the index.
+    pub fn missing_urls(&self) -> &[String] {
+        &self.missing_urls
+    }
+
+    /// Returns the value of `Range` header, e.g. `bytes=100-199, 300-399`, or
+    /// `None` if there is nothing to fetch.
+    pub fn range_header(&self) -> Option<String> {
+        if self.ranges.is_empty() {
+            return None;
+        }
+        let ranges = self
+            .ranges
+            .iter()
+            .map(|range| format!("{}-{}", range.start, range.end - 1))
+            .collect::<Vec<_>>();
+        Some(format!("bytes={}", ranges.join(", ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundle::{Bundle, Exchange};
+
+    #[test]
+    fn fetch_plan() -> Result<()> {
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .primary_url("https://example.com/a.html".parse()?)
+            .exchange(Exchange::from((
+                "https://example.com/a.html",
+                b"a".to_vec(),
+            )))
+            .exchange(Exchange::from(("https://example.com/b.js", b"b".to_vec())))
+            .exchange(Exchange::from(("https://example.com/c.css", b"c".to_vec())))
+            .build()?;
+        let bytes = bundle.encode()?;
+
+        // The index can be parsed without responses.
+        let responses_start = bundle_responses_start(&bytes)?;
+        let index = Index::from_bytes(&bytes[..responses_start])?;
+        assert_eq!(index.version(), &Version::VersionB2);
+        assert_eq!(index.entries().len(), 3);
+        assert!(Index::from_bytes(&bytes[..responses_start / 2]).is_err());
+
+        let a = index.get("https://example.com/a.html").unwrap();
+        let b = index.get("https://example.com/b.js").unwrap();
+        let c = index.get("https://example.com/c.css").unwrap();
+
+        let plan = FetchPlan::new(
+            &index,
+            ["https://example.com/b.js", "https://example.com/a.html"],
+        );
+        assert_eq!(plan.ranges(), vec![a.offset..b.offset + b.length]);
+
+        let plan = FetchPlan::new(
+            &index,
+            [
+                "https://example.com/c.css",
+                "https://example.com/a.html",
+                "https://example.com/missing",
+            ],
+        );
+        assert_eq!(plan.ranges(), [a.range(), c.range()]);
+        assert_eq!(plan.missing_urls(), ["https://example.com/missing"]);
+        assert_eq!(
+            plan.range_header().unwrap(),
+            format!(
+                "bytes={}-{}, {}-{}",
+                a.offset,
+                a.offset + a.length - 1,
+                c.offset,
+                c.offset + c.length - 1
+            )
+        );
+        assert!(FetchPlan::new(&index, []).range_header().is_none());
+
+        let range = c.range();
+        let response = c.response_from(&bytes[range.start as usize..range.end as usize])?;
+        assert_eq!(response.body(), b"c");
+        Ok(())
+    }
+
+    /// Returns the offset of the first response.
+    fn bundle_responses_start(bytes: &[u8]) -> Result<usize> {
+        let index = Index::from_bytes(bytes)?;
+        Ok(index
+            .entries()
+            .iter()
+            .map(|entry| entry.offset as usize)
+            .min()
+            .unwrap())
+    }
+}
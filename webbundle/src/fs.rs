@@ -1 +1,6 @@
-mod builder;
+#[cfg(feature = "archive")]
+mod archive;
+pub(crate) mod builder;
+#[cfg(feature = "compress")]
+pub(crate) mod compress;
+mod fingerprint;
@@ -0,0 +1,215 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::builder::ExchangeBuilder;
+use crate::prelude::*;
+use std::io::{Read, Seek};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+impl crate::builder::Builder {
+    /// Append exchanges from the files in the given archive.
+    ///
+    /// The format is detected from the extension: `.zip`, `.tar`, `.tar.gz`
+    /// or `.tgz`. Paths in the archive are mapped to URLs in the same way as
+    /// `exchanges_from_dir`. `precompressed` and `directory_listing` options
+    /// are not supported for archives.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use webbundle::{Bundle, Version};
+    /// let bundle = Bundle::builder()
+    ///     .version(Version::VersionB2)
+    ///     .exchanges_from_archive("dist.tar.gz")?
+    ///     .build()?;
+    /// # std::result::Result::Ok::<_, anyhow::Error>(())
+    /// ```
+    pub fn exchanges_from_archive(self, path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .context("Invalid archive path")?
+            .to_ascii_lowercase();
+        let file = std::io::BufReader::new(
+            std::fs::File::open(path)
+                .with_context(|| format!("Failed to open {}", path.display()))?,
+        );
+        if name.ends_with(".zip") {
+            self.exchanges_from_zip(file)
+        } else if name.ends_with(".tar") {
+            self.exchanges_from_tar(file)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            self.exchanges_from_tar(flate2::read::GzDecoder::new(file))
+        } else {
+            bail!("Unknown archive format: {}", path.display())
+        }
+    }
+
+    /// Append exchanges from the files in the given zip archive.
+    pub fn exchanges_from_zip(mut self, reader: impl Read + Seek) -> Result<Self> {
+        let mut archive = zip::ZipArchive::new(reader)?;
+        let mut exchange_builder = self.archive_exchange_builder();
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i)?;
+            if !file.is_file() {
+                continue;
+            }
+            let relative_path = match normalize(Path::new(file.name())) {
+                Some(relative_path) => relative_path,
+                None => {
+                    log::warn!("Skipping invalid path: {}", file.name());
+                    continue;
+                }
+            };
+            let mut body = Vec::new();
+            file.read_to_end(&mut body)?;
+            exchange_builder = exchange_builder.file_entry(&relative_path, body, None)?;
+        }
+        exchange_builder.finish()?;
+        self.exchanges.append(&mut exchange_builder.build());
+        Ok(self)
+    }
+
+    /// Append exchanges from the files in the given tar archive.
+    pub fn exchanges_from_tar(mut self, reader: impl Read) -> Result<Self> {
+        let mut archive = tar::Archive::new(reader);
+        let mut exchange_builder = self.archive_exchange_builder();
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let path = entry.path()?.to_path_buf();
+            let relative_path = match normalize(&path) {
+                Some(relative_path) => relative_path,
+                None => {
+                    log::warn!("Skipping invalid path: {}", path.display());
+                    continue;
+                }
+            };
+            let modified = entry
+                .header()
+                .mtime()
+                .ok()
+                .map(|mtime| SystemTime::UNIX_EPOCH + Duration::from_secs(mtime));
+            let mut body = Vec::new();
+            entry.read_to_end(&mut body)?;
+            exchange_builder = exchange_builder.file_entry(&relative_path, body, modified)?;
+        }
+        exchange_builder.finish()?;
+        self.exchanges.append(&mut exchange_builder.build());
+        Ok(self)
+    }
+
+    fn archive_exchange_builder(&self) -> ExchangeBuilder<'static> {
+        let mut options = self.fs_options.clone();
+        options.precompressed = false;
+        options.directory_listing = false;
+        ExchangeBuilder::new(PathBuf::new()).options(options)
+    }
+}
+
+/// Removes `.` components, e.g. `./index.html` in tar. Returns `None` if the
+/// path is not a plain relative path, e.g. `../a` or `/a`.
+fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(c) => normalized.push(c),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    (!normalized.as_os_str().is_empty()).then_some(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundle::{Bundle, Exchange, Version};
+    use http::StatusCode;
+    use std::io::{Cursor, Write as _};
+
+    fn find_exchange_by_url<'a>(exchanges: &'a [Exchange], url: &str) -> Result<&'a Exchange> {
+        exchanges
+            .iter()
+            .find(|e| e.request.url() == url)
+            .context("not found")
+    }
+
+    #[test]
+    fn exchanges_from_zip() -> Result<()> {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::FileOptions::default();
+        zip.add_directory("js/", options)?;
+        zip.start_file("index.html", options)?;
+        zip.write_all(b"Hello")?;
+        zip.start_file("js/hello.js", options)?;
+        zip.write_all(b"console.log('hello');")?;
+        let bytes = zip.finish()?.into_inner();
+
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchanges_from_zip(Cursor::new(bytes))?
+            .build()?;
+        assert_eq!(bundle.exchanges().len(), 3);
+
+        let top_dir = find_exchange_by_url(bundle.exchanges(), "")?;
+        assert_eq!(top_dir.response.body(), b"Hello");
+        let index_html = find_exchange_by_url(bundle.exchanges(), "index.html")?;
+        assert_eq!(index_html.response.status(), StatusCode::MOVED_PERMANENTLY);
+        let hello_js = find_exchange_by_url(bundle.exchanges(), "js/hello.js")?;
+        assert_eq!(hello_js.response.body(), b"console.log('hello');");
+        Ok(())
+    }
+
+    #[test]
+    fn exchanges_from_tar() -> Result<()> {
+        let mut tar = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        header.set_mode(0o644);
+        header.set_mtime(1_600_000_000);
+        tar.append_data(&mut header, "./a/b.txt", &b"Hello"[..])?;
+        let bytes = tar.into_inner()?;
+
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .cache_policy(crate::CachePolicy::NoCache)
+            .exchanges_from_tar(&bytes[..])?
+            .build()?;
+        assert_eq!(bundle.exchanges().len(), 1);
+        let exchange = &bundle.exchanges()[0];
+        assert_eq!(exchange.request.url(), "a/b.txt");
+        assert_eq!(exchange.response.body(), b"Hello");
+        assert_eq!(
+            exchange.response.headers()["last-modified"],
+            "Sun, 13 Sep 2020 12:26:40 GMT"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn normalize_test() {
+        assert_eq!(
+            normalize(Path::new("./a/b.txt")),
+            Some(PathBuf::from("a/b.txt"))
+        );
+        assert_eq!(normalize(Path::new("../a")), None);
+        assert_eq!(normalize(Path::new("/a")), None);
+        assert_eq!(normalize(Path::new(".")), None);
+    }
+}
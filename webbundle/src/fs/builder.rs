@@ -12,16 +12,281 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::bundle::{Exchange, Response};
+use crate::bundle::{Exchange, Request, Response};
+use crate::glob::glob_match;
 use crate::prelude::*;
-use headers::{ContentType, HeaderValue};
+use headers::{CacheControl, ContentType, ETag, HeaderMapExt as _, HeaderValue, LastModified};
 use http::StatusCode;
+use sha2::{Digest as _, Sha256};
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 use tokio::fs;
 use tokio::io::AsyncReadExt;
 use walkdir::WalkDir;
 
+pub(crate) type UrlMapper = Arc<dyn Fn(&Path) -> Option<String> + Send + Sync>;
+pub(crate) type Progress = Arc<dyn Fn(&Path, usize) + Send + Sync>;
+
+/// Options for walking a directory, which are set through `Builder`.
+#[derive(Clone, Default)]
+pub(crate) struct Options {
+    pub url_mapper: Option<UrlMapper>,
+    pub not_found_page: Option<String>,
+    pub directory_listing: bool,
+    pub precompressed: bool,
+    pub cache_policy: Option<CachePolicy>,
+    pub fingerprint: bool,
+    pub excludes: Vec<String>,
+    pub progress: Option<Progress>,
+    #[cfg(feature = "compress")]
+    pub compression: Option<super::compress::Compression>,
+    #[cfg(feature = "compress")]
+    pub compress_skip: Option<Vec<String>>,
+}
+
+/// Caching headers for exchanges created from files.
+///
+/// Every policy sets `Cache-Control`, `ETag` (a hash of the body) and
+/// `Last-Modified` (the modification time of the file).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CachePolicy {
+    /// `Cache-Control: no-cache`. Caches must revalidate the resources.
+    NoCache,
+    /// `Cache-Control: public, max-age=<seconds>`.
+    MaxAge(Duration),
+    /// `Cache-Control: public, immutable, max-age=31536000`. This is for
+    /// resources whose URLs change whenever their contents change.
+    ImmutableAssets,
+}
+
+impl CachePolicy {
+    fn cache_control(&self) -> CacheControl {
+        match self {
+            CachePolicy::NoCache => CacheControl::new().with_no_cache(),
+            CachePolicy::MaxAge(max_age) => {
+                CacheControl::new().with_public().with_max_age(*max_age)
+            }
+            CachePolicy::ImmutableAssets => CacheControl::new()
+                .with_public()
+                .with_max_age(Duration::from_secs(31_536_000))
+                .with_immutable(),
+        }
+    }
+}
+
+/// A cache of file contents, which is reused across builds.
+///
+/// A file is read again only if its modification time or its size has
+/// changed since the last build. See `Builder::exchanges_from_dir_cached`.
+///
+/// The contents are not hashed to detect changes, as hashing would read
+/// every file, which the cache avoids. Like `make`, a file rewritten with
+/// the same size within the resolution of the modification time, e.g. a
+/// second on some file systems, is not detected.
+#[derive(Debug, Default)]
+pub struct BuildCache {
+    entries: HashMap<PathBuf, CacheEntry>,
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    modified: SystemTime,
+    len: u64,
+    body: Vec<u8>,
+}
+
+impl BuildCache {
+    /// Creates an empty cache.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Returns the number of cached files.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if no file is cached.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
 impl crate::builder::Builder {
+    /// Sets a function which maps a file path to its URL in the bundle.
+    ///
+    /// The function is called with the path of each file, relative to the
+    /// directory given to `exchanges_from_dir`. Returning `None` skips the file.
+    ///
+    /// When a mapper is set, `index.html` files are not treated specially;
+    /// each file becomes exactly one exchange for the returned URL.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # async {
+    /// use webbundle::{Bundle, Version};
+    /// let bundle = Bundle::builder()
+    ///     .version(Version::VersionB2)
+    ///     .url_mapper(|path| {
+    ///         let path = path.to_str()?;
+    ///         Some(format!("https://example.com/{}", path.strip_suffix(".html").unwrap_or(path)))
+    ///     })
+    ///     .exchanges_from_dir("build").await?
+    ///     .build()?;
+    /// # std::result::Result::Ok::<_, anyhow::Error>(bundle)
+    /// # };
+    /// ```
+    pub fn url_mapper(
+        mut self,
+        url_mapper: impl Fn(&Path) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.fs_options.url_mapper = Some(Arc::new(url_mapper));
+        self
+    }
+
+    /// Sets the file name of error pages, e.g. `404.html`.
+    ///
+    /// Files with this name are served with `404 Not Found` status. Such an
+    /// exchange becomes the response for URLs in its directory which don't
+    /// match any other exchange. See [`Bundle::not_found_exchange`].
+    ///
+    /// [`Bundle::not_found_exchange`]: crate::Bundle::not_found_exchange
+    pub fn not_found_page(mut self, file_name: impl Into<String>) -> Self {
+        self.fs_options.not_found_page = Some(file_name.into());
+        self
+    }
+
+    /// Sets whether to generate a directory listing for directories which
+    /// don't have `index.html` file.
+    ///
+    /// The directory serves the generated HTML, as it would serve the contents
+    /// of `index.html`. This has no effect if `url_mapper` is set.
+    pub fn directory_listing(mut self, directory_listing: bool) -> Self {
+        self.fs_options.directory_listing = directory_listing;
+        self
+    }
+
+    /// Sets whether to use precompressed files.
+    ///
+    /// If a file, e.g. `app.js`, has a sibling `app.js.br` or `app.js.gz`,
+    /// the exchange for `app.js` uses the bytes of the compressed file with
+    /// `Content-Encoding` header, and the compressed files are not bundled as
+    /// separate URLs. Brotli is preferred if both exist because a bundle can
+    /// have only one response for each URL, and version b2 has no variants
+    /// to choose an encoding by `Accept-Encoding`. The ignored `.gz` file is
+    /// logged as a warning.
+    pub fn precompressed(mut self, precompressed: bool) -> Self {
+        self.fs_options.precompressed = precompressed;
+        self
+    }
+
+    /// Sets the caching headers for exchanges created from files.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # async {
+    /// use webbundle::{Bundle, CachePolicy, Version};
+    /// let bundle = Bundle::builder()
+    ///     .version(Version::VersionB2)
+    ///     .cache_policy(CachePolicy::ImmutableAssets)
+    ///     .exchanges_from_dir("build").await?
+    ///     .build()?;
+    /// # std::result::Result::Ok::<_, anyhow::Error>(bundle)
+    /// # };
+    /// ```
+    pub fn cache_policy(mut self, cache_policy: CachePolicy) -> Self {
+        self.fs_options.cache_policy = Some(cache_policy);
+        self
+    }
+
+    /// Sets whether to rename resources to content-hashed URLs.
+    ///
+    /// A resource, e.g. `js/app.js`, is renamed to `js/app.<hash>.js`, and
+    /// references to it in HTML, CSS and JavaScript files, including the
+    /// specifiers of ES modules, are rewritten. HTML files are
+    /// never renamed because they are entry points. This is useful with
+    /// [`CachePolicy::ImmutableAssets`].
+    pub fn fingerprint(mut self, fingerprint: bool) -> Self {
+        self.fs_options.fingerprint = fingerprint;
+        self
+    }
+
+    /// Sets a function which is called for each file, e.g. to show progress.
+    ///
+    /// The function is called with the path of the file, relative to the
+    /// directory or the archive, and the size of its body, after the file is
+    /// read.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # async {
+    /// use webbundle::{Bundle, Version};
+    /// let bundle = Bundle::builder()
+    ///     .version(Version::VersionB2)
+    ///     .progress(|path, len| eprintln!("{}: {len} bytes", path.display()))
+    ///     .exchanges_from_dir("build").await?
+    ///     .build()?;
+    /// # std::result::Result::Ok::<_, anyhow::Error>(bundle)
+    /// # };
+    /// ```
+    pub fn progress(mut self, progress: impl Fn(&Path, usize) + Send + Sync + 'static) -> Self {
+        self.fs_options.progress = Some(Arc::new(progress));
+        self
+    }
+
+    /// Compresses response bodies with the given algorithm, setting
+    /// `Content-Encoding` header.
+    ///
+    /// Bodies whose content types are in the skip list, which is
+    /// [`DEFAULT_COMPRESS_SKIP`](crate::DEFAULT_COMPRESS_SKIP) unless
+    /// `compress_skip` is set, are not compressed. Neither are bodies which
+    /// don't get smaller, nor precompressed files.
+    #[cfg(feature = "compress")]
+    pub fn compress(mut self, compression: super::compress::Compression) -> Self {
+        self.fs_options.compression = Some(compression);
+        self
+    }
+
+    /// Sets the content types, e.g. `image/png` or `video/*`, whose bodies
+    /// are not compressed. This replaces the default list.
+    #[cfg(feature = "compress")]
+    pub fn compress_skip(mut self, content_types: impl IntoIterator<Item = String>) -> Self {
+        self.fs_options.compress_skip = Some(content_types.into_iter().collect());
+        self
+    }
+
+    /// Excludes files which match the given glob, e.g. `*.map` or
+    /// `node_modules/**`. This can be called multiple times.
+    ///
+    /// The glob matches the path relative to the directory. See
+    /// [`glob_match`](crate::glob_match) for the syntax, e.g. a glob without
+    /// `/` matches the name of a file or of any of its parent directories. A
+    /// leading `/` is allowed, e.g. `/dist/**`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # async {
+    /// use webbundle::{Bundle, Version};
+    /// let bundle = Bundle::builder()
+    ///     .version(Version::VersionB2)
+    ///     .exclude("*.map")
+    ///     .exclude("node_modules/**")
+    ///     .exchanges_from_dir("build").await?
+    ///     .build()?;
+    /// # std::result::Result::Ok::<_, anyhow::Error>(bundle)
+    /// # };
+    /// ```
+    pub fn exclude(mut self, glob: impl Into<String>) -> Self {
+        self.fs_options.excludes.push(glob.into());
+        self
+    }
+
     /// Append exchanges from files rooted at the given directory.
     ///
     /// One exchange is created for each file, however, two exchanges
@@ -46,6 +311,7 @@ impl crate::builder::Builder {
     pub async fn exchanges_from_dir(mut self, dir: impl AsRef<Path>) -> Result<Self> {
         self.exchanges.append(
             &mut ExchangeBuilder::new(PathBuf::from(dir.as_ref()))
+                .options(self.fs_options.clone())
                 .walk()
                 .await?
                 .build(),
@@ -53,10 +319,43 @@ impl crate::builder::Builder {
         Ok(self)
     }
 
+    /// Same as `exchanges_from_dir`, but reuses the contents of unchanged
+    /// files from the given `cache`, and updates the cache.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # async {
+    /// use webbundle::{BuildCache, Bundle, Version};
+    /// let mut cache = BuildCache::new();
+    /// for _ in 0..2 {
+    ///     let bundle = Bundle::builder()
+    ///         .version(Version::VersionB2)
+    ///         .exchanges_from_dir_cached("build", &mut cache).await?
+    ///         .build()?;
+    /// }
+    /// # std::result::Result::Ok::<_, anyhow::Error>(())
+    /// # };
+    /// ```
+    pub async fn exchanges_from_dir_cached(
+        mut self,
+        dir: impl AsRef<Path>,
+        cache: &mut BuildCache,
+    ) -> Result<Self> {
+        let mut exchange_builder = ExchangeBuilder::new(PathBuf::from(dir.as_ref()))
+            .options(self.fs_options.clone())
+            .cache(cache)
+            .walk()
+            .await?;
+        self.exchanges.append(&mut exchange_builder.exchanges);
+        Ok(self)
+    }
+
     /// Sync version of `exchanges_from_dir`.
     pub fn exchanges_from_dir_sync(mut self, dir: impl AsRef<Path>) -> Result<Self> {
         self.exchanges.append(
             &mut ExchangeBuilder::new(PathBuf::from(dir.as_ref()))
+                .options(self.fs_options.clone())
                 .walk_sync()?
                 .build(),
         );
@@ -64,23 +363,50 @@ impl crate::builder::Builder {
     }
 }
 
-pub(crate) struct ExchangeBuilder {
+pub(crate) struct ExchangeBuilder<'a> {
     base_dir: PathBuf,
     exchanges: Vec<Exchange>,
+    options: Options,
+    // Updated as files are read. The entries of the files which are not
+    // visited, i.e. removed, are evicted only after a successful walk, so
+    // that a failed build keeps the cache.
+    cache: Option<&'a mut BuildCache>,
+    visited: HashSet<PathBuf>,
+    // The index of an exchange -> the path of the file for the exchange.
+    file_paths: HashMap<usize, PathBuf>,
 }
 
 // TODO: Refactor so that async and sync variants share more code.
-impl ExchangeBuilder {
+impl<'a> ExchangeBuilder<'a> {
     pub fn new(base_dir: PathBuf) -> Self {
         ExchangeBuilder {
             base_dir,
             exchanges: Vec::new(),
+            options: Options::default(),
+            cache: None,
+            visited: HashSet::new(),
+            file_paths: HashMap::new(),
         }
     }
 
+    pub fn cache(mut self, cache: &'a mut BuildCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    pub fn options(mut self, options: Options) -> Self {
+        self.options = options;
+        self
+    }
+
     pub async fn walk(mut self) -> Result<Self> {
         // TODO: Walkdir is not async.
-        for entry in WalkDir::new(&self.base_dir) {
+        let base_dir = self.base_dir.clone();
+        let excludes = self.options.excludes.clone();
+        for entry in WalkDir::new(&base_dir)
+            .into_iter()
+            .filter_entry(|entry| !is_excluded(&excludes, &base_dir, entry.path()))
+        {
             let entry = entry?;
             log::debug!("visit: {:?}", entry);
             let file_type = entry.file_type();
@@ -91,29 +417,57 @@ impl ExchangeBuilder {
                 );
                 continue;
             }
+            if file_type.is_dir() {
+                self = self.exchange_directory_listing(entry.path()).await?;
+                continue;
+            }
             if !file_type.is_file() {
                 continue;
             }
+            if self.is_precompressed_sibling(entry.path()) {
+                continue;
+            }
+            if let Some(url_mapper) = self.options.url_mapper.clone() {
+                let relative_path = pathdiff::diff_paths(entry.path(), &self.base_dir).unwrap();
+                match url_mapper(&relative_path) {
+                    Some(url) => self = self.exchange(url, &relative_path).await?,
+                    None => log::debug!("url_mapper skips: {}", relative_path.display()),
+                }
+                continue;
+            }
             if entry.path().file_name().unwrap() == "index.html" {
                 let dir = entry.path().parent().unwrap();
 
                 let relative_url = pathdiff::diff_paths(dir, &self.base_dir).unwrap();
                 let relative_path = pathdiff::diff_paths(entry.path(), &self.base_dir).unwrap();
                 // for <dir> -> Serves the contents of <dir>/index.html
-                self = self.exchange(&relative_url, &relative_path).await?;
+                self = self
+                    .exchange(relative_url.as_path(), &relative_path)
+                    .await?;
 
                 // for <dir>/index.html -> redirect to "./"
                 self = self.exchange_redirect(&relative_path, "./")?;
             } else {
                 let relative_path = pathdiff::diff_paths(entry.path(), &self.base_dir).unwrap();
-                self = self.exchange(&relative_path, &relative_path).await?;
+                self = self
+                    .exchange(relative_path.as_path(), &relative_path)
+                    .await?;
             }
         }
+        self.finish()?;
+        if let Some(cache) = self.cache.as_deref_mut() {
+            cache.entries.retain(|path, _| self.visited.contains(path));
+        }
         Ok(self)
     }
 
     pub fn walk_sync(mut self) -> Result<Self> {
-        for entry in WalkDir::new(&self.base_dir) {
+        let base_dir = self.base_dir.clone();
+        let excludes = self.options.excludes.clone();
+        for entry in WalkDir::new(&base_dir)
+            .into_iter()
+            .filter_entry(|entry| !is_excluded(&excludes, &base_dir, entry.path()))
+        {
             let entry = entry?;
             log::debug!("visit: {:?}", entry);
             let file_type = entry.file_type();
@@ -124,61 +478,309 @@ impl ExchangeBuilder {
                 );
                 continue;
             }
+            if file_type.is_dir() {
+                self = self.exchange_directory_listing_sync(entry.path())?;
+                continue;
+            }
             if !file_type.is_file() {
                 continue;
             }
+            if self.is_precompressed_sibling(entry.path()) {
+                continue;
+            }
+            if let Some(url_mapper) = self.options.url_mapper.clone() {
+                let relative_path = pathdiff::diff_paths(entry.path(), &self.base_dir).unwrap();
+                match url_mapper(&relative_path) {
+                    Some(url) => self = self.exchange_sync(url, &relative_path)?,
+                    None => log::debug!("url_mapper skips: {}", relative_path.display()),
+                }
+                continue;
+            }
             if entry.path().file_name().unwrap() == "index.html" {
                 let dir = entry.path().parent().unwrap();
 
                 let relative_url = pathdiff::diff_paths(dir, &self.base_dir).unwrap();
                 let relative_path = pathdiff::diff_paths(entry.path(), &self.base_dir).unwrap();
                 // for <dir> -> Serves the contents of <dir>/index.html
-                self = self.exchange_sync(relative_url, &relative_path)?;
+                self = self.exchange_sync(relative_url.as_path(), &relative_path)?;
 
                 // for <dir>/index.html -> redirect to "./"
                 self = self.exchange_redirect(&relative_path, "./")?;
             } else {
                 let relative_path = pathdiff::diff_paths(entry.path(), &self.base_dir).unwrap();
-                self = self.exchange_sync(&relative_path, &relative_path)?;
+                self = self.exchange_sync(relative_path.as_path(), &relative_path)?;
             }
         }
+        self.finish()?;
         Ok(self)
     }
 
+    /// Processes the exchanges after all files are added. Bodies are
+    /// compressed after fingerprinting, which rewrites them.
+    pub fn finish(&mut self) -> Result<()> {
+        if self.options.fingerprint {
+            super::fingerprint::fingerprint(&mut self.exchanges, &self.file_paths)?;
+        }
+        #[cfg(feature = "compress")]
+        if let Some(compression) = self.options.compression {
+            let skip = match &self.options.compress_skip {
+                Some(skip) => skip.clone(),
+                None => super::compress::DEFAULT_COMPRESS_SKIP
+                    .iter()
+                    .map(|content_type| content_type.to_string())
+                    .collect(),
+            };
+            super::compress::compress(&mut self.exchanges, compression, &skip)?;
+        }
+        Ok(())
+    }
+
     pub fn build(self) -> Vec<Exchange> {
         self.exchanges
     }
 
     pub async fn exchange(
         mut self,
-        relative_url: impl AsRef<Path>,
+        relative_url: impl Into<Request>,
         relative_path: impl AsRef<Path>,
     ) -> Result<Self> {
-        self.exchanges.push(
-            (
-                relative_url.as_ref(),
-                self.read_file(&relative_path).await?,
-                ContentType::from(mime_guess::from_path(&relative_path).first_or_octet_stream()),
-            )
-                .into(),
-        );
-        Ok(self)
+        let (body, content_encoding) = match self.precompressed_file(&relative_path) {
+            Some((path, content_encoding)) => (self.read_file(path).await?, Some(content_encoding)),
+            None => (self.read_file(&relative_path).await?, None),
+        };
+        let modified = self.modified(&relative_path);
+        self.push_file(
+            relative_url,
+            relative_path,
+            body,
+            content_encoding,
+            modified,
+        )
     }
 
     pub fn exchange_sync(
+        self,
+        relative_url: impl Into<Request>,
+        relative_path: impl AsRef<Path>,
+    ) -> Result<Self> {
+        let (body, content_encoding) = match self.precompressed_file(&relative_path) {
+            Some((path, content_encoding)) => (self.read_file_sync(path)?, Some(content_encoding)),
+            None => (self.read_file_sync(&relative_path)?, None),
+        };
+        let modified = self.modified(&relative_path);
+        self.push_file(
+            relative_url,
+            relative_path,
+            body,
+            content_encoding,
+            modified,
+        )
+    }
+
+    /// Adds exchanges for a file whose contents are already read, e.g. an
+    /// entry of an archive. URLs are mapped in the same way as `walk`.
+    #[cfg(feature = "archive")]
+    pub fn file_entry(
+        self,
+        relative_path: &Path,
+        body: Vec<u8>,
+        modified: Option<SystemTime>,
+    ) -> Result<Self> {
+        if is_excluded(&self.options.excludes, Path::new(""), relative_path) {
+            return Ok(self);
+        }
+        if let Some(url_mapper) = self.options.url_mapper.clone() {
+            return match url_mapper(relative_path) {
+                Some(url) => self.push_file(url, relative_path, body, None, modified),
+                None => Ok(self),
+            };
+        }
+        if relative_path.file_name() == Some("index.html".as_ref()) {
+            let relative_url = relative_path.parent().unwrap_or_else(|| Path::new(""));
+            self.push_file(relative_url, relative_path, body, None, modified)?
+                .exchange_redirect(relative_path, "./")
+        } else {
+            self.push_file(relative_path, relative_path, body, None, modified)
+        }
+    }
+
+    fn modified(&self, relative_path: impl AsRef<Path>) -> Option<SystemTime> {
+        std::fs::metadata(self.base_dir.join(relative_path))
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+
+    fn push_file(
         mut self,
-        relative_url: impl AsRef<Path>,
+        relative_url: impl Into<Request>,
         relative_path: impl AsRef<Path>,
+        body: Vec<u8>,
+        content_encoding: Option<&str>,
+        modified: Option<SystemTime>,
     ) -> Result<Self> {
+        let relative_path = relative_path.as_ref();
+        if let Some(progress) = &self.options.progress {
+            progress(relative_path, body.len());
+        }
+        let mut exchange: Exchange = (
+            relative_url,
+            body,
+            ContentType::from(mime_guess::from_path(relative_path).first_or_octet_stream()),
+        )
+            .into();
+        if self.is_not_found_page(relative_path) {
+            *exchange.response.status_mut() = StatusCode::NOT_FOUND;
+        }
+        if let Some(content_encoding) = content_encoding {
+            exchange.response.headers_mut().insert(
+                http::header::CONTENT_ENCODING,
+                HeaderValue::from_str(content_encoding)?,
+            );
+        }
+        if let Some(cache_policy) = &self.options.cache_policy {
+            let etag = etag(exchange.response.body())?;
+            let headers = exchange.response.headers_mut();
+            headers.typed_insert(cache_policy.cache_control());
+            headers.typed_insert(etag);
+            if let Some(modified) = modified {
+                headers.typed_insert(LastModified::from(modified));
+            }
+        }
+        self.file_paths
+            .insert(self.exchanges.len(), relative_path.to_path_buf());
+        self.exchanges.push(exchange);
+        Ok(self)
+    }
+
+    const PRECOMPRESSED_EXTENSIONS: [(&'static str, &'static str); 2] =
+        [("br", "br"), ("gz", "gzip")];
+
+    /// Returns the compressed sibling of the given file, with its content encoding.
+    fn precompressed_file(
+        &self,
+        relative_path: impl AsRef<Path>,
+    ) -> Option<(PathBuf, &'static str)> {
+        if !self.options.precompressed {
+            return None;
+        }
+        let relative_path = relative_path.as_ref();
+        let mut siblings =
+            Self::PRECOMPRESSED_EXTENSIONS
+                .iter()
+                .filter_map(|(extension, content_encoding)| {
+                    let mut path = relative_path.as_os_str().to_owned();
+                    path.push(".");
+                    path.push(extension);
+                    let path = PathBuf::from(path);
+                    self.base_dir
+                        .join(&path)
+                        .is_file()
+                        .then_some((path, *content_encoding))
+                });
+        let sibling = siblings.next()?;
+        for (ignored, _) in siblings {
+            log::warn!(
+                "{}: Using {} and ignoring {}. A bundle can have only one encoding for a URL",
+                relative_path.display(),
+                sibling.0.display(),
+                ignored.display()
+            );
+        }
+        Some(sibling)
+    }
+
+    /// Returns true if the given file is a compressed sibling of another file.
+    fn is_precompressed_sibling(&self, path: &Path) -> bool {
+        self.options.precompressed
+            && Self::PRECOMPRESSED_EXTENSIONS
+                .iter()
+                .any(|(extension, _)| path.extension() == Some(extension.as_ref()))
+            && path.with_extension("").is_file()
+    }
+
+    fn is_not_found_page(&self, relative_path: &Path) -> bool {
+        match (&self.options.not_found_page, relative_path.file_name()) {
+            (Some(not_found_page), Some(file_name)) => file_name == not_found_page.as_str(),
+            _ => false,
+        }
+    }
+
+    fn needs_directory_listing(&self, has_index: bool) -> bool {
+        self.options.directory_listing && self.options.url_mapper.is_none() && !has_index
+    }
+
+    /// Returns whether the entry of a directory is listed. Symbolic links,
+    /// excluded files, the not found page and precompressed siblings, which
+    /// are not served at their own URLs, are not listed.
+    fn is_listed(&self, path: &Path, file_type: std::fs::FileType) -> bool {
+        !file_type.is_symlink()
+            && !is_excluded(&self.options.excludes, &self.base_dir, path)
+            && !self.is_not_found_page(path)
+            && !self.is_precompressed_sibling(path)
+    }
+
+    async fn exchange_directory_listing(self, dir: &Path) -> Result<Self> {
+        let has_index = fs::metadata(dir.join("index.html"))
+            .await
+            .is_ok_and(|metadata| metadata.is_file());
+        if !self.needs_directory_listing(has_index) {
+            return Ok(self);
+        }
+        let mut names = Vec::new();
+        let mut entries = fs::read_dir(dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if self.is_listed(&entry.path(), entry.file_type().await?) {
+                names.push(entry.file_name().to_string_lossy().to_string());
+            }
+        }
+        Ok(self.directory_listing(dir, names))
+    }
+
+    fn exchange_directory_listing_sync(self, dir: &Path) -> Result<Self> {
+        if !self.needs_directory_listing(dir.join("index.html").is_file()) {
+            return Ok(self);
+        }
+        let mut names = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            if self.is_listed(&entry.path(), entry.file_type()?) {
+                names.push(entry.file_name().to_string_lossy().to_string());
+            }
+        }
+        Ok(self.directory_listing(dir, names))
+    }
+
+    fn directory_listing(mut self, dir: &Path, mut names: Vec<String>) -> Self {
+        let relative_url = pathdiff::diff_paths(dir, &self.base_dir).unwrap();
+        names.sort();
+
+        // The URL of a directory doesn't end with "/". Links have to include
+        // the directory name so that they are resolved under the directory.
+        let prefix = match relative_url.file_name() {
+            Some(name) => format!("{}/", percent_encode(&name.to_string_lossy())),
+            None => String::new(),
+        };
+        let links = names
+            .iter()
+            .map(|name| {
+                let href = html_escape(&format!("{prefix}{}", percent_encode(name)));
+                format!("<li><a href=\"{href}\">{}</a></li>\n", html_escape(name))
+            })
+            .collect::<String>();
+        let title = html_escape(&format!("/{}", relative_url.display()));
+        let html = format!(
+            "<!DOCTYPE html>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<h1>Directory listing for {title}</h1>\n<ul>\n{links}</ul>\n"
+        );
+
         self.exchanges.push(
             (
-                relative_url.as_ref(),
-                self.read_file_sync(&relative_path)?,
-                ContentType::from(mime_guess::from_path(&relative_path).first_or_octet_stream()),
+                relative_url.as_path(),
+                html.into_bytes(),
+                ContentType::html(),
             )
                 .into(),
         );
-        Ok(self)
+        self
     }
 
     fn exchange_redirect(mut self, relative_url: &Path, location: &str) -> Result<Self> {
@@ -198,16 +800,43 @@ impl ExchangeBuilder {
         Ok(response)
     }
 
-    async fn read_file(&self, relative_path: impl AsRef<Path>) -> Result<Vec<u8>> {
+    async fn read_file(&mut self, relative_path: impl AsRef<Path>) -> Result<Vec<u8>> {
         ensure!(
             relative_path.as_ref().is_relative(),
             format!("Path is not relative: {}", relative_path.as_ref().display())
         );
         let path = self.base_dir.join(relative_path);
 
+        let cache = match self.cache.as_deref_mut() {
+            Some(cache) => cache,
+            None => {
+                let mut file = tokio::io::BufReader::new(fs::File::open(&path).await?);
+                let mut body = Vec::new();
+                file.read_to_end(&mut body).await?;
+                return Ok(body);
+            }
+        };
+
+        let metadata = fs::metadata(&path).await?;
+        let (modified, len) = (metadata.modified()?, metadata.len());
+        self.visited.insert(path.clone());
+        match cache.entries.get(&path) {
+            Some(entry) if entry.modified == modified && entry.len == len => {
+                return Ok(entry.body.clone())
+            }
+            _ => log::debug!("cache miss: {}", path.display()),
+        }
         let mut file = tokio::io::BufReader::new(fs::File::open(&path).await?);
         let mut body = Vec::new();
         file.read_to_end(&mut body).await?;
+        cache.entries.insert(
+            path,
+            CacheEntry {
+                modified,
+                len,
+                body: body.clone(),
+            },
+        );
         Ok(body)
     }
 
@@ -227,6 +856,65 @@ impl ExchangeBuilder {
     }
 }
 
+/// Returns true if the path matches any of the exclude globs. See
+/// `Builder::exclude`.
+fn is_excluded(excludes: &[String], base_dir: &Path, path: &Path) -> bool {
+    if excludes.is_empty() {
+        return false;
+    }
+    let relative_path = match pathdiff::diff_paths(path, base_dir) {
+        Some(relative_path) => relative_path,
+        None => return false,
+    };
+    let components = relative_path
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>();
+    if components.is_empty() {
+        return false;
+    }
+    let relative_path = components.join("/");
+    let absolute_path = format!("/{relative_path}");
+    excludes.iter().any(|glob| {
+        if glob.starts_with('/') {
+            glob_match(glob, &absolute_path)
+        } else {
+            glob_match(glob, &relative_path)
+        }
+    })
+}
+
+/// Returns a strong ETag which is derived from the SHA-256 hash of the body.
+pub(crate) fn etag(body: &[u8]) -> Result<ETag> {
+    let hash = Sha256::digest(body);
+    let hex = hash[..16]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<String>();
+    format!("\"{hex}\"").parse().context("Invalid ETag")
+}
+
+/// Percent-encodes a path segment, e.g. a file name, so that characters such
+/// as spaces, `#` and `?` are not parsed as delimiters.
+fn percent_encode(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -285,6 +973,364 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn walk_with_url_mapper() -> Result<()> {
+        let base_dir = {
+            let mut path = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+            path.push("tests/builder");
+            path
+        };
+
+        let url_mapper: UrlMapper = Arc::new(|path: &Path| {
+            if path.extension()? == "js" {
+                return None;
+            }
+            Some(format!("https://example.com/{}", path.to_str()?))
+        });
+        let exchanges = ExchangeBuilder::new(base_dir)
+            .options(Options {
+                url_mapper: Some(url_mapper),
+                ..Default::default()
+            })
+            .walk()
+            .await?
+            .build();
+        assert_eq!(exchanges.len(), 1);
+
+        let index_html = find_exchange_by_url(&exchanges, "https://example.com/index.html")?;
+        assert_eq!(index_html.response.status(), StatusCode::OK);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn walk_with_excludes() -> Result<()> {
+        let base_dir = {
+            let mut path = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+            path.push("tests/builder");
+            path
+        };
+
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .exclude("*.js")
+            .exchanges_from_dir(&base_dir)
+            .await?
+            .build()?;
+        assert_eq!(bundle.exchanges().len(), 2);
+        assert!(find_exchange_by_url(bundle.exchanges(), "js/hello.js").is_err());
+
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .exclude("js/**")
+            .exchanges_from_dir_sync(&base_dir)?
+            .build()?;
+        assert_eq!(bundle.exchanges().len(), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn walk_with_progress() -> Result<()> {
+        let base_dir = {
+            let mut path = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+            path.push("tests/builder");
+            path
+        };
+
+        let paths = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let progress_paths = paths.clone();
+        Bundle::builder()
+            .version(Version::VersionB2)
+            .progress(move |path, len| {
+                progress_paths
+                    .lock()
+                    .unwrap()
+                    .push((path.to_path_buf(), len))
+            })
+            .exchanges_from_dir(base_dir)
+            .await?;
+        let mut paths = paths.lock().unwrap().clone();
+        paths.sort();
+        assert_eq!(
+            paths,
+            [
+                (PathBuf::from("index.html"), 80),
+                (PathBuf::from("js/hello.js"), 38)
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn glob_match_test() {
+        let excluded = |glob: &str, path: &str| {
+            is_excluded(&[glob.to_string()], Path::new(""), Path::new(path))
+        };
+        assert!(excluded("*.map", "a.js.map"));
+        assert!(excluded("*.map", "js/a.js.map"));
+        assert!(!excluded("*.map", "a.js"));
+        assert!(excluded("node_modules", "a/node_modules/b.js"));
+        assert!(excluded("node_modules/**", "node_modules"));
+        assert!(excluded("node_modules/**", "node_modules/a/b.js"));
+        assert!(!excluded("node_modules/**", "a/node_modules/b.js"));
+        assert!(excluded("**/node_modules/**", "a/node_modules/b.js"));
+        assert!(excluded("js/*.js", "js/a.js"));
+        assert!(!excluded("js/*.js", "js/a/b.js"));
+        assert!(excluded("js/**/*.js", "js/a/b.js"));
+        assert!(excluded("js/**/*.js", "js/a.js"));
+        assert!(excluded("?.txt", "a.txt"));
+        assert!(excluded("/js/*.js", "js/a.js"));
+        assert!(!excluded("/a.js", "js/a.js"));
+    }
+
+    #[tokio::test]
+    async fn walk_with_not_found_page() -> Result<()> {
+        let base_dir = {
+            let mut path = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+            path.push("tests/not_found");
+            path
+        };
+
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .not_found_page("404.html")
+            .exchanges_from_dir(base_dir)
+            .await?
+            .build()?;
+
+        let not_found = find_exchange_by_url(bundle.exchanges(), "docs/404.html")?;
+        assert_eq!(not_found.response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            bundle
+                .not_found_exchange("docs/missing.html")
+                .context("no error page")?
+                .request
+                .url(),
+            "docs/404.html"
+        );
+        assert_eq!(
+            bundle
+                .not_found_exchange("missing.html")
+                .context("no error page")?
+                .request
+                .url(),
+            "404.html"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn walk_with_directory_listing() -> Result<()> {
+        let base_dir = {
+            let mut path = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+            path.push("tests/builder");
+            path
+        };
+
+        let exchanges = ExchangeBuilder::new(base_dir)
+            .options(Options {
+                directory_listing: true,
+                ..Default::default()
+            })
+            .walk()
+            .await?
+            .build();
+        assert_eq!(exchanges.len(), 4);
+
+        // The top directory has index.html.
+        let top_dir = find_exchange_by_url(&exchanges, "")?;
+        assert!(!String::from_utf8_lossy(top_dir.response.body()).contains("Directory listing"));
+
+        let js_dir = find_exchange_by_url(&exchanges, "js")?;
+        assert_eq!(js_dir.response.status(), StatusCode::OK);
+        assert_eq!(js_dir.response.headers()["content-type"], "text/html");
+        assert!(String::from_utf8_lossy(js_dir.response.body())
+            .contains(r#"<a href="js/hello.js">hello.js</a>"#));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn directory_listing_entries() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let docs = dir.path().join("my docs");
+        std::fs::create_dir(&docs)?;
+        for name in ["a b#?.txt", "404.html", "app.js", "app.js.br", "app.js.gz"] {
+            std::fs::write(docs.join(name), name)?;
+        }
+
+        for sync in [false, true] {
+            let builder = ExchangeBuilder::new(dir.path().to_path_buf()).options(Options {
+                directory_listing: true,
+                precompressed: true,
+                not_found_page: Some("404.html".to_string()),
+                ..Default::default()
+            });
+            let exchanges = if sync {
+                builder.walk_sync()?.build()
+            } else {
+                builder.walk().await?.build()
+            };
+            let listing = find_exchange_by_url(&exchanges, "my docs")?;
+            let body = String::from_utf8_lossy(listing.response.body());
+            assert!(body.contains(r#"<a href="my%20docs/a%20b%23%3F.txt">a b#?.txt</a>"#));
+            assert!(body.contains(r#"<a href="my%20docs/app.js">app.js</a>"#));
+            assert!(!body.contains("404.html"));
+            assert!(!body.contains("app.js.br"));
+            assert!(!body.contains("app.js.gz"));
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn walk_with_precompressed() -> Result<()> {
+        let base_dir = {
+            let mut path = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+            path.push("tests/precompressed");
+            path
+        };
+
+        let exchanges = ExchangeBuilder::new(base_dir.clone())
+            .options(Options {
+                precompressed: true,
+                ..Default::default()
+            })
+            .walk()
+            .await?
+            .build();
+        assert_eq!(exchanges.len(), 2);
+
+        let app_js = find_exchange_by_url(&exchanges, "app.js")?;
+        assert_eq!(app_js.response.headers()["content-encoding"], "br");
+        assert_eq!(app_js.response.headers()["content-type"], "text/javascript");
+        assert_eq!(
+            app_js.response.body(),
+            &std::fs::read(base_dir.join("app.js.br"))?
+        );
+
+        let style_css = find_exchange_by_url(&exchanges, "style.css")?;
+        assert_eq!(style_css.response.headers()["content-encoding"], "gzip");
+        assert_eq!(
+            style_css.response.headers()["content-length"],
+            std::fs::read(base_dir.join("style.css.gz"))?
+                .len()
+                .to_string()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn walk_with_cache_policy() -> Result<()> {
+        let base_dir = {
+            let mut path = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+            path.push("tests/builder");
+            path
+        };
+
+        let exchanges = ExchangeBuilder::new(base_dir)
+            .options(Options {
+                cache_policy: Some(CachePolicy::ImmutableAssets),
+                ..Default::default()
+            })
+            .walk()
+            .await?
+            .build();
+
+        let a_js = find_exchange_by_url(&exchanges, "js/hello.js")?;
+        let headers = a_js.response.headers();
+        assert_eq!(
+            headers["cache-control"],
+            "public, immutable, max-age=31536000"
+        );
+        assert_eq!(
+            headers.typed_get::<ETag>(),
+            Some(etag(a_js.response.body())?)
+        );
+        assert!(headers.contains_key("last-modified"));
+
+        // Redirects don't have caching headers.
+        let index_html = find_exchange_by_url(&exchanges, "index.html")?;
+        assert!(!index_html.response.headers().contains_key("etag"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn exchanges_from_dir_cached() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::write(dir.path().join("a.txt"), "a")?;
+        std::fs::write(dir.path().join("b.txt"), "b")?;
+
+        let mut cache = BuildCache::new();
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchanges_from_dir_cached(dir.path(), &mut cache)
+            .await?
+            .build()?;
+        assert_eq!(bundle.exchanges().len(), 2);
+        assert_eq!(cache.len(), 2);
+
+        // A changed file is read again, and a removed file is evicted.
+        std::fs::write(dir.path().join("a.txt"), "aa")?;
+        std::fs::remove_file(dir.path().join("b.txt"))?;
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchanges_from_dir_cached(dir.path(), &mut cache)
+            .await?
+            .build()?;
+        assert_eq!(bundle.exchanges().len(), 1);
+        assert_eq!(bundle.exchanges()[0].response.body(), b"aa");
+        assert_eq!(cache.len(), 1);
+
+        // An unchanged file is served from the cache.
+        cache
+            .entries
+            .get_mut(&dir.path().join("a.txt"))
+            .context("not cached")?
+            .body = b"cached".to_vec();
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchanges_from_dir_cached(dir.path(), &mut cache)
+            .await?
+            .build()?;
+        assert_eq!(bundle.exchanges()[0].response.body(), b"cached");
+
+        // A failed build keeps the cache.
+        assert!(Bundle::builder()
+            .version(Version::VersionB2)
+            .exchanges_from_dir_cached(dir.path().join("missing"), &mut cache)
+            .await
+            .is_err());
+        assert_eq!(cache.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn walk_with_fingerprint() -> Result<()> {
+        let base_dir = {
+            let mut path = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+            path.push("tests/builder");
+            path
+        };
+
+        let exchanges = ExchangeBuilder::new(base_dir)
+            .options(Options {
+                fingerprint: true,
+                ..Default::default()
+            })
+            .walk()
+            .await?
+            .build();
+        assert_eq!(exchanges.len(), 3);
+
+        let hello_js = exchanges
+            .iter()
+            .find(|e| e.request.url().starts_with("js/hello."))
+            .context("not found")?;
+        assert_ne!(hello_js.request.url(), "js/hello.js");
+        assert!(hello_js.request.url().ends_with(".js"));
+        assert!(find_exchange_by_url(&exchanges, "index.html").is_ok());
+        Ok(())
+    }
+
     fn find_exchange_by_url<'a>(exchanges: &'a [Exchange], url: &str) -> Result<&'a Exchange> {
         exchanges
             .iter()
@@ -0,0 +1,219 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Compresses response bodies, with `Content-Encoding` header.
+
+use crate::bundle::{Exchange, Response};
+use crate::prelude::*;
+use headers::{ContentType, HeaderMapExt as _, HeaderValue};
+use std::borrow::Cow;
+use std::io::{Read as _, Write as _};
+
+/// The algorithm to compress response bodies. See `Builder::compress`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// `Content-Encoding: gzip`.
+    Gzip,
+    /// `Content-Encoding: br`.
+    Brotli,
+}
+
+impl Compression {
+    fn content_encoding(&self) -> &'static str {
+        match self {
+            Compression::Gzip => "gzip",
+            Compression::Brotli => "br",
+        }
+    }
+
+    fn compress(&self, body: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Compression::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+                encoder.write_all(body)?;
+                Ok(encoder.finish()?)
+            }
+            Compression::Brotli => {
+                let mut compressed = Vec::new();
+                {
+                    let mut encoder = brotli::CompressorWriter::new(&mut compressed, 4096, 11, 22);
+                    encoder.write_all(body)?;
+                }
+                Ok(compressed)
+            }
+        }
+    }
+}
+
+/// Content types which are not compressed by default because they are
+/// compressed already. `*` matches any subtype.
+pub const DEFAULT_COMPRESS_SKIP: &[&str] = &[
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "image/webp",
+    "image/avif",
+    "video/*",
+    "audio/*",
+    "font/woff",
+    "font/woff2",
+    "application/zip",
+    "application/gzip",
+    "application/pdf",
+];
+
+fn matches_content_type(pattern: &str, essence: &str) -> bool {
+    match pattern.strip_suffix("/*") {
+        Some(type_) => essence.split('/').next() == Some(type_),
+        None => pattern.eq_ignore_ascii_case(essence),
+    }
+}
+
+/// Returns the body of the response, decoded with its `Content-Encoding`,
+/// i.e. `gzip`, `deflate`, `br` or `identity`. A body without
+/// `Content-Encoding` is returned as is.
+///
+/// # Examples
+///
+/// ```
+/// use webbundle::{decoded_body, Exchange};
+/// let exchange = Exchange::from(("a.txt".to_string(), b"hello".to_vec()));
+/// assert_eq!(decoded_body(&exchange.response)?.as_ref(), b"hello");
+/// # std::result::Result::Ok::<_, anyhow::Error>(())
+/// ```
+pub fn decoded_body(response: &Response) -> Result<Cow<'_, [u8]>> {
+    let body = response.body().as_slice();
+    let content_encoding = match response.headers().get(http::header::CONTENT_ENCODING) {
+        Some(content_encoding) => content_encoding.to_str()?.trim().to_ascii_lowercase(),
+        None => return Ok(Cow::Borrowed(body)),
+    };
+    let mut decoded = Vec::new();
+    match content_encoding.as_str() {
+        "identity" => return Ok(Cow::Borrowed(body)),
+        "gzip" | "x-gzip" => {
+            flate2::read::GzDecoder::new(body).read_to_end(&mut decoded)?;
+        }
+        "deflate" => {
+            flate2::read::ZlibDecoder::new(body).read_to_end(&mut decoded)?;
+        }
+        "br" => {
+            brotli::Decompressor::new(body, 4096).read_to_end(&mut decoded)?;
+        }
+        _ => bail!("Unsupported Content-Encoding: {}", content_encoding),
+    }
+    Ok(Cow::Owned(decoded))
+}
+
+/// Compresses the bodies of the exchanges, except for ones whose content
+/// types match `skip`, which are already encoded, or which don't get
+/// smaller.
+pub(crate) fn compress(
+    exchanges: &mut [Exchange],
+    compression: Compression,
+    skip: &[String],
+) -> Result<()> {
+    for exchange in exchanges {
+        let response = &exchange.response;
+        if response.body().is_empty()
+            || response
+                .headers()
+                .contains_key(http::header::CONTENT_ENCODING)
+        {
+            continue;
+        }
+        let essence = response
+            .headers()
+            .typed_get::<ContentType>()
+            .map(|content_type| {
+                mime_guess::Mime::from(content_type)
+                    .essence_str()
+                    .to_string()
+            })
+            .unwrap_or_default();
+        if skip
+            .iter()
+            .any(|pattern| matches_content_type(pattern, &essence))
+        {
+            continue;
+        }
+        let compressed = compression.compress(response.body())?;
+        if compressed.len() >= response.body().len() {
+            continue;
+        }
+        exchange.response.headers_mut().insert(
+            http::header::CONTENT_ENCODING,
+            HeaderValue::from_static(compression.content_encoding()),
+        );
+        super::fingerprint::set_body(exchange, compressed)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compress_exchanges() -> Result<()> {
+        let text = b"hello ".repeat(100);
+        let mut exchanges = vec![
+            Exchange::from(("a.txt".to_string(), text.clone())),
+            Exchange::from(("b.png".to_string(), text.clone())),
+            // Too small to get smaller.
+            Exchange::from(("c.txt".to_string(), b"c".to_vec())),
+        ];
+        let skip = DEFAULT_COMPRESS_SKIP
+            .iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>();
+        compress(&mut exchanges, Compression::Gzip, &skip)?;
+
+        let headers = exchanges[0].response.headers();
+        assert_eq!(headers["content-encoding"], "gzip");
+        assert_eq!(
+            headers["content-length"],
+            exchanges[0].response.body().len().to_string().as_str()
+        );
+        assert_eq!(decoded_body(&exchanges[0].response)?.as_ref(), text);
+
+        assert!(!exchanges[1]
+            .response
+            .headers()
+            .contains_key("content-encoding"));
+        assert!(!exchanges[2]
+            .response
+            .headers()
+            .contains_key("content-encoding"));
+
+        compress(&mut exchanges[1..], Compression::Brotli, &[])?;
+        assert_eq!(decoded_body(&exchanges[1].response)?.as_ref(), text);
+        assert_eq!(decoded_body(&exchanges[2].response)?.as_ref(), b"c");
+
+        exchanges[2].response.headers_mut().insert(
+            http::header::CONTENT_ENCODING,
+            HeaderValue::from_static("zstd"),
+        );
+        assert!(decoded_body(&exchanges[2].response).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn matches_content_type_test() {
+        assert!(matches_content_type("video/*", "video/mp4"));
+        assert!(!matches_content_type("image/*", "text/html"));
+        assert!(matches_content_type("font/woff2", "font/woff2"));
+        assert!(!matches_content_type("font/woff", "font/woff2"));
+    }
+}
@@ -0,0 +1,363 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Renames resources to content-hashed URLs, e.g. `app.js` to
+//! `app.3f9a61c0.js`, and rewrites references to them in HTML, CSS and
+//! JavaScript.
+
+use crate::bundle::{Exchange, Request};
+use headers::{ContentLength, ContentType, ETag, HeaderMapExt as _};
+use http::StatusCode;
+use sha2::{Digest as _, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+#[derive(PartialEq, Eq)]
+enum Kind {
+    Html,
+    Css,
+    Script,
+    Asset,
+}
+
+fn kind(exchange: &Exchange) -> Kind {
+    let mime = exchange
+        .response
+        .headers()
+        .typed_get::<ContentType>()
+        .map(mime_guess::Mime::from);
+    match mime {
+        Some(mime) if mime.essence_str() == "text/html" => Kind::Html,
+        Some(mime) if mime.essence_str() == "text/css" => Kind::Css,
+        Some(mime)
+            if matches!(
+                mime.essence_str(),
+                "application/javascript" | "text/javascript"
+            ) =>
+        {
+            Kind::Script
+        }
+        _ => Kind::Asset,
+    }
+}
+
+fn is_encoded(exchange: &Exchange) -> bool {
+    exchange
+        .response
+        .headers()
+        .contains_key(http::header::CONTENT_ENCODING)
+}
+
+/// Inserts the hash before the extension of the last segment, e.g.
+/// `js/app.js` becomes `js/app.<hash>.js`.
+fn insert_hash(url: &str, hash: &str) -> String {
+    let (dir, name) = match url.rfind('/') {
+        Some(pos) => url.split_at(pos + 1),
+        None => ("", url),
+    };
+    match name.rfind('.') {
+        Some(pos) if pos > 0 => format!("{dir}{}.{hash}{}", &name[..pos], &name[pos..]),
+        _ => format!("{dir}{name}.{hash}"),
+    }
+}
+
+fn content_hash(body: &[u8]) -> String {
+    Sha256::digest(body)[..4]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Returns the URL-style reference from the directory of `from` to `to`.
+fn reference(from: &Path, to: &Path) -> Option<String> {
+    let dir = from.parent().unwrap_or_else(|| Path::new(""));
+    let relative = pathdiff::diff_paths(to, dir)?;
+    let segments = relative
+        .iter()
+        .map(|s| s.to_str())
+        .collect::<Option<Vec<_>>>()?;
+    Some(segments.join("/"))
+}
+
+/// Returns the root-absolute reference to `to`, e.g. `/js/app.js`, which
+/// assumes that the directory is served at the root.
+fn root_reference(to: &Path) -> Option<String> {
+    let segments = to.iter().map(|s| s.to_str()).collect::<Option<Vec<_>>>()?;
+    Some(format!("/{}", segments.join("/")))
+}
+
+/// Returns the quoted or parenthesized forms of the reference, e.g.
+/// `"js/app.js"`, `'./js/app.js'`, `` `js/app.js` `` or `url(js/app.js)`.
+/// Quoted forms cover the specifiers of ES modules, e.g.
+/// `import { f } from "./app.js"` and `export * from './app.js'`.
+fn quoted(reference: &str) -> Vec<String> {
+    let mut forms = Vec::new();
+    for prefix in ["", "./"] {
+        for (open, close) in [('"', '"'), ('\'', '\''), ('`', '`'), ('(', ')')] {
+            forms.push(format!("{open}{prefix}{reference}{close}"));
+        }
+    }
+    forms
+}
+
+fn rewrite(body: &[u8], references: &[(String, String)]) -> Option<Vec<u8>> {
+    let mut text = std::str::from_utf8(body).ok()?.to_string();
+    for (from, to) in references {
+        for (from, to) in quoted(from).into_iter().zip(quoted(to)) {
+            text = text.replace(&from, &to);
+        }
+    }
+    Some(text.into_bytes())
+}
+
+/// Returns whether the body of the file at `path` refers to the file at `to`.
+fn refers_to(body: &[u8], path: &Path, to: &Path) -> bool {
+    let text = match std::str::from_utf8(body) {
+        Ok(text) => text,
+        Err(_) => return false,
+    };
+    [reference(path, to), root_reference(to)]
+        .into_iter()
+        .flatten()
+        .flat_map(|reference| quoted(&reference))
+        .any(|form| text.contains(&form))
+}
+
+pub(super) fn set_body(exchange: &mut Exchange, body: Vec<u8>) -> crate::Result<()> {
+    let headers = exchange.response.headers_mut();
+    headers.typed_insert(ContentLength(body.len() as u64));
+    if headers.contains_key(http::header::ETAG) {
+        headers.typed_insert::<ETag>(super::builder::etag(&body)?);
+    }
+    *exchange.response.body_mut() = body;
+    Ok(())
+}
+
+/// Fingerprints the exchanges. `file_paths` maps the index of an exchange to
+/// the path of the file from which the exchange is created.
+///
+/// Assets are renamed first. Stylesheets and scripts are rewritten before
+/// they are renamed, so that they can refer to renamed assets. A script is
+/// renamed after the scripts which it imports, so that its hash changes when
+/// theirs do. Scripts in an import cycle, and scripts which import them, are
+/// rewritten, but not renamed, as the hashes in a cycle would depend on each
+/// other. HTML documents are rewritten, but never renamed. References between stylesheets, e.g. `@import`, are not
+/// rewritten.
+pub(crate) fn fingerprint(
+    exchanges: &mut [Exchange],
+    file_paths: &HashMap<usize, PathBuf>,
+) -> crate::Result<()> {
+    // Maps a file path to its renamed file path.
+    let mut renamed: HashMap<PathBuf, PathBuf> = HashMap::new();
+
+    for kind_to_rename in [Kind::Asset, Kind::Css] {
+        for (&index, path) in file_paths {
+            let exchange = &mut exchanges[index];
+            if exchange.response.status() != StatusCode::OK || kind(exchange) != kind_to_rename {
+                continue;
+            }
+            if kind_to_rename == Kind::Css {
+                rewrite_references(exchange, path, &renamed)?;
+            }
+            rename(exchange, path, &mut renamed);
+        }
+    }
+
+    let mut scripts: Vec<(usize, &PathBuf)> = file_paths
+        .iter()
+        .map(|(&index, path)| (index, path))
+        .filter(|&(index, _)| {
+            let exchange = &exchanges[index];
+            exchange.response.status() == StatusCode::OK && kind(exchange) == Kind::Script
+        })
+        .collect();
+    loop {
+        let (ready, pending): (Vec<_>, Vec<_>) = scripts.iter().partition(|&&(index, path)| {
+            !scripts.iter().any(|&(other_index, other)| {
+                other_index != index && refers_to(exchanges[index].response.body(), path, other)
+            })
+        });
+        if ready.is_empty() {
+            break;
+        }
+        for (index, path) in ready {
+            let exchange = &mut exchanges[index];
+            rewrite_references(exchange, path, &renamed)?;
+            rename(exchange, path, &mut renamed);
+        }
+        scripts = pending;
+    }
+    for (index, path) in scripts {
+        rewrite_references(&mut exchanges[index], path, &renamed)?;
+    }
+
+    for (&index, path) in file_paths {
+        let exchange = &mut exchanges[index];
+        if kind(exchange) == Kind::Html {
+            rewrite_references(exchange, path, &renamed)?;
+        }
+    }
+    Ok(())
+}
+
+fn rename(exchange: &mut Exchange, path: &Path, renamed: &mut HashMap<PathBuf, PathBuf>) {
+    let hash = content_hash(exchange.response.body());
+    let url = insert_hash(exchange.request.url(), &hash);
+    exchange.request = Request::new(url, exchange.request.headers().clone());
+    let renamed_path = PathBuf::from(insert_hash(&path.to_string_lossy(), &hash));
+    renamed.insert(path.to_path_buf(), renamed_path);
+}
+
+fn rewrite_references(
+    exchange: &mut Exchange,
+    path: &Path,
+    renamed: &HashMap<PathBuf, PathBuf>,
+) -> crate::Result<()> {
+    if is_encoded(exchange) {
+        return Ok(());
+    }
+    if let Some(body) = rewrite(exchange.response.body(), &references_from(path, renamed)) {
+        set_body(exchange, body)?;
+    }
+    Ok(())
+}
+
+/// Returns the pairs of the relative and the root-absolute references from
+/// the file at `path` to the renamed files.
+fn references_from(path: &Path, renamed: &HashMap<PathBuf, PathBuf>) -> Vec<(String, String)> {
+    renamed
+        .iter()
+        .flat_map(|(from, to)| {
+            [
+                reference(path, from).zip(reference(path, to)),
+                root_reference(from).zip(root_reference(to)),
+            ]
+        })
+        .flatten()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_hash_test() {
+        assert_eq!(insert_hash("app.js", "1234"), "app.1234.js");
+        assert_eq!(insert_hash("js/app.min.js", "1234"), "js/app.min.1234.js");
+        assert_eq!(insert_hash("a.b/LICENSE", "1234"), "a.b/LICENSE.1234");
+        assert_eq!(insert_hash(".htaccess", "1234"), ".htaccess.1234");
+    }
+
+    #[test]
+    fn fingerprint_test() -> crate::Result<()> {
+        let mut exchanges = vec![
+            Exchange::from((
+                "index.html".to_string(),
+                br#"<link href="css/style.css"><script src="./js/app.js"></script>"#.to_vec(),
+            )),
+            Exchange::from((
+                "css/style.css".to_string(),
+                b"body { background: url(../img/a.png); }".to_vec(),
+            )),
+            Exchange::from(("js/app.js".to_string(), b"app".to_vec())),
+            Exchange::from(("img/a.png".to_string(), b"png".to_vec())),
+        ];
+        let file_paths = exchanges
+            .iter()
+            .enumerate()
+            .map(|(index, exchange)| (index, PathBuf::from(exchange.request.url())))
+            .collect();
+        fingerprint(&mut exchanges, &file_paths)?;
+
+        let png_url = format!("img/a.{}.png", content_hash(b"png"));
+        let app_url = format!("js/app.{}.js", content_hash(b"app"));
+        let css_body = format!("body {{ background: url(../{png_url}); }}");
+        let css_url = format!("css/style.{}.css", content_hash(css_body.as_bytes()));
+
+        assert_eq!(exchanges[0].request.url(), "index.html");
+        assert_eq!(
+            String::from_utf8_lossy(exchanges[0].response.body()),
+            format!(r#"<link href="{css_url}"><script src="./{app_url}"></script>"#)
+        );
+        assert_eq!(exchanges[1].request.url(), &css_url);
+        assert_eq!(
+            String::from_utf8_lossy(exchanges[1].response.body()),
+            css_body
+        );
+        assert_eq!(
+            exchanges[1].response.headers()["content-length"],
+            css_body.len().to_string()
+        );
+        assert_eq!(exchanges[2].request.url(), &app_url);
+        assert_eq!(exchanges[3].request.url(), &png_url);
+        Ok(())
+    }
+
+    #[test]
+    fn fingerprint_modules() -> crate::Result<()> {
+        let mut exchanges = vec![
+            Exchange::from((
+                "index.html".to_string(),
+                br#"<script type="module" src="/js/main.js"></script>"#.to_vec(),
+            )),
+            Exchange::from((
+                "js/main.js".to_string(),
+                br#"import { f } from "./lib.js"; export * from '../util.js';"#.to_vec(),
+            )),
+            Exchange::from((
+                "js/lib.js".to_string(),
+                br#"export { g as f } from "/util.js";"#.to_vec(),
+            )),
+            Exchange::from(("util.js".to_string(), b"export const g = 1;".to_vec())),
+            // An import cycle.
+            Exchange::from(("a.js".to_string(), br#"import "./b.js";"#.to_vec())),
+            Exchange::from(("b.js".to_string(), br#"import "./a.js";"#.to_vec())),
+        ];
+        let file_paths = exchanges
+            .iter()
+            .enumerate()
+            .map(|(index, exchange)| (index, PathBuf::from(exchange.request.url())))
+            .collect();
+        fingerprint(&mut exchanges, &file_paths)?;
+
+        let util_url = format!("util.{}.js", content_hash(b"export const g = 1;"));
+        let lib_body = format!(r#"export {{ g as f }} from "/{util_url}";"#);
+        let lib_url = format!("js/lib.{}.js", content_hash(lib_body.as_bytes()));
+        let main_body = format!(
+            r#"import {{ f }} from "./{}"; export * from '../{util_url}';"#,
+            &lib_url["js/".len()..]
+        );
+        let main_url = format!("js/main.{}.js", content_hash(main_body.as_bytes()));
+
+        assert_eq!(
+            String::from_utf8_lossy(exchanges[0].response.body()),
+            format!(r#"<script type="module" src="/{main_url}"></script>"#)
+        );
+        assert_eq!(exchanges[1].request.url(), &main_url);
+        assert_eq!(
+            String::from_utf8_lossy(exchanges[1].response.body()),
+            main_body
+        );
+        assert_eq!(exchanges[2].request.url(), &lib_url);
+        assert_eq!(
+            String::from_utf8_lossy(exchanges[2].response.body()),
+            lib_body
+        );
+        assert_eq!(exchanges[3].request.url(), &util_url);
+        assert_eq!(exchanges[4].request.url(), "a.js");
+        assert_eq!(exchanges[5].request.url(), "b.js");
+        Ok(())
+    }
+}
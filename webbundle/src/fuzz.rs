@@ -0,0 +1,172 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `Arbitrary` implementations for property testing and fuzzing.
+//!
+//! Generated values are always valid, i.e. a generated bundle can be encoded,
+//! and decoding the result gives the same bundle. `Response` is an alias of
+//! `http::Response`, so it doesn't implement `Arbitrary` by itself; use
+//! `Exchange` to generate responses.
+
+use crate::bundle::{Bundle, Exchange, HeaderMap, IndexOrder, Request, Response, Version};
+use arbitrary::{Arbitrary, Result, Unstructured};
+use http::header::{HeaderName, HeaderValue};
+use http::StatusCode;
+use std::collections::HashSet;
+
+const URL_CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789-_.";
+const HEADER_NAME_CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyz-";
+
+fn string_from(
+    u: &mut Unstructured,
+    chars: &[u8],
+    min_len: usize,
+    max_len: usize,
+) -> Result<String> {
+    let len = u.int_in_range(min_len..=max_len)?;
+    (0..len).map(|_| Ok(*u.choose(chars)? as char)).collect()
+}
+
+fn url(u: &mut Unstructured) -> Result<String> {
+    let segments = u.int_in_range(0..=3)?;
+    let mut url = "https://example.com/".to_string();
+    for i in 0..segments {
+        if i > 0 {
+            url.push('/');
+        }
+        url.push_str(&string_from(u, URL_CHARS, 1, 12)?);
+    }
+    Ok(url)
+}
+
+fn headers(u: &mut Unstructured) -> Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    for _ in 0..u.int_in_range(0..=4)? {
+        let name = string_from(u, HEADER_NAME_CHARS, 1, 16)?;
+        // Names must not start or end with `-`, e.g. `-a`.
+        let name = format!("x{name}x");
+        // Visible ASCII without leading or trailing spaces.
+        let value = (0..u.int_in_range(1..=32)?)
+            .map(|_| Ok(u.int_in_range(0x21..=0x7e_u8)? as char))
+            .collect::<Result<String>>()?;
+        headers.insert(
+            HeaderName::from_bytes(name.as_bytes()).expect("valid header name"),
+            HeaderValue::from_str(&value).expect("valid header value"),
+        );
+    }
+    Ok(headers)
+}
+
+fn response(u: &mut Unstructured) -> Result<Response> {
+    let mut response = Response::new(Vec::<u8>::arbitrary(u)?);
+    *response.status_mut() =
+        StatusCode::from_u16(u.int_in_range(100..=599)?).expect("valid status code");
+    *response.headers_mut() = headers(u)?;
+    Ok(response)
+}
+
+impl<'a> Arbitrary<'a> for Version {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(if bool::arbitrary(u)? {
+            Version::VersionB2
+        } else {
+            Version::Version1
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for Request {
+    /// Generates a request for an absolute url without headers, which is
+    /// the only kind of request a bundle can encode.
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(url(u)?.into())
+    }
+}
+
+impl<'a> Arbitrary<'a> for Exchange {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Exchange {
+            request: Request::arbitrary(u)?,
+            response: response(u)?,
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for Bundle {
+    /// Generates a bundle whose exchanges have unique urls.
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let version = Version::arbitrary(u)?;
+        let mut urls = HashSet::new();
+        let mut exchanges = Vec::new();
+        for _ in 0..u.int_in_range(0..=16)? {
+            let exchange = Exchange::arbitrary(u)?;
+            if urls.insert(exchange.request.url().clone()) {
+                exchanges.push(exchange);
+            }
+        }
+        let primary_url = if bool::arbitrary(u)? {
+            Some(url(u)?.parse().expect("valid url"))
+        } else {
+            None
+        };
+        Ok(Bundle {
+            version,
+            primary_url,
+            exchanges,
+            index_order: IndexOrder::Canonical,
+            ignored_sections: Vec::new(),
+            signatures: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_and_decode_arbitrary_bundles() -> crate::Result<()> {
+        // A simple xorshift keeps the test deterministic.
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        for _ in 0..100 {
+            let data = (0..4096)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    state as u8
+                })
+                .collect::<Vec<u8>>();
+            let bundle = Bundle::arbitrary(&mut Unstructured::new(&data))?;
+            let bytes = bundle.encode()?;
+            let decoded = Bundle::from_bytes(&bytes)?;
+            assert_eq!(decoded.version(), bundle.version());
+            assert_eq!(decoded.primary_url(), bundle.primary_url());
+            assert_eq!(decoded.exchanges().len(), bundle.exchanges().len());
+            // The decoder may reorder exchanges.
+            for exchange in bundle.exchanges() {
+                let url = exchange.request.url();
+                let decoded = decoded
+                    .exchanges()
+                    .iter()
+                    .find(|e| e.request.url() == url)
+                    .unwrap();
+                assert_eq!(decoded.response.status(), exchange.response.status());
+                assert_eq!(decoded.response.headers(), exchange.response.headers());
+                assert_eq!(decoded.response.body(), exchange.response.body());
+            }
+        }
+        Ok(())
+    }
+}
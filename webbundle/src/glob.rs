@@ -0,0 +1,165 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    Char(char),
+    /// `?`
+    Any,
+    /// `*`
+    Star,
+    /// `**`
+    Globstar,
+    /// `**/`
+    GlobstarDir,
+}
+
+fn tokenize(glob: &str) -> Vec<Token> {
+    let chars = glob.chars().collect::<Vec<_>>();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let token = match &chars[i..] {
+            ['*', '*', '/', ..] => Token::GlobstarDir,
+            ['*', '*', ..] => Token::Globstar,
+            ['*', ..] => Token::Star,
+            ['?', ..] => Token::Any,
+            [c, ..] => Token::Char(*c),
+            [] => unreachable!(),
+        };
+        i += match token {
+            Token::GlobstarDir => 3,
+            Token::Globstar => 2,
+            _ => 1,
+        };
+        tokens.push(token);
+    }
+    tokens
+}
+
+/// Returns whether the path, e.g. `js/a.js` or a url, matches the glob.
+///
+/// - `*` matches any characters except `/`, and `?` matches any character
+///   except `/`.
+/// - `**` matches any characters, including `/`. `**/` also matches no
+///   directories, e.g. `js/**/*.js` matches `js/a.js`, and a trailing `/**`
+///   also matches the directory itself, e.g. `dir/**` matches `dir`.
+/// - A glob without `/` matches any segment of the path, e.g. `*.js`
+///   matches `https://example.com/a/b.js`, and `node_modules` matches
+///   `node_modules/a.js`.
+///
+/// This takes time proportional to the product of the lengths of the glob
+/// and the path, never backtracking exponentially.
+///
+/// # Examples
+///
+/// ```
+/// use webbundle::glob_match;
+/// assert!(glob_match("*.js", "https://example.com/a/b.js"));
+/// assert!(glob_match("https://example.com/**", "https://example.com/a/b.js"));
+/// assert!(!glob_match("https://example.com/*", "https://example.com/a/b.js"));
+/// ```
+pub fn glob_match(glob: &str, path: &str) -> bool {
+    if !glob.contains('/') {
+        return path.split('/').any(|segment| matches(glob, segment));
+    }
+    match glob.strip_suffix("/**") {
+        Some(dir) if matches(dir, path) => true,
+        _ => matches(glob, path),
+    }
+}
+
+/// Matches the whole path by simulating the glob as an automaton, whose
+/// states are the positions in the tokens.
+fn matches(glob: &str, path: &str) -> bool {
+    let tokens = tokenize(glob);
+    let mut states = vec![false; tokens.len() + 1];
+    states[0] = true;
+    close(&tokens, &mut states);
+    for c in path.chars() {
+        let mut next = vec![false; tokens.len() + 1];
+        for (i, token) in tokens.iter().enumerate() {
+            if !states[i] {
+                continue;
+            }
+            match *token {
+                Token::Char(expected) if expected == c => next[i + 1] = true,
+                Token::Any if c != '/' => next[i + 1] = true,
+                Token::Star if c != '/' => next[i] = true,
+                Token::Globstar => next[i] = true,
+                Token::GlobstarDir => {
+                    next[i] = true;
+                    if c == '/' {
+                        next[i + 1] = true;
+                    }
+                }
+                _ => {}
+            }
+        }
+        close(&tokens, &mut next);
+        if !next.contains(&true) {
+            return false;
+        }
+        states = next;
+    }
+    states[tokens.len()]
+}
+
+/// Adds the states which are reachable without consuming a character, i.e.
+/// the states after wildcards which match nothing.
+fn close(tokens: &[Token], states: &mut [bool]) {
+    for (i, token) in tokens.iter().enumerate() {
+        if states[i] && matches!(token, Token::Star | Token::Globstar | Token::GlobstarDir) {
+            states[i + 1] = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_match_test() {
+        assert!(glob_match("*.js", "https://example.com/a/b.js"));
+        assert!(!glob_match("*.js", "https://example.com/a.json"));
+        assert!(glob_match("*", "https://example.com/"));
+        assert!(glob_match("https://example.com/*", "https://example.com/"));
+        assert!(glob_match("**/a?c/*", "https://example.com/abc/d"));
+        assert!(!glob_match("*/a?c", "https://example.com/ac"));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+        assert!(glob_match("", ""));
+        assert!(!glob_match("", "a"));
+        assert!(glob_match("?", "é"));
+
+        assert!(glob_match("*.map", "js/a.js.map"));
+        assert!(glob_match("node_modules", "a/node_modules/b.js"));
+        assert!(glob_match("node_modules/**", "node_modules"));
+        assert!(glob_match("node_modules/**", "node_modules/a/b.js"));
+        assert!(!glob_match("node_modules/**", "a/node_modules/b.js"));
+        assert!(glob_match("**/node_modules/**", "a/node_modules/b.js"));
+        assert!(glob_match("js/*.js", "js/a.js"));
+        assert!(!glob_match("js/*.js", "js/a/b.js"));
+        assert!(glob_match("js/**/*.js", "js/a/b.js"));
+        assert!(glob_match("js/**/*.js", "js/a.js"));
+        assert!(glob_match("js/**.js", "js/a/b.js"));
+    }
+
+    #[test]
+    fn glob_match_is_not_exponential() {
+        let path = "a".repeat(10_000);
+        assert!(!glob_match(&format!("{}b", "*a".repeat(100)), &path));
+        assert!(!glob_match(&format!("{}/b", "**a".repeat(100)), &path));
+    }
+}
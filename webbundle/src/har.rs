@@ -0,0 +1,216 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::bundle::{Exchange, Response};
+use crate::prelude::*;
+use base64::Engine as _;
+use headers::{ContentLength, HeaderMapExt as _};
+use http::header::{HeaderName, HeaderValue, CONTENT_ENCODING, CONTENT_LENGTH};
+use http::StatusCode;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::path::Path;
+
+// HAR 1.2: http://www.softwareishard.com/blog/har-12-spec/
+// Only the fields which are used here are declared.
+
+#[derive(Deserialize)]
+struct Har {
+    log: Log,
+}
+
+#[derive(Deserialize)]
+struct Log {
+    entries: Vec<Entry>,
+}
+
+#[derive(Deserialize)]
+struct Entry {
+    request: HarRequest,
+    response: HarResponse,
+}
+
+#[derive(Deserialize)]
+struct HarRequest {
+    method: String,
+    url: String,
+}
+
+#[derive(Deserialize)]
+struct HarResponse {
+    status: u16,
+    headers: Vec<Header>,
+    content: Content,
+}
+
+#[derive(Deserialize)]
+struct Header {
+    name: String,
+    value: String,
+}
+
+#[derive(Deserialize)]
+struct Content {
+    text: Option<String>,
+    encoding: Option<String>,
+}
+
+impl crate::builder::Builder {
+    /// Append exchanges from the given HAR (HTTP Archive) bytes.
+    ///
+    /// One exchange is created for each `GET` entry. If several entries have
+    /// the same URL, only the first one is used.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use webbundle::{Bundle, Version};
+    /// let bundle = Bundle::builder()
+    ///     .version(Version::VersionB2)
+    ///     .exchanges_from_har(std::fs::read("example.har")?)?
+    ///     .build()?;
+    /// # std::result::Result::Ok::<_, anyhow::Error>(())
+    /// ```
+    pub fn exchanges_from_har(mut self, har: impl AsRef<[u8]>) -> Result<Self> {
+        let har: Har = serde_json::from_slice(har.as_ref()).context("Invalid HAR")?;
+        let mut seen_urls = HashSet::new();
+        for entry in har.log.entries {
+            if entry.request.method != "GET" {
+                log::warn!(
+                    "Skipping {} request: {}",
+                    entry.request.method,
+                    entry.request.url
+                );
+                continue;
+            }
+            if !seen_urls.insert(entry.request.url.clone()) {
+                log::warn!("Skipping duplicated url: {}", entry.request.url);
+                continue;
+            }
+            let response = entry.response.into_response()?;
+            self.exchanges.push(Exchange {
+                request: entry.request.url.into(),
+                response,
+            });
+        }
+        Ok(self)
+    }
+
+    /// Same as `exchanges_from_har`, but reads HAR from the given file.
+    pub fn exchanges_from_har_file(self, path: impl AsRef<Path>) -> Result<Self> {
+        let har = std::fs::read(path.as_ref())
+            .with_context(|| format!("Failed to read {}", path.as_ref().display()))?;
+        self.exchanges_from_har(har)
+    }
+}
+
+impl HarResponse {
+    fn into_response(self) -> Result<Response> {
+        let body = match (self.content.text, self.content.encoding.as_deref()) {
+            (None, _) => Vec::new(),
+            (Some(text), Some("base64")) => base64::engine::general_purpose::STANDARD
+                .decode(text)
+                .context("Invalid base64 content")?,
+            (Some(text), _) => text.into_bytes(),
+        };
+        let content_length = ContentLength(body.len() as u64);
+        let mut response = Response::new(body);
+        *response.status_mut() = StatusCode::from_u16(self.status)?;
+        for Header { name, value } in self.headers {
+            // Skip HTTP/2 pseudo headers.
+            if name.starts_with(':') {
+                continue;
+            }
+            let (name, value) = match (
+                HeaderName::from_bytes(name.as_bytes()),
+                HeaderValue::from_str(&value),
+            ) {
+                (Ok(name), Ok(value)) => (name, value),
+                _ => {
+                    log::warn!("Skipping invalid header: {}", name);
+                    continue;
+                }
+            };
+            // HAR content is already decoded.
+            if name == CONTENT_ENCODING || name == CONTENT_LENGTH {
+                continue;
+            }
+            response.headers_mut().append(name, value);
+        }
+        response.headers_mut().typed_insert(content_length);
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bundle::{Bundle, Version};
+    use crate::prelude::*;
+
+    #[test]
+    fn exchanges_from_har() -> Result<()> {
+        let har = r#"{
+  "log": {
+    "version": "1.2",
+    "entries": [
+      {
+        "request": { "method": "GET", "url": "https://example.com/" },
+        "response": {
+          "status": 200,
+          "headers": [
+            { "name": ":status", "value": "200" },
+            { "name": "Content-Type", "value": "text/html" },
+            { "name": "Content-Encoding", "value": "gzip" }
+          ],
+          "content": { "size": 5, "mimeType": "text/html", "text": "Hello" }
+        }
+      },
+      {
+        "request": { "method": "GET", "url": "https://example.com/a.png" },
+        "response": {
+          "status": 200,
+          "headers": [],
+          "content": { "size": 3, "text": "AQID", "encoding": "base64" }
+        }
+      },
+      {
+        "request": { "method": "POST", "url": "https://example.com/api" },
+        "response": { "status": 204, "headers": [], "content": { "size": 0 } }
+      },
+      {
+        "request": { "method": "GET", "url": "https://example.com/" },
+        "response": { "status": 304, "headers": [], "content": { "size": 0 } }
+      }
+    ]
+  }
+}"#;
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchanges_from_har(har)?
+            .build()?;
+        assert_eq!(bundle.exchanges().len(), 2);
+
+        let top = &bundle.exchanges()[0];
+        assert_eq!(top.request.url(), "https://example.com/");
+        assert_eq!(top.response.status(), 200);
+        assert_eq!(top.response.body(), b"Hello");
+        assert_eq!(top.response.headers()["content-type"], "text/html");
+        assert_eq!(top.response.headers()["content-length"], "5");
+        assert!(!top.response.headers().contains_key("content-encoding"));
+
+        let png = &bundle.exchanges()[1];
+        assert_eq!(png.response.body(), b"\x01\x02\x03");
+        Ok(())
+    }
+}
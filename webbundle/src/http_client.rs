@@ -0,0 +1,131 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::bundle::{Exchange, Response};
+use crate::prelude::*;
+use headers::{ContentLength, HeaderMapExt as _};
+use http::header::{CONNECTION, TRANSFER_ENCODING};
+
+impl crate::builder::Builder {
+    /// Fetches the given url with `GET` method, and appends the response as
+    /// an exchange.
+    ///
+    /// Redirects are followed. The exchange is stored for the given url,
+    /// with the final response.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # async {
+    /// use webbundle::{Bundle, Version};
+    /// let bundle = Bundle::builder()
+    ///     .version(Version::VersionB2)
+    ///     .primary_url("https://example.com/".parse()?)
+    ///     .exchange_from_url("https://example.com/").await?
+    ///     .build()?;
+    /// # std::result::Result::Ok::<_, anyhow::Error>(bundle)
+    /// # };
+    /// ```
+    pub async fn exchange_from_url(mut self, url: &str) -> Result<Self> {
+        let exchange = fetch(&reqwest::Client::new(), url).await?;
+        self.exchanges.push(exchange);
+        Ok(self)
+    }
+}
+
+/// Fetches the given url and returns the response as an exchange.
+pub(crate) async fn fetch(client: &reqwest::Client, url: &str) -> Result<Exchange> {
+    log::debug!("fetch: {}", url);
+    let res = client
+        .get(url)
+        .send()
+        .await
+        .with_context(|| format!("Failed to fetch {url}"))?;
+    let status = res.status();
+    let mut headers = res.headers().clone();
+    let body = res.bytes().await?.to_vec();
+
+    // These describe the connection, not the resource.
+    headers.remove(CONNECTION);
+    headers.remove(TRANSFER_ENCODING);
+    headers.typed_insert(ContentLength(body.len() as u64));
+
+    let mut response = Response::new(body);
+    *response.status_mut() = status;
+    *response.headers_mut() = headers;
+    Ok(Exchange {
+        request: url.into(),
+        response,
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::bundle::{Bundle, Version};
+    use crate::prelude::*;
+    use std::io::{Read as _, Write as _};
+
+    /// Starts a HTTP server which serves the given responses, keyed by path,
+    /// and returns its origin, e.g. `http://127.0.0.1:1234`.
+    pub(crate) fn serve(responses: Vec<(&'static str, &'static str, &'static str)>) -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let origin = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut buf = [0; 4096];
+                let n = stream.read(&mut buf).unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]);
+                let path = request.split(' ').nth(1).unwrap_or("/").to_string();
+                let (status, content_type, body) = responses
+                    .iter()
+                    .find(|(p, _, _)| *p == path)
+                    .map(|(_, content_type, body)| ("200 OK", *content_type, *body))
+                    .unwrap_or(("404 Not Found", "text/plain", ""));
+                write!(
+                    stream,
+                    "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                )
+                .unwrap();
+            }
+        });
+        origin
+    }
+
+    #[tokio::test]
+    async fn exchange_from_url() -> Result<()> {
+        let origin = serve(vec![("/", "text/html", "Hello")]);
+        let url = format!("{origin}/");
+
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchange_from_url(&url)
+            .await?
+            .exchange_from_url(&format!("{origin}/missing"))
+            .await?
+            .build()?;
+        assert_eq!(bundle.exchanges().len(), 2);
+
+        let exchange = &bundle.exchanges()[0];
+        assert_eq!(exchange.request.url(), &url);
+        assert_eq!(exchange.response.status(), 200);
+        assert_eq!(exchange.response.body(), b"Hello");
+        assert_eq!(exchange.response.headers()["content-type"], "text/html");
+        assert!(!exchange.response.headers().contains_key("connection"));
+
+        assert_eq!(bundle.exchanges()[1].response.status(), 404);
+        Ok(())
+    }
+}
@@ -42,19 +42,101 @@
 //!     .version(Version::VersionB2)
 //!     .build()?;
 //! println!("Created bundle: {:#?}", bundle);
-//! let write = std::io::BufWriter::new(std::fs::File::create("example.wbn")?);
-//! bundle.write_to(write)?;
+//! bundle.write_to_file("example.wbn")?;
 //! # Result::Ok::<(), anyhow::Error>(())
 //! # };
 //! ```
+//!
+//! # Features
+//!
+//! Without any feature, this library only parses and creates bundles in
+//! memory, and compiles for `wasm32-unknown-unknown`, e.g. to run in web apps
+//! or Cloudflare Workers. The following features add more:
+//!
+//! - `fs`: Create bundles from files, using `tokio`.
+//! - `archive`: Convert bundles from and to zip or tar archives.
+//! - `har`, `mhtml`, `warc`: Import exchanges from these formats.
+//! - `http-client`: Fetch exchanges and remote bundles over HTTP.
+//! - `serde`, `arbitrary`: Implement these traits for bundles.
+//! - `signing`: Sign and verify bundles with Ed25519 keys, e.g. for Isolated
+//!   Web Apps.
+//! - `http-body`, `tower`, `axum`: Serve bundles with these crates.
+//! - `conformance`: Run the conformance tests against the spec, i.e.
+//!   `cargo test --features conformance --test conformance`.
+//!
+//! `fs`, `archive` and `http-client` don't support `wasm32-unknown-unknown`.
+//!
+//! [`testing`] generates bundles for benchmarks and fuzzers, e.g.
+//! `webbundle::testing::synthetic_bundle(depth, width, body_size)`.
 mod builder;
 mod bundle;
+mod canonical_url;
 mod decoder;
 mod encoder;
+#[cfg(feature = "archive")]
+mod export;
+mod fetch_plan;
+mod glob;
 mod prelude;
+mod resolve;
+mod split;
+mod stats;
+pub mod testing;
+mod validate;
 pub use builder::Builder;
-pub use bundle::{Body, Bundle, Exchange, Request, Response, Uri, Version};
+pub use bundle::{Body, Bundle, Exchange, IndexOrder, Method, Request, Response, Uri, Version};
+pub use canonical_url::canonicalize_url;
+pub use decoder::DecodeOptions;
+pub use fetch_plan::{FetchPlan, Index, IndexEntry};
+pub use glob::glob_match;
 pub use prelude::Result;
+pub use stats::{ContentTypeStats, Stats};
+pub use validate::{validate, Finding, Severity};
+
+#[cfg(feature = "archive")]
+pub use export::MANIFEST_PATH;
 
+#[cfg(feature = "axum")]
+mod axum_support;
+#[cfg(feature = "http-client")]
+mod crawler;
+#[cfg(feature = "http-body")]
+mod encoded_body;
 #[cfg(feature = "fs")]
 mod fs;
+#[cfg(feature = "arbitrary")]
+mod fuzz;
+#[cfg(feature = "har")]
+mod har;
+#[cfg(feature = "http-client")]
+mod http_client;
+#[cfg(feature = "mhtml")]
+mod mhtml;
+#[cfg(feature = "http-client")]
+mod remote;
+#[cfg(feature = "serde")]
+mod serialize;
+#[cfg(feature = "tower")]
+mod service;
+#[cfg(feature = "signing")]
+mod signing;
+#[cfg(feature = "http-client")]
+mod sitemap;
+#[cfg(feature = "warc")]
+mod warc;
+#[cfg(feature = "axum")]
+pub use axum_support::WbnFile;
+#[cfg(feature = "http-body")]
+pub use encoded_body::EncodedBundleBody;
+#[cfg(feature = "fs")]
+pub use fs::builder::{BuildCache, CachePolicy};
+#[cfg(feature = "compress")]
+pub use fs::compress::{decoded_body, Compression, DEFAULT_COMPRESS_SKIP};
+#[cfg(feature = "http-client")]
+pub use remote::RemoteBundle;
+#[cfg(feature = "tower")]
+pub use service::BundleService;
+#[cfg(feature = "signing")]
+pub use signing::{
+    public_key_from_pem, sign, verify, web_bundle_id, IntegrityBlock, Signature, SigningKey,
+};
@@ -0,0 +1,321 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! MHTML (RFC 2557) conversion.
+
+use crate::bundle::{Bundle, Exchange};
+use crate::prelude::*;
+use base64::Engine as _;
+use headers::{ContentType, HeaderMapExt as _};
+use std::io::Write;
+
+impl crate::builder::Builder {
+    /// Append exchanges from the given MHTML archive.
+    ///
+    /// One exchange is created for each part which has `Content-Location`
+    /// header. The first part, which is the main document, becomes the first
+    /// exchange.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use webbundle::{Bundle, Version};
+    /// let bundle = Bundle::builder()
+    ///     .version(Version::VersionB2)
+    ///     .exchanges_from_mhtml(std::fs::read("page.mhtml")?)?
+    ///     .build()?;
+    /// # std::result::Result::Ok::<_, anyhow::Error>(())
+    /// ```
+    pub fn exchanges_from_mhtml(mut self, mhtml: impl AsRef<[u8]>) -> Result<Self> {
+        let mhtml = mhtml.as_ref();
+        let (headers, body) = split_headers(mhtml);
+        let content_type = header_value(&headers, "content-type").context("No Content-Type")?;
+        let boundary = parameter(&content_type, "boundary").context("No boundary")?;
+        let delimiter = format!("--{boundary}");
+
+        for part in split_parts(body, delimiter.as_bytes()) {
+            let (headers, body) = split_headers(part);
+            let location = match header_value(&headers, "content-location") {
+                Some(location) => location,
+                None => {
+                    log::warn!("Skipping a part without Content-Location");
+                    continue;
+                }
+            };
+            let body = match header_value(&headers, "content-transfer-encoding")
+                .map(|encoding| encoding.to_ascii_lowercase())
+                .as_deref()
+            {
+                Some("base64") => {
+                    let encoded = body
+                        .iter()
+                        .filter(|b| !b.is_ascii_whitespace())
+                        .copied()
+                        .collect::<Vec<_>>();
+                    base64::engine::general_purpose::STANDARD
+                        .decode(encoded)
+                        .context("Invalid base64 part")?
+                }
+                Some("quoted-printable") => decode_quoted_printable(body),
+                _ => body.to_vec(),
+            };
+            let exchange = match header_value(&headers, "content-type") {
+                Some(content_type) => (location, body, content_type.parse::<ContentType>()?).into(),
+                None => (location, body).into(),
+            };
+            self.exchanges.push(exchange);
+        }
+        Ok(self)
+    }
+}
+
+impl Bundle {
+    /// Writes the successful responses of this bundle as an MHTML archive.
+    ///
+    /// The exchange for the primary url, if any, becomes the first part.
+    /// Bodies are encoded with base64.
+    pub fn write_mhtml<W: Write>(&self, mut write: W) -> Result<()> {
+        let mut exchanges = self
+            .exchanges
+            .iter()
+            .filter(|exchange| exchange.response.status().is_success())
+            .collect::<Vec<&Exchange>>();
+        if let Some(primary_url) = &self.primary_url {
+            let primary_url = primary_url.to_string();
+            // A stable sort keeps the order of the others.
+            exchanges.sort_by_key(|exchange| *exchange.request.url() != primary_url);
+        }
+
+        let boundary = "----MultipartBoundary--webbundle----";
+        let main_type = exchanges
+            .first()
+            .and_then(|exchange| exchange.response.headers().typed_get::<ContentType>())
+            .map(|content_type| {
+                mime_guess::Mime::from(content_type)
+                    .essence_str()
+                    .to_string()
+            })
+            .unwrap_or_else(|| "text/html".to_string());
+        write!(
+            write,
+            "From: <Saved by webbundle>\r\nMIME-Version: 1.0\r\nContent-Type: multipart/related;\r\n\ttype=\"{main_type}\";\r\n\tboundary=\"{boundary}\"\r\n\r\n"
+        )?;
+        for exchange in exchanges {
+            write!(write, "--{boundary}\r\n")?;
+            if let Some(content_type) = exchange.response.headers().get(http::header::CONTENT_TYPE)
+            {
+                write!(write, "Content-Type: {}\r\n", content_type.to_str()?)?;
+            }
+            write!(
+                write,
+                "Content-Transfer-Encoding: base64\r\nContent-Location: {}\r\n\r\n",
+                exchange.request.url()
+            )?;
+            let encoded =
+                base64::engine::general_purpose::STANDARD.encode(exchange.response.body());
+            for line in encoded.as_bytes().chunks(76) {
+                write.write_all(line)?;
+                write.write_all(b"\r\n")?;
+            }
+            write.write_all(b"\r\n")?;
+        }
+        write!(write, "--{boundary}--\r\n")?;
+        Ok(())
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Splits a MIME entity into its unfolded header lines and its body.
+fn split_headers(entity: &[u8]) -> (Vec<String>, &[u8]) {
+    let (head, body) = match (find(entity, b"\r\n\r\n"), find(entity, b"\n\n")) {
+        (Some(crlf), Some(lf)) if lf + 1 < crlf => (&entity[..lf], &entity[lf + 2..]),
+        (Some(crlf), _) => (&entity[..crlf], &entity[crlf + 4..]),
+        (None, Some(lf)) => (&entity[..lf], &entity[lf + 2..]),
+        (None, None) => (entity, &entity[entity.len()..]),
+    };
+    let mut headers: Vec<String> = Vec::new();
+    for line in String::from_utf8_lossy(head).lines() {
+        match headers.last_mut() {
+            // A folded line continues the previous header.
+            Some(last) if line.starts_with([' ', '\t']) => {
+                last.push(' ');
+                last.push_str(line.trim());
+            }
+            _ => headers.push(line.to_string()),
+        }
+    }
+    (headers, body)
+}
+
+fn header_value(headers: &[String], name: &str) -> Option<String> {
+    headers.iter().find_map(|header| {
+        let (header_name, value) = header.split_once(':')?;
+        header_name
+            .trim()
+            .eq_ignore_ascii_case(name)
+            .then(|| value.trim().to_string())
+    })
+}
+
+/// Gets a parameter of a header value, e.g. `boundary` of `Content-Type`.
+fn parameter(value: &str, name: &str) -> Option<String> {
+    value.split(';').skip(1).find_map(|param| {
+        let (param_name, value) = param.split_once('=')?;
+        param_name
+            .trim()
+            .eq_ignore_ascii_case(name)
+            .then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+/// Splits a multipart body into parts, without the line breaks around delimiters.
+fn split_parts<'a>(body: &'a [u8], delimiter: &[u8]) -> Vec<&'a [u8]> {
+    let mut parts = Vec::new();
+    let mut rest = match find(body, delimiter) {
+        Some(start) => &body[start + delimiter.len()..],
+        None => return parts,
+    };
+    while !rest.starts_with(b"--") {
+        // Skip the rest of the delimiter line.
+        let start = match find(rest, b"\n") {
+            Some(pos) => pos + 1,
+            None => break,
+        };
+        rest = &rest[start..];
+        let end = find(rest, delimiter).unwrap_or(rest.len());
+        let mut part = &rest[..end];
+        part = part.strip_suffix(b"\n").unwrap_or(part);
+        part = part.strip_suffix(b"\r").unwrap_or(part);
+        parts.push(part);
+        if end == rest.len() {
+            break;
+        }
+        rest = &rest[end + delimiter.len()..];
+    }
+    parts
+}
+
+fn decode_quoted_printable(input: &[u8]) -> Vec<u8> {
+    let hex = |b: u8| (b as char).to_digit(16).map(|d| d as u8);
+    let mut output = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        match input[i] {
+            b'=' if input[i + 1..].starts_with(b"\r\n") => i += 3,
+            b'=' if input[i + 1..].starts_with(b"\n") => i += 2,
+            b'=' if i + 2 < input.len() => match (hex(input[i + 1]), hex(input[i + 2])) {
+                (Some(high), Some(low)) => {
+                    output.push(high << 4 | low);
+                    i += 3;
+                }
+                _ => {
+                    output.push(b'=');
+                    i += 1;
+                }
+            },
+            b => {
+                output.push(b);
+                i += 1;
+            }
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundle::Version;
+
+    const MHTML: &str = "From: <Saved by Blink>\r
+Subject: Example\r
+MIME-Version: 1.0\r
+Content-Type: multipart/related;\r
+\ttype=\"text/html\";\r
+\tboundary=\"----MultipartBoundary--abc----\"\r
+\r
+\r
+------MultipartBoundary--abc----\r
+Content-Type: text/html\r
+Content-Transfer-Encoding: quoted-printable\r
+Content-Location: https://example.com/\r
+\r
+<p class=3D\"a\">Hello=\r
+ World</p>\r
+------MultipartBoundary--abc----\r
+Content-Type: image/png\r
+Content-Transfer-Encoding: base64\r
+Content-Location: https://example.com/a.png\r
+\r
+AQID\r
+------MultipartBoundary--abc------\r
+";
+
+    #[test]
+    fn exchanges_from_mhtml() -> Result<()> {
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchanges_from_mhtml(MHTML)?
+            .build()?;
+        assert_eq!(bundle.exchanges().len(), 2);
+
+        let html = &bundle.exchanges()[0];
+        assert_eq!(html.request.url(), "https://example.com/");
+        assert_eq!(html.response.body(), b"<p class=\"a\">Hello World</p>");
+        assert_eq!(html.response.headers()["content-type"], "text/html");
+
+        let png = &bundle.exchanges()[1];
+        assert_eq!(png.request.url(), "https://example.com/a.png");
+        assert_eq!(png.response.body(), b"\x01\x02\x03");
+        Ok(())
+    }
+
+    #[test]
+    fn write_mhtml_and_read_it() -> Result<()> {
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .primary_url("https://example.com/".parse()?)
+            .exchange(Exchange::from((
+                "https://example.com/a.js".to_string(),
+                b"console.log('a');".to_vec(),
+            )))
+            .exchange(Exchange::from((
+                "https://example.com/".to_string(),
+                b"<script src=a.js></script>".to_vec(),
+                ContentType::html(),
+            )))
+            .build()?;
+
+        let mut mhtml = Vec::new();
+        bundle.write_mhtml(&mut mhtml)?;
+
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchanges_from_mhtml(mhtml)?
+            .build()?;
+        assert_eq!(bundle.exchanges().len(), 2);
+        assert_eq!(bundle.exchanges()[0].request.url(), "https://example.com/");
+        assert_eq!(
+            bundle.exchanges()[0].response.body(),
+            b"<script src=a.js></script>"
+        );
+        assert_eq!(bundle.exchanges()[1].response.body(), b"console.log('a');");
+        Ok(())
+    }
+}
@@ -0,0 +1,248 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::bundle::{Exchange, Request};
+use crate::fetch_plan::{FetchPlan, Index};
+use crate::prelude::*;
+use http::header::RANGE;
+use http::StatusCode;
+use std::ops::Range;
+
+/// The number of bytes which are fetched first to read the index.
+const INITIAL_FETCH_LEN: u64 = 64 * 1024;
+/// The maximum number of bytes to read the index.
+const MAX_INDEX_LEN: u64 = 64 * 1024 * 1024;
+
+/// Represents a bundle on a HTTP server, which is fetched incrementally with
+/// `Range` requests.
+///
+/// # Examples
+///
+/// ```no_run
+/// # async {
+/// use webbundle::RemoteBundle;
+/// let bundle = RemoteBundle::open("https://example.com/large.wbn").await?;
+/// if let Some(exchange) = bundle.get("https://example.com/a.js").await? {
+///     println!("{} bytes", exchange.response.body().len());
+/// }
+/// # std::result::Result::Ok::<_, anyhow::Error>(())
+/// # };
+/// ```
+#[derive(Debug)]
+pub struct RemoteBundle {
+    client: reqwest::Client,
+    url: String,
+    index: Index,
+}
+
+impl RemoteBundle {
+    /// Fetches the index of the bundle at the given url.
+    pub async fn open(url: &str) -> Result<RemoteBundle> {
+        Self::open_with_client(reqwest::Client::new(), url).await
+    }
+
+    /// Same as `open`, but uses the given client.
+    pub async fn open_with_client(client: reqwest::Client, url: &str) -> Result<RemoteBundle> {
+        let mut len = INITIAL_FETCH_LEN;
+        loop {
+            let bytes = fetch_range(&client, url, 0..len).await?;
+            match Index::from_bytes(&bytes) {
+                Ok(index) => {
+                    return Ok(RemoteBundle {
+                        client,
+                        url: url.to_string(),
+                        index,
+                    })
+                }
+                // The whole bundle is fetched, or the index is too large.
+                Err(err) if (bytes.len() as u64) < len || len >= MAX_INDEX_LEN => {
+                    return Err(err.context(format!("Failed to read the index of {url}")))
+                }
+                Err(_) => len *= 4,
+            }
+        }
+    }
+
+    /// Gets the index.
+    pub fn index(&self) -> &Index {
+        &self.index
+    }
+
+    /// Fetches the response for the given url. Returns `None` if the bundle
+    /// doesn't have it.
+    pub async fn get(&self, url: &str) -> Result<Option<Exchange>> {
+        let entry = match self.index.get(url) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        let bytes = fetch_range(&self.client, &self.url, entry.range()).await?;
+        Ok(Some(Exchange {
+            request: Request::from(url),
+            response: entry.response_from(bytes)?,
+        }))
+    }
+
+    /// Fetches the responses for the given urls. Adjacent responses are
+    /// fetched with one request. Urls which the bundle doesn't have are
+    /// ignored.
+    pub async fn get_all<'a>(
+        &self,
+        urls: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Vec<Exchange>> {
+        let urls = urls.into_iter().collect::<Vec<_>>();
+        let plan = FetchPlan::new(&self.index, urls.iter().copied());
+        let mut fetched = Vec::new();
+        for range in plan.ranges() {
+            let bytes = fetch_range(&self.client, &self.url, range.clone()).await?;
+            fetched.push((range.start, bytes));
+        }
+
+        let mut exchanges = Vec::new();
+        for url in urls {
+            let entry = match self.index.get(url) {
+                Some(entry) => entry,
+                None => continue,
+            };
+            let (start, bytes) = fetched
+                .iter()
+                .find(|(start, bytes)| {
+                    *start <= entry.offset
+                        && entry.offset + entry.length <= start + bytes.len() as u64
+                })
+                .context("No fetched range for the response")?;
+            let offset = (entry.offset - start) as usize;
+            exchanges.push(Exchange {
+                request: Request::from(url),
+                response: entry.response_from(&bytes[offset..offset + entry.length as usize])?,
+            });
+        }
+        Ok(exchanges)
+    }
+}
+
+/// Fetches the given range. The result can be shorter than the range if the
+/// resource is shorter. An empty range, e.g. of an index entry whose length
+/// is zero, is not fetched, as a range request can't express it.
+async fn fetch_range(client: &reqwest::Client, url: &str, range: Range<u64>) -> Result<Vec<u8>> {
+    log::debug!("fetch_range: {} {:?}", url, range);
+    if range.is_empty() {
+        return Ok(Vec::new());
+    }
+    let res = client
+        .get(url)
+        .header(RANGE, format!("bytes={}-{}", range.start, range.end - 1))
+        .send()
+        .await
+        .with_context(|| format!("Failed to fetch {url}"))?;
+    let status = res.status();
+    let bytes = res.bytes().await?;
+    match status {
+        StatusCode::PARTIAL_CONTENT => Ok(bytes.to_vec()),
+        // The server doesn't support range requests.
+        StatusCode::OK => {
+            let start = (range.start as usize).min(bytes.len());
+            let end = (range.end as usize).min(bytes.len());
+            Ok(bytes[start..end].to_vec())
+        }
+        StatusCode::RANGE_NOT_SATISFIABLE => Ok(Vec::new()),
+        status => bail!("Failed to fetch {}: {}", url, status),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundle::{Bundle, Version};
+    use std::io::{Read as _, Write as _};
+    use std::sync::{Arc, Mutex};
+
+    /// Starts a HTTP server which serves the given bytes with range requests
+    /// support, and returns its url and the requested ranges.
+    fn serve_bytes(bytes: Vec<u8>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/example.wbn", listener.local_addr().unwrap());
+        let ranges = Arc::new(Mutex::new(Vec::new()));
+        let requested = ranges.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut buf = [0; 4096];
+                let n = stream.read(&mut buf).unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]).to_string();
+                let range = request
+                    .lines()
+                    .find_map(|line| line.strip_prefix("range: bytes="))
+                    .unwrap()
+                    .to_string();
+                requested.lock().unwrap().push(range.clone());
+                let (start, end) = range.split_once('-').unwrap();
+                let start = start.parse::<usize>().unwrap();
+                let end = (end.parse::<usize>().unwrap() + 1).min(bytes.len());
+                write!(
+                    stream,
+                    "HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    end - start
+                )
+                .unwrap();
+                stream.write_all(&bytes[start..end]).unwrap();
+            }
+        });
+        (url, ranges)
+    }
+
+    #[tokio::test]
+    async fn remote_bundle() -> Result<()> {
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchange(Exchange::from((
+                "https://example.com/a.html",
+                b"a".to_vec(),
+            )))
+            .exchange(Exchange::from(("https://example.com/b.js", b"b".to_vec())))
+            .exchange(Exchange::from(("https://example.com/c.css", b"c".to_vec())))
+            .build()?;
+        let (url, ranges) = serve_bytes(bundle.encode()?);
+
+        let remote = RemoteBundle::open(&url).await?;
+        assert_eq!(remote.index().entries().len(), 3);
+
+        let exchange = remote.get("https://example.com/b.js").await?.unwrap();
+        assert_eq!(exchange.response.body(), b"b");
+        assert!(remote.get("https://example.com/missing").await?.is_none());
+
+        let exchanges = remote
+            .get_all(["https://example.com/c.css", "https://example.com/a.html"])
+            .await?;
+        assert_eq!(exchanges.len(), 2);
+        assert_eq!(exchanges[0].response.body(), b"c");
+        assert_eq!(exchanges[1].response.body(), b"a");
+
+        let b = remote.index().get("https://example.com/b.js").unwrap();
+        let ranges = ranges.lock().unwrap();
+        assert_eq!(ranges.len(), 4);
+        assert_eq!(
+            ranges[1],
+            format!("{}-{}", b.offset, b.offset + b.length - 1)
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn fetch_empty_range() -> Result<()> {
+        // Nothing listens on the port, so a request would fail.
+        let bytes = fetch_range(&reqwest::Client::new(), "http://127.0.0.1:1/", 10..10).await?;
+        assert!(bytes.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,215 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::bundle::{Bundle, Exchange};
+use http::header::LOCATION;
+
+const MAX_REDIRECTS: usize = 10;
+
+impl Bundle {
+    /// Resolves the given url in the same way as a browser which loads it
+    /// from this bundle, and returns the exchange which has the final response.
+    ///
+    /// Redirects, e.g. `index.html` to `./`, which `exchanges_from_dir`
+    /// generates, are followed. A url with a trailing slash matches an exchange
+    /// without it, and vice versa, e.g. `docs/` matches `docs`. If nothing
+    /// matches, the error page from `not_found_exchange` is returned, if any.
+    ///
+    /// Returns `None` if a redirect loops or its target is not found.
+    pub fn resolve(&self, url: &str) -> Option<&Exchange> {
+        let mut url = url.to_string();
+        for _ in 0..=MAX_REDIRECTS {
+            let exchange = match self.find_exchange(&url) {
+                Some(exchange) => exchange,
+                None => return self.not_found_exchange(&url),
+            };
+            if !exchange.response.status().is_redirection() {
+                return Some(exchange);
+            }
+            let location = exchange.response.headers().get(LOCATION)?.to_str().ok()?;
+            url = resolve_reference(exchange.request.url(), location);
+        }
+        log::warn!("Too many redirects: {}", url);
+        None
+    }
+
+    pub(crate) fn find_exchange(&self, url: &str) -> Option<&Exchange> {
+        let find = |url: &str| {
+            self.exchanges
+                .iter()
+                .find(|exchange| exchange.request.url() == url)
+        };
+        find(url).or_else(|| match url.strip_suffix('/') {
+            Some(url) => find(url),
+            None => find(&format!("{url}/")),
+        })
+    }
+}
+
+fn has_scheme(url: &str) -> bool {
+    match url.find(':') {
+        Some(pos) => {
+            let scheme = &url[..pos];
+            scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+        }
+        None => false,
+    }
+}
+
+/// Splits a url into its origin, e.g. `https://example.com`, and its path
+/// without a query and a fragment. A relative url has an empty origin.
+fn split_origin(url: &str) -> (&str, &str) {
+    let url = url.split(['?', '#']).next().unwrap_or_default();
+    let authority_start = match url.find("://") {
+        Some(pos) if has_scheme(url) => pos + 3,
+        _ => return ("", url),
+    };
+    match url[authority_start..].find('/') {
+        Some(pos) => url.split_at(authority_start + pos),
+        None => (url, "/"),
+    }
+}
+
+/// Removes `.` and `..` segments from a path, which can be relative.
+///
+/// See [5.2.4. Remove Dot Segments](https://www.rfc-editor.org/rfc/rfc3986#section-5.2.4).
+pub(crate) fn remove_dot_segments(path: &str) -> String {
+    let (root, path) = match path.strip_prefix('/') {
+        Some(path) => ("/", path),
+        None => ("", path),
+    };
+    let segments = path.split('/').collect::<Vec<_>>();
+    let mut output: Vec<&str> = Vec::new();
+    for (i, segment) in segments.iter().enumerate() {
+        let is_last = i + 1 == segments.len();
+        match *segment {
+            "." => {}
+            ".." => {
+                output.pop();
+            }
+            segment => {
+                output.push(segment);
+                continue;
+            }
+        }
+        // Keep the trailing slash, e.g. `a/.` is `a/`.
+        if is_last {
+            output.push("");
+        }
+    }
+    format!("{root}{}", output.join("/"))
+}
+
+/// Resolves a reference, e.g. `./` or `/a.html`, against the base url. The
+/// base url can be relative, e.g. `docs/index.html`. A fragment is dropped.
+pub(crate) fn resolve_reference(base: &str, reference: &str) -> String {
+    let reference = reference.split('#').next().unwrap_or_default();
+    if has_scheme(reference) {
+        return reference.to_string();
+    }
+    let (origin, base_path) = split_origin(base);
+    if let Some(rest) = reference.strip_prefix("//") {
+        let scheme = origin.split(':').next().unwrap_or("https");
+        return format!("{scheme}://{rest}");
+    }
+    let (path, query) = match reference.find('?') {
+        Some(pos) => reference.split_at(pos),
+        None => (reference, ""),
+    };
+    let path = if path.starts_with('/') {
+        path.to_string()
+    } else if path.is_empty() {
+        base_path.to_string()
+    } else {
+        let dir = match base_path.rfind('/') {
+            Some(pos) => &base_path[..=pos],
+            None => "",
+        };
+        format!("{dir}{path}")
+    };
+    format!("{origin}{}{query}", remove_dot_segments(&path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundle::{Response, Version};
+    use crate::prelude::*;
+    use http::StatusCode;
+
+    fn redirect(url: &str, location: &str) -> Exchange {
+        let mut response = Response::new(Vec::new());
+        *response.status_mut() = StatusCode::MOVED_PERMANENTLY;
+        response
+            .headers_mut()
+            .insert(LOCATION, location.parse().unwrap());
+        Exchange {
+            request: url.into(),
+            response,
+        }
+    }
+
+    #[test]
+    fn resolve_reference_test() {
+        assert_eq!(resolve_reference("index.html", "./"), "");
+        assert_eq!(resolve_reference("docs/index.html", "./"), "docs/");
+        assert_eq!(
+            resolve_reference("docs/a/b.html", "../c.html"),
+            "docs/c.html"
+        );
+        assert_eq!(
+            resolve_reference("https://example.com/a/b.html?q", "/c.html?x#y"),
+            "https://example.com/c.html?x"
+        );
+        assert_eq!(
+            resolve_reference("https://example.com", "a.html"),
+            "https://example.com/a.html"
+        );
+        assert_eq!(
+            resolve_reference("https://example.com/", "//cdn.example.com/a.js"),
+            "https://cdn.example.com/a.js"
+        );
+        assert_eq!(
+            resolve_reference("https://example.com/", "https://example.org/"),
+            "https://example.org/"
+        );
+    }
+
+    #[test]
+    fn resolve() -> Result<()> {
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchange(Exchange::from(("", b"top".to_vec())))
+            .exchange(redirect("index.html", "./"))
+            .exchange(Exchange::from(("docs", b"docs".to_vec())))
+            .exchange(redirect("docs/index.html", "./"))
+            .exchange(redirect("https://example.com/old", "/new"))
+            .exchange(Exchange::from(("https://example.com/new", b"new".to_vec())))
+            .exchange(redirect("loop1", "loop2"))
+            .exchange(redirect("loop2", "loop1"))
+            .build()?;
+
+        let body = |url: &str| bundle.resolve(url).map(|e| e.response.body().clone());
+        assert_eq!(body("index.html").unwrap(), b"top");
+        assert_eq!(body("docs/index.html").unwrap(), b"docs");
+        assert_eq!(body("docs/").unwrap(), b"docs");
+        assert_eq!(body("https://example.com/old").unwrap(), b"new");
+        assert!(bundle.resolve("loop1").is_none());
+        assert!(bundle.resolve("missing.html").is_none());
+        Ok(())
+    }
+}
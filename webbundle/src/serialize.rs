@@ -0,0 +1,290 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `Serialize` and `Deserialize` implementations.
+//!
+//! A bundle is represented as follows in JSON:
+//!
+//! ```json
+//! {
+//!   "version": "b2",
+//!   "primary_url": "https://example.com/",
+//!   "exchanges": [
+//!     {
+//!       "request": { "url": "https://example.com/", "headers": [] },
+//!       "response": {
+//!         "status": 200,
+//!         "headers": [["content-type", "text/html"]],
+//!         "body": "SGVsbG8="
+//!       }
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! Headers are a list of name-value pairs so that a header can have several
+//! values. A body is encoded with base64. An unknown version is represented
+//! by its four bytes in hex, e.g. `"31300000"`.
+
+use crate::bundle::{Bundle, Exchange, HeaderMap, IndexOrder, Request, Response, Version};
+use base64::Engine as _;
+use http::header::{HeaderName, HeaderValue};
+use http::StatusCode;
+use serde::de::Error as _;
+use serde::ser::{Error as _, SerializeSeq as _, SerializeStruct as _};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+impl Serialize for Version {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Version {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(|err| D::Error::custom(format!("{err:#}")))
+    }
+}
+
+struct Headers<'a>(&'a HeaderMap);
+
+impl Serialize for Headers<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.0.len()))?;
+        for (name, value) in self.0 {
+            let value = value.to_str().map_err(S::Error::custom)?;
+            seq.serialize_element(&(name.as_str(), value))?;
+        }
+        seq.end()
+    }
+}
+
+fn to_header_map<E: serde::de::Error>(headers: Vec<(String, String)>) -> Result<HeaderMap, E> {
+    let mut header_map = HeaderMap::new();
+    for (name, value) in headers {
+        header_map.append(
+            HeaderName::from_bytes(name.as_bytes()).map_err(E::custom)?,
+            HeaderValue::from_str(&value).map_err(E::custom)?,
+        );
+    }
+    Ok(header_map)
+}
+
+impl Serialize for Request {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Request", 3)?;
+        state.serialize_field("url", self.url())?;
+        // Omit the default method.
+        if self.method() == http::Method::GET {
+            state.skip_field("method")?;
+        } else {
+            state.serialize_field("method", self.method().as_str())?;
+        }
+        state.serialize_field("headers", &Headers(self.headers()))?;
+        state.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Request {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct Repr {
+            url: String,
+            #[serde(default)]
+            method: Option<String>,
+            #[serde(default)]
+            headers: Vec<(String, String)>,
+        }
+        let repr = Repr::deserialize(deserializer)?;
+        let request = Request::new(repr.url, to_header_map(repr.headers)?);
+        Ok(match repr.method {
+            Some(method) => request.with_method(method.parse().map_err(D::Error::custom)?),
+            None => request,
+        })
+    }
+}
+
+struct ResponseRef<'a>(&'a Response);
+
+impl Serialize for ResponseRef<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Response", 3)?;
+        state.serialize_field("status", &self.0.status().as_u16())?;
+        state.serialize_field("headers", &Headers(self.0.headers()))?;
+        state.serialize_field(
+            "body",
+            &base64::engine::general_purpose::STANDARD.encode(self.0.body()),
+        )?;
+        state.end()
+    }
+}
+
+impl Serialize for Exchange {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Exchange", 2)?;
+        state.serialize_field("request", &self.request)?;
+        state.serialize_field("response", &ResponseRef(&self.response))?;
+        state.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Exchange {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct ResponseRepr {
+            status: u16,
+            #[serde(default)]
+            headers: Vec<(String, String)>,
+            #[serde(default)]
+            body: String,
+        }
+        #[derive(Deserialize)]
+        struct Repr {
+            request: Request,
+            response: ResponseRepr,
+        }
+        let repr = Repr::deserialize(deserializer)?;
+        let body = base64::engine::general_purpose::STANDARD
+            .decode(repr.response.body)
+            .map_err(D::Error::custom)?;
+        let mut response = Response::new(body);
+        *response.status_mut() =
+            StatusCode::from_u16(repr.response.status).map_err(D::Error::custom)?;
+        *response.headers_mut() = to_header_map(repr.response.headers)?;
+        Ok(Exchange {
+            request: repr.request,
+            response,
+        })
+    }
+}
+
+impl Serialize for Bundle {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Bundle", 3)?;
+        state.serialize_field("version", &self.version)?;
+        state.serialize_field(
+            "primary_url",
+            &self.primary_url.as_ref().map(|uri| uri.to_string()),
+        )?;
+        state.serialize_field("exchanges", &self.exchanges)?;
+        state.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Bundle {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct Repr {
+            version: Version,
+            #[serde(default)]
+            primary_url: Option<String>,
+            exchanges: Vec<Exchange>,
+        }
+        let repr = Repr::deserialize(deserializer)?;
+        Ok(Bundle {
+            version: repr.version,
+            primary_url: repr
+                .primary_url
+                .map(|url| url.parse())
+                .transpose()
+                .map_err(D::Error::custom)?,
+            exchanges: repr.exchanges,
+            index_order: IndexOrder::Canonical,
+            ignored_sections: Vec::new(),
+            signatures: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::*;
+    use headers::ContentType;
+
+    #[test]
+    fn json_round_trip() -> Result<()> {
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .primary_url("https://example.com/".parse()?)
+            .exchange(Exchange::from((
+                "https://example.com/",
+                b"Hello".to_vec(),
+                ContentType::html(),
+            )))
+            .build()?;
+
+        let json = serde_json::to_value(&bundle)?;
+        assert_eq!(json["version"], "b2");
+        assert_eq!(json["primary_url"], "https://example.com/");
+        let response = &json["exchanges"][0]["response"];
+        assert_eq!(response["status"], 200);
+        assert_eq!(response["body"], "SGVsbG8=");
+        assert!(response["headers"]
+            .as_array()
+            .unwrap()
+            .contains(&serde_json::json!(["content-type", "text/html"])));
+
+        let decoded: Bundle = serde_json::from_value(json)?;
+        assert_eq!(decoded.version(), &Version::VersionB2);
+        assert_eq!(decoded.encode()?, bundle.encode()?);
+        Ok(())
+    }
+
+    #[test]
+    fn request() -> Result<()> {
+        let request = Request::from("https://example.com/");
+        let json = serde_json::to_value(&request)?;
+        assert!(json.get("method").is_none());
+        assert_eq!(
+            serde_json::from_value::<Request>(json)?.method(),
+            http::Method::GET
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert("accept", "text/html".parse()?);
+        let request = Request::new("https://example.com/".to_string(), headers);
+        let json = serde_json::to_value(&request)?;
+        assert_eq!(
+            json["headers"],
+            serde_json::json!([["accept", "text/html"]])
+        );
+        assert_eq!(
+            serde_json::from_value::<Request>(json)?.headers(),
+            request.headers()
+        );
+
+        let request = request.with_method(http::Method::POST);
+        let json = serde_json::to_value(&request)?;
+        assert_eq!(json["method"], "POST");
+        assert_eq!(
+            serde_json::from_value::<Request>(json)?.method(),
+            http::Method::POST
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_version() -> Result<()> {
+        let version = Version::Unknown([0x31, 0x30, 0, 0]);
+        let json = serde_json::to_string(&version)?;
+        assert_eq!(json, r#""31300000""#);
+        assert_eq!(serde_json::from_str::<Version>(&json)?, version);
+        assert!(serde_json::from_str::<Version>(r#""b3""#).is_err());
+        assert!(serde_json::from_str::<Version>(r#""31300é0""#).is_err());
+        Ok(())
+    }
+}
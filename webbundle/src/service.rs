@@ -0,0 +1,202 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::bundle::Bundle;
+use bytes::Bytes;
+use http::{Method, StatusCode};
+use http_body::Full;
+use std::convert::Infallible;
+use std::future::Ready;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+/// A `tower::Service` which serves the exchanges of a bundle, as a static
+/// site.
+///
+/// The path of a request is matched as [`Bundle::resolve`] does, and error
+/// pages are used for missing resources. Redirects in the bundle are served
+/// as they are, with their `3xx` status and `Location`, so that clients
+/// follow them and see the final url. Responses have the headers of the
+/// exchanges, e.g. `Content-Type`. Only `GET` and `HEAD` are allowed.
+///
+/// # Examples
+///
+/// ```no_run
+/// use webbundle::{Bundle, BundleService};
+/// let bundle = Bundle::from_bytes(std::fs::read("site.wbn")?)?;
+/// // e.g. `axum::Router::new().fallback(service)`
+/// let service = BundleService::new(bundle);
+/// # std::result::Result::Ok::<_, anyhow::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct BundleService {
+    bundle: Arc<Bundle>,
+    base_url: Option<String>,
+}
+
+impl BundleService {
+    /// Creates a service for a bundle whose urls are relative to the root,
+    /// e.g. a bundle from `exchanges_from_dir`. A request for `/a/b.html`
+    /// is served by the exchange for `a/b.html`.
+    pub fn new(bundle: impl Into<Arc<Bundle>>) -> Self {
+        BundleService {
+            bundle: bundle.into(),
+            base_url: None,
+        }
+    }
+
+    /// Sets the base url for the urls in the bundle, e.g.
+    /// `https://example.com/`. A request for `/a/b.html` is served by the
+    /// exchange for `https://example.com/a/b.html`.
+    pub fn base_url(mut self, base_url: impl Into<String>) -> Self {
+        let mut base_url = base_url.into();
+        if !base_url.ends_with('/') {
+            base_url.push('/');
+        }
+        self.base_url = Some(base_url);
+        self
+    }
+
+    /// Returns the response for the given method and path.
+    pub fn response(&self, method: &Method, path: &str) -> http::Response<Full<Bytes>> {
+        if method != Method::GET && method != Method::HEAD {
+            return status_response(StatusCode::METHOD_NOT_ALLOWED);
+        }
+        let path = path.trim_start_matches('/');
+        let url = match &self.base_url {
+            Some(base_url) => format!("{base_url}{path}"),
+            None => path.to_string(),
+        };
+        let exchange = match self
+            .bundle
+            .find_exchange(&url)
+            .or_else(|| self.bundle.not_found_exchange(&url))
+        {
+            Some(exchange) => exchange,
+            None => return status_response(StatusCode::NOT_FOUND),
+        };
+        let body = if method == Method::HEAD {
+            Bytes::new()
+        } else {
+            Bytes::from(exchange.response.body().clone())
+        };
+        let mut response = http::Response::new(Full::new(body));
+        *response.status_mut() = exchange.response.status();
+        *response.headers_mut() = exchange.response.headers().clone();
+        response
+    }
+}
+
+fn status_response(status: StatusCode) -> http::Response<Full<Bytes>> {
+    let mut response = http::Response::new(Full::new(Bytes::new()));
+    *response.status_mut() = status;
+    response
+}
+
+impl<B> tower_service::Service<http::Request<B>> for BundleService {
+    type Response = http::Response<Full<Bytes>>;
+    type Error = Infallible;
+    type Future = Ready<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        std::future::ready(Ok(self.response(req.method(), req.uri().path())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundle::{Exchange, Version};
+    use crate::prelude::*;
+    use headers::ContentType;
+    use http_body::Body as _;
+    use tower_service::Service as _;
+
+    fn call(service: &mut BundleService, method: Method, uri: &str) -> (StatusCode, Vec<u8>) {
+        let request = http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(())
+            .unwrap();
+        let mut response = service.call(request).into_inner().unwrap();
+        let mut cx = Context::from_waker(std::task::Waker::noop());
+        let body = match std::pin::Pin::new(response.body_mut()).poll_data(&mut cx) {
+            Poll::Ready(Some(data)) => data.unwrap().to_vec(),
+            _ => Vec::new(),
+        };
+        (response.status(), body)
+    }
+
+    #[test]
+    fn bundle_service() -> Result<()> {
+        let mut redirect = Exchange::from(("index.html", vec![]));
+        *redirect.response.status_mut() = StatusCode::MOVED_PERMANENTLY;
+        redirect
+            .response
+            .headers_mut()
+            .insert(http::header::LOCATION, "./".parse()?);
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchange(Exchange::from(("", b"top".to_vec(), ContentType::html())))
+            .exchange(redirect)
+            .exchange(Exchange::from(("a.js", b"a".to_vec())))
+            .build()?;
+        let mut service = BundleService::new(bundle);
+
+        assert_eq!(
+            call(&mut service, Method::GET, "/"),
+            (StatusCode::OK, b"top".to_vec())
+        );
+        assert_eq!(
+            call(&mut service, Method::GET, "/index.html?q=1"),
+            (StatusCode::MOVED_PERMANENTLY, vec![])
+        );
+        let response = service.response(&Method::GET, "/index.html");
+        assert_eq!(response.headers()["location"], "./");
+        assert_eq!(
+            call(&mut service, Method::HEAD, "/a.js"),
+            (StatusCode::OK, vec![])
+        );
+        assert_eq!(
+            call(&mut service, Method::GET, "/missing").0,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            call(&mut service, Method::POST, "/").0,
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+
+        let response = service.response(&Method::GET, "/");
+        assert_eq!(response.headers()["content-type"], "text/html");
+        Ok(())
+    }
+
+    #[test]
+    fn bundle_service_with_base_url() -> Result<()> {
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchange(Exchange::from(("https://example.com/a.js", b"a".to_vec())))
+            .build()?;
+        let mut service = BundleService::new(bundle).base_url("https://example.com");
+        assert_eq!(
+            call(&mut service, Method::GET, "/a.js"),
+            (StatusCode::OK, b"a".to_vec())
+        );
+        Ok(())
+    }
+}
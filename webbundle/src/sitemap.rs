@@ -0,0 +1,188 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::http_client::fetch;
+use crate::prelude::*;
+use std::collections::HashSet;
+
+impl crate::builder::Builder {
+    /// Fetches each URL listed in the given sitemap, and appends the responses
+    /// as exchanges.
+    ///
+    /// `url_or_path` is either a `http(s)://` URL or a path to a local file.
+    /// A sitemap index, which lists other sitemaps, is also supported. Only
+    /// a local sitemap can list paths to local files, so that a remote one
+    /// can't read them.
+    /// See <https://www.sitemaps.org/protocol.html>.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # async {
+    /// use webbundle::{Bundle, Version};
+    /// let bundle = Bundle::builder()
+    ///     .version(Version::VersionB2)
+    ///     .exchanges_from_sitemap("https://example.com/sitemap.xml").await?
+    ///     .build()?;
+    /// # std::result::Result::Ok::<_, anyhow::Error>(bundle)
+    /// # };
+    /// ```
+    pub async fn exchanges_from_sitemap(mut self, url_or_path: &str) -> Result<Self> {
+        let client = reqwest::Client::new();
+        let mut sitemaps = vec![url_or_path.to_string()];
+        let mut seen = HashSet::new();
+        while let Some(sitemap) = sitemaps.pop() {
+            if !seen.insert(sitemap.clone()) {
+                continue;
+            }
+            let is_remote = is_url(&sitemap);
+            let xml = if is_remote {
+                let exchange = fetch(&client, &sitemap).await?;
+                ensure!(
+                    exchange.response.status().is_success(),
+                    format!(
+                        "Failed to fetch {}: {}",
+                        sitemap,
+                        exchange.response.status()
+                    )
+                );
+                String::from_utf8(exchange.response.into_body())?
+            } else {
+                std::fs::read_to_string(&sitemap)
+                    .with_context(|| format!("Failed to read {sitemap}"))?
+            };
+            let locs = locs(&xml);
+            if is_remote {
+                if let Some(loc) = locs.iter().find(|loc| !is_url(loc)) {
+                    bail!("{sitemap}: A remote sitemap can't list a local path: {loc}");
+                }
+            }
+            if xml.contains("<sitemapindex") {
+                // Keep the order of the listed sitemaps.
+                sitemaps.extend(locs.into_iter().rev());
+                continue;
+            }
+            for loc in locs {
+                if !seen.insert(loc.clone()) {
+                    continue;
+                }
+                self.exchanges.push(fetch(&client, &loc).await?);
+            }
+        }
+        Ok(self)
+    }
+}
+
+fn is_url(s: &str) -> bool {
+    s.starts_with("http://") || s.starts_with("https://")
+}
+
+/// Returns the contents of `<loc>` elements.
+fn locs(xml: &str) -> Vec<String> {
+    let mut locs = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find("<loc>") {
+        rest = &rest[start + 5..];
+        let end = match rest.find("</loc>") {
+            Some(end) => end,
+            None => break,
+        };
+        locs.push(unescape(rest[..end].trim()));
+        rest = &rest[end..];
+    }
+    locs
+}
+
+fn unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundle::{Bundle, Version};
+    use crate::http_client::tests::serve;
+
+    #[test]
+    fn locs_test() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+  <url><loc>https://example.com/</loc></url>
+  <url>
+    <loc>
+      https://example.com/?a=1&amp;b=2
+    </loc>
+    <lastmod>2005-01-01</lastmod>
+  </url>
+</urlset>"#;
+        assert_eq!(
+            locs(xml),
+            vec!["https://example.com/", "https://example.com/?a=1&b=2"]
+        );
+    }
+
+    #[tokio::test]
+    async fn exchanges_from_sitemap() -> Result<()> {
+        let origin = serve(vec![
+            ("/a.html", "text/html", "a"),
+            ("/b.html", "text/html", "b"),
+        ]);
+
+        let dir = tempfile::tempdir()?;
+        let sitemap = dir.path().join("sitemap.xml");
+        std::fs::write(
+            &sitemap,
+            format!(
+                "<urlset><url><loc>{origin}/a.html</loc></url><url><loc>{origin}/b.html</loc></url></urlset>"
+            ),
+        )?;
+        let index = dir.path().join("sitemap-index.xml");
+        std::fs::write(
+            &index,
+            format!(
+                "<sitemapindex><sitemap><loc>{}</loc></sitemap></sitemapindex>",
+                sitemap.display()
+            ),
+        )?;
+
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchanges_from_sitemap(index.to_str().unwrap())
+            .await?
+            .build()?;
+        assert_eq!(bundle.exchanges().len(), 2);
+        assert_eq!(
+            bundle.exchanges()[0].request.url(),
+            &format!("{origin}/a.html")
+        );
+        assert_eq!(bundle.exchanges()[1].response.body(), b"b");
+
+        // A remote sitemap can't read local files.
+        let remote = serve(vec![(
+            "/sitemap-index.xml",
+            "application/xml",
+            "<sitemapindex><sitemap><loc>/etc/passwd</loc></sitemap></sitemapindex>",
+        )]);
+        assert!(Bundle::builder()
+            .version(Version::VersionB2)
+            .exchanges_from_sitemap(&format!("{remote}/sitemap-index.xml"))
+            .await
+            .is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,247 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::bundle::{Bundle, Exchange, Uri};
+use crate::encoder;
+use crate::prelude::*;
+
+// The maximum length of a CBOR item header, e.g. the length of a byte string.
+const CBOR_HEADER_LEN: usize = 9;
+// Covers the growth of the CBOR headers in the top level structure, which
+// depend on the number of exchanges and the section lengths.
+const TOP_LEVEL_SLACK: usize = 8 * CBOR_HEADER_LEN;
+
+impl Bundle {
+    /// Splits this bundle into bundles whose encoded sizes are at most
+    /// `max_size` bytes.
+    ///
+    /// Exchanges are packed greedily in their order; no exchange is
+    /// duplicated. The primary url is kept only in the bundle which has the
+    /// exchange for it, or in the first bundle if there is no such exchange.
+    /// This fails if an exchange doesn't fit into a bundle by itself.
+    pub fn split_max_size(&self, max_size: usize) -> Result<Vec<Bundle>> {
+        let base_size = self.base_size_upper_bound()?;
+
+        let mut groups: Vec<Vec<Exchange>> = Vec::new();
+        let mut current: Vec<Exchange> = Vec::new();
+        let mut current_size = base_size;
+        for exchange in &self.exchanges {
+            let size = encoded_size_upper_bound(exchange)?;
+            ensure!(
+                base_size + size <= max_size,
+                format!(
+                    "{} doesn't fit into {} bytes",
+                    exchange.request.url(),
+                    max_size
+                )
+            );
+            if current_size + size > max_size {
+                groups.push(std::mem::take(&mut current));
+                current_size = base_size;
+            }
+            current.push(exchange.clone());
+            current_size += size;
+        }
+        if !current.is_empty() || groups.is_empty() {
+            groups.push(current);
+        }
+        Ok(self.bundles_from_groups(groups))
+    }
+
+    /// Splits this bundle by URL scopes, e.g. `["/static/", "/app/"]`.
+    ///
+    /// Returns one bundle for each scope, in the same order, followed by a
+    /// bundle for the exchanges which are not in any scope. An exchange
+    /// belongs to the longest matching scope. A scope which starts with `/`
+    /// is matched against the path of the URL; other scopes are matched
+    /// against the whole URL, e.g. `https://example.com/static/`.
+    pub fn split_by_scope(&self, scopes: &[impl AsRef<str>]) -> Vec<Bundle> {
+        let mut groups: Vec<Vec<Exchange>> = vec![Vec::new(); scopes.len() + 1];
+        for exchange in &self.exchanges {
+            let url = exchange.request.url();
+            let path = url
+                .parse::<Uri>()
+                .map(|uri| uri.path().to_string())
+                .unwrap_or_default();
+            let index = scopes
+                .iter()
+                .map(|scope| scope.as_ref())
+                .enumerate()
+                .filter(|(_, scope)| {
+                    if scope.starts_with('/') {
+                        path.starts_with(scope)
+                    } else {
+                        url.starts_with(scope)
+                    }
+                })
+                .max_by_key(|(_, scope)| scope.len())
+                .map(|(index, _)| index)
+                .unwrap_or(scopes.len());
+            groups[index].push(exchange.clone());
+        }
+        self.bundles_from_groups(groups)
+    }
+
+    /// Returns the upper bound of the encoded size of this bundle without
+    /// exchanges.
+    pub(crate) fn base_size_upper_bound(&self) -> Result<usize> {
+        let empty = Bundle {
+            version: self.version.clone(),
+            primary_url: self.primary_url.clone(),
+            exchanges: Vec::new(),
+            index_order: self.index_order,
+            ignored_sections: Vec::new(),
+            signatures: None,
+        };
+        Ok(empty.encode()?.len() + TOP_LEVEL_SLACK)
+    }
+
+    /// Creates bundles which share the version of this bundle.
+    pub(crate) fn bundles_from_groups(&self, groups: Vec<Vec<Exchange>>) -> Vec<Bundle> {
+        let primary_url = self.primary_url.as_ref().map(|uri| uri.to_string());
+        let primary_index = primary_url
+            .as_ref()
+            .and_then(|url| {
+                groups.iter().position(|exchanges| {
+                    exchanges
+                        .iter()
+                        .any(|exchange| exchange.request.url() == url)
+                })
+            })
+            .unwrap_or(0);
+        groups
+            .into_iter()
+            .enumerate()
+            .map(|(i, exchanges)| Bundle {
+                version: self.version.clone(),
+                primary_url: if i == primary_index {
+                    self.primary_url.clone()
+                } else {
+                    None
+                },
+                exchanges,
+                index_order: self.index_order,
+                ignored_sections: Vec::new(),
+                signatures: None,
+            })
+            .collect()
+    }
+}
+
+/// Returns the upper bound of the bytes which the exchange adds to the
+/// index section and the responses section.
+pub(crate) fn encoded_size_upper_bound(exchange: &Exchange) -> Result<usize> {
+    let index_entry = CBOR_HEADER_LEN + exchange.request.url().len() + 1 + 2 * CBOR_HEADER_LEN;
+    let response = 1
+        + CBOR_HEADER_LEN
+        + encoder::encode_headers(&exchange.response)?.len()
+        + CBOR_HEADER_LEN
+        + exchange.response.body().len();
+    Ok(index_entry + response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundle::Version;
+
+    fn bundle() -> Result<Bundle> {
+        let mut builder = Bundle::builder()
+            .version(Version::VersionB2)
+            .primary_url("https://example.com/5.txt".parse()?);
+        for i in 0..10 {
+            builder = builder.exchange(Exchange::from((
+                format!("https://example.com/{i}.txt"),
+                vec![b'a'; 100],
+            )));
+        }
+        builder.build()
+    }
+
+    #[test]
+    fn split_max_size() -> Result<()> {
+        let bundle = bundle()?;
+        let bundles = bundle.split_max_size(600)?;
+        assert!(bundles.len() > 1);
+        for b in &bundles {
+            assert!(b.encode()?.len() <= 600);
+            assert!(!b.exchanges().is_empty());
+        }
+
+        let urls = bundles
+            .iter()
+            .flat_map(|b| b.exchanges().iter().map(|e| e.request.url().clone()))
+            .collect::<Vec<_>>();
+        let expected = bundle
+            .exchanges()
+            .iter()
+            .map(|e| e.request.url().clone())
+            .collect::<Vec<_>>();
+        assert_eq!(urls, expected);
+
+        let with_primary_url = bundles
+            .iter()
+            .filter(|b| b.primary_url().is_some())
+            .collect::<Vec<_>>();
+        assert_eq!(with_primary_url.len(), 1);
+        assert!(with_primary_url[0]
+            .exchanges()
+            .iter()
+            .any(|e| e.request.url() == "https://example.com/5.txt"));
+        Ok(())
+    }
+
+    #[test]
+    fn split_max_size_too_small() -> Result<()> {
+        assert!(bundle()?.split_max_size(100).is_err());
+        assert_eq!(bundle()?.split_max_size(1_000_000)?.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn split_by_scope() -> Result<()> {
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .primary_url("https://example.com/app/index.html".parse()?)
+            .exchange(Exchange::from(("https://example.com/static/a.css", vec![])))
+            .exchange(Exchange::from((
+                "https://example.com/static/img/b.png",
+                vec![],
+            )))
+            .exchange(Exchange::from((
+                "https://example.com/app/index.html",
+                vec![],
+            )))
+            .exchange(Exchange::from(("https://example.com/favicon.ico", vec![])))
+            .build()?;
+
+        let urls = |bundle: &Bundle| {
+            bundle
+                .exchanges()
+                .iter()
+                .map(|e| e.request.url().clone())
+                .collect::<Vec<_>>()
+        };
+        let bundles =
+            bundle.split_by_scope(&["/static/", "https://example.com/static/img/", "/app/"]);
+        assert_eq!(bundles.len(), 4);
+        assert_eq!(urls(&bundles[0]), ["https://example.com/static/a.css"]);
+        assert_eq!(urls(&bundles[1]), ["https://example.com/static/img/b.png"]);
+        assert_eq!(urls(&bundles[2]), ["https://example.com/app/index.html"]);
+        assert_eq!(urls(&bundles[3]), ["https://example.com/favicon.ico"]);
+        assert!(bundles[2].primary_url().is_some());
+        assert!(bundles[0].primary_url().is_none());
+        Ok(())
+    }
+}
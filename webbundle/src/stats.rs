@@ -0,0 +1,123 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::bundle::Bundle;
+use crate::prelude::*;
+use crate::split::encoded_size_upper_bound;
+use headers::{ContentType, HeaderMapExt as _};
+use std::collections::BTreeMap;
+
+/// The number of exchanges in [`Stats::largest`].
+const LARGEST_LEN: usize = 10;
+
+/// Represents the summary of a bundle.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// The number of exchanges.
+    pub exchanges: usize,
+    /// The total size of the response bodies.
+    pub body_bytes: usize,
+    /// The counts and the body sizes for each content type, e.g. `text/html`.
+    /// Exchanges without `Content-Type` are counted under an empty string.
+    pub content_types: BTreeMap<String, ContentTypeStats>,
+    /// The urls and the body sizes of the largest exchanges, in descending order.
+    pub largest: Vec<(String, usize)>,
+    /// The estimated size of the encoded bundle. This is never smaller than
+    /// the actual size.
+    pub encoded_size: usize,
+}
+
+/// Represents the summary of the exchanges which have the same content type.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ContentTypeStats {
+    /// The number of exchanges.
+    pub count: usize,
+    /// The total size of the response bodies.
+    pub body_bytes: usize,
+}
+
+impl Bundle {
+    /// Returns the summary of this bundle.
+    pub fn stats(&self) -> Result<Stats> {
+        let mut stats = Stats {
+            exchanges: self.exchanges.len(),
+            encoded_size: self.base_size_upper_bound()?,
+            ..Stats::default()
+        };
+        for exchange in &self.exchanges {
+            let len = exchange.response.body().len();
+            stats.body_bytes += len;
+            stats.encoded_size += encoded_size_upper_bound(exchange)?;
+
+            let content_type = exchange
+                .response
+                .headers()
+                .typed_get::<ContentType>()
+                .map(|content_type| {
+                    mime_guess::Mime::from(content_type)
+                        .essence_str()
+                        .to_string()
+                })
+                .unwrap_or_default();
+            let content_type_stats = stats.content_types.entry(content_type).or_default();
+            content_type_stats.count += 1;
+            content_type_stats.body_bytes += len;
+
+            stats.largest.push((exchange.request.url().clone(), len));
+        }
+        // A stable sort keeps the order of the exchanges which have the same size.
+        stats
+            .largest
+            .sort_by_key(|(_, len)| std::cmp::Reverse(*len));
+        stats.largest.truncate(LARGEST_LEN);
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundle::{Exchange, Version};
+
+    #[test]
+    fn stats() -> Result<()> {
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchange(Exchange::from(("index.html", vec![0; 100])))
+            .exchange(Exchange::from(("a.js", vec![0; 300])))
+            .exchange(Exchange::from(("b.js", vec![0; 200])))
+            .build()?;
+        let stats = bundle.stats()?;
+        assert_eq!(stats.exchanges, 3);
+        assert_eq!(stats.body_bytes, 600);
+        assert_eq!(
+            stats.content_types["text/javascript"],
+            ContentTypeStats {
+                count: 2,
+                body_bytes: 500
+            }
+        );
+        assert_eq!(stats.content_types["text/html"].count, 1);
+        assert_eq!(
+            stats.largest,
+            [
+                ("a.js".to_string(), 300),
+                ("b.js".to_string(), 200),
+                ("index.html".to_string(), 100)
+            ]
+        );
+        assert!(stats.encoded_size >= bundle.encode()?.len());
+        Ok(())
+    }
+}
@@ -0,0 +1,206 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Generators of bundles for benchmarks and fuzzers.
+
+use crate::bundle::{Bundle, Exchange, Version};
+
+/// The origin of the generated modules, so that the bundles are valid.
+const BASE_URL: &str = "https://example.com/";
+
+/// Returns a bundle of JavaScript modules, which import each other as a tree,
+/// like the ones which `webbundle-bench` generates.
+///
+/// The root module is `https://example.com/a0.mjs`, and each module imports
+/// `width` child modules, e.g. `https://example.com/a0/a0_a1.mjs`, until the
+/// tree is `depth` levels deep. Each module is padded with a comment to be
+/// at least `body_size` bytes. The padding is [`Rng::text`], so the same
+/// arguments always generate the same bundle.
+///
+/// ```
+/// let bundle = webbundle::testing::synthetic_bundle(2, 3, 1024);
+/// assert_eq!(bundle.exchanges().len(), 1 + 3 + 9);
+/// ```
+pub fn synthetic_bundle(depth: u32, width: u32, body_size: usize) -> Bundle {
+    let mut exchanges = Vec::new();
+    let mut rng = Rng::new(0x2545_f491_4f6c_dd1d);
+    add_module(
+        "a0",
+        BASE_URL,
+        depth,
+        width,
+        body_size,
+        &mut rng,
+        &mut exchanges,
+    );
+    let mut builder = Bundle::builder().version(Version::VersionB2);
+    for exchange in exchanges {
+        builder = builder.exchange(exchange);
+    }
+    builder.build().expect("Generated bundles are valid")
+}
+
+/// Adds the module `name` in `dir`, and its descendants.
+fn add_module(
+    name: &str,
+    dir: &str,
+    depth: u32,
+    width: u32,
+    body_size: usize,
+    rng: &mut Rng,
+    exchanges: &mut Vec<Exchange>,
+) {
+    // The children are in the directory named after the last segment of
+    // this module, e.g. "https://example.com/a0/a1/a0_a1_a2.mjs".
+    let short_name = name.rsplit('_').next().unwrap();
+    let child_dir = format!("{dir}{short_name}/");
+    let children = if depth == 0 {
+        Vec::new()
+    } else {
+        (0..width)
+            .map(|index| format!("{name}_a{index}"))
+            .collect::<Vec<_>>()
+    };
+
+    let mut body = String::new();
+    for child in &children {
+        body.push_str(&format!(
+            "import {{ f_{child} }} from \"./{short_name}/{child}.mjs\";\n"
+        ));
+    }
+    let mut ops = children
+        .iter()
+        .map(|child| format!("f_{child}()"))
+        .collect::<Vec<_>>();
+    ops.push("a".to_string());
+    body.push_str(&format!(
+        "export function f_{name}() {{\n    let a = 1;\n    return {};\n}}\n",
+        ops.join(" + ")
+    ));
+    // "/* " and " */\n"
+    let padding = body_size.saturating_sub(body.len() + 7);
+    if padding > 0 {
+        body.push_str(&format!("/* {} */\n", rng.text(padding)));
+    }
+    exchanges.push(Exchange::from((
+        format!("{dir}{name}.mjs"),
+        body.into_bytes(),
+    )));
+
+    for child in &children {
+        add_module(
+            child,
+            &child_dir,
+            depth - 1,
+            width,
+            body_size,
+            rng,
+            exchanges,
+        );
+    }
+}
+
+/// A xorshift PRNG, so that the same seed generates the same bundles, e.g.
+/// in `webbundle-bench`.
+pub struct Rng(u64);
+
+impl Rng {
+    /// Returns a generator of the seed.
+    pub fn new(seed: u64) -> Rng {
+        // Xorshift never leaves zero.
+        Rng(seed.max(1))
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Returns a number in `0..=max`.
+    pub fn up_to(&mut self, max: usize) -> usize {
+        (self.next_u64() % (max as u64 + 1)) as usize
+    }
+
+    /// Returns random bytes.
+    pub fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0..len).map(|_| self.next_u64() as u8).collect()
+    }
+
+    /// Returns random alphanumeric characters, in lines of 80 characters, so
+    /// that the text compresses like minified code rather than repeated
+    /// bytes.
+    pub fn text(&mut self, len: usize) -> String {
+        const CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+        (0..len)
+            .map(|i| {
+                if i % 81 == 80 {
+                    '\n'
+                } else {
+                    CHARS[self.next_u64() as usize % CHARS.len()] as char
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn synthetic_bundle_tree() -> crate::Result<()> {
+        let bundle = synthetic_bundle(2, 2, 0);
+        let urls = bundle
+            .exchanges()
+            .iter()
+            .map(|exchange| exchange.request.url().strip_prefix(BASE_URL).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                "a0.mjs",
+                "a0/a0_a0.mjs",
+                "a0/a0/a0_a0_a0.mjs",
+                "a0/a0/a0_a0_a1.mjs",
+                "a0/a0_a1.mjs",
+                "a0/a1/a0_a1_a0.mjs",
+                "a0/a1/a0_a1_a1.mjs",
+            ]
+        );
+        assert_eq!(
+            std::str::from_utf8(bundle.exchanges()[4].response.body())?,
+            r#"import { f_a0_a1_a0 } from "./a1/a0_a1_a0.mjs";
+import { f_a0_a1_a1 } from "./a1/a0_a1_a1.mjs";
+export function f_a0_a1() {
+    let a = 1;
+    return f_a0_a1_a0() + f_a0_a1_a1() + a;
+}
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn synthetic_bundle_body_size() -> crate::Result<()> {
+        let bundle = synthetic_bundle(1, 3, 1000);
+        for exchange in bundle.exchanges() {
+            assert_eq!(exchange.response.body().len(), 1000);
+        }
+        assert_eq!(synthetic_bundle(1, 3, 1000).encode()?, bundle.encode()?);
+        assert!(crate::validate(&bundle.encode()?).is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,401 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Checks whether a bundle can be loaded by browsers, e.g. Google Chrome,
+//! which are stricter than [`Bundle::from_bytes`].
+
+use crate::bundle::{Bundle, Uri, Version};
+use http::header::LOCATION;
+use std::fmt;
+
+/// Represents how serious a [`Finding`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Browsers would load the bundle, but may not behave as expected.
+    Warning,
+    /// Browsers would reject the bundle or the exchange.
+    Error,
+}
+
+/// Represents a problem found by [`validate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub severity: Severity,
+    /// The url of the exchange, if the problem is specific to an exchange.
+    pub url: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.severity {
+            Severity::Warning => write!(f, "warning: ")?,
+            Severity::Error => write!(f, "error: ")?,
+        }
+        if let Some(url) = &self.url {
+            write!(f, "{url:?}: ")?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Validates the given bytes of a bundle, and returns the problems found.
+/// Bytes which can't be parsed, including crafted lengths, are reported as an
+/// error, so this can be used for untrusted input.
+///
+/// In addition to what is required to parse the bundle, this checks that:
+///
+/// - The version is `b2`.
+/// - The bundle ends with its length.
+/// - CBOR items are encoded canonically, i.e. with the shortest heads and
+///   sorted map keys.
+/// - The urls are absolute.
+/// - The statuses are neither informational (`1xx`), nor redirects without
+///   `Location` header.
+///
+/// # Examples
+///
+/// ```no_run
+/// for finding in webbundle::validate(std::fs::read("example.wbn")?) {
+///     println!("{finding}");
+/// }
+/// # std::result::Result::Ok::<_, anyhow::Error>(())
+/// ```
+pub fn validate(bytes: impl AsRef<[u8]>) -> Vec<Finding> {
+    let bytes = bytes.as_ref();
+    let mut findings = Vec::new();
+    let mut error = |url: Option<&str>, message: String| {
+        findings.push(Finding {
+            severity: Severity::Error,
+            url: url.map(str::to_string),
+            message,
+        })
+    };
+
+    let bundle = match Bundle::from_bytes(bytes) {
+        Ok(bundle) => bundle,
+        Err(err) => {
+            error(None, format!("Failed to parse the bundle: {err:#}"));
+            return findings;
+        }
+    };
+
+    if bytes.len() < 8
+        || u64::from_be_bytes(bytes[bytes.len() - 8..].try_into().unwrap()) != bytes.len() as u64
+    {
+        error(
+            None,
+            "The bundle doesn't end with its length as 64-bit big-endian".to_string(),
+        );
+    }
+    if let Err(message) = check_canonical(bytes) {
+        error(None, format!("Not canonical CBOR: {message}"));
+    }
+    if let Some(primary_url) = bundle.primary_url() {
+        if !is_absolute(primary_url) {
+            error(
+                None,
+                format!("The primary url is not absolute: {primary_url}"),
+            );
+        }
+    }
+    for exchange in bundle.exchanges() {
+        let url = exchange.request.url();
+        match url.parse::<Uri>() {
+            Ok(uri) if is_absolute(&uri) => {}
+            _ => error(Some(url), "The url is not absolute".to_string()),
+        }
+        let status = exchange.response.status();
+        if status.is_informational() {
+            error(Some(url), format!("Status {status} is not allowed"));
+        }
+        if status.is_redirection() && !exchange.response.headers().contains_key(LOCATION) {
+            error(
+                Some(url),
+                format!("Status {status} without Location header"),
+            );
+        }
+    }
+
+    if bundle.version() != &Version::VersionB2 {
+        findings.push(Finding {
+            severity: Severity::Warning,
+            url: None,
+            message: format!(
+                "Version {:?} is not supported by Google Chrome, which supports only b2",
+                String::from_utf8_lossy(bundle.version().bytes())
+            ),
+        });
+    }
+    // Errors first.
+    findings.sort_by_key(|finding| std::cmp::Reverse(finding.severity));
+    findings
+}
+
+fn is_absolute(uri: &Uri) -> bool {
+    match uri.scheme_str() {
+        Some("http" | "https") => uri.authority().is_some(),
+        Some("uuid-in-package") => true,
+        _ => false,
+    }
+}
+
+/// Checks that the bundle and the CBOR items nested in byte strings, i.e.
+/// `sectionLengths` and response headers, are canonical.
+fn check_canonical(bytes: &[u8]) -> Result<(), &'static str> {
+    let index = webbundle_core::parse_index(bytes).map_err(|_| "Invalid index")?;
+    let mut checker = Checker::new(bytes);
+    checker.head()?; // The top array
+    checker.item()?; // The magic
+    checker.item()?; // The version
+    let section_lengths = checker.bytes()?;
+    check_nested(section_lengths)?;
+    checker.item()?; // The sections
+
+    // Browsers read the trailing length as the last 8 bytes, with or without
+    // the head of a byte string.
+    if !matches!(bytes.len() - checker.position, 8 | 9) {
+        return Err("Extra bytes after the sections");
+    }
+
+    for entry in index.entries {
+        let mut checker = Checker::new(&bytes[entry.offset as usize..]);
+        checker.head()?; // The response array
+        let headers = checker.bytes()?;
+        check_nested(headers)?;
+    }
+    Ok(())
+}
+
+fn check_nested(bytes: &[u8]) -> Result<(), &'static str> {
+    let mut checker = Checker::new(bytes);
+    checker.item()?;
+    if checker.position != bytes.len() {
+        return Err("Extra bytes in a byte string");
+    }
+    Ok(())
+}
+
+/// Walks CBOR items, checking the rules of canonical CBOR.
+///
+/// See [4.2.1. Core Deterministic Encoding Requirements](https://www.rfc-editor.org/rfc/rfc8949#section-4.2.1)
+/// and [3.9. Canonical CBOR](https://tools.ietf.org/html/rfc7049#section-3.9).
+struct Checker<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    /// The number of arrays and maps which the current item is nested in.
+    depth: usize,
+}
+
+/// The maximum nesting of arrays and maps, so that crafted sections can't
+/// overflow the stack. Bundles themselves nest only a few levels.
+const MAX_DEPTH: usize = 64;
+
+impl<'a> Checker<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Checker {
+            bytes,
+            position: 0,
+            depth: 0,
+        }
+    }
+
+    fn take(&mut self, len: u64) -> Result<&'a [u8], &'static str> {
+        let rest = &self.bytes[self.position..];
+        if len > rest.len() as u64 {
+            return Err("Unexpected end");
+        }
+        self.position += len as usize;
+        Ok(&rest[..len as usize])
+    }
+
+    /// Reads the head of an item, and returns the major type and the argument.
+    fn head(&mut self) -> Result<(u8, u64), &'static str> {
+        let initial = self.take(1)?[0];
+        let major_type = initial >> 5;
+        let (len, min) = match initial & 0x1f {
+            info @ 0..=23 => return Ok((major_type, info as u64)),
+            24 => (1, 24),
+            25 => (2, 0x100),
+            26 => (4, 0x1_0000),
+            27 => (8, 0x1_0000_0000),
+            31 => return Err("Indefinite length"),
+            _ => return Err("Reserved additional information"),
+        };
+        let value = self
+            .take(len)?
+            .iter()
+            .fold(0, |value, &b| value << 8 | b as u64);
+        if value < min {
+            return Err("The head is not the shortest");
+        }
+        Ok((major_type, value))
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], &'static str> {
+        match self.head()? {
+            (2, len) => self.take(len),
+            _ => Err("Expected a byte string"),
+        }
+    }
+
+    /// Reads an item, including nested items, and returns its encoded bytes.
+    fn item(&mut self) -> Result<&'a [u8], &'static str> {
+        let start = self.position;
+        let (major_type, argument) = self.head()?;
+        if matches!(major_type, 4 | 5) {
+            if self.depth == MAX_DEPTH {
+                return Err("Too deeply nested");
+            }
+            self.depth += 1;
+        }
+        match major_type {
+            // Integers
+            0 | 1 => {}
+            // Byte and text strings
+            2 | 3 => {
+                self.take(argument)?;
+            }
+            4 => {
+                for _ in 0..argument {
+                    self.item()?;
+                }
+            }
+            5 => {
+                let mut last_key: Option<&[u8]> = None;
+                for _ in 0..argument {
+                    let key = self.item()?;
+                    if last_key.is_some_and(|last_key| last_key >= key) {
+                        return Err("Map keys are not sorted, or duplicated");
+                    }
+                    last_key = Some(key);
+                    self.item()?;
+                }
+            }
+            _ => return Err("Unexpected major type"),
+        }
+        if matches!(major_type, 4 | 5) {
+            self.depth -= 1;
+        }
+        Ok(&self.bytes[start..self.position])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundle::Exchange;
+
+    #[test]
+    fn valid_bundle() -> crate::Result<()> {
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .primary_url("https://example.com/".parse()?)
+            .exchange(Exchange::from((
+                "https://example.com/".to_string(),
+                b"hello".to_vec(),
+            )))
+            .exchange(Exchange::from((
+                "https://example.com/a.js".to_string(),
+                b"a".to_vec(),
+            )))
+            .build()?;
+        assert_eq!(validate(bundle.encode()?), []);
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_bundle() -> crate::Result<()> {
+        let mut redirect = Exchange::from(("https://example.com/a".to_string(), Vec::new()));
+        *redirect.response.status_mut() = http::StatusCode::FOUND;
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchange(Exchange::from((
+                "index.html".to_string(),
+                b"hello".to_vec(),
+            )))
+            .exchange(redirect)
+            .build()?;
+        let findings = validate(bundle.encode()?);
+        assert_eq!(
+            findings
+                .iter()
+                .map(|finding| finding.url.as_deref())
+                .collect::<Vec<_>>(),
+            [Some("index.html"), Some("https://example.com/a")]
+        );
+
+        assert_eq!(
+            validate(b"foo")[0].to_string(),
+            "error: Failed to parse the bundle: bundle: Expected an array"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn huge_declared_length() -> crate::Result<()> {
+        let mut bytes = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchange(Exchange::from((
+                "https://example.com/".to_string(),
+                b"0123456789".to_vec(),
+            )))
+            .build()?
+            .encode()?;
+        // The body claims u64::MAX bytes, but the response ends after 10.
+        let body = bytes
+            .windows(11)
+            .position(|window| window == b"\x4a0123456789")
+            .unwrap();
+        bytes[body] = 0x5b;
+        bytes[body + 1..body + 9].fill(0xff);
+        let findings = validate(&bytes);
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].severity, Severity::Error);
+        assert!(findings[0]
+            .message
+            .starts_with("Failed to parse the bundle: "));
+        Ok(())
+    }
+
+    #[test]
+    fn check_canonical_items() {
+        assert_eq!(check_nested(&[0x18, 0x18]), Ok(()));
+        assert_eq!(
+            check_nested(&[0x18, 0x17]),
+            Err("The head is not the shortest")
+        );
+        // {"a": 1, "b": 2}
+        assert_eq!(check_nested(&[0xa2, 0x61, b'a', 1, 0x61, b'b', 2]), Ok(()));
+        // {"b": 1, "a": 2}
+        assert_eq!(
+            check_nested(&[0xa2, 0x61, b'b', 1, 0x61, b'a', 2]),
+            Err("Map keys are not sorted, or duplicated")
+        );
+        // {"aa": 1, "b": 2}, where shorter keys must come first.
+        assert_eq!(
+            check_nested(&[0xa2, 0x62, b'a', b'a', 1, 0x61, b'b', 2]),
+            Err("Map keys are not sorted, or duplicated")
+        );
+        assert_eq!(check_nested(&[0x9f, 0xff]), Err("Indefinite length"));
+        // [[[...[]...]]]
+        let mut nested = vec![0x81; MAX_DEPTH - 1];
+        nested.push(0x80);
+        assert_eq!(check_nested(&nested), Ok(()));
+        assert_eq!(
+            check_nested(&vec![0x81; 1_000_000]),
+            Err("Too deeply nested")
+        );
+    }
+}
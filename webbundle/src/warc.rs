@@ -0,0 +1,287 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! WARC (ISO 28500) import.
+
+use crate::bundle::{Exchange, Response};
+use crate::prelude::*;
+use headers::{ContentLength, HeaderMapExt as _};
+use http::header::{HeaderName, HeaderValue, TRANSFER_ENCODING};
+use http::StatusCode;
+use std::collections::HashSet;
+use std::io::{BufRead, Read};
+
+impl crate::builder::Builder {
+    /// Append exchanges from the `response` records of the given WARC file.
+    ///
+    /// Only successful responses (`2xx`) are used. See
+    /// [`exchanges_from_warc_with_filter`](Self::exchanges_from_warc_with_filter)
+    /// to choose records by other criteria. If several records have the same
+    /// URL, only the first one is used.
+    ///
+    /// The reader must be uncompressed. For `.warc.gz`, wrap it with a
+    /// decoder which supports multiple gzip members, e.g.
+    /// `flate2::read::MultiGzDecoder`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use webbundle::{Bundle, Version};
+    /// let warc = std::io::BufReader::new(std::fs::File::open("crawl.warc")?);
+    /// let bundle = Bundle::builder()
+    ///     .version(Version::VersionB2)
+    ///     .exchanges_from_warc(warc)?
+    ///     .build()?;
+    /// # std::result::Result::Ok::<_, anyhow::Error>(())
+    /// ```
+    pub fn exchanges_from_warc(self, reader: impl BufRead) -> Result<Self> {
+        self.exchanges_from_warc_with_filter(reader, |exchange| {
+            exchange.response.status().is_success()
+        })
+    }
+
+    /// Same as `exchanges_from_warc`, but uses the exchanges for which the
+    /// given `filter` returns true, e.g. to choose by status or content type.
+    pub fn exchanges_from_warc_with_filter(
+        mut self,
+        mut reader: impl BufRead,
+        filter: impl Fn(&Exchange) -> bool,
+    ) -> Result<Self> {
+        let mut seen_urls = HashSet::new();
+        while let Some(record) = read_record(&mut reader)? {
+            if !record.warc_type.eq_ignore_ascii_case("response") {
+                continue;
+            }
+            let url = match record.target_uri {
+                Some(url) => url,
+                None => continue,
+            };
+            if !url.starts_with("http:") && !url.starts_with("https:") {
+                // e.g. "dns:" records.
+                continue;
+            }
+            let response = match parse_http_response(&record.block) {
+                Ok(response) => response,
+                Err(err) => {
+                    log::warn!("Skipping {}: {:?}", url, err);
+                    continue;
+                }
+            };
+            let exchange = Exchange {
+                request: url.clone().into(),
+                response,
+            };
+            if !filter(&exchange) || !seen_urls.insert(url) {
+                continue;
+            }
+            self.exchanges.push(exchange);
+        }
+        Ok(self)
+    }
+}
+
+struct Record {
+    warc_type: String,
+    target_uri: Option<String>,
+    block: Vec<u8>,
+}
+
+fn read_record(reader: &mut impl BufRead) -> Result<Option<Record>> {
+    // Skip empty lines between records.
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        if !line.trim().is_empty() {
+            break;
+        }
+    }
+    ensure!(
+        line.starts_with("WARC/"),
+        format!("Invalid WARC record: {}", line.trim_end())
+    );
+
+    let mut warc_type = String::new();
+    let mut target_uri = None;
+    let mut content_length = None;
+    loop {
+        line.clear();
+        ensure!(reader.read_line(&mut line)? != 0, "Unexpected end of WARC");
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            let value = value.trim();
+            match name.trim().to_ascii_lowercase().as_str() {
+                "warc-type" => warc_type = value.to_string(),
+                // WARC 0.x wraps the URI with "<>".
+                "warc-target-uri" => {
+                    target_uri = Some(value.trim_matches(|c| c == '<' || c == '>').to_string())
+                }
+                "content-length" => content_length = Some(value.parse::<u64>()?),
+                _ => {}
+            }
+        }
+    }
+    let content_length = content_length.context("No Content-Length in WARC record")?;
+    // Content-Length is untrusted, so the block grows only as it is read.
+    let mut block = Vec::new();
+    reader
+        .take(content_length)
+        .read_to_end(&mut block)
+        .context("Failed to read WARC record block")?;
+    ensure!(
+        block.len() as u64 == content_length,
+        "Unexpected end of WARC record block"
+    );
+    Ok(Some(Record {
+        warc_type,
+        target_uri,
+        block,
+    }))
+}
+
+fn parse_http_response(block: &[u8]) -> Result<Response> {
+    let head_end = block
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .context("No end of HTTP headers")?;
+    let head = std::str::from_utf8(&block[..head_end])?;
+    let mut body = block[head_end + 4..].to_vec();
+
+    let mut lines = head.split("\r\n");
+    let status_line = lines.next().unwrap_or_default();
+    let status = status_line
+        .split(' ')
+        .nth(1)
+        .context("Invalid HTTP status line")?;
+    let mut response = Response::new(Vec::new());
+    *response.status_mut() = StatusCode::from_bytes(status.as_bytes())?;
+    for line in lines {
+        if let Some((name, value)) = line.split_once(':') {
+            response.headers_mut().append(
+                HeaderName::from_bytes(name.trim().as_bytes())?,
+                HeaderValue::from_str(value.trim())?,
+            );
+        }
+    }
+    if let Some(transfer_encoding) = response.headers_mut().remove(TRANSFER_ENCODING) {
+        if transfer_encoding.to_str()?.eq_ignore_ascii_case("chunked") {
+            body = decode_chunked(&body)?;
+        }
+    }
+    response
+        .headers_mut()
+        .typed_insert(ContentLength(body.len() as u64));
+    *response.body_mut() = body;
+    Ok(response)
+}
+
+fn decode_chunked(mut input: &[u8]) -> Result<Vec<u8>> {
+    let mut output = Vec::new();
+    loop {
+        let line_end = input
+            .windows(2)
+            .position(|window| window == b"\r\n")
+            .context("Invalid chunk")?;
+        let size = std::str::from_utf8(&input[..line_end])?;
+        // Ignore chunk extensions.
+        let size = size.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16)?;
+        input = &input[line_end + 2..];
+        if size == 0 {
+            return Ok(output);
+        }
+        ensure!(input.len() >= size, "Truncated chunk");
+        output.extend_from_slice(&input[..size]);
+        input = input[size..]
+            .strip_prefix(b"\r\n")
+            .unwrap_or(&input[size..]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundle::{Bundle, Version};
+
+    fn record(warc_type: &str, url: &str, block: &str) -> String {
+        format!(
+            "WARC/1.0\r\nWARC-Type: {warc_type}\r\nWARC-Target-URI: {url}\r\nContent-Length: {}\r\n\r\n{block}\r\n\r\n",
+            block.len()
+        )
+    }
+
+    #[test]
+    fn exchanges_from_warc() -> Result<()> {
+        let warc = [
+            record("warcinfo", "", "software: test\r\n"),
+            record("request", "https://example.com/", "GET / HTTP/1.1\r\n\r\n"),
+            record(
+                "response",
+                "https://example.com/",
+                "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n\r\nHello",
+            ),
+            record(
+                "response",
+                "<https://example.com/a.js>",
+                "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n2\r\nde\r\n0\r\n\r\n",
+            ),
+            record(
+                "response",
+                "https://example.com/missing",
+                "HTTP/1.1 404 Not Found\r\n\r\n",
+            ),
+        ]
+        .concat();
+
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchanges_from_warc(warc.as_bytes())?
+            .build()?;
+        assert_eq!(bundle.exchanges().len(), 2);
+
+        let top = &bundle.exchanges()[0];
+        assert_eq!(top.request.url(), "https://example.com/");
+        assert_eq!(top.response.body(), b"Hello");
+        assert_eq!(top.response.headers()["content-type"], "text/html");
+
+        let a_js = &bundle.exchanges()[1];
+        assert_eq!(a_js.request.url(), "https://example.com/a.js");
+        assert_eq!(a_js.response.body(), b"abcde");
+        assert_eq!(a_js.response.headers()["content-length"], "5");
+        assert!(!a_js.response.headers().contains_key("transfer-encoding"));
+
+        let bundle = Bundle::builder()
+            .version(Version::VersionB2)
+            .exchanges_from_warc_with_filter(warc.as_bytes(), |exchange| {
+                exchange.response.status() == StatusCode::NOT_FOUND
+            })?
+            .build()?;
+        assert_eq!(bundle.exchanges().len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn truncated_warc() {
+        let warc = "WARC/1.0\r\nWARC-Type: response\r\nContent-Length: 18446744073709551615\r\n\r\nHTTP/1.1 200 OK\r\n";
+        assert!(Bundle::builder()
+            .version(Version::VersionB2)
+            .exchanges_from_warc(warc.as_bytes())
+            .is_err());
+    }
+}
@@ -0,0 +1,667 @@
+// Copyright 2021 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Conformance tests against the b2 format of
+//! [Web Bundles](https://wpack-wg.github.io/bundled-responses/draft-ietf-wpack-bundled-responses.html).
+//!
+//! Run with `cargo test --features conformance --test conformance`. The test
+//! vectors are written with a minimal CBOR encoder below, independently of
+//! the encoder of this crate, and each case checks a behavior of the spec.
+//! The output reports which behaviors this crate passes:
+//!
+//! - `PASS`, `FAIL`: The case passes or fails.
+//! - `XFAIL`: The case is a known failure, listed in `KNOWN_FAILURES`.
+//! - `XPASS`: The case is listed in `KNOWN_FAILURES`, but passes.
+//!
+//! `FAIL` and `XPASS` fail the test, so that `KNOWN_FAILURES` stays up to
+//! date.
+//!
+//! If `WPT_DIR` is set to a checkout of
+//! [web-platform-tests](https://github.com/web-platform-tests/wpt), the test
+//! also decodes the bundles in `web-bundle/resources/wbn`, and checks that
+//! they survive encoding and decoding again. Some of them are invalid on
+//! purpose, so failures to decode them are only reported:
+//!
+//! ```shell
+//! git clone --depth 1 --filter=blob:none --sparse https://github.com/web-platform-tests/wpt
+//! git -C wpt sparse-checkout set web-bundle/resources/wbn
+//! WPT_DIR=wpt cargo test --features conformance --test conformance
+//! ```
+
+use std::path::Path;
+
+use webbundle::{Bundle, DecodeOptions, Exchange, Severity, Version};
+
+const MAGIC: [u8; 8] = [0xf0, 0x9f, 0x8c, 0x90, 0xf0, 0x9f, 0x93, 0xa6];
+const VERSION_B2: &[u8; 4] = b"b2\0\0";
+const URL: &str = "https://example.com/";
+const SCRIPT_URL: &str = "https://example.com/script.js";
+
+/// The cases which this crate fails today, with the reasons.
+const KNOWN_FAILURES: &[(&str, &str)] = &[
+    (
+        "decode: accepts obs-text in header values",
+        "header values are converted from UTF-8 strings",
+    ),
+    (
+        "decode: rejects unknown critical sections",
+        "the critical section isn't parsed",
+    ),
+    (
+        "decode: rejects duplicate header names",
+        "the last value of a duplicate header wins",
+    ),
+    (
+        "encode: the trailing length is a byte string",
+        "the length is written without the head of a byte string",
+    ),
+];
+
+/// A minimal CBOR encoder, which writes items in the given order, so that
+/// vectors can be non-canonical on purpose.
+enum Cbor {
+    Uint(u64),
+    Bytes(Vec<u8>),
+    Text(&'static str),
+    Array(Vec<Cbor>),
+    Map(Vec<(Cbor, Cbor)>),
+}
+
+impl Cbor {
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.encode_to(&mut bytes);
+        bytes
+    }
+
+    fn encode_to(&self, bytes: &mut Vec<u8>) {
+        match self {
+            Cbor::Uint(n) => head(bytes, 0, *n),
+            Cbor::Bytes(b) => {
+                head(bytes, 2, b.len() as u64);
+                bytes.extend_from_slice(b);
+            }
+            Cbor::Text(s) => {
+                head(bytes, 3, s.len() as u64);
+                bytes.extend_from_slice(s.as_bytes());
+            }
+            Cbor::Array(items) => {
+                head(bytes, 4, items.len() as u64);
+                for item in items {
+                    item.encode_to(bytes);
+                }
+            }
+            Cbor::Map(entries) => {
+                head(bytes, 5, entries.len() as u64);
+                for (key, value) in entries {
+                    key.encode_to(bytes);
+                    value.encode_to(bytes);
+                }
+            }
+        }
+    }
+}
+
+fn head(bytes: &mut Vec<u8>, major_type: u8, n: u64) {
+    let major_type = major_type << 5;
+    if n < 24 {
+        bytes.push(major_type | n as u8);
+    } else if n <= u8::MAX as u64 {
+        bytes.extend_from_slice(&[major_type | 24, n as u8]);
+    } else if n <= u16::MAX as u64 {
+        bytes.push(major_type | 25);
+        bytes.extend_from_slice(&(n as u16).to_be_bytes());
+    } else if n <= u32::MAX as u64 {
+        bytes.push(major_type | 26);
+        bytes.extend_from_slice(&(n as u32).to_be_bytes());
+    } else {
+        bytes.push(major_type | 27);
+        bytes.extend_from_slice(&n.to_be_bytes());
+    }
+}
+
+/// Returns a response, `[headers: bstr .cbor {* bstr => bstr}, payload: bstr]`.
+/// The headers must be sorted, and include pseudo-headers.
+fn response(headers: &[(&str, &[u8])], body: &[u8]) -> Vec<u8> {
+    let headers = Cbor::Map(
+        headers
+            .iter()
+            .map(|(name, value)| {
+                (
+                    Cbor::Bytes(name.as_bytes().to_vec()),
+                    Cbor::Bytes(value.to_vec()),
+                )
+            })
+            .collect(),
+    );
+    [
+        vec![0x82],
+        Cbor::Bytes(headers.encode()).encode(),
+        Cbor::Bytes(body.to_vec()).encode(),
+    ]
+    .concat()
+}
+
+fn ok(body: &[u8]) -> Vec<u8> {
+    response(&[(":status", b"200")], body)
+}
+
+/// The sections of a vector. The responses section is added last, unless it
+/// is given.
+enum Section {
+    Index,
+    Primary(&'static str),
+    Responses,
+    Other(&'static str, Cbor),
+}
+
+/// Returns a b2 bundle of the sections, where the index maps the urls to the
+/// responses, in the given order. The index is sorted as canonical CBOR.
+fn bundle(sections: Vec<Section>, responses: &[(&'static str, Vec<u8>)]) -> Vec<u8> {
+    let mut responses_bytes = Vec::new();
+    head(&mut responses_bytes, 4, responses.len() as u64);
+    let mut index = Vec::new();
+    for (url, response) in responses {
+        index.push((*url, [responses_bytes.len() as u64, response.len() as u64]));
+        responses_bytes.extend_from_slice(response);
+    }
+    // Shorter keys first, then bytewise.
+    index.sort_by_key(|(url, _)| (url.len(), *url));
+    let index = Cbor::Map(
+        index
+            .into_iter()
+            .map(|(url, [offset, length])| {
+                (
+                    Cbor::Text(url),
+                    Cbor::Array(vec![Cbor::Uint(offset), Cbor::Uint(length)]),
+                )
+            })
+            .collect(),
+    )
+    .encode();
+
+    let mut sections = sections;
+    if !sections
+        .iter()
+        .any(|section| matches!(section, Section::Responses))
+    {
+        sections.push(Section::Responses);
+    }
+    let sections = sections
+        .into_iter()
+        .map(|section| match section {
+            Section::Index => ("index", index.clone()),
+            Section::Primary(url) => ("primary", Cbor::Text(url).encode()),
+            Section::Responses => ("responses", responses_bytes.clone()),
+            Section::Other(name, item) => (name, item.encode()),
+        })
+        .collect::<Vec<_>>();
+    let section_lengths = Cbor::Array(
+        sections
+            .iter()
+            .flat_map(|(name, bytes)| [Cbor::Text(name), Cbor::Uint(bytes.len() as u64)])
+            .collect(),
+    );
+
+    let mut bytes = vec![0x85];
+    bytes.extend(Cbor::Bytes(MAGIC.to_vec()).encode());
+    bytes.extend(Cbor::Bytes(VERSION_B2.to_vec()).encode());
+    bytes.extend(Cbor::Bytes(section_lengths.encode()).encode());
+    head(&mut bytes, 4, sections.len() as u64);
+    for (_, section) in sections {
+        bytes.extend(section);
+    }
+    let len = bytes.len() as u64 + 9;
+    bytes.extend(Cbor::Bytes(len.to_be_bytes().to_vec()).encode());
+    bytes
+}
+
+/// The bundle of the spec's simplest form, with one response.
+fn minimal() -> Vec<u8> {
+    bundle(vec![Section::Index], &[(URL, ok(b"Hello"))])
+}
+
+type CaseResult = Result<(), String>;
+
+fn check(condition: bool, message: impl Into<String>) -> CaseResult {
+    if condition {
+        Ok(())
+    } else {
+        Err(message.into())
+    }
+}
+
+fn decode(bytes: &[u8]) -> Result<Bundle, String> {
+    Bundle::from_bytes(bytes).map_err(|err| format!("{err:#}"))
+}
+
+fn rejects(bytes: &[u8]) -> CaseResult {
+    check(decode(bytes).is_err(), "decoded an invalid bundle")
+}
+
+fn encode(bundle: &Bundle) -> Result<Vec<u8>, String> {
+    bundle.encode().map_err(|err| format!("{err:#}"))
+}
+
+/// Returns the bundle, which has exchanges with various headers and a primary
+/// url, to test the encoder.
+fn sample() -> Bundle {
+    let mut script = http::Response::new(b"console.log(1);".to_vec());
+    script.headers_mut().insert(
+        "content-type",
+        http::HeaderValue::from_static("text/javascript"),
+    );
+    script
+        .headers_mut()
+        .insert("x-a", http::HeaderValue::from_static("a"));
+    let mut redirect = http::Response::new(Vec::new());
+    *redirect.status_mut() = http::StatusCode::MOVED_PERMANENTLY;
+    redirect
+        .headers_mut()
+        .insert("location", http::HeaderValue::from_static("/script.js"));
+    Bundle::builder()
+        .version(Version::VersionB2)
+        .primary_url(URL.parse().unwrap())
+        .exchange(Exchange::from((URL.to_string(), b"Hi".to_vec())))
+        .exchange(Exchange {
+            request: SCRIPT_URL.to_string().into(),
+            response: script,
+        })
+        .exchange(Exchange {
+            request: "https://example.com/old.js".to_string().into(),
+            response: redirect,
+        })
+        .build()
+        .unwrap()
+}
+
+fn sorted(bundle: &Bundle) -> Vec<&Exchange> {
+    let mut exchanges = bundle.exchanges().iter().collect::<Vec<_>>();
+    exchanges.sort_by_key(|exchange| exchange.request.url());
+    exchanges
+}
+
+/// Compares the exchanges of the bundles, ignoring their order.
+fn same_exchanges(a: &Bundle, b: &Bundle) -> CaseResult {
+    check(
+        a.exchanges().len() == b.exchanges().len(),
+        "the numbers of exchanges differ",
+    )?;
+    for (a, b) in sorted(a).into_iter().zip(sorted(b)) {
+        let url = a.request.url();
+        check(url == b.request.url(), format!("{url}: the urls differ"))?;
+        check(
+            a.response.status() == b.response.status(),
+            format!("{url}: the statuses differ"),
+        )?;
+        check(
+            a.response.headers() == b.response.headers(),
+            format!("{url}: the headers differ"),
+        )?;
+        check(
+            a.response.body() == b.response.body(),
+            format!("{url}: the bodies differ"),
+        )?;
+    }
+    Ok(())
+}
+
+/// A case, which is named after the behavior of the spec.
+type Case = (&'static str, fn() -> CaseResult);
+
+const CASES: &[Case] = &[
+    ("decode: a minimal bundle", || {
+        let bundle = decode(&minimal())?;
+        check(bundle.version() == &Version::VersionB2, "wrong version")?;
+        check(bundle.primary_url().is_none(), "unexpected primary url")?;
+        check(bundle.exchanges().len() == 1, "wrong number of exchanges")?;
+        let exchange = &bundle.exchanges()[0];
+        check(exchange.request.url() == URL, "wrong url")?;
+        check(exchange.response.status() == 200, "wrong status")?;
+        check(exchange.response.body() == b"Hello", "wrong body")
+    }),
+    ("decode: the primary section", || {
+        let bytes = bundle(
+            vec![Section::Index, Section::Primary(URL)],
+            &[(URL, ok(b""))],
+        );
+        check(
+            decode(&bytes)?.primary_url() == &Some(URL.parse().unwrap()),
+            "wrong primary url",
+        )
+    }),
+    ("decode: sections in any order before responses", || {
+        let bytes = bundle(
+            vec![Section::Primary(URL), Section::Index],
+            &[(URL, ok(b""))],
+        );
+        check(decode(&bytes)?.exchanges().len() == 1, "missing exchange")
+    }),
+    ("decode: ignores unknown sections", || {
+        let bytes = bundle(
+            vec![
+                Section::Other("unknown", Cbor::Array(vec![Cbor::Uint(1)])),
+                Section::Index,
+            ],
+            &[(URL, ok(b""))],
+        );
+        check(decode(&bytes)?.exchanges().len() == 1, "missing exchange")
+    }),
+    ("decode: the critical section lists known sections", || {
+        let bytes = bundle(
+            vec![
+                Section::Other("critical", Cbor::Array(vec![Cbor::Text("index")])),
+                Section::Index,
+            ],
+            &[(URL, ok(b""))],
+        );
+        let mut options = DecodeOptions::default();
+        options.strict = true;
+        Bundle::from_bytes_with_options(bytes, &options).map_err(|err| format!("{err:#}"))?;
+        Ok(())
+    }),
+    ("decode: rejects unknown critical sections", || {
+        let bytes = bundle(
+            vec![
+                Section::Other("critical", Cbor::Array(vec![Cbor::Text("unknown")])),
+                Section::Other("unknown", Cbor::Uint(0)),
+                Section::Index,
+            ],
+            &[(URL, ok(b""))],
+        );
+        rejects(&bytes)
+    }),
+    ("decode: responses are located by the index", || {
+        // The responses are in the reverse order of the index.
+        let bytes = bundle(
+            vec![Section::Index],
+            &[(SCRIPT_URL, ok(b"script")), (URL, ok(b"html"))],
+        );
+        let bundle = decode(&bytes)?;
+        for exchange in bundle.exchanges() {
+            let expected: &[u8] = if exchange.request.url() == URL {
+                b"html"
+            } else {
+                b"script"
+            };
+            check(
+                exchange.response.body() == expected,
+                format!("wrong body of {}", exchange.request.url()),
+            )?;
+        }
+        check(bundle.exchanges().len() == 2, "wrong number of exchanges")
+    }),
+    ("decode: response headers", || {
+        let bytes = bundle(
+            vec![Section::Index],
+            &[(
+                URL,
+                response(
+                    &[
+                        (":status", b"301"),
+                        ("location", b"/new"),
+                        ("content-type", b"text/html"),
+                    ],
+                    b"",
+                ),
+            )],
+        );
+        let bundle = decode(&bytes)?;
+        let response = &bundle.exchanges()[0].response;
+        check(response.status() == 301, "wrong status")?;
+        check(response.headers()["location"] == "/new", "wrong location")?;
+        check(
+            response.headers()["content-type"] == "text/html",
+            "wrong content-type",
+        )
+    }),
+    ("decode: accepts obs-text in header values", || {
+        let bytes = bundle(
+            vec![Section::Index],
+            &[(
+                URL,
+                response(&[(":status", b"200"), ("x-a", b"\xe9t\xe9")], b""),
+            )],
+        );
+        let bundle = decode(&bytes)?;
+        check(
+            bundle.exchanges()[0].response.headers()["x-a"].as_bytes() == b"\xe9t\xe9",
+            "wrong header value",
+        )
+    }),
+    (
+        "decode: the trailing length with or without a byte string head",
+        || {
+            let bytes = minimal();
+            decode(&bytes)?;
+            // Without the head of the byte string.
+            let mut raw = bytes[..bytes.len() - 9].to_vec();
+            raw.extend_from_slice(&(bytes.len() as u64 - 1).to_be_bytes());
+            decode(&raw)?;
+            Ok(())
+        },
+    ),
+    ("decode: rejects a wrong magic", || {
+        let mut bytes = minimal();
+        bytes[2] = 0;
+        rejects(&bytes)
+    }),
+    (
+        "decode: rejects a bundle which isn't an array of 5 items",
+        || {
+            let mut bytes = minimal();
+            bytes[0] = 0x84;
+            rejects(&bytes)
+        },
+    ),
+    ("decode: rejects sections after responses", || {
+        let bytes = bundle(
+            vec![Section::Index, Section::Responses, Section::Primary(URL)],
+            &[(URL, ok(b""))],
+        );
+        rejects(&bytes)
+    }),
+    ("decode: rejects duplicate section names", || {
+        let bytes = bundle(
+            vec![Section::Index, Section::Primary(URL), Section::Primary(URL)],
+            &[(URL, ok(b""))],
+        );
+        rejects(&bytes)
+    }),
+    ("decode: rejects a response without :status", || {
+        let bytes = bundle(
+            vec![Section::Index],
+            &[(URL, response(&[("x-a", b"a")], b""))],
+        );
+        rejects(&bytes)
+    }),
+    ("decode: rejects unknown pseudo-headers", || {
+        let bytes = bundle(
+            vec![Section::Index],
+            &[(
+                URL,
+                response(&[(":method", b"GET"), (":status", b"200")], b""),
+            )],
+        );
+        rejects(&bytes)
+    }),
+    ("decode: rejects uppercase header names", || {
+        let bytes = bundle(
+            vec![Section::Index],
+            &[(URL, response(&[("X-A", b"a"), (":status", b"200")], b""))],
+        );
+        rejects(&bytes)
+    }),
+    ("decode: rejects duplicate header names", || {
+        let bytes = bundle(
+            vec![Section::Index],
+            &[(
+                URL,
+                response(&[("x-a", b"a"), ("x-a", b"b"), (":status", b"200")], b""),
+            )],
+        );
+        rejects(&bytes)
+    }),
+    ("decode: rejects a response which isn't a pair", || {
+        let mut response = ok(b"");
+        response[0] = 0x83;
+        response.extend(Cbor::Bytes(Vec::new()).encode());
+        rejects(&bundle(vec![Section::Index], &[(URL, response)]))
+    }),
+    ("decode: rejects an index out of the bundle", || {
+        let bytes = minimal();
+        rejects(&bytes[..bytes.len() - 12])
+    }),
+    ("encode: the same bytes as a minimal bundle", || {
+        let encoded = encode(&decode(&minimal())?)?;
+        // Compare the bytes before the trailing length, which is checked by
+        // another case.
+        check(
+            encoded[..encoded.len() - 8] == minimal()[..minimal().len() - 9],
+            "the bytes differ",
+        )
+    }),
+    ("encode: canonical CBOR", || {
+        let findings = webbundle::validate(&encode(&sample())?);
+        match findings
+            .iter()
+            .find(|finding| finding.severity == Severity::Error)
+        {
+            Some(finding) => Err(finding.to_string()),
+            None => Ok(()),
+        }
+    }),
+    ("encode: the responses section is the last", || {
+        let bytes = encode(&sample())?;
+        let index = webbundle_core::parse_index(&bytes).map_err(|err| err.to_string())?;
+        let last = index.sections.last().unwrap();
+        check(last.name == "responses", "the last section isn't responses")?;
+        check(
+            bytes.len() as u64 - (last.offset + last.length) <= 9,
+            "extra bytes after the responses section",
+        )
+    }),
+    (
+        "encode: the trailing length is the length of the bundle",
+        || {
+            let bytes = encode(&sample())?;
+            let len = u64::from_be_bytes(bytes[bytes.len() - 8..].try_into().unwrap());
+            check(len == bytes.len() as u64, "wrong length")
+        },
+    ),
+    ("encode: the trailing length is a byte string", || {
+        let bytes = encode(&sample())?;
+        check(bytes[bytes.len() - 9] == 0x48, "no head of a byte string")
+    }),
+    (
+        "encode: strict mode rejects requests other than GET",
+        || {
+            let mut exchange = Exchange::from((URL.to_string(), b"Hi".to_vec()));
+            exchange.request = exchange.request.with_method(http::Method::POST);
+            let built = Bundle::builder()
+                .version(Version::VersionB2)
+                .strict(true)
+                .exchange(exchange)
+                .build();
+            check(built.is_err(), "built a POST request in strict mode")
+        },
+    ),
+    ("encode: decoding gives the same exchanges", || {
+        let bundle = sample();
+        let decoded = decode(&encode(&bundle)?)?;
+        check(
+            decoded.primary_url() == bundle.primary_url(),
+            "wrong primary url",
+        )?;
+        same_exchanges(&decoded, &bundle)
+    }),
+];
+
+/// Decodes and encodes the bundles of web-platform-tests, returning the
+/// number of failures.
+fn run_wpt(dir: &Path) -> usize {
+    let dir = dir.join("web-bundle/resources/wbn");
+    let mut paths = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| path.extension().is_some_and(|extension| extension == "wbn"))
+            .collect::<Vec<_>>(),
+        Err(err) => {
+            println!("FAIL wpt: {}: {err}", dir.display());
+            return 1;
+        }
+    };
+    paths.sort();
+    let mut failures = 0;
+    for path in paths {
+        let name = format!("wpt: {}", path.file_name().unwrap().to_string_lossy());
+        let bundle = match Bundle::from_bytes(std::fs::read(&path).unwrap()) {
+            Ok(bundle) => bundle,
+            Err(err) => {
+                println!("SKIP {name}: {err:#}");
+                continue;
+            }
+        };
+        match encode(&bundle)
+            .and_then(|bytes| decode(&bytes))
+            .and_then(|decoded| same_exchanges(&bundle, &decoded))
+        {
+            Ok(()) => println!("PASS {name}"),
+            Err(err) => {
+                println!("FAIL {name}: {err}");
+                failures += 1;
+            }
+        }
+    }
+    failures
+}
+
+fn main() {
+    let mut passed = 0;
+    let mut unexpected = Vec::new();
+    for (name, case) in CASES {
+        let known_failure = KNOWN_FAILURES.iter().find(|(known, _)| known == name);
+        match (case(), known_failure) {
+            (Ok(()), None) => {
+                println!("PASS {name}");
+                passed += 1;
+            }
+            (Ok(()), Some(_)) => {
+                println!("XPASS {name}: remove it from KNOWN_FAILURES");
+                passed += 1;
+                unexpected.push(name);
+            }
+            (Err(err), None) => {
+                println!("FAIL {name}: {err}");
+                unexpected.push(name);
+            }
+            (Err(err), Some((_, reason))) => println!("XFAIL {name}: {err} ({reason})"),
+        }
+    }
+    for (known, _) in KNOWN_FAILURES {
+        assert!(
+            CASES.iter().any(|(name, _)| name == known),
+            "Unknown case in KNOWN_FAILURES: {known}"
+        );
+    }
+    println!("{passed} of {} spec behaviors pass", CASES.len());
+
+    let wpt_failures = match std::env::var_os("WPT_DIR") {
+        Some(dir) => run_wpt(Path::new(&dir)),
+        None => 0,
+    };
+    if !unexpected.is_empty() || wpt_failures > 0 {
+        std::process::exit(1);
+    }
+}